@@ -0,0 +1,102 @@
+#!/usr/bin/env -S uv run
+"""Benchmark building pytest nodes with rytest against pytest's own collection.
+
+Each module of the payload is collected in this process both ways: by
+pytest, from a `Module` node it imports and inspects, and by rytest, from the
+tests it parsed. Both trees are expanded down to their items, so the rytest
+side includes the fixture registration pytest's `collect` does as well.
+
+Dependencies:
+pytest>=7.0.0
+"""
+
+import argparse
+import sys
+import time
+from pathlib import Path
+from typing import Callable, List, Tuple
+
+import pytest
+from _pytest.config import _prepareconfig
+
+import rytest_core
+
+
+def collect_items(node) -> List[str]:
+    """The node ids of the items under `node`, collecting every collector below it."""
+    nodeids = []
+    pending = [node]
+    while pending:
+        node = pending.pop()
+        if isinstance(node, pytest.Item):
+            nodeids.append(node.nodeid)
+        else:
+            pending.extend(reversed(node.collect()))
+    return nodeids
+
+
+def best_of(rounds: int, collect: Callable[[], List[str]]) -> Tuple[float, List[str]]:
+    """The shortest time `collect` takes over `rounds` runs, after one to warm up, and what it collects."""
+    nodeids = collect()
+    best = float("inf")
+    for _ in range(rounds):
+        start = time.perf_counter()
+        nodeids = collect()
+        best = min(best, time.perf_counter() - start)
+    return best, nodeids
+
+
+def main() -> int:
+    """Run the benchmark comparison."""
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("payload", nargs="?", default=Path(__file__).parent / "test_payload", type=Path)
+    parser.add_argument("--rounds", type=int, default=5, help="timed rounds per file (default: 5)")
+    args = parser.parse_args()
+
+    payload = args.payload.resolve()
+    files = sorted(payload.glob("test_*.py"))
+    config = _prepareconfig([str(payload), "-p", "no:cacheprovider", "-p", "no:rytest_collect"])
+    config._do_configure()
+    try:
+        session = pytest.Session.from_config(config)
+        collector = rytest_core.Collector(config)
+
+        def pytest_collect(path: Path) -> List[str]:
+            return collect_items(pytest.Module.from_parent(session, path=path))
+
+        def rytest_collect(path: Path) -> List[str]:
+            module = collector.pytest_collect_file(str(path), session)
+            return [] if module is None else collect_items(module)
+
+        print(f"{'file':<28} {'tests':>7} {'pytest ms':>11} {'rytest ms':>11} {'speedup':>9}")
+        totals = [0.0, 0.0]
+        identical = True
+        for path in files:
+            pytest_time, expected = best_of(args.rounds, lambda: pytest_collect(path))
+            rytest_time, found = best_of(args.rounds, lambda: rytest_collect(path))
+            totals[0] += pytest_time
+            totals[1] += rytest_time
+            print(
+                f"{path.name:<28} {len(expected):>7} {pytest_time * 1e3:>11.2f} "
+                f"{rytest_time * 1e3:>11.2f} {pytest_time / rytest_time:>8.2f}x"
+            )
+            if found != expected:
+                identical = False
+                print(f"  only pytest: {sorted(set(expected) - set(found))}")
+                print(f"  only rytest: {sorted(set(found) - set(expected))}")
+        print(
+            f"{'total':<28} {'':>7} {totals[0] * 1e3:>11.2f} "
+            f"{totals[1] * 1e3:>11.2f} {totals[0] / totals[1]:>8.2f}x"
+        )
+    finally:
+        config._ensure_unconfigure()
+
+    if not identical:
+        print("\nThe collectors built different tests.")
+        return 1
+    print("\nBoth collectors built the same tests, in the same order.")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
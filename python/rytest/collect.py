@@ -4,17 +4,412 @@ from pathlib import Path
 
 import rytest_core
 
+_collector_key = pytest.StashKey["rytest_core.Collector"]()
+_verify_key = pytest.StashKey["list[str]"]()
+_lint_key = pytest.StashKey["list[str]"]()
+_changed_key = pytest.StashKey["tuple[int, int]"]()
+_shard_key = pytest.StashKey["tuple[int, int]"]()
+
+
+def _collector(config):
+    """The collector shared by every hook in this session."""
+    if _collector_key not in config.stash:
+        config.stash[_collector_key] = rytest_core.Collector(config)
+    return config.stash[_collector_key]
+
+
+def pytest_addoption(parser):
+    group = parser.getgroup("rytest")
+    # The plugin may be registered both as a module and through its entry point
+    if any("--rytest-json" in option.names() for option in group.options):
+        return
+    group.addoption(
+        "--rytest-json",
+        metavar="PATH",
+        default=None,
+        help="Write the collected tree as JSON to PATH.",
+    )
+    group.addoption(
+        "--rytest-verify",
+        action="store_true",
+        default=False,
+        help="Also collect with pytest's own collector and report where they differ.",
+    )
+    group.addoption(
+        "--rytest-fixtures-per-test",
+        action="store_true",
+        default=False,
+        help="Show the fixtures each test uses, resolved statically, without running tests.",
+    )
+    group.addoption(
+        "--rytest-lint",
+        action="store_true",
+        default=False,
+        help="Report unknown, unused and shadowed fixtures, failing the session if there are any.",
+    )
+    group.addoption(
+        "--rytest-prune-skipped",
+        action="store_true",
+        default=False,
+        help="Deselect tests that skip or skipif marks always skip, evaluated statically.",
+    )
+    group.addoption(
+        "--rytest-changed-since",
+        metavar="REF",
+        default=None,
+        help="Only collect test files affected by changes since the git REF, through their imports.",
+    )
+    group.addoption(
+        "--rytest-shard",
+        metavar="INDEX/TOTAL",
+        default=None,
+        help="Only run shard INDEX (from 0) of TOTAL, splitting the collected tests deterministically.",
+    )
+    group.addoption(
+        "--rytest-shard-by",
+        choices=("file", "class", "test"),
+        default="file",
+        help="What --rytest-shard keeps together in one shard (default: file).",
+    )
+    group.addoption(
+        "--rytest-durations",
+        metavar="PATH",
+        action="append",
+        default=[],
+        help="Balance --rytest-shard by the durations in PATH, a JSON object mapping node ids to seconds "
+        "(repeatable; later files win). Every shard must be given the same files.",
+    )
+    group.addoption(
+        "--rytest-store-durations",
+        metavar="PATH",
+        default=None,
+        help="Merge how long each test took into PATH, in the format --rytest-durations reads.",
+    )
+    group.addoption(
+        "--rytest-errors",
+        choices=("fallback", "report", "continue"),
+        default="fallback",
+        help="For modules rytest cannot collect statically: leave them to pytest (default), "
+        "report them as collection errors, or collect their other tests and warn.",
+    )
+    group.addoption(
+        "--rytest-no-daemon",
+        action="store_true",
+        default=False,
+        help="Parse files in this process even if `rytest daemon` is running.",
+    )
+    group.addoption(
+        "--rytest-profile",
+        action="store_true",
+        default=False,
+        help="Time collection phase by phase and report the phases and the slowest files.",
+    )
+    group.addoption(
+        "--rytest-profile-top",
+        metavar="N",
+        type=int,
+        default=10,
+        help="How many of the slowest files --rytest-profile reports (default 10).",
+    )
+    group.addoption(
+        "--rytest-profile-trace",
+        metavar="PATH",
+        default=None,
+        help="Write the collection profile to PATH as Chrome trace JSON. Implies --rytest-profile.",
+    )
+
+
+def _item_record(item):
+    """`(nodeid, line, params, marks)` of a collected item, as the Rust side expects."""
+    line = item.location[1]
+    callspec = getattr(item, "callspec", None)
+    params = callspec.params.items() if callspec is not None else ()
+    return (
+        item.nodeid,
+        line + 1 if line is not None else None,
+        [(name, repr(value)) for name, value in params],
+        [mark.name for mark in item.iter_markers()],
+    )
+
+
+def _stock_items(node):
+    """Collect `node` recursively with pytest's own collectors."""
+    if isinstance(node, pytest.Item):
+        yield node
+        return
+    for child in node.collect():
+        yield from _stock_items(child)
+
+
+def _verify(session, collector):
+    """Diff the modules rytest collected against `_pytest.python` collection."""
+    from _pytest.python import Module
+
+    records, errors = [], []
+    for path in collector.rytest_modules():
+        module = Module.from_parent(session, path=Path(path))
+        try:
+            records.extend(_item_record(item) for item in _stock_items(module))
+        except Exception as e:
+            errors.append((module.nodeid, f"{type(e).__name__}: {e}"))
+    return collector.verify(records, errors)
+
+
+def _lint(session, collector, items):
+    """Check fixture usage across every collected test, including ones pytest collected."""
+    fixturemanager = getattr(session, "_fixturemanager", None)
+    # Fixtures from plugins are registered without a base node id
+    known = [
+        name
+        for name, fixturedefs in getattr(fixturemanager, "_arg2fixturedefs", {}).items()
+        if any(getattr(fixturedef, "baseid", None) == "" for fixturedef in fixturedefs)
+    ]
+    tests = [(item.nodeid, list(getattr(item, "fixturenames", ()))) for item in items]
+    return collector.lint(known, tests)
+
+
+def _show_fixtures_per_test(session):
+    """Print each test's fixture closure in the layout of `--fixtures-per-test`."""
+    reporter = session.config.pluginmanager.get_plugin("terminalreporter")
+    if reporter is None:
+        return
+    items = session.items
+    reports = _collector(session.config).fixtures_per_test([item.nodeid for item in items])
+    for item, lines in zip(items, reports):
+        if not lines:
+            continue
+        path, line, _ = item.location
+        reporter.write_line("")
+        reporter.write_sep("-", f"fixtures used by {item.name}")
+        reporter.write_sep("-", f"({path}:{line + 1 if line is not None else '?'})")
+        for text in lines:
+            reporter.write_line(text)
+
+
+def _parse_shard(value):
+    """`(index, total)` from an `INDEX/TOTAL` option value."""
+    try:
+        index, total = (int(part) for part in value.split("/"))
+    except ValueError:
+        raise pytest.UsageError(f"--rytest-shard: expected INDEX/TOTAL, not {value}") from None
+    if not 0 <= index < total:
+        raise pytest.UsageError(f"--rytest-shard: INDEX must be from 0 to TOTAL-1, not {value}")
+    return index, total
+
+
+# The name pytest registers the `pytest11` entry point, RytestCollector, under
+_plugin_name = "rytest_collect"
+
 
 def pytest_configure(config):
-    config.pluginmanager.register(RytestCollector(config))
+    # Installed, pytest has already loaded RytestCollector through its entry point
+    if not config.pluginmanager.has_plugin(_plugin_name):
+        config.pluginmanager.register(RytestCollector, _plugin_name)
+
+
+def _register(config, name, plugin):
+    if not config.pluginmanager.has_plugin(name):
+        config.pluginmanager.register(plugin, name)
+
+
+def _configure(config):
+    """Register the plugins behind rytest's optional features, once per session."""
+    _register(config, "rytest_doctests", RytestDoctests())
+    shard = config.getoption("rytest_shard")
+    if shard is not None:
+        _register(config, "rytest_shard", RytestShard(_parse_shard(shard)))
+    # xdist workers report to the controller, which stores for them
+    store = config.getoption("rytest_store_durations")
+    if store is not None and not hasattr(config, "workerinput"):
+        _register(config, "rytest_durations", RytestDurations(store))
+    if config.pluginmanager.hasplugin("xdist"):
+        _register(config, "rytest_xdist", RytestXdist())
+    trace = config.getoption("rytest_profile_trace")
+    # Workers collect without a terminal to report to
+    if (config.getoption("rytest_profile") or trace is not None) and not hasattr(config, "workerinput"):
+        _register(config, "rytest_profile", RytestProfile(config.getoption("rytest_profile_top"), trace))
+    _use_shared_collection(config)
+
+
+def _use_shared_collection(config):
+    """On an xdist worker, take parsed modules from the file the controller shared."""
+    workerinput = getattr(config, "workerinput", {})
+    if "rytest_collection" not in workerinput:
+        return
+    try:
+        _collector(config).use_shared_collection(
+            config, workerinput["rytest_collection"], workerinput["rytest_collection_hash"]
+        )
+    except (OSError, ValueError) as e:
+        config.issue_config_time_warning(
+            pytest.PytestWarning(f"rytest: parsing files on this worker, the shared collection is unusable: {e}"),
+            stacklevel=2,
+        )
+
+
+class RytestXdist:
+    """Plugin that parses test modules once, in the xdist controller, for every worker.
+
+    Each worker would otherwise read and parse every file itself. The
+    controller saves the parsed modules in the cache directory and passes the
+    file and the hash of the node ids it collects to the workers. A worker
+    takes the modules from the file, parsing only files changed since, and
+    warns if the node ids it collected hash differently.
+    """
+
+    def __init__(self):
+        self.shared = None
+
+    def pytest_xdist_setupnodes(self, config, specs):
+        invocation_dir = config.invocation_params.dir
+        args = [str(invocation_dir / arg.split("::")[0]) for arg in config.args]
+        git_ref = config.getoption("rytest_changed_since")
+        try:
+            # Hashing only the node ids of the test files workers will collect
+            if git_ref is not None:
+                _collector(config).changed_since(config, git_ref, args)
+            path, collection_hash, _ = _collector(config).share_collection(config, args)
+        except (OSError, ValueError):
+            # Workers parse files themselves
+            return
+        self.shared = (str(path), collection_hash)
+
+    def pytest_configure_node(self, node):
+        if self.shared is not None:
+            path, collection_hash = self.shared
+            node.workerinput["rytest_collection"] = path
+            node.workerinput["rytest_collection_hash"] = collection_hash
+
+    def pytest_collection_finish(self, session):
+        if "rytest_collection" not in getattr(session.config, "workerinput", {}):
+            return
+        try:
+            _collector(session.config).check_shared_collection()
+        except ValueError as e:
+            session.warn(pytest.PytestWarning(f"rytest: {e}"))
+
+    def pytest_unconfigure(self, config):
+        if self.shared is not None:
+            Path(self.shared[0]).unlink(missing_ok=True)
+
+
+class RytestProfile:
+    """Plugin that times collection phase by phase, for `--rytest-profile`.
+
+    Timing covers the whole of `pytest_collection`, so the time pytest spends
+    outside rytest's hooks, for example on modules left to it, shows up as
+    "other" in the report printed in the terminal summary.
+    """
+
+    def __init__(self, top, trace):
+        self.top = top
+        self.trace = trace
+        self.report = []
+
+    @pytest.hookimpl(hookwrapper=True)
+    def pytest_collection(self, session):
+        collector = _collector(session.config)
+        collector.start_profile()
+        yield
+        self.report = collector.finish_profile(self.top, self.trace)
+
+    def pytest_terminal_summary(self, terminalreporter):
+        if not self.report:
+            return
+        terminalreporter.write_sep("=", "rytest profile")
+        for line in self.report:
+            terminalreporter.write_line(line)
+
+
+class RytestShard:
+    """Plugin that deselects the tests outside this job's `--rytest-shard`.
+
+    It runs after every other `pytest_collection_modifyitems`, so shards split
+    the tests left after `-k`, `-m` and other deselection.
+    """
+
+    def __init__(self, shard):
+        self.shard = shard
+
+    @pytest.hookimpl(trylast=True)
+    def pytest_collection_modifyitems(self, config, items):
+        try:
+            kept = set(
+                _collector(config).shard(
+                    [item.nodeid for item in items],
+                    self.shard,
+                    config.getoption("rytest_shard_by"),
+                    config.getoption("rytest_durations"),
+                )
+            )
+        except ValueError as e:
+            raise pytest.UsageError(f"--rytest-shard: {e}") from None
+        deselected = [item for i, item in enumerate(items) if i not in kept]
+        config.stash[_shard_key] = (len(kept), len(items))
+        if deselected:
+            items[:] = [item for i, item in enumerate(items) if i in kept]
+            config.hook.pytest_deselected(items=deselected)
+
+    def pytest_report_collectionfinish(self, config):
+        if _shard_key not in config.stash:
+            return None
+        selected, total = config.stash[_shard_key]
+        index, shards = self.shard
+        by = config.getoption("rytest_shard_by")
+        return f"rytest: shard {index} of {shards} by {by}: {selected} of {total} tests"
+
+
+class RytestDurations:
+    """Plugin that stores how long each test took in a file, for sharing through `--rytest-durations`."""
+
+    def __init__(self, path):
+        self.path = path
+        self.durations = {}
+
+    def pytest_runtest_logreport(self, report):
+        # Setup, call and teardown together
+        self.durations[report.nodeid] = self.durations.get(report.nodeid, 0.0) + report.duration
+
+    def pytest_sessionfinish(self, session):
+        if self.durations:
+            _collector(session.config).store_durations(self.path, list(self.durations.items()))
+
+
+class RytestDoctests:
+    """Plugin that spares importing modules without doctests under `--doctest-modules`.
+
+    pytest's doctest plugin imports every module to look for doctests. rytest
+    finds them statically, so `DoctestModule` collectors are dropped for
+    modules it knows have none, and the doctests it finds are recorded in the
+    manifest with their names and lines.
+    """
+
+    @staticmethod
+    @pytest.hookimpl(hookwrapper=True)
+    def pytest_collect_file(file_path=None, parent=None):
+        outcome = yield
+        if file_path is None or parent is None:
+            return
+        from _pytest.doctest import DoctestModule, DoctestTextfile
+
+        doctest_types = (DoctestModule, DoctestTextfile)
+        nodes = outcome.get_result() or []
+        if not any(isinstance(node, doctest_types) for node in nodes):
+            return
+        found = _collector(parent.config).doctests(str(file_path))
+        if found is not None and not found:
+            outcome.force_result([node for node in nodes if not isinstance(node, doctest_types)])
 
 
 class RytestCollector:
-    """Plugin that replaces pytest's default collection with a Rust implementation."""
+    """Plugin that replaces pytest's default collection with a Rust implementation.
+
+    The class itself is the plugin, as registered through the entry point or
+    by `-p rytest.collect`, and its `pytest_configure` registers the rest.
+    """
 
-    def __init__(self, config):
-        self.config = config
-        self.collector = rytest_core.Collector(config)
+    pytest_addoption = staticmethod(pytest_addoption)
+    pytest_configure = staticmethod(_configure)
 
     @staticmethod
     @pytest.hookimpl(tryfirst=True)
@@ -26,9 +421,7 @@ class RytestCollector:
         if not str(file_path).endswith('.py'):
             return None
 
-        # Create a new collector instance for this file
-        collector = rytest_core.Collector(parent.config)
-        result = collector.pytest_collect_file(str(file_path), parent)
+        result = _collector(parent.config).pytest_collect_file(str(file_path), parent)
         return result if result is not None else None
 
     @staticmethod
@@ -39,7 +432,113 @@ class RytestCollector:
             # If path or parent is not provided, skip collection
             return None
 
-        # Create a new collector instance for this directory
-        collector = rytest_core.Collector(parent.config)
-        result = collector.pytest_collect_directory(str(path), parent)
+        result = _collector(parent.config).pytest_collect_directory(str(path), parent)
         return result if result is not None else None
+
+    @staticmethod
+    @pytest.hookimpl(tryfirst=True)
+    def pytest_collection(session):
+        """Find the files changed since `--rytest-changed-since` before anything is collected."""
+        config = session.config
+        git_ref = config.getoption("rytest_changed_since")
+        if git_ref is None:
+            return None
+        invocation_dir = config.invocation_params.dir
+        args = [str(invocation_dir / arg.split("::")[0]) for arg in config.args]
+        try:
+            config.stash[_changed_key] = _collector(config).changed_since(config, git_ref, args)
+        except ValueError as e:
+            raise pytest.UsageError(f"--rytest-changed-since: {e}") from None
+        return None
+
+    @staticmethod
+    def pytest_ignore_collect(collection_path, config):
+        """Leave out test files no change affects, so no nodes are created for them."""
+        if _changed_key not in config.stash:
+            return None
+        if collection_path.is_dir() or collection_path.name == "conftest.py":
+            return None
+        return None if _collector(config).is_affected(str(collection_path)) else True
+
+    @staticmethod
+    def pytest_report_header(config):
+        socket = _collector(config).daemon_socket()
+        if socket is not None:
+            return f"rytest: modules parsed by the daemon on {socket}"
+        return None
+
+    @staticmethod
+    def pytest_report_collectionfinish(config):
+        if _changed_key in config.stash:
+            selected, total = config.stash[_changed_key]
+            git_ref = config.getoption("rytest_changed_since")
+            return f"rytest: changes since {git_ref} affect {selected} of {total} test files"
+        return None
+
+    @staticmethod
+    @pytest.hookimpl(tryfirst=True)
+    def pytest_collection_modifyitems(session, config, items):
+        """Record and verify the collected tests, before any are deselected, then prune skipped ones."""
+        if config.getoption("rytest_verify"):
+            config.stash[_verify_key] = _verify(session, _collector(config))
+        if config.getoption("rytest_lint"):
+            config.stash[_lint_key] = _lint(session, _collector(config), items)
+        if config.getoption("rytest_json") is not None:
+            _collector(config).record_fallback_tests([_item_record(item) for item in items])
+        if config.getoption("rytest_prune_skipped"):
+            skipped = set(_collector(config).prune_skipped())
+            if skipped:
+                pruned = [item for item in items if item.nodeid in skipped]
+                items[:] = [item for item in items if item.nodeid not in skipped]
+                config.hook.pytest_deselected(items=pruned)
+
+    @staticmethod
+    def pytest_deselected(items):
+        if not items:
+            return
+        config = items[0].config
+        if config.getoption("rytest_json") is None:
+            return
+        _collector(config).deselect([item.nodeid for item in items])
+
+    @staticmethod
+    def pytest_collection_finish(session):
+        path = session.config.getoption("rytest_json")
+        if path is not None:
+            _collector(session.config).write_manifest(path)
+        if session.config.getoption("rytest_fixtures_per_test"):
+            _show_fixtures_per_test(session)
+
+    @staticmethod
+    @pytest.hookimpl(tryfirst=True)
+    def pytest_runtestloop(session):
+        """Like `--fixtures-per-test`, the report replaces running the tests."""
+        if session.config.getoption("rytest_fixtures_per_test"):
+            return True
+        return None
+
+    @staticmethod
+    def pytest_terminal_summary(terminalreporter, config):
+        if _verify_key in config.stash:
+            report = config.stash[_verify_key]
+            if not report:
+                terminalreporter.write_sep("=", "rytest verify: collection matches pytest", green=True)
+            else:
+                terminalreporter.write_sep("=", "rytest verify: collection differs from pytest", red=True)
+                for line in report:
+                    terminalreporter.write_line(line)
+        if _lint_key in config.stash:
+            report = config.stash[_lint_key]
+            if not report:
+                terminalreporter.write_sep("=", "rytest lint: no fixture problems", green=True)
+            else:
+                terminalreporter.write_sep("=", f"rytest lint: {len(report)} fixture problems", red=True)
+                for line in report:
+                    terminalreporter.write_line(line)
+
+    @staticmethod
+    @pytest.hookimpl(trylast=True)
+    def pytest_sessionfinish(session, exitstatus):
+        failed = session.config.stash.get(_verify_key, None) or session.config.stash.get(_lint_key, None)
+        if failed and exitstatus == pytest.ExitCode.OK:
+            session.exitstatus = pytest.ExitCode.TESTS_FAILED
@@ -29,6 +29,9 @@ cd tests/pytest
 uv pip install -e .
 cd ../..
 
+echo "==> Running Rust core tests..."
+cargo test
+
 echo "==> Building Rust collector..."
 # Clean any old builds
 rm -rf target/wheels/
@@ -0,0 +1,92 @@
+//! Values stored in pytest's cache directory, as `config.cache.get` and
+//! `config.cache.set` store them: one JSON file per key under `v/`.
+
+use crate::config::IniConfig;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// The files pytest's cacheprovider writes when it creates the cache directory
+const SUPPORTING_FILES: [(&str, &str); 2] = [
+    (".gitignore", "# Created by pytest automatically.\n*\n"),
+    (
+        "CACHEDIR.TAG",
+        "Signature: 8a477f597d28d172789f06886806bc55\n\
+         # This file is a cache directory tag created by pytest.\n\
+         # For information about cache directory tags, see:\n\
+         #\thttps://bford.info/cachedir/spec.html\n",
+    ),
+];
+
+/// The file holding the value of `key`, such as `rytest/imports`
+pub fn value_path(config: &IniConfig, key: &str) -> PathBuf {
+    config.cache_path().join("v").join(key)
+}
+
+/// The cached value of `key`, if there is one and it is valid JSON
+pub fn get(config: &IniConfig, key: &str) -> Option<Value> {
+    let contents = fs::read_to_string(value_path(config, key)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Create the cache directory with its supporting files, if it does not exist
+fn ensure_cache_dir(config: &IniConfig) -> io::Result<()> {
+    let cache_dir = config.cache_path();
+    if !cache_dir.exists() {
+        fs::create_dir_all(&cache_dir)?;
+        for (name, contents) in SUPPORTING_FILES {
+            fs::write(cache_dir.join(name), contents)?;
+        }
+    }
+    Ok(())
+}
+
+/// A directory for files rytest keeps in the cache, as `config.cache.mkdir`
+/// creates them under `d/`
+pub fn mkdir(config: &IniConfig, name: &str) -> io::Result<PathBuf> {
+    ensure_cache_dir(config)?;
+    let path = config.cache_path().join("d").join(name);
+    fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+/// Cache `value` under `key`, creating the cache directory as pytest does
+pub fn set(config: &IniConfig, key: &str, value: &Value) -> io::Result<()> {
+    ensure_cache_dir(config)?;
+    let path = value_path(config, key);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // pytest writes values with sorted keys and two-space indentation
+    fs::write(path, serde_json::to_string_pretty(value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_get_and_set() {
+        let dir = std::env::temp_dir().join("rytest_cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = IniConfig::with_defaults(dir.clone());
+
+        assert_eq!(get(&config, "rytest/imports"), None);
+        set(&config, "rytest/imports", &json!({"version": 1})).unwrap();
+        assert_eq!(get(&config, "rytest/imports"), Some(json!({"version": 1})));
+        assert!(dir.join(".pytest_cache/v/rytest/imports").is_file());
+        assert!(dir.join(".pytest_cache/.gitignore").is_file());
+        assert_eq!(
+            mkdir(&config, "rytest").unwrap(),
+            dir.join(".pytest_cache/d/rytest")
+        );
+
+        fs::write(value_path(&config, "rytest/imports"), "{").unwrap();
+        assert_eq!(get(&config, "rytest/imports"), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
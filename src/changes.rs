@@ -0,0 +1,495 @@
+//! Which tests an edit touches, for rerunning them on save.
+//!
+//! Each test is fingerprinted by the code of its function, its marks and its
+//! parameter values, so editing one test or one parameter touches only the
+//! node ids it generates. Comments and blank lines do not count. The rest of
+//! a module is fingerprinted as a whole: editing a helper, a fixture or an
+//! import touches every test in the module.
+
+use crate::config::IniConfig;
+use crate::fixtures::FixtureIndex;
+use crate::impact::Impact;
+use crate::imports::ImportGraph;
+use crate::index::ModuleIndex;
+use crate::parser::{self, ParseError, TestItem, TestKind};
+use crate::session;
+use crate::source;
+use crate::walk;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+fn hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The tests of one module, in collection order, with their fingerprints
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleTests {
+    /// Fingerprint of the module's code outside its test functions
+    pub rest: u64,
+    pub tests: Vec<(String, u64)>,
+}
+
+/// How the tests of a module changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TestChanges {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl TestChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+
+    /// E.g. `1 new, 2 modified, 1 removed`
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = [
+            (self.added.len(), "new"),
+            (self.modified.len(), "modified"),
+            (self.removed.len(), "removed"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{} {}", count, what))
+        .collect();
+        if parts.is_empty() {
+            "no test changes".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+impl ModuleTests {
+    /// Fingerprint the tests of the module at `path`, resolved from `source`
+    pub fn new(source: &str, path: &Path, items: &[TestItem], config: &IniConfig) -> Self {
+        let lines = parser::logical_lines(source);
+        let mut in_tests = vec![false; lines.len()];
+        let mut functions: HashMap<usize, u64> = HashMap::new();
+        for item in items.iter().filter(|item| item.kind != TestKind::Class) {
+            let Some(def) = lines
+                .iter()
+                .position(|line| line.line_number == item.line_number)
+            else {
+                continue;
+            };
+            let indent = lines[def].indent;
+            let end = lines[def + 1..]
+                .iter()
+                .position(|line| line.indent <= indent)
+                .map_or(lines.len(), |n| def + 1 + n);
+            let start = lines[..def]
+                .iter()
+                .position(|line| line.line_number >= item.first_line)
+                .unwrap_or(def);
+            in_tests[start..end].fill(true);
+            // Parametrize marks count through the parameters of each node id
+            let marks: Vec<String> = item
+                .marks
+                .iter()
+                .filter(|mark| mark.name != "parametrize")
+                .map(|mark| format!("{:?}", mark))
+                .collect();
+            let code: Vec<(usize, &str)> = lines[def..end]
+                .iter()
+                .map(|line| (line.indent - indent, line.text.as_str()))
+                .collect();
+            functions.insert(item.line_number, hash((code, marks)));
+        }
+        let rest: Vec<(usize, &str)> = lines
+            .iter()
+            .zip(&in_tests)
+            .filter(|(_, in_test)| !**in_test)
+            .map(|(line, _)| (line.indent, line.text.as_str()))
+            .collect();
+        let tests = session::module_tests(path, items, config)
+            .into_iter()
+            .map(|test| {
+                let function = functions.get(&test.line_number).copied();
+                let fingerprint = hash((function, format!("{:?}", test.params)));
+                (test.nodeid, fingerprint)
+            })
+            .collect();
+        ModuleTests {
+            rest: hash(rest),
+            tests,
+        }
+    }
+
+    pub fn nodeids(&self) -> Vec<String> {
+        self.tests
+            .iter()
+            .map(|(nodeid, _)| nodeid.clone())
+            .collect()
+    }
+
+    /// How the tests changed since `old`
+    ///
+    /// When the code outside the test functions changed, every remaining
+    /// test is modified.
+    pub fn diff(&self, old: &ModuleTests) -> TestChanges {
+        let before: HashMap<&str, u64> = old
+            .tests
+            .iter()
+            .map(|(nodeid, fingerprint)| (nodeid.as_str(), *fingerprint))
+            .collect();
+        let after: HashSet<&str> = self
+            .tests
+            .iter()
+            .map(|(nodeid, _)| nodeid.as_str())
+            .collect();
+        let mut changes = TestChanges::default();
+        for (nodeid, fingerprint) in &self.tests {
+            match before.get(nodeid.as_str()) {
+                None => changes.added.push(nodeid.clone()),
+                Some(old_fingerprint)
+                    if *old_fingerprint != *fingerprint || old.rest != self.rest =>
+                {
+                    changes.modified.push(nodeid.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        changes.removed = old
+            .tests
+            .iter()
+            .filter(|(nodeid, _)| !after.contains(nodeid.as_str()))
+            .map(|(nodeid, _)| nodeid.clone())
+            .collect();
+        changes
+    }
+}
+
+/// What a round of file changes means for the tests
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rerun {
+    /// Changed test modules, relative to the rootdir, and how their tests changed
+    pub modules: Vec<(String, TestChanges)>,
+    /// Tests of other modules that import a changed file or are below a changed conftest
+    pub affected: Vec<String>,
+    /// The changed files other than test modules, relative to the rootdir
+    pub causes: Vec<String>,
+    /// Changed test modules that could not be parsed; their tests are kept as they were
+    pub errors: Vec<(String, ParseError)>,
+}
+
+impl Rerun {
+    /// The node ids to run: new and modified tests, then affected ones
+    pub fn nodeids(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.modules
+            .iter()
+            .flat_map(|(_, changes)| changes.added.iter().chain(&changes.modified))
+            .chain(&self.affected)
+            .filter(|nodeid| seen.insert(nodeid.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// The tests under some paths, kept up to date as files change
+pub struct TestTree {
+    args: Vec<PathBuf>,
+    config: IniConfig,
+    index: ModuleIndex,
+    /// The fingerprinted tests of every test module that could be parsed
+    modules: BTreeMap<PathBuf, ModuleTests>,
+    /// The directories of the files the tests import, transitively
+    imported_dirs: BTreeSet<PathBuf>,
+}
+
+impl TestTree {
+    /// Parse and fingerprint the test modules under `args`
+    pub fn new(args: &[PathBuf], config: &IniConfig) -> Self {
+        let mut tree = TestTree {
+            args: args.to_vec(),
+            config: config.clone(),
+            index: ModuleIndex::build(args, config),
+            modules: BTreeMap::new(),
+            imported_dirs: BTreeSet::new(),
+        };
+        let mut fixtures = FixtureIndex::default();
+        for path in walk::collect_paths(args, config) {
+            if let Ok(tests) = tree.fingerprint(&mut fixtures, &path) {
+                tree.modules.insert(path, tests);
+            }
+        }
+        tree.update_graph();
+        tree
+    }
+
+    /// Update the import graph, returning it
+    fn update_graph(&mut self) -> ImportGraph {
+        let graph = ImportGraph::update_cache(&self.args, &self.config);
+        self.imported_dirs = graph
+            .modules
+            .keys()
+            .filter_map(|file| {
+                self.config
+                    .rootdir
+                    .join(file)
+                    .parent()
+                    .map(Path::to_path_buf)
+            })
+            .collect();
+        graph
+    }
+
+    /// The directories holding the files the tests import, to watch besides
+    /// the tests themselves
+    pub fn imported_dirs(&self) -> &BTreeSet<PathBuf> {
+        &self.imported_dirs
+    }
+
+    /// The number of test modules
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    /// The number of tests
+    pub fn tests(&self) -> usize {
+        self.modules.values().map(|module| module.tests.len()).sum()
+    }
+
+    /// Whether `path` is collected as a test module, given or found under the args
+    fn is_test_module(&self, path: &Path) -> bool {
+        self.args.iter().any(|arg| {
+            if arg.is_dir() {
+                path.starts_with(arg) && walk::is_test_module(path, &self.config)
+            } else {
+                path == arg
+            }
+        })
+    }
+
+    fn fingerprint(
+        &mut self,
+        fixtures: &mut FixtureIndex,
+        path: &Path,
+    ) -> Result<ModuleTests, ParseError> {
+        let config = &self.config;
+        let parsed = self.index.get(path)?;
+        session::index_conftests(
+            fixtures,
+            path,
+            &config.rootdir,
+            &config.python_classes,
+            &config.python_functions,
+        );
+        let items = session::resolve_module(fixtures, path, parsed)?;
+        let source = source::read(path)
+            .map_err(|e| ParseError::new(format!("Failed to read file: {}", e)))?;
+        Ok(ModuleTests::new(&source, path, &items, config))
+    }
+
+    /// Bring the tree up to date after `changed` files or directories changed
+    pub fn apply(&mut self, changed: &[PathBuf]) -> Rerun {
+        let rootdir = self.config.rootdir.clone();
+        let relative = |path: &Path| walk::relative_nodeid_path(path, &rootdir);
+        let mut rerun = Rerun::default();
+        let mut handled = HashSet::new();
+        let mut fixtures = FixtureIndex::default();
+        for path in changed {
+            self.index.update(path, &self.config);
+            if path.is_file() && self.is_test_module(path) {
+                handled.insert(path.clone());
+                match self.fingerprint(&mut fixtures, path) {
+                    Ok(tests) => {
+                        let old = self.modules.get(path).cloned().unwrap_or_default();
+                        rerun.modules.push((relative(path), tests.diff(&old)));
+                        self.modules.insert(path.clone(), tests);
+                    }
+                    Err(err) => rerun.errors.push((relative(path), err)),
+                }
+                continue;
+            }
+            let deleted: Vec<PathBuf> = match path.exists() {
+                true => Vec::new(),
+                false => self
+                    .modules
+                    .keys()
+                    .filter(|module| module.starts_with(path))
+                    .cloned()
+                    .collect(),
+            };
+            if deleted.is_empty() {
+                rerun.causes.push(relative(path));
+            }
+            for module in deleted {
+                let removed = self.modules.remove(&module).unwrap_or_default().nodeids();
+                rerun.modules.push((
+                    relative(&module),
+                    TestChanges {
+                        removed,
+                        ..TestChanges::default()
+                    },
+                ));
+                handled.insert(module);
+            }
+        }
+
+        let graph = self.update_graph();
+        let impact = Impact::new(&graph, changed, &self.config);
+        rerun.affected = self
+            .modules
+            .iter()
+            .filter(|(path, _)| !handled.contains(*path) && impact.selects(path))
+            .flat_map(|(_, tests)| tests.nodeids())
+            .collect();
+        rerun
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const TEST_MODELS: &str = "\
+import pytest
+from app import models
+
+
+def make_user():
+    return models.User()
+
+
+# A comment
+def test_create():
+    assert make_user()
+
+
+@pytest.mark.parametrize('n', [1, 2, 3])
+def test_count(n):
+    assert n
+
+
+class TestUser:
+    def test_name(self):
+        assert make_user().name
+";
+
+    fn write(dir: &Path, file: &str, source: &str) -> PathBuf {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_module_tests() {
+        let config = IniConfig::with_defaults(PathBuf::from("/repo"));
+        let path = Path::new("/repo/tests/test_models.py");
+        let tests = |source: &str| {
+            let items = parser::parse_source(
+                source,
+                "/repo/tests/test_models.py",
+                &config.python_classes,
+                &config.python_functions,
+            )
+            .unwrap();
+            ModuleTests::new(source, path, &items, &config)
+        };
+        let before = tests(TEST_MODELS);
+        assert_eq!(
+            before.nodeids(),
+            vec![
+                "tests/test_models.py::test_create",
+                "tests/test_models.py::test_count[1]",
+                "tests/test_models.py::test_count[2]",
+                "tests/test_models.py::test_count[3]",
+                "tests/test_models.py::TestUser::test_name",
+            ]
+        );
+
+        // Comments and blank lines do not count
+        let after = tests(&TEST_MODELS.replace("# A comment\n", "\n\n"));
+        assert!(after.diff(&before).is_empty());
+
+        let after =
+            tests(&TEST_MODELS.replace("assert make_user()\n", "assert make_user() is not None\n"));
+        assert_eq!(
+            after.diff(&before).modified,
+            vec!["tests/test_models.py::test_create"]
+        );
+
+        let after = tests(&TEST_MODELS.replace("[1, 2, 3]", "[1, 2, 4]"));
+        let changes = after.diff(&before);
+        assert_eq!(changes.added, vec!["tests/test_models.py::test_count[4]"]);
+        assert_eq!(changes.removed, vec!["tests/test_models.py::test_count[3]"]);
+        assert!(changes.modified.is_empty());
+        assert_eq!(changes.summary(), "1 new, 1 removed");
+
+        let after = tests(&TEST_MODELS.replace("def test_name", "def test_full_name"));
+        let changes = after.diff(&before);
+        assert_eq!(
+            changes.added,
+            vec!["tests/test_models.py::TestUser::test_full_name"]
+        );
+        assert_eq!(
+            changes.removed,
+            vec!["tests/test_models.py::TestUser::test_name"]
+        );
+
+        // A helper may be used by any test
+        let after = tests(&TEST_MODELS.replace("models.User()", "models.User(name='x')"));
+        assert_eq!(after.diff(&before).modified.len(), 5);
+    }
+
+    #[test]
+    fn test_apply() {
+        let dir = std::env::temp_dir().join("rytest_changes");
+        let _ = fs::remove_dir_all(&dir);
+        write(&dir, "pytest.ini", "[pytest]\n");
+        write(&dir, "app/__init__.py", "");
+        let models = write(&dir, "app/models.py", "class User: pass\n");
+        let test_models = write(&dir, "tests/test_models.py", TEST_MODELS);
+        write(&dir, "tests/test_other.py", "def test_other(): pass\n");
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        let mut tree = TestTree::new(&[dir.join("tests")], &config);
+        assert_eq!((tree.len(), tree.tests()), (2, 6));
+        assert!(tree.imported_dirs().contains(&dir.join("app")));
+
+        write(
+            &dir,
+            "tests/test_models.py",
+            &TEST_MODELS.replace("[1, 2, 3]", "[1, 2, 3, 5]"),
+        );
+        let rerun = tree.apply(std::slice::from_ref(&test_models));
+        assert_eq!(rerun.modules[0].0, "tests/test_models.py");
+        assert_eq!(rerun.nodeids(), vec!["tests/test_models.py::test_count[5]"]);
+        assert!(rerun.affected.is_empty());
+
+        fs::write(&models, "class User:\n    name = 'x'\n").unwrap();
+        let rerun = tree.apply(&[models]);
+        assert_eq!(rerun.causes, vec!["app/models.py"]);
+        assert_eq!(rerun.nodeids().len(), 6);
+
+        write(
+            &dir,
+            "tests/test_models.py",
+            "import pytest\n@pytest.mark.parametrize('n')\ndef test_count(n): pass\n",
+        );
+        let rerun = tree.apply(std::slice::from_ref(&test_models));
+        assert_eq!(rerun.errors.len(), 1);
+        assert!(rerun.nodeids().is_empty());
+        assert_eq!(tree.tests(), 7);
+
+        fs::remove_file(&test_models).unwrap();
+        let rerun = tree.apply(&[test_models]);
+        assert_eq!(rerun.modules[0].1.removed.len(), 6);
+        assert_eq!(tree.tests(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,560 @@
+//! Native loading of pytest's ini-style configuration.
+//!
+//! Mirrors pytest's `locate_config`: starting from the common ancestor of the
+//! given arguments, every directory up to the filesystem root is checked for
+//! `pytest.ini`, `.pytest.ini`, `pyproject.toml`, `tox.ini` and `setup.cfg`
+//! (in that order) and the first file carrying a pytest section wins.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Candidate configuration files, in pytest's order of precedence
+const CONFIG_FILES: [&str; 5] = [
+    "pytest.ini",
+    ".pytest.ini",
+    "pyproject.toml",
+    "tox.ini",
+    "setup.cfg",
+];
+
+const DEFAULT_PYTHON_FILES: [&str; 2] = ["test_*.py", "*_test.py"];
+const DEFAULT_PYTHON_CLASSES: [&str; 1] = ["Test"];
+const DEFAULT_PYTHON_FUNCTIONS: [&str; 1] = ["test"];
+const DEFAULT_CACHE_DIR: &str = ".pytest_cache";
+const DEFAULT_NORECURSEDIRS: [&str; 9] = [
+    "*.egg",
+    ".*",
+    "_darcs",
+    "build",
+    "CVS",
+    "dist",
+    "node_modules",
+    "venv",
+    "{arch}",
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ConfigError::Parse(path, msg) => write!(f, "{}: {}", path.display(), msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A raw ini value, before it is interpreted according to the option type
+#[derive(Debug, Clone, PartialEq)]
+enum IniValue {
+    String(String),
+    List(Vec<String>),
+}
+
+/// The pytest options relevant to collection, resolved the way `config.getini` does
+#[derive(Debug, Clone, PartialEq)]
+pub struct IniConfig {
+    pub rootdir: PathBuf,
+    pub inifile: Option<PathBuf>,
+    pub python_files: Vec<String>,
+    pub python_classes: Vec<String>,
+    pub python_functions: Vec<String>,
+    pub testpaths: Vec<String>,
+    pub norecursedirs: Vec<String>,
+    pub markers: Vec<String>,
+    pub addopts: Vec<String>,
+    /// Directories pytest adds to `sys.path`, relative to the ini file
+    pub pythonpath: Vec<String>,
+    /// pytest's cache directory, relative to the rootdir
+    pub cache_dir: String,
+}
+
+impl IniConfig {
+    /// Configuration used when no ini file is found
+    pub fn with_defaults(rootdir: PathBuf) -> Self {
+        let owned = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        IniConfig {
+            rootdir,
+            inifile: None,
+            python_files: owned(&DEFAULT_PYTHON_FILES),
+            python_classes: owned(&DEFAULT_PYTHON_CLASSES),
+            python_functions: owned(&DEFAULT_PYTHON_FUNCTIONS),
+            testpaths: Vec::new(),
+            norecursedirs: owned(&DEFAULT_NORECURSEDIRS),
+            markers: Vec::new(),
+            addopts: Vec::new(),
+            pythonpath: Vec::new(),
+            cache_dir: DEFAULT_CACHE_DIR.to_string(),
+        }
+    }
+
+    /// Locate and load the configuration for the given invocation arguments
+    pub fn locate(args: &[PathBuf], invocation_dir: &Path) -> Result<Self, ConfigError> {
+        let ancestor = common_ancestor(args, invocation_dir);
+        let mut found_pyproject = None;
+        for dir in ancestor.ancestors() {
+            for name in CONFIG_FILES {
+                let candidate = dir.join(name);
+                if !candidate.is_file() {
+                    continue;
+                }
+                if name == "pyproject.toml" && found_pyproject.is_none() {
+                    found_pyproject = Some(candidate.clone());
+                }
+                if let Some(config) = Self::load(&candidate)? {
+                    return Ok(config);
+                }
+            }
+        }
+
+        // Since pytest 8.1 a pyproject.toml without a pytest table still sets the rootdir
+        if let Some(pyproject) = found_pyproject {
+            let mut config = Self::with_defaults(
+                pyproject
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_else(|| PathBuf::from(".")),
+            );
+            config.inifile = Some(pyproject);
+            return Ok(config);
+        }
+        Ok(Self::with_defaults(default_rootdir(&ancestor)))
+    }
+
+    /// Load a single configuration file, returning `None` if it has no pytest section
+    pub fn load(path: &Path) -> Result<Option<Self>, ConfigError> {
+        let content =
+            fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        let values = if name.ends_with(".toml") {
+            parse_toml_section(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?
+        } else {
+            let mut sections =
+                parse_ini(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+            let section = if name == "setup.cfg" {
+                "tool:pytest"
+            } else {
+                "pytest"
+            };
+            match sections.remove(section) {
+                Some(values) => Some(values),
+                // pytest.ini is always a match, even without a [pytest] section
+                None if name == "pytest.ini" || name == ".pytest.ini" => Some(HashMap::new()),
+                None => None,
+            }
+        };
+
+        let Some(values) = values else {
+            return Ok(None);
+        };
+
+        let rootdir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let mut config = Self::with_defaults(rootdir);
+        config.inifile = Some(path.to_path_buf());
+
+        for (key, value) in values {
+            match key.as_str() {
+                "python_files" => config.python_files = as_args(value),
+                "python_classes" => config.python_classes = as_args(value),
+                "python_functions" => config.python_functions = as_args(value),
+                "testpaths" => config.testpaths = as_args(value),
+                "norecursedirs" => config.norecursedirs = as_args(value),
+                "markers" => config.markers = as_linelist(value),
+                "addopts" => config.addopts = as_args(value),
+                "pythonpath" => config.pythonpath = as_args(value),
+                "cache_dir" => {
+                    if let IniValue::String(dir) = value {
+                        config.cache_dir = dir.trim().to_string();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(config))
+    }
+
+    /// Look up a value by its ini name, as `config.getini` would
+    pub fn getini(&self, name: &str) -> Option<&[String]> {
+        match name {
+            "python_files" => Some(&self.python_files),
+            "python_classes" => Some(&self.python_classes),
+            "python_functions" => Some(&self.python_functions),
+            "testpaths" => Some(&self.testpaths),
+            "norecursedirs" => Some(&self.norecursedirs),
+            "markers" => Some(&self.markers),
+            "addopts" => Some(&self.addopts),
+            "pythonpath" => Some(&self.pythonpath),
+            "cache_dir" => Some(std::slice::from_ref(&self.cache_dir)),
+            _ => None,
+        }
+    }
+
+    /// The `pythonpath` directories, resolved against the ini file's directory
+    pub fn pythonpath_dirs(&self) -> Vec<PathBuf> {
+        let base = self
+            .inifile
+            .as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(&self.rootdir);
+        self.pythonpath.iter().map(|dir| base.join(dir)).collect()
+    }
+
+    /// The cache directory, as pytest's `cacheprovider` resolves it
+    pub fn cache_path(&self) -> PathBuf {
+        self.rootdir.join(&self.cache_dir)
+    }
+}
+
+/// Find the deepest directory shared by all existing arguments
+fn common_ancestor(args: &[PathBuf], invocation_dir: &Path) -> PathBuf {
+    let mut ancestor: Option<PathBuf> = None;
+    for arg in args {
+        let path = if arg.is_absolute() {
+            arg.clone()
+        } else {
+            invocation_dir.join(arg)
+        };
+        if !path.exists() {
+            continue;
+        }
+        let dir = if path.is_dir() {
+            path
+        } else {
+            path.parent().map(Path::to_path_buf).unwrap_or(path)
+        };
+        ancestor = Some(match ancestor {
+            None => dir,
+            Some(current) => current
+                .ancestors()
+                .find(|candidate| dir.starts_with(candidate))
+                .map(Path::to_path_buf)
+                .unwrap_or(current),
+        });
+    }
+    ancestor.unwrap_or_else(|| invocation_dir.to_path_buf())
+}
+
+/// Without an ini file pytest roots at the nearest ancestor with a `setup.py`
+fn default_rootdir(ancestor: &Path) -> PathBuf {
+    ancestor
+        .ancestors()
+        .find(|dir| dir.join("setup.py").is_file())
+        .unwrap_or(ancestor)
+        .to_path_buf()
+}
+
+/// Interpret a value of pytest's `args` ini type
+fn as_args(value: IniValue) -> Vec<String> {
+    match value {
+        IniValue::String(s) => shlex_split(&s),
+        IniValue::List(items) => items,
+    }
+}
+
+/// Interpret a value of pytest's `linelist` ini type
+fn as_linelist(value: IniValue) -> Vec<String> {
+    match value {
+        IniValue::String(s) => s
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect(),
+        IniValue::List(items) => items,
+    }
+}
+
+/// Extract `[tool.pytest.ini_options]` from a pyproject.toml
+fn parse_toml_section(content: &str) -> Result<Option<HashMap<String, IniValue>>, String> {
+    let document: toml::Table = content
+        .parse()
+        .map_err(|e: toml::de::Error| e.to_string())?;
+    let options = document
+        .get("tool")
+        .and_then(|tool| tool.get("pytest"))
+        .and_then(|pytest| pytest.get("ini_options"))
+        .and_then(|options| options.as_table());
+
+    let Some(options) = options else {
+        return Ok(None);
+    };
+
+    // pytest stringifies every scalar and keeps arrays as lists
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Boolean(b) => if *b { "True" } else { "False" }.to_string(),
+        other => other.to_string(),
+    };
+
+    Ok(Some(
+        options
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::Array(items) => IniValue::List(items.iter().map(scalar).collect()),
+                    other => IniValue::String(scalar(other)),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+    ))
+}
+
+/// Parse an ini file the way `iniconfig` does
+fn parse_ini(content: &str) -> Result<HashMap<String, HashMap<String, IniValue>>, String> {
+    let mut sections: HashMap<String, HashMap<String, IniValue>> = HashMap::new();
+    let mut section: Option<String> = None;
+    let mut key: Option<String> = None;
+
+    for (lineno, raw) in content.lines().enumerate() {
+        let line = raw.trim_end();
+        let stripped = line.trim_start();
+        if stripped.is_empty() || stripped.starts_with('#') || stripped.starts_with(';') {
+            continue;
+        }
+
+        // Indented lines continue the previous value
+        if line.starts_with([' ', '\t']) {
+            let (Some(section), Some(key)) = (&section, &key) else {
+                return Err(format!("line {}: unexpected continuation line", lineno + 1));
+            };
+            if let Some(IniValue::String(value)) =
+                sections.get_mut(section).and_then(|s| s.get_mut(key))
+            {
+                value.push('\n');
+                value.push_str(stripped);
+            }
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .split(']')
+                .next()
+                .filter(|_| name.contains(']'))
+                .ok_or_else(|| format!("line {}: unterminated section header", lineno + 1))?;
+            sections.entry(name.to_string()).or_default();
+            section = Some(name.to_string());
+            key = None;
+            continue;
+        }
+
+        let Some(current) = &section else {
+            return Err(format!("line {}: no section header defined", lineno + 1));
+        };
+        let split = line
+            .find(['=', ':'])
+            .ok_or_else(|| format!("line {}: unexpected line: {:?}", lineno + 1, line))?;
+        let name = line[..split].trim().to_string();
+        let value = line[split + 1..].trim().to_string();
+        sections
+            .entry(current.clone())
+            .or_default()
+            .insert(name.clone(), IniValue::String(value));
+        key = Some(name);
+    }
+
+    Ok(sections)
+}
+
+/// Split a string into arguments following POSIX shell quoting, like `shlex.split`
+pub fn shlex_split(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    args.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    current.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(next @ ('"' | '\\' | '$' | '`')) => current.push(next),
+                            Some(next) => {
+                                current.push('\\');
+                                current.push(next);
+                            }
+                            None => current.push('\\'),
+                        },
+                        c => current.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            '#' if !in_word => break,
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_project(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rytest_config_{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_shlex_split() {
+        assert_eq!(
+            shlex_split("-p rytest.collect  -q"),
+            vec!["-p", "rytest.collect", "-q"]
+        );
+        assert_eq!(
+            shlex_split(r#"-k "a and b" 'c d'"#),
+            vec!["-k", "a and b", "c d"]
+        );
+        assert_eq!(
+            shlex_split("check_*.py\n    *_check.py"),
+            vec!["check_*.py", "*_check.py"]
+        );
+    }
+
+    #[test]
+    fn test_pyproject_toml() {
+        let dir = temp_project("pyproject");
+        fs::write(
+            dir.join("pyproject.toml"),
+            r#"
+[project]
+name = "example"
+
+[tool.pytest.ini_options]
+python_files = ["check_*.py"]
+python_functions = "check_ verify_"
+testpaths = ["tests"]
+markers = ["slow: marks tests as slow", "serial"]
+addopts = "-p rytest.collect -q"
+pythonpath = ["src"]
+cache_dir = ".cache/pytest"
+"#,
+        )
+        .unwrap();
+
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        assert_eq!(config.rootdir, dir);
+        assert_eq!(config.inifile, Some(dir.join("pyproject.toml")));
+        assert_eq!(config.python_files, vec!["check_*.py"]);
+        assert_eq!(config.python_classes, vec!["Test"]);
+        assert_eq!(config.python_functions, vec!["check_", "verify_"]);
+        assert_eq!(config.testpaths, vec!["tests"]);
+        assert_eq!(config.markers, vec!["slow: marks tests as slow", "serial"]);
+        assert_eq!(config.addopts, vec!["-p", "rytest.collect", "-q"]);
+        assert_eq!(config.pythonpath_dirs(), vec![dir.join("src")]);
+        assert_eq!(config.cache_path(), dir.join(".cache/pytest"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_ini_files_and_precedence() {
+        let dir = temp_project("precedence");
+        fs::write(
+            dir.join("setup.cfg"),
+            "[tool:pytest]\npython_classes = Check\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("tox.ini"),
+            "[tox]\nenvlist = py311\n\n[pytest]\nmarkers =\n    slow: slow tests\n    serial\nnorecursedirs = .git build\n",
+        )
+        .unwrap();
+        // pyproject.toml without a pytest table does not match
+        fs::write(dir.join("pyproject.toml"), "[project]\nname = \"x\"\n").unwrap();
+        fs::write(dir.join("tests").join("pyproject.toml"), "").unwrap();
+
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        assert_eq!(config.inifile, Some(dir.join("tox.ini")));
+        assert_eq!(config.markers, vec!["slow: slow tests", "serial"]);
+        assert_eq!(config.norecursedirs, vec![".git", "build"]);
+        assert_eq!(config.python_classes, vec!["Test"]);
+
+        // An empty pytest.ini takes precedence over everything else
+        fs::write(dir.join("pytest.ini"), "").unwrap();
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        assert_eq!(config.inifile, Some(dir.join("pytest.ini")));
+        assert_eq!(config.norecursedirs.len(), DEFAULT_NORECURSEDIRS.len());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_bare_pyproject_sets_rootdir() {
+        let dir = temp_project("bare_pyproject");
+        fs::write(dir.join("pyproject.toml"), "[project]\nname = \"x\"\n").unwrap();
+
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        assert_eq!(config.rootdir, dir);
+        assert_eq!(config.python_files, vec!["test_*.py", "*_test.py"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_nearest_directory_wins() {
+        let dir = temp_project("nearest");
+        fs::write(
+            dir.join("pytest.ini"),
+            "[pytest]\npython_files = outer_*.py\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("tests").join("setup.cfg"),
+            "[tool:pytest]\npython_files = inner_*.py\n",
+        )
+        .unwrap();
+
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        assert_eq!(config.rootdir, dir.join("tests"));
+        assert_eq!(config.python_files, vec!["inner_*.py"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+//! A long-running process keeping the parsed modules of a tree in memory.
+//!
+//! `rytest daemon` builds a [`ModuleIndex`], keeps it up to date with a
+//! [`Watcher`], and answers requests on a Unix socket in pytest's cache
+//! directory (`d/rytest/daemon.sock`), so the pytest plugin gets parsed
+//! modules without reading and parsing every file itself.
+//!
+//! The protocol is one JSON [`Request`] per line, each answered by one JSON
+//! [`Response`] line. Parsed modules are sent as serde serializes
+//! [`ParsedModule`]; the format is private to one version of rytest.
+
+use crate::cache;
+use crate::config::IniConfig;
+use crate::index::ModuleIndex;
+use crate::parser::{ParseError, ParsedModule};
+use crate::session;
+use crate::watch::Watcher;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a client waits for an answer before parsing files itself
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Request {
+    /// The parsed module at an absolute path, with the ini patterns it is parsed with
+    Parse {
+        path: PathBuf,
+        python_classes: Vec<String>,
+        python_functions: Vec<String>,
+    },
+    Status,
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Module(ParsedModule),
+    /// The file could not be parsed, or the request was not understood
+    Error(ParseError),
+    Status {
+        pid: u32,
+        rootdir: PathBuf,
+        /// Files in the index
+        modules: usize,
+        /// Directories watched
+        directories: usize,
+    },
+    Stopping,
+}
+
+/// The socket of the daemon for `config`'s rootdir
+pub fn socket_path(config: &IniConfig) -> PathBuf {
+    config.cache_path().join("d/rytest/daemon.sock")
+}
+
+/// The state shared by the watching thread and the connections
+struct Shared {
+    index: Mutex<ModuleIndex>,
+    pid: u32,
+    rootdir: PathBuf,
+    directories: AtomicUsize,
+    stopping: AtomicBool,
+}
+
+impl Shared {
+    fn index(&self) -> std::sync::MutexGuard<'_, ModuleIndex> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn answer(&self, request: Request) -> Response {
+        match request {
+            Request::Parse {
+                path,
+                python_classes,
+                python_functions,
+            } => {
+                let mut index = self.index();
+                let parsed = if index.has_patterns(&python_classes, &python_functions) {
+                    index.get(&path)
+                } else {
+                    drop(index);
+                    session::parse_file(&path, &python_classes, &python_functions)
+                };
+                match parsed {
+                    Ok(module) => Response::Module(module),
+                    Err(err) => Response::Error(err),
+                }
+            }
+            Request::Status => Response::Status {
+                pid: self.pid,
+                rootdir: self.rootdir.clone(),
+                modules: self.index().len(),
+                directories: self.directories.load(Ordering::SeqCst),
+            },
+            Request::Stop => {
+                self.stopping.store(true, Ordering::SeqCst);
+                Response::Stopping
+            }
+        }
+    }
+}
+
+/// Answer the requests of one connection until it is closed or the daemon stops
+fn handle(shared: &Shared, stream: UnixStream, socket: &Path) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        if shared.stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        let response = match serde_json::from_str(&line?) {
+            Ok(request) => shared.answer(request),
+            Err(err) => Response::Error(ParseError::new(format!("bad request: {}", err))),
+        };
+        writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+        if matches!(response, Response::Stopping) {
+            // Wake the accept loop so it notices
+            let _ = UnixStream::connect(socket);
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Index the test modules and conftest files under `args` and serve them
+/// until a [`Request::Stop`]
+///
+/// Fails if another daemon is serving the same rootdir. A socket left
+/// behind by a daemon that was killed is replaced. `ready` is called with
+/// the socket path and the number of indexed files once requests are
+/// accepted.
+pub fn serve(
+    args: &[PathBuf],
+    config: &IniConfig,
+    ready: impl FnOnce(&Path, usize),
+) -> io::Result<()> {
+    let socket = socket_path(config);
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already serving {}", socket.display()),
+            ));
+        }
+        fs::remove_file(&socket)?;
+    }
+    cache::mkdir(config, "rytest")?;
+
+    // Watch before indexing, so nothing changed meanwhile is missed
+    let mut watcher = Watcher::new(args, config)?;
+    let shared = Arc::new(Shared {
+        index: Mutex::new(ModuleIndex::build(args, config)),
+        pid: std::process::id(),
+        rootdir: config.rootdir.clone(),
+        directories: AtomicUsize::new(watcher.len()),
+        stopping: AtomicBool::new(false),
+    });
+    let listener = UnixListener::bind(&socket)?;
+    ready(&socket, shared.index().len());
+
+    let watching = Arc::clone(&shared);
+    let watch_config = config.clone();
+    thread::spawn(move || {
+        while let Ok(changed) = watcher.wait() {
+            let mut index = watching.index();
+            for path in changed {
+                index.update(&path, &watch_config);
+            }
+            watching.directories.store(watcher.len(), Ordering::SeqCst);
+        }
+    });
+
+    for stream in listener.incoming() {
+        if shared.stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let shared = Arc::clone(&shared);
+        let socket = socket.clone();
+        thread::spawn(move || handle(&shared, stream, &socket));
+    }
+    fs::remove_file(&socket)
+}
+
+/// A connection to a running daemon
+pub struct Client {
+    socket: PathBuf,
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// Connect to the daemon for `config`'s rootdir, if one is running
+    pub fn connect(config: &IniConfig) -> io::Result<Self> {
+        let socket = socket_path(config);
+        let stream = UnixStream::connect(&socket)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok(Client {
+            socket,
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+        })
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    pub fn request(&mut self, request: &Request) -> io::Result<Response> {
+        writeln!(self.writer, "{}", serde_json::to_string(request)?)?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// The parsed module at `path`, or why it could not be parsed
+    ///
+    /// Fails only if the daemon did not answer.
+    pub fn parse(
+        &mut self,
+        path: &Path,
+        python_classes: &[String],
+        python_functions: &[String],
+    ) -> io::Result<Result<ParsedModule, ParseError>> {
+        let request = Request::Parse {
+            path: path.to_path_buf(),
+            python_classes: python_classes.to_vec(),
+            python_functions: python_functions.to_vec(),
+        };
+        match self.request(&request)? {
+            Response::Module(module) => Ok(Ok(module)),
+            Response::Error(err) => Ok(Err(err)),
+            response => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected response: {:?}", response),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_daemon() {
+        let dir = std::env::temp_dir().join("rytest_daemon");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("pytest.ini"), "[pytest]\n").unwrap();
+        let test_a = dir.join("tests/test_a.py");
+        fs::write(
+            &test_a,
+            "import pytest\n\n@pytest.mark.parametrize('x', [1, 2.5, 'a', None])\ndef test_a(x): pass\n",
+        )
+        .unwrap();
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let server = {
+            let config = config.clone();
+            let args = vec![dir.join("tests")];
+            thread::spawn(move || {
+                serve(&args, &config, |socket, modules| {
+                    ready_tx.send((socket.to_path_buf(), modules)).unwrap()
+                })
+            })
+        };
+        let (socket, modules) = ready_rx.recv().unwrap();
+        assert_eq!(socket, dir.join(".pytest_cache/d/rytest/daemon.sock"));
+        assert_eq!(modules, 1);
+        assert!(serve(&[], &config, |_, _| ()).is_err());
+
+        let mut client = Client::connect(&config).unwrap();
+        let local =
+            session::parse_file(&test_a, &config.python_classes, &config.python_functions).unwrap();
+        let served = client
+            .parse(&test_a, &config.python_classes, &config.python_functions)
+            .unwrap()
+            .unwrap();
+        assert_eq!(served.items[0].parameters, local.items[0].parameters);
+        assert_eq!(served.items[0].line_number, 4);
+
+        // Other patterns are parsed for the request alone
+        let served = client
+            .parse(&test_a, &config.python_classes, &["check_*".to_string()])
+            .unwrap()
+            .unwrap();
+        assert!(served.items.is_empty());
+
+        fs::write(dir.join("tests/test_b.py"), "def test_b(): pass\n").unwrap();
+        let status = |client: &mut Client| match client.request(&Request::Status).unwrap() {
+            Response::Status { modules, .. } => modules,
+            response => panic!("{:?}", response),
+        };
+        for _ in 0..100 {
+            if status(&mut client) == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(status(&mut client), 2);
+
+        let missing = client
+            .parse(&dir.join("tests/test_c.py"), &config.python_classes, &[])
+            .unwrap();
+        assert!(missing.is_err());
+
+        assert!(matches!(
+            client.request(&Request::Stop).unwrap(),
+            Response::Stopping
+        ));
+        server.join().unwrap().unwrap();
+        assert!(!socket.exists());
+        assert!(Client::connect(&config).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+//! Doctest discovery, as pytest's doctest plugin finds `DoctestModule` and
+//! `DoctestTextfile` items with the standard library's `DocTestFinder`.
+
+use crate::literal::Literal;
+use crate::parser;
+use glob_match::glob_match;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The `--doctest-glob` pytest uses when none is given
+pub const DEFAULT_GLOB: &str = "test*.txt";
+
+/// The doctest options of a pytest invocation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DoctestOptions {
+    /// `--doctest-modules`: look for doctests in every Python module
+    pub modules: bool,
+    /// `--doctest-glob` patterns for text files; empty means [`DEFAULT_GLOB`]
+    pub globs: Vec<String>,
+}
+
+impl DoctestOptions {
+    /// Pick the doctest options out of command line arguments, e.g. `addopts`
+    pub fn extend(&mut self, args: &[String]) {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--doctest-modules" {
+                self.modules = true;
+            } else if arg == "--doctest-glob" {
+                self.globs.extend(args.next().cloned());
+            } else if let Some(glob) = arg.strip_prefix("--doctest-glob=") {
+                self.globs.push(glob.to_string());
+            }
+        }
+    }
+
+    /// Whether pytest collects `path` as a `DoctestTextfile`
+    ///
+    /// `.txt` and `.rst` files given on the command line always are; others
+    /// must match a glob.
+    pub fn is_textfile(&self, path: &Path, explicit: bool) -> bool {
+        let extension = path.extension().and_then(|e| e.to_str());
+        if extension == Some("py") {
+            return false;
+        }
+        if explicit && matches!(extension, Some("txt" | "rst")) {
+            return true;
+        }
+        if self.globs.is_empty() {
+            fnmatch_ex(DEFAULT_GLOB, path)
+        } else {
+            self.globs.iter().any(|glob| fnmatch_ex(glob, path))
+        }
+    }
+
+    /// Whether pytest collects `path` as a `DoctestModule`
+    ///
+    /// With `--doctest-modules` that is every Python module except
+    /// `__main__.py` and a `setup.py` using setuptools or distutils.
+    pub fn is_module(&self, path: &Path) -> bool {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        self.modules
+            && name.ends_with(".py")
+            && name != "__main__.py"
+            && !(name == "setup.py" && is_setup_py(path))
+    }
+}
+
+fn is_setup_py(path: &Path) -> bool {
+    let contents = fs::read(path).unwrap_or_default();
+    let contains = |needle: &[u8]| contents.windows(needle.len()).any(|w| w == needle);
+    contains(b"setuptools") || contains(b"distutils")
+}
+
+/// pytest's `fnmatch_ex`: patterns without a `/` match the file name only
+fn fnmatch_ex(pattern: &str, path: &Path) -> bool {
+    if !pattern.contains('/') {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        return glob_match(pattern, name);
+    }
+    let path = path.to_string_lossy();
+    if pattern.starts_with('/') {
+        glob_match(pattern, &path)
+    } else {
+        glob_match(&format!("**/{}", pattern), &path)
+    }
+}
+
+/// A docstring or text file with examples, collected as one `DoctestItem`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Doctest {
+    /// Dotted name such as `pkg.mod.Class.method`, or the text file's name
+    pub name: String,
+    /// 0-based line of the docstring, as `DocTest.lineno`
+    pub lineno: usize,
+}
+
+/// Whether `text` has a `>>>` prompt, as `DocTestParser` looks for examples
+pub fn has_examples(text: &str) -> bool {
+    text.lines()
+        .any(|line| line.trim_start_matches([' ', '\t']).starts_with(">>>"))
+}
+
+/// The doctest of a text file named `name`, if it has examples
+pub fn textfile_doctest(source: &str, name: &str) -> Option<Doctest> {
+    has_examples(source).then(|| Doctest {
+        name: name.to_string(),
+        lineno: 0,
+    })
+}
+
+/// The string a statement consists of, if it is a docstring
+fn docstring(text: &str) -> Option<String> {
+    match Literal::parse(text) {
+        Ok(Literal::Str(value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Whether a line matches `DocTestFinder`'s `(^|.*:)\s*\w*("|')`
+fn opens_string(line: &str) -> bool {
+    let quoted = |rest: &str| {
+        let rest = rest.trim_start();
+        let rest = rest.trim_start_matches(|c: char| c.is_alphanumeric() || c == '_');
+        rest.starts_with(['"', '\''])
+    };
+    quoted(line)
+        || line
+            .match_indices(':')
+            .any(|(colon, _)| quoted(&line[colon + 1..]))
+}
+
+/// The 0-based line of the docstring, scanning forward from `start` as
+/// `DocTestFinder._find_lineno` does
+fn docstring_line(raw: &[&str], start: usize) -> usize {
+    (start..raw.len())
+        .find(|&lineno| opens_string(raw[lineno]))
+        .unwrap_or(start)
+}
+
+/// The first line matching `^\s*class\s*NAME\b`, where doctest starts looking
+/// for a class docstring
+fn class_line(raw: &[&str], name: &str) -> Option<usize> {
+    raw.iter().position(|line| {
+        let Some(rest) = line.trim_start().strip_prefix("class") else {
+            return false;
+        };
+        rest.trim_start()
+            .strip_prefix(name)
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+    })
+}
+
+/// Whether a decorator makes the function a property's setter or deleter,
+/// which leaves the getter's docstring in place
+fn is_accessor(decorator: &str) -> bool {
+    decorator.ends_with(".setter") || decorator.ends_with(".deleter")
+}
+
+/// The doctests in the docstrings of a module named `module`, sorted by name
+///
+/// Like `DocTestFinder`, this looks at the module, its functions and classes,
+/// and recursively at the methods, properties and classes defined in those
+/// classes. A later definition of a name replaces the earlier one.
+pub fn module_doctests(source: &str, module: &str) -> Vec<Doctest> {
+    let raw: Vec<&str> = source.lines().collect();
+    let lines = parser::logical_lines(source);
+    // Names with a docstring that has examples map to its line
+    let mut found: BTreeMap<String, Option<usize>> = BTreeMap::new();
+
+    if let Some(first) = lines.first() {
+        if first.indent == 0 && docstring(&first.text).is_some_and(|doc| has_examples(&doc)) {
+            found.insert(module.to_string(), Some(docstring_line(&raw, 0)));
+        }
+    }
+
+    // Enclosing definitions by indent; `None` for ones doctest does not look inside
+    let mut scopes: Vec<(usize, Option<String>)> = Vec::new();
+    // A definition whose first statement may be its docstring: indent, name, scan start
+    let mut pending: Option<(usize, String, usize)> = None;
+    let mut decorated_at = None;
+    let mut accessor = false;
+
+    for line in &lines {
+        if let Some((indent, name, start)) = pending.take() {
+            if line.indent > indent && docstring(&line.text).is_some_and(|d| has_examples(&d)) {
+                found.insert(name, Some(docstring_line(&raw, start)));
+            }
+        }
+        while scopes
+            .last()
+            .is_some_and(|(indent, _)| line.indent <= *indent)
+        {
+            scopes.pop();
+        }
+
+        let text = line.text.as_str();
+        if text.starts_with('@') {
+            decorated_at.get_or_insert(line.line_number);
+            accessor |= is_accessor(text);
+            continue;
+        }
+        let first_line = decorated_at.take().unwrap_or(line.line_number);
+        let accessor = std::mem::take(&mut accessor);
+        let parent = match scopes.last() {
+            Some((_, parent)) => parent.clone(),
+            None => Some(module.to_string()),
+        };
+
+        let definition = parser::def_name(text)
+            .map(|name| (name, false))
+            .or_else(|| parser::class_name(text).map(|name| (name, true)));
+        if let Some((name, is_class)) = definition {
+            let dotted = parent
+                .filter(|_| !accessor)
+                .map(|parent| format!("{}.{}", parent, name));
+            if let Some(dotted) = &dotted {
+                let start = if is_class {
+                    class_line(&raw, name).unwrap_or(line.line_number - 1)
+                } else {
+                    first_line - 1
+                };
+                found.insert(dotted.clone(), None);
+                pending = Some((line.indent, dotted.clone(), start));
+            }
+            scopes.push((line.indent, dotted.filter(|_| is_class)));
+        }
+    }
+
+    found
+        .into_iter()
+        .filter_map(|(name, lineno)| {
+            Some(Doctest {
+                name,
+                lineno: lineno?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_doctests() {
+        let source = r#""""Module docs.
+
+>>> 1 + 1
+2
+"""
+import functools
+
+
+def plain():
+    """No examples here."""
+
+
+@functools.cache
+def cached(x):
+    '''
+    >>> cached(2)
+    2
+    '''
+    def inner():
+        """>>> never collected"""
+    return x
+
+
+class Shape:
+    r"""A shape.
+
+    >>> Shape().sides
+    0
+    """
+
+    sides = 0
+
+    @property
+    def area(self):
+        """
+        >>> Shape().area
+        0
+        """
+        return 0
+
+    @area.setter
+    def area(self, value):
+        pass
+
+    class Inner:
+        def method(self):
+            """>>> Shape.Inner().method()"""
+
+
+def replaced():
+    """>>> replaced()"""
+
+
+def replaced():
+    pass
+
+
+if True:
+    async def conditional():
+        """>>> 'conditional'"""
+"#;
+        let doctests: Vec<(String, usize)> = module_doctests(source, "pkg.mod")
+            .into_iter()
+            .map(|doctest| (doctest.name, doctest.lineno))
+            .collect();
+        let expected = [
+            ("pkg.mod", 0),
+            ("pkg.mod.Shape", 24),
+            ("pkg.mod.Shape.Inner.method", 46),
+            ("pkg.mod.Shape.area", 34),
+            ("pkg.mod.cached", 14),
+            ("pkg.mod.conditional", 59),
+        ];
+        let expected: Vec<(String, usize)> = expected
+            .iter()
+            .map(|(name, lineno)| (name.to_string(), *lineno))
+            .collect();
+        assert_eq!(doctests, expected);
+
+        assert!(module_doctests("x = '>>> 1'\n", "m").is_empty());
+        assert_eq!(
+            textfile_doctest("Intro\n\n  >>> 1\n  1\n", "test_x.txt"),
+            Some(Doctest {
+                name: "test_x.txt".into(),
+                lineno: 0
+            })
+        );
+        assert_eq!(textfile_doctest("no examples\n", "test_x.txt"), None);
+    }
+
+    #[test]
+    fn test_doctest_options() {
+        let mut options = DoctestOptions::default();
+        assert!(options.is_textfile(Path::new("/r/test_a.txt"), false));
+        assert!(!options.is_textfile(Path::new("/r/README.rst"), false));
+        assert!(options.is_textfile(Path::new("/r/README.rst"), true));
+        assert!(!options.is_textfile(Path::new("/r/test_a.py"), true));
+        assert!(!options.is_module(Path::new("/r/mod.py")));
+
+        let args = ["-q", "--doctest-modules", "--doctest-glob", "*.rst"];
+        options.extend(&args.map(String::from));
+        options.extend(&["--doctest-glob=docs/*.md".to_string()]);
+        assert_eq!(options.globs, vec!["*.rst", "docs/*.md"]);
+        assert!(options.is_textfile(Path::new("/r/README.rst"), false));
+        assert!(!options.is_textfile(Path::new("/r/test_a.txt"), false));
+        assert!(options.is_textfile(Path::new("/r/docs/guide.md"), false));
+        assert!(!options.is_textfile(Path::new("/r/guide.md"), false));
+        assert!(options.is_module(Path::new("/r/mod.py")));
+        assert!(!options.is_module(Path::new("/r/pkg/__main__.py")));
+    }
+}
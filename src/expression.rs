@@ -0,0 +1,251 @@
+//! The `-k` / `-m` matching language, following pytest's `_pytest.mark.expression`.
+//!
+//! ```text
+//! expression: expr? EOF
+//! expr:       and_expr ('or' and_expr)*
+//! and_expr:   not_expr ('and' not_expr)*
+//! not_expr:   'not' not_expr | '(' expr ')' | ident
+//! ```
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Ident(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Ident(String),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+}
+
+/// A compiled match expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '+' | '-' | '.' | '[' | ']' | '\\' | '/')
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' {
+            tokens.push((i + 1, Token::LParen));
+            i += 1;
+        } else if c == ')' {
+            tokens.push((i + 1, Token::RParen));
+            i += 1;
+        } else if is_ident_char(c) {
+            let start = i;
+            while i < chars.len() && is_ident_char(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let token = match word.as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                "True" | "False" | "None" => {
+                    return Err(ExpressionError {
+                        column: start + 1,
+                        message: format!("unexpected reserved word {:?}", word),
+                    })
+                }
+                _ => Token::Ident(word),
+            };
+            tokens.push((start + 1, token));
+        } else {
+            return Err(ExpressionError {
+                column: i + 1,
+                message: format!("unexpected character {:?}", c),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map(|(c, _)| *c)
+            .unwrap_or(self.end + 1)
+    }
+
+    fn error(&self, expected: &str) -> ExpressionError {
+        let found = match self.peek() {
+            Some(token) => format!("{:?}", token),
+            None => "end of input".to_string(),
+        };
+        ExpressionError {
+            column: self.column(),
+            message: format!("expected {}; got {}", expected, found),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.and_expr()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            node = Node::Or(Box::new(node), Box::new(self.and_expr()?));
+        }
+        Ok(node)
+    }
+
+    fn and_expr(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.not_expr()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            node = Node::And(Box::new(node), Box::new(self.not_expr()?));
+        }
+        Ok(node)
+    }
+
+    fn not_expr(&mut self) -> Result<Node, ExpressionError> {
+        match self.peek().cloned() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Node::Not(Box::new(self.not_expr()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let node = self.expr()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(self.error("right parenthesis"));
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(Node::Ident(name))
+            }
+            _ => Err(self.error("not OR left parenthesis OR identifier")),
+        }
+    }
+}
+
+impl Expression {
+    pub fn compile(input: &str) -> Result<Self, ExpressionError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Ok(Expression { root: Node::Empty });
+        }
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        let root = parser.expr()?;
+        if parser.pos != parser.tokens.len() {
+            return Err(parser.error("end of input"));
+        }
+        Ok(Expression { root })
+    }
+
+    /// Evaluate the expression, resolving identifiers with `matcher`
+    pub fn evaluate(&self, matcher: &dyn Fn(&str) -> bool) -> bool {
+        fn eval(node: &Node, matcher: &dyn Fn(&str) -> bool) -> bool {
+            match node {
+                Node::Empty => false,
+                Node::Ident(name) => matcher(name),
+                Node::Not(inner) => !eval(inner, matcher),
+                Node::And(a, b) => eval(a, matcher) && eval(b, matcher),
+                Node::Or(a, b) => eval(a, matcher) || eval(b, matcher),
+            }
+        }
+        eval(&self.root, matcher)
+    }
+
+    /// `-k` semantics: case-insensitive substring match against any keyword
+    pub fn matches_keywords(&self, keywords: &[String]) -> bool {
+        let lowered: Vec<String> = keywords.iter().map(|k| k.to_lowercase()).collect();
+        self.evaluate(&|ident| {
+            let ident = ident.to_lowercase();
+            lowered.iter().any(|k| k.contains(&ident))
+        })
+    }
+
+    /// `-m` semantics: exact match against mark names
+    pub fn matches_marks(&self, marks: &[String]) -> bool {
+        self.evaluate(&|ident| marks.iter().any(|m| m == ident))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_keyword_matching() {
+        let kw = keywords(&["test_mod.py", "TestClass", "test_method[1-a]", "slow"]);
+        let matches = |expr: &str| Expression::compile(expr).unwrap().matches_keywords(&kw);
+        assert!(matches("method"));
+        assert!(matches("testclass and not fast"));
+        assert!(matches("(other or 1-a) and mod"));
+        assert!(!matches("not slow"));
+        assert!(!matches(""));
+    }
+
+    #[test]
+    fn test_mark_matching() {
+        let marks = keywords(&["slow", "parametrize"]);
+        let matches = |expr: &str| Expression::compile(expr).unwrap().matches_marks(&marks);
+        assert!(matches("slow"));
+        assert!(!matches("slo"));
+        assert!(matches("not serial"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Expression::compile("a and").unwrap_err().column, 6);
+        assert_eq!(Expression::compile("(a").unwrap_err().column, 3);
+        assert!(Expression::compile("a $ b").is_err());
+        assert!(Expression::compile("True").is_err());
+    }
+}
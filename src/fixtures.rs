@@ -0,0 +1,823 @@
+//! Static index of `@pytest.fixture` definitions in test modules and conftest files.
+
+use crate::literal::Literal;
+use crate::parser::{
+    evaluate, parse_args, split_call, Arg, ErrorKind, Parameters, ParseError, TestItem, TestKind,
+};
+use crate::walk;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum FixtureScope {
+    Function,
+    Class,
+    Module,
+    Package,
+    Session,
+}
+
+impl FixtureScope {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "function" => Some(FixtureScope::Function),
+            "class" => Some(FixtureScope::Class),
+            "module" => Some(FixtureScope::Module),
+            "package" => Some(FixtureScope::Package),
+            "session" => Some(FixtureScope::Session),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FixtureScope::Function => "function",
+            FixtureScope::Class => "class",
+            FixtureScope::Module => "module",
+            FixtureScope::Package => "package",
+            FixtureScope::Session => "session",
+        }
+    }
+}
+
+/// Fixtures pytest provides itself, with their scope and the fixtures they request
+///
+/// `request` is not a fixture definition but is resolvable like one.
+const BUILTINS: &[(&str, FixtureScope, &[&str])] = &[
+    ("cache", FixtureScope::Function, &["request"]),
+    ("capfd", FixtureScope::Function, &["request"]),
+    ("capfdbinary", FixtureScope::Function, &["request"]),
+    ("caplog", FixtureScope::Function, &["request"]),
+    ("capsys", FixtureScope::Function, &["request"]),
+    ("capsysbinary", FixtureScope::Function, &["request"]),
+    ("capteesys", FixtureScope::Function, &["request"]),
+    ("doctest_namespace", FixtureScope::Session, &[]),
+    ("monkeypatch", FixtureScope::Function, &[]),
+    ("pytestconfig", FixtureScope::Session, &["request"]),
+    ("record_property", FixtureScope::Function, &["request"]),
+    (
+        "record_testsuite_property",
+        FixtureScope::Session,
+        &["request"],
+    ),
+    ("record_xml_attribute", FixtureScope::Function, &["request"]),
+    ("recwarn", FixtureScope::Function, &[]),
+    ("request", FixtureScope::Function, &[]),
+    (
+        "tmp_path",
+        FixtureScope::Function,
+        &["request", "tmp_path_factory"],
+    ),
+    ("tmp_path_factory", FixtureScope::Session, &["request"]),
+    ("tmpdir", FixtureScope::Function, &["tmp_path"]),
+    ("tmpdir_factory", FixtureScope::Session, &["request"]),
+];
+
+/// Scope and requested fixtures of a builtin fixture
+pub fn builtin(name: &str) -> Option<(FixtureScope, &'static [&'static str])> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _, _)| *builtin == name)
+        .map(|&(_, scope, argnames)| (scope, argnames))
+}
+
+/// The `params=` of a fixture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FixtureParams {
+    Values(Vec<Literal>),
+    /// Source text of params that could not be evaluated statically, or
+    /// `ids=` and its source if their ids could not be
+    Unresolved(String),
+}
+
+/// The arguments of a `@pytest.fixture(...)` decorator
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixtureDecorator {
+    pub name: Option<String>,
+    /// `None` when the scope is not a literal, e.g. a callable
+    pub scope: Option<FixtureScope>,
+    pub autouse: bool,
+    pub params: Option<FixtureParams>,
+    /// Explicit ids, if given as a literal list
+    pub ids: Option<Vec<Option<String>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The name tests request it by: `name=` if given, else the function name
+    pub name: String,
+    pub function: String,
+    pub path: String,
+    /// Line of the first decorator, like pytest reports
+    pub line_number: usize,
+    /// Names of the enclosing classes, outermost first
+    pub classes: Vec<String>,
+    /// Fixtures it requests itself, without `self`
+    pub argnames: Vec<String>,
+    pub scope: Option<FixtureScope>,
+    pub autouse: bool,
+    pub params: Option<FixtureParams>,
+    pub ids: Option<Vec<Option<String>>>,
+}
+
+/// Recognise `@pytest.fixture` or `@pytest.fixture(...)`, with or without the `pytest.` prefix
+pub fn parse_decorator(expr: &str) -> Option<FixtureDecorator> {
+    let expr = expr.trim().trim_start_matches('@').trim();
+    let (path, args) = match split_call(expr) {
+        Some((callee, args)) => (callee, parse_args(args)),
+        None => (expr, Vec::new()),
+    };
+    if path != "pytest.fixture" && path != "fixture" {
+        return None;
+    }
+
+    let mut ids_source = None;
+    let mut decorator = FixtureDecorator {
+        name: None,
+        scope: Some(FixtureScope::Function),
+        autouse: false,
+        params: None,
+        ids: None,
+    };
+    for arg in args {
+        let Arg::Keyword(key, value) = arg else {
+            continue;
+        };
+        let literal = evaluate(&value).ok();
+        match key.as_str() {
+            "name" => decorator.name = literal.as_ref().and_then(Literal::as_str).map(String::from),
+            "scope" => {
+                decorator.scope = literal
+                    .as_ref()
+                    .and_then(Literal::as_str)
+                    .and_then(FixtureScope::parse)
+            }
+            "autouse" => decorator.autouse = literal == Some(Literal::Bool(true)),
+            "params" => {
+                decorator.params = Some(match literal.as_ref().and_then(Literal::as_sequence) {
+                    Some(values) => FixtureParams::Values(values.to_vec()),
+                    None => FixtureParams::Unresolved(value.trim().to_string()),
+                })
+            }
+            "ids" if literal == Some(Literal::None) => {}
+            "ids" => {
+                decorator.ids = literal.as_ref().and_then(literal_ids);
+                ids_source = Some(value.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+    // Ids only pytest can compute, such as those of a callable, leave the
+    // generated tests unknown just like params that cannot be evaluated
+    if let (Some(FixtureParams::Values(values)), Some(source)) = (&decorator.params, ids_source) {
+        if decorator.ids.as_ref().map(Vec::len) != Some(values.len()) {
+            decorator.params = Some(FixtureParams::Unresolved(format!("ids={}", source)));
+        }
+    }
+    Some(decorator)
+}
+
+/// Explicit ids stringified the way pytest's `_idval` does, a `None` entry
+/// keeping the generated id; `None` if pytest alone can turn them into ids
+fn literal_ids(ids: &Literal) -> Option<Vec<Option<String>>> {
+    ids.as_sequence()?
+        .iter()
+        .map(|id| match id {
+            Literal::None => Some(None),
+            _ => id.id().map(Some),
+        })
+        .collect()
+}
+
+impl Fixture {
+    /// `file` is rendered relative to `rootdir`, as in node ids
+    pub fn to_json(&self, rootdir: &Path) -> Value {
+        let mut fixture = json!({
+            "name": self.name,
+            "function": self.function,
+            "file": walk::relative_nodeid_path(Path::new(&self.path), rootdir),
+            "line": self.line_number,
+            "classes": self.classes,
+            "scope": self.scope.map(|scope| scope.as_str()),
+            "autouse": self.autouse,
+            "params": match &self.params {
+                Some(FixtureParams::Values(values)) => {
+                    Value::from(values.iter().map(Literal::repr).collect::<Vec<_>>())
+                }
+                _ => Value::Null,
+            },
+            "ids": self.ids,
+        });
+        if let Some(FixtureParams::Unresolved(source)) = &self.params {
+            fixture["params_source"] = Value::from(source.as_str());
+        }
+        fixture
+    }
+}
+
+/// Where a name in a test's fixture closure is provided from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FixtureSource {
+    /// The closest `@pytest.fixture` definition visible from the test
+    Defined { path: String, line_number: usize },
+    /// One of pytest's own fixtures, or `request`
+    Builtin,
+    /// An argument parametrized directly rather than by a fixture
+    Parametrized,
+    /// Not indexed: provided by a plugin, or an error at setup time
+    Unknown,
+}
+
+/// One entry of a test's fixture closure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequiredFixture {
+    pub name: String,
+    /// `None` for unknown fixtures and scopes that are not literals
+    pub scope: Option<FixtureScope>,
+    pub source: FixtureSource,
+}
+
+impl RequiredFixture {
+    pub fn to_json(&self, rootdir: &Path) -> Value {
+        let (kind, file, line) = match &self.source {
+            FixtureSource::Defined { path, line_number } => (
+                "fixture",
+                Some(walk::relative_nodeid_path(Path::new(path), rootdir)),
+                Some(*line_number),
+            ),
+            FixtureSource::Builtin => ("builtin", None, None),
+            FixtureSource::Parametrized => ("parametrized", None, None),
+            FixtureSource::Unknown => ("unknown", None, None),
+        };
+        json!({
+            "name": self.name,
+            "scope": self.scope.map(|scope| scope.as_str()),
+            "kind": kind,
+            "file": file,
+            "line": line,
+        })
+    }
+
+    /// One line of the fixtures-per-test report, e.g. `db [session scope] -- tests/conftest.py:3`
+    pub fn describe(&self, rootdir: &Path) -> String {
+        let mut line = self.name.clone();
+        if let Some(scope) = self.scope.filter(|&scope| scope != FixtureScope::Function) {
+            line.push_str(&format!(" [{} scope]", scope.as_str()));
+        }
+        let location = match &self.source {
+            FixtureSource::Defined { path, line_number } => format!(
+                "{}:{}",
+                walk::relative_nodeid_path(Path::new(path), rootdir),
+                line_number
+            ),
+            FixtureSource::Builtin => "builtin".to_string(),
+            FixtureSource::Parametrized => "parametrized".to_string(),
+            FixtureSource::Unknown => "not found".to_string(),
+        };
+        format!("{} -- {}", line, location)
+    }
+}
+
+/// The fixtures-per-test report lines for one test, sorted by name
+///
+/// Like `pytest --fixtures-per-test`, this leaves out `request`, directly
+/// parametrized arguments and fixtures whose names start with an underscore.
+pub fn describe_closure(fixtures: &[RequiredFixture], rootdir: &Path) -> Vec<String> {
+    let mut shown: Vec<&RequiredFixture> = fixtures
+        .iter()
+        .filter(|f| f.source != FixtureSource::Parametrized)
+        .filter(|f| f.name != "request" && !f.name.starts_with('_'))
+        .collect();
+    shown.sort_by(|a, b| a.name.cmp(&b.name));
+    shown.iter().map(|f| f.describe(rootdir)).collect()
+}
+
+/// Every fixture definition found so far, in discovery order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FixtureIndex {
+    pub fixtures: Vec<Fixture>,
+    /// Files already indexed, including ones without fixtures
+    pub files: Vec<String>,
+    /// Indexed conftest files defining `pytest_generate_tests`
+    pub generate_tests: Vec<String>,
+    /// Positions in `fixtures` by name
+    by_name: HashMap<String, Vec<usize>>,
+}
+
+impl FixtureIndex {
+    /// Add the fixtures defined in the file at `path`
+    pub fn add_file(&mut self, path: &str, fixtures: Vec<Fixture>) {
+        self.files.push(path.to_string());
+        for fixture in fixtures {
+            self.by_name
+                .entry(fixture.name.clone())
+                .or_default()
+                .push(self.fixtures.len());
+            self.fixtures.push(fixture);
+        }
+    }
+
+    pub fn contains_file(&self, path: &str) -> bool {
+        self.files.iter().any(|f| f == path)
+    }
+
+    /// Definitions of `name`, in discovery order
+    pub fn get<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a Fixture> + 'a {
+        self.by_name
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.fixtures[i])
+    }
+
+    /// Definitions in the file at `path`
+    pub fn in_file<'a>(&'a self, path: &'a str) -> impl Iterator<Item = &'a Fixture> + 'a {
+        self.fixtures.iter().filter(move |f| f.path == path)
+    }
+
+    /// Definitions of `name` visible from `path` within `classes`, closest last
+    ///
+    /// This is pytest's override order: conftest files from the rootdir down,
+    /// then the module, then its classes from the outermost in. When a scope
+    /// defines a name twice, only the last definition counts.
+    pub fn visible(&self, name: &str, path: &str, classes: &[String]) -> Vec<&Fixture> {
+        let mut visible: Vec<&Fixture> = Vec::new();
+        for fixture in self.get(name) {
+            let applies = if fixture.path == path {
+                classes.starts_with(&fixture.classes)
+            } else {
+                let conftest = Path::new(&fixture.path);
+                conftest.file_name().is_some_and(|n| n == "conftest.py")
+                    && conftest
+                        .parent()
+                        .is_some_and(|dir| Path::new(path).starts_with(dir))
+            };
+            if !applies {
+                continue;
+            }
+            visible.retain(|f| f.path != fixture.path || f.classes != fixture.classes);
+            visible.push(fixture);
+        }
+        visible.sort_by_key(|f| {
+            if f.path == path {
+                (1, f.classes.len())
+            } else {
+                (0, Path::new(&f.path).components().count())
+            }
+        });
+        visible
+    }
+
+    /// The closest definition's scope, `function` for unknown or dynamic scopes
+    fn scope_of(&self, name: &str, path: &str, classes: &[String]) -> FixtureScope {
+        match self.visible(name, path, classes).last() {
+            Some(fixture) => fixture.scope.unwrap_or(FixtureScope::Function),
+            None => builtin(name).map_or(FixtureScope::Function, |(scope, _)| scope),
+        }
+    }
+
+    /// Names of the autouse fixtures that apply within `classes` of `path`
+    ///
+    /// Like pytest, conftest fixtures come first, then the module's, then each
+    /// class's; names within one scope are sorted, as `dir()` lists them.
+    fn autouse_names(&self, path: &str, classes: &[String]) -> Vec<String> {
+        let mut autouse: Vec<&Fixture> = self
+            .fixtures
+            .iter()
+            .filter(|f| f.autouse)
+            .filter(|f| {
+                self.visible(&f.name, path, classes)
+                    .iter()
+                    .any(|v| std::ptr::eq(*v, *f))
+            })
+            .collect();
+        autouse.sort_by(|a, b| {
+            let rank = |f: &Fixture| {
+                if f.path == path {
+                    (1, f.classes.len())
+                } else {
+                    (0, Path::new(&f.path).components().count())
+                }
+            };
+            rank(a)
+                .cmp(&rank(b))
+                .then_with(|| a.function.cmp(&b.function))
+        });
+        autouse.into_iter().map(|f| f.name.clone()).collect()
+    }
+
+    /// The fixture names a test uses, ordered as pytest's `fixturenames`
+    ///
+    /// Autouse fixtures come first, then `usefixtures` marks, then the test's
+    /// own arguments, then whatever those request in turn, builtin fixtures
+    /// included. The result is sorted by scope, broadest first. Arguments
+    /// parametrized directly are listed but not resolved as fixtures.
+    pub fn closure(&self, item: &TestItem) -> Vec<String> {
+        let direct: Vec<&String> = item
+            .parameters
+            .iter()
+            .flat_map(|p| p.argnames.iter().filter(|name| !p.indirect.contains(name)))
+            .collect();
+        let usefixtures = item
+            .marks
+            .iter()
+            .filter(|mark| mark.name == "usefixtures")
+            .flat_map(|mark| &mark.args)
+            .filter_map(|arg| match arg {
+                Arg::Positional(src) => Literal::parse(src).ok()?.as_str().map(String::from),
+                Arg::Keyword(..) => None,
+            });
+
+        let mut names: Vec<String> = Vec::new();
+        for name in self
+            .autouse_names(&item.path, &item.classes)
+            .into_iter()
+            .chain(usefixtures)
+            .chain(item.argnames.iter().cloned())
+        {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let mut i = 0;
+        while i < names.len() {
+            let name = names[i].clone();
+            i += 1;
+            if direct.contains(&&name) {
+                continue;
+            }
+            // An override requesting its own name uses the definition it overrides too
+            let mut requested: Vec<&str> = Vec::new();
+            let mut chained = true;
+            for fixture in self.visible(&name, &item.path, &item.classes).iter().rev() {
+                requested.extend(fixture.argnames.iter().map(String::as_str));
+                if !fixture.argnames.contains(&name) {
+                    chained = false;
+                    break;
+                }
+            }
+            if chained {
+                if let Some((_, argnames)) = builtin(&name) {
+                    requested.extend(argnames);
+                }
+            }
+            for arg in requested {
+                if !names.iter().any(|name| name == arg) {
+                    names.push(arg.to_string());
+                }
+            }
+        }
+        names.sort_by_key(|name| {
+            if direct.contains(&name) {
+                Reverse(FixtureScope::Function)
+            } else {
+                Reverse(self.scope_of(name, &item.path, &item.classes))
+            }
+        });
+        names
+    }
+
+    /// Where each name in `closure` is provided from, as seen by `item`
+    fn required(&self, item: &TestItem, closure: &[String]) -> Vec<RequiredFixture> {
+        closure
+            .iter()
+            .map(|name| {
+                let direct = item
+                    .parameters
+                    .iter()
+                    .find(|p| p.argnames.contains(name) && !p.indirect.contains(name));
+                let (scope, source) = if let Some(params) = direct {
+                    (
+                        Some(params.scope.unwrap_or(FixtureScope::Function)),
+                        FixtureSource::Parametrized,
+                    )
+                } else if let Some(fixture) = self.visible(name, &item.path, &item.classes).last() {
+                    let source = FixtureSource::Defined {
+                        path: fixture.path.clone(),
+                        line_number: fixture.line_number,
+                    };
+                    (fixture.scope, source)
+                } else if let Some((scope, _)) = builtin(name) {
+                    (Some(scope), FixtureSource::Builtin)
+                } else {
+                    (None, FixtureSource::Unknown)
+                };
+                RequiredFixture {
+                    name: name.clone(),
+                    scope,
+                    source,
+                }
+            })
+            .collect()
+    }
+
+    /// Record the fixture closure of `item` and prepend the parametrizations
+    /// it gets from fixtures with `params`
+    ///
+    /// Fixture params apply in closure order before any parametrize mark, as
+    /// pytest's fixture manager parametrizes first. A fixture whose name is
+    /// parametrized by a mark is overridden by it, and indirect marks take the
+    /// scope of the fixtures they feed. Fails if a fixture's params could not
+    /// be evaluated, since the tests it generates are then unknown.
+    pub fn resolve(&self, item: &mut TestItem) -> Result<(), ParseError> {
+        if item.kind == TestKind::Class {
+            return Ok(());
+        }
+        let closure = self.closure(item);
+        item.fixtures = self.required(item, &closure);
+        let mut parameters = Vec::new();
+        for name in closure {
+            if item.parameters.iter().any(|p| p.argnames.contains(&name)) {
+                continue;
+            }
+            for fixture in self.visible(&name, &item.path, &item.classes).iter().rev() {
+                match &fixture.params {
+                    Some(FixtureParams::Values(values)) => {
+                        parameters.push(Parameters {
+                            argnames: vec![name.clone()],
+                            argvalues: values.iter().map(|value| vec![value.clone()]).collect(),
+                            ids: fixture.ids.clone().unwrap_or_default(),
+                            marks: Vec::new(),
+                            indirect: vec![name.clone()],
+                            scope: Some(fixture.scope.unwrap_or(FixtureScope::Function)),
+                        });
+                        break;
+                    }
+                    Some(FixtureParams::Unresolved(source)) => {
+                        let error = ParseError::with_kind(
+                            ErrorKind::FixtureParams,
+                            format!(
+                                "Fixture {} has params rytest cannot evaluate: {}",
+                                name, source
+                            ),
+                        )
+                        .about(source);
+                        // Only read again to point at the params
+                        return Err(match crate::source::read(Path::new(&fixture.path)) {
+                            Ok(file) => error.locate(
+                                &file,
+                                &fixture.path,
+                                fixture.line_number,
+                                fixture.line_number,
+                            ),
+                            Err(_) => error,
+                        });
+                    }
+                    None if fixture.argnames.contains(&name) => {}
+                    None => break,
+                }
+            }
+        }
+
+        for params in &mut item.parameters {
+            let all_indirect = !params.indirect.is_empty()
+                && params
+                    .argnames
+                    .iter()
+                    .all(|name| params.indirect.contains(name));
+            if params.scope.is_none() && all_indirect {
+                params.scope = params
+                    .argnames
+                    .iter()
+                    .map(|name| self.scope_of(name, &item.path, &item.classes))
+                    .min();
+            }
+        }
+        parameters.append(&mut item.parameters);
+        item.parameters = parameters;
+        Ok(())
+    }
+
+    pub fn to_json(&self, rootdir: &Path) -> Value {
+        Value::from(
+            self.fixtures
+                .iter()
+                .map(|fixture| fixture.to_json(rootdir))
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+
+    #[test]
+    fn test_parse_decorator() {
+        let bare = parse_decorator("@pytest.fixture").unwrap();
+        assert_eq!(bare.scope, Some(FixtureScope::Function));
+        assert!(!bare.autouse && bare.params.is_none() && bare.name.is_none());
+
+        let full = parse_decorator(
+            r#"@fixture(scope="session", autouse=True, name="db", params=[1, "a"], ids=["one", None])"#,
+        )
+        .unwrap();
+        assert_eq!(full.name.as_deref(), Some("db"));
+        assert_eq!(full.scope, Some(FixtureScope::Session));
+        assert!(full.autouse);
+        assert_eq!(
+            full.params,
+            Some(FixtureParams::Values(vec![
+                Literal::Int(1),
+                Literal::Str("a".into())
+            ]))
+        );
+        assert_eq!(full.ids, Some(vec![Some("one".into()), None]));
+
+        let numbered = parse_decorator("@pytest.fixture(params=[1, 2], ids=[10, 2.5])").unwrap();
+        assert_eq!(
+            numbered.ids,
+            Some(vec![Some("10".into()), Some("2.5".into())])
+        );
+
+        for ids in ["idfn", "lambda p: str(p)", "[(1,), 'b']", "['a']"] {
+            let src = format!("@pytest.fixture(params=[1, 2], ids={})", ids);
+            assert_eq!(
+                parse_decorator(&src).unwrap().params,
+                Some(FixtureParams::Unresolved(format!("ids={}", ids))),
+                "{}",
+                ids
+            );
+        }
+
+        let dynamic =
+            parse_decorator("@pytest.fixture(scope=pick_scope, params=[f(i) for i in x])").unwrap();
+        assert_eq!(dynamic.scope, None);
+        assert_eq!(
+            dynamic.params,
+            Some(FixtureParams::Unresolved("[f(i) for i in x]".into()))
+        );
+
+        assert!(parse_decorator("@pytest.mark.fixture").is_none());
+        assert!(parse_decorator("@other.fixture").is_none());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let patterns = |p: &str| vec![p.to_string()];
+        let parse = |source: &str, path: &str| {
+            parse_module(source, path, &patterns("Test"), &patterns("test")).unwrap()
+        };
+        let conftest = parse(
+            r#"
+@pytest.fixture(scope="session", autouse=True)
+def env():
+    pass
+
+@pytest.fixture(params=[1, 2])
+def base():
+    pass
+
+@pytest.fixture(params=[f(i) for i in x])
+def dynamic():
+    pass
+"#,
+            "/p/conftest.py",
+        );
+        let module = parse(
+            r#"
+@pytest.fixture
+def base(base, request):
+    pass
+
+@pytest.fixture(scope="module", params=["x"], ids=["only"])
+def mod():
+    pass
+
+@pytest.mark.usefixtures("mod")
+def test_a(base, tmp_path):
+    pass
+
+@pytest.mark.parametrize("mod", [7, 8], indirect=True)
+def test_b(mod):
+    pass
+
+def test_c(dynamic):
+    pass
+"#,
+            "/p/sub/test_m.py",
+        );
+        let mut index = FixtureIndex::default();
+        index.add_file("/p/conftest.py", conftest.fixtures);
+        index.add_file("/p/sub/test_m.py", module.fixtures);
+        let mut items = module.items;
+
+        assert_eq!(
+            index.closure(&items[0]),
+            vec![
+                "env",
+                "tmp_path_factory",
+                "mod",
+                "base",
+                "tmp_path",
+                "request"
+            ]
+        );
+        assert_eq!(index.visible("base", "/p/sub/test_m.py", &[]).len(), 2);
+        assert!(index.visible("base", "/q/test_m.py", &[]).is_empty());
+
+        let names = |item: &TestItem| -> Vec<String> {
+            item.expand().into_iter().map(|call| call.name).collect()
+        };
+        index.resolve(&mut items[0]).unwrap();
+        assert_eq!(names(&items[0]), vec!["test_a[only-1]", "test_a[only-2]"]);
+        assert_eq!(items[0].parameters[0].scope, Some(FixtureScope::Module));
+        let base = &items[0].fixtures[3];
+        assert_eq!(base.name, "base");
+        assert_eq!(
+            base.source,
+            FixtureSource::Defined {
+                path: "/p/sub/test_m.py".into(),
+                line_number: 2
+            }
+        );
+        assert_eq!(
+            describe_closure(&items[0].fixtures, Path::new("/p")),
+            vec![
+                "base -- sub/test_m.py:2",
+                "env [session scope] -- conftest.py:2",
+                "mod [module scope] -- sub/test_m.py:6",
+                "tmp_path -- builtin",
+                "tmp_path_factory [session scope] -- builtin",
+            ]
+        );
+
+        index.resolve(&mut items[1]).unwrap();
+        assert_eq!(names(&items[1]), vec!["test_b[7]", "test_b[8]"]);
+        assert_eq!(items[1].parameters[0].scope, Some(FixtureScope::Module));
+
+        assert_eq!(
+            items[1].fixtures[1].source,
+            FixtureSource::Defined {
+                path: "/p/sub/test_m.py".into(),
+                line_number: 6
+            }
+        );
+
+        assert!(index.resolve(&mut items[2]).is_err());
+    }
+
+    #[test]
+    fn test_builtin_overrides() {
+        let patterns = |p: &str| vec![p.to_string()];
+        let module = parse_module(
+            r#"
+@pytest.fixture
+def tmp_path(tmp_path):
+    pass
+
+@pytest.fixture
+def monkeypatch():
+    pass
+
+@pytest.mark.parametrize("n", [1], scope="module")
+def test_a(tmp_path, monkeypatch, n, mocker):
+    pass
+"#,
+            "/p/test_m.py",
+            &patterns("Test"),
+            &patterns("test"),
+        )
+        .unwrap();
+        let mut index = FixtureIndex::default();
+        index.add_file("/p/test_m.py", module.fixtures);
+        let mut item = module.items[0].clone();
+        index.resolve(&mut item).unwrap();
+
+        let names: Vec<&str> = item.fixtures.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "tmp_path_factory",
+                "tmp_path",
+                "monkeypatch",
+                "n",
+                "mocker",
+                "request"
+            ]
+        );
+        let sources: Vec<&FixtureSource> = item.fixtures.iter().map(|f| &f.source).collect();
+        let defined = |line_number| FixtureSource::Defined {
+            path: "/p/test_m.py".into(),
+            line_number,
+        };
+        assert_eq!(
+            sources,
+            vec![
+                &FixtureSource::Builtin,
+                &defined(2),
+                &defined(6),
+                &FixtureSource::Parametrized,
+                &FixtureSource::Unknown,
+                &FixtureSource::Builtin,
+            ]
+        );
+        assert_eq!(item.fixtures[0].scope, Some(FixtureScope::Session));
+        assert_eq!(item.fixtures[3].scope, Some(FixtureScope::Module));
+        assert_eq!(item.fixtures[4].scope, None);
+    }
+}
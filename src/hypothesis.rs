@@ -0,0 +1,201 @@
+//! Hypothesis's `@given`, `@example` and `@settings` decorators, which turn a
+//! test into a property-based one.
+//!
+//! `@given` supplies some of the test's arguments itself and hides them from
+//! the signature pytest inspects, so they are not requested as fixtures.
+
+use crate::literal::Literal;
+use crate::parser::{parse_args, split_call, Arg};
+use serde::{Deserialize, Serialize};
+
+/// A Hypothesis decorator on a test function
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decorator {
+    /// `@given(...)`: how many positional strategies, and the keyword argument names
+    Given {
+        positional: usize,
+        keywords: Vec<String>,
+        /// `@given(...)`, inferring a strategy for every required argument
+        infer_all: bool,
+    },
+    /// `@example(...)`, an explicit example run before generated ones
+    Example,
+    /// `@settings(...)`, with `max_examples` if it is a literal
+    Settings { max_examples: Option<u64> },
+}
+
+/// Recognise `@given(...)`, `@example(...)` or `@settings(...)`, with or
+/// without the `hypothesis.` prefix
+pub fn parse_decorator(expr: &str) -> Option<Decorator> {
+    let expr = expr.trim().trim_start_matches('@').trim();
+    let (path, args) = split_call(expr)?;
+    let name = path.strip_prefix("hypothesis.").unwrap_or(path);
+    let args = parse_args(args);
+    match name {
+        "given" => {
+            let positional: Vec<&str> = args
+                .iter()
+                .filter_map(|arg| match arg {
+                    Arg::Positional(value) => Some(value.as_str()),
+                    Arg::Keyword(..) => None,
+                })
+                .collect();
+            Some(Decorator::Given {
+                infer_all: positional == ["..."],
+                positional: positional.len(),
+                keywords: args
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        Arg::Keyword(key, _) => Some(key.clone()),
+                        Arg::Positional(_) => None,
+                    })
+                    .collect(),
+            })
+        }
+        "example" => Some(Decorator::Example),
+        "settings" => Some(Decorator::Settings {
+            max_examples: args.iter().find_map(|arg| match arg {
+                Arg::Keyword(key, value) if key == "max_examples" => {
+                    match Literal::evaluate(value) {
+                        Ok(Literal::Int(n)) => u64::try_from(n).ok(),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }),
+        }),
+        _ => None,
+    }
+}
+
+/// What the Hypothesis decorators of a test say about it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Hypothesis {
+    /// The arguments `@given` provides
+    pub given: Vec<String>,
+    /// How many `@example`s the test has
+    pub examples: usize,
+    /// `max_examples` from `@settings`; `None` leaves it to the active
+    /// settings profile, 100 examples by default
+    pub max_examples: Option<u64>,
+}
+
+impl Hypothesis {
+    /// Combine the Hypothesis decorators of a test
+    ///
+    /// `positional` are the test's positional-or-keyword parameters, which
+    /// positional strategies bind from the right, and `required` the ones
+    /// without a default, which `@given(...)` infers. Returns `None` unless
+    /// the test has a `@given`, as the other decorators do nothing alone.
+    pub fn from_decorators(
+        decorators: &[Decorator],
+        positional: &[String],
+        required: &[String],
+    ) -> Option<Self> {
+        let mut hypothesis = Hypothesis::default();
+        let mut given = false;
+        for decorator in decorators {
+            match decorator {
+                Decorator::Given {
+                    positional: count,
+                    keywords,
+                    infer_all,
+                } => {
+                    given = true;
+                    if *infer_all {
+                        hypothesis.given.extend(required.iter().cloned());
+                    } else {
+                        let start = positional.len().saturating_sub(*count);
+                        hypothesis.given.extend(positional[start..].iter().cloned());
+                    }
+                    hypothesis.given.extend(keywords.iter().cloned());
+                }
+                Decorator::Example => hypothesis.examples += 1,
+                Decorator::Settings { max_examples } => {
+                    hypothesis.max_examples = max_examples.or(hypothesis.max_examples)
+                }
+            }
+        }
+        given.then_some(hypothesis)
+    }
+
+    /// Whether `argname` is provided by `@given` rather than a fixture
+    pub fn provides(&self, argname: &str) -> bool {
+        self.given.iter().any(|given| given == argname)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_decorator() {
+        assert_eq!(
+            parse_decorator("given(st.integers(), st.text())"),
+            Some(Decorator::Given {
+                positional: 2,
+                keywords: Vec::new(),
+                infer_all: false,
+            })
+        );
+        assert_eq!(
+            parse_decorator("hypothesis.given(y=st.text())"),
+            Some(Decorator::Given {
+                positional: 0,
+                keywords: names(&["y"]),
+                infer_all: false,
+            })
+        );
+        assert_eq!(
+            parse_decorator("hypothesis.example(1, y='a').xfail()"),
+            Some(Decorator::Example)
+        );
+        assert_eq!(
+            parse_decorator("settings(max_examples=50, deadline=None)"),
+            Some(Decorator::Settings {
+                max_examples: Some(50)
+            })
+        );
+        assert_eq!(
+            parse_decorator("settings(max_examples=N)"),
+            Some(Decorator::Settings { max_examples: None })
+        );
+        assert_eq!(parse_decorator("given"), None);
+        assert_eq!(parse_decorator("pytest.mark.given(1)"), None);
+    }
+
+    #[test]
+    fn test_from_decorators() {
+        let decorators: Vec<Decorator> = [
+            "example(1, 'a')",
+            "example(2, 'b')",
+            "given(st.integers(), st.text())",
+            "settings(max_examples=10)",
+        ]
+        .iter()
+        .filter_map(|expr| parse_decorator(expr))
+        .collect();
+        let positional = names(&["tmp_path", "x", "s", "flag"]);
+        let hypothesis =
+            Hypothesis::from_decorators(&decorators, &positional, &positional[..3]).unwrap();
+        assert_eq!(hypothesis.given, names(&["s", "flag"]));
+        assert_eq!(hypothesis.examples, 2);
+        assert_eq!(hypothesis.max_examples, Some(10));
+        assert!(hypothesis.provides("flag") && !hypothesis.provides("tmp_path"));
+
+        let infer = [parse_decorator("given(...)").unwrap()];
+        let hypothesis = Hypothesis::from_decorators(&infer, &positional, &positional[..3]);
+        assert_eq!(hypothesis.unwrap().given, names(&["tmp_path", "x", "s"]));
+
+        let settings_only = [parse_decorator("settings(max_examples=5)").unwrap()];
+        assert_eq!(
+            Hypothesis::from_decorators(&settings_only, &positional, &[]),
+            None
+        );
+    }
+}
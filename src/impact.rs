@@ -0,0 +1,257 @@
+//! Selecting the test files a change can affect, from the files `git`
+//! reports changed and the import graph.
+
+use crate::config::IniConfig;
+use crate::imports::ImportGraph;
+use crate::walk;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Run `git` in `dir`, returning its output
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "core.quotepath=off"])
+        .args(args)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git {} failed: {}", args.join(" "), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Run a `git` command given `-z`, returning the paths it lists unquoted
+fn git_paths(dir: &Path, args: &[&str]) -> Result<Vec<String>, String> {
+    Ok(git(dir, args)?
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// The files that differ from `git_ref` in the working tree of the repository
+/// containing `dir`, untracked files included, as absolute paths below the
+/// canonical repository root
+///
+/// Renames are listed under both names.
+pub fn changed_files(dir: &Path, git_ref: &str) -> Result<Vec<PathBuf>, String> {
+    let toplevel = git(dir, &["rev-parse", "--show-toplevel"])?;
+    let toplevel = PathBuf::from(toplevel.trim_end_matches('\n'));
+    let toplevel = toplevel.canonicalize().unwrap_or(toplevel);
+    // Resolved first so that a ref starting with `-` is not taken for an option
+    let commit = git(
+        &toplevel,
+        &["rev-parse", "--verify", "--end-of-options", git_ref],
+    )?;
+    let mut files = git_paths(
+        &toplevel,
+        &[
+            "diff",
+            "--name-only",
+            "-z",
+            "--no-renames",
+            commit.trim(),
+            "--",
+        ],
+    )?;
+    files.extend(git_paths(
+        &toplevel,
+        &["ls-files", "-z", "--others", "--exclude-standard"],
+    )?);
+    Ok(files.iter().map(|file| toplevel.join(file)).collect())
+}
+
+/// Which files a set of changes can affect
+#[derive(Debug, Clone, PartialEq)]
+pub struct Impact {
+    rootdir: PathBuf,
+    /// The changed files and the files importing them, transitively,
+    /// relative to the rootdir
+    affected: BTreeSet<String>,
+    /// Directories whose conftest file is affected, so every test below is
+    conftest_dirs: Vec<PathBuf>,
+    /// Whether the ini file changed, which can affect any test
+    everything: bool,
+}
+
+impl Impact {
+    /// The impact of `changed` files on the modules in `graph`
+    pub fn new(graph: &ImportGraph, changed: &[PathBuf], config: &IniConfig) -> Self {
+        let changed: Vec<String> = changed
+            .iter()
+            .map(|file| walk::relative_nodeid_path(file, &config.rootdir))
+            .collect();
+        let affected = graph.affected(changed.iter().map(String::as_str));
+        let conftest_dirs = affected
+            .iter()
+            .map(|file| config.rootdir.join(file))
+            .filter(|file| file.file_name().is_some_and(|name| name == "conftest.py"))
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+        let everything = config.inifile.as_ref().is_some_and(|inifile| {
+            affected.contains(&walk::relative_nodeid_path(inifile, &config.rootdir))
+        });
+        Impact {
+            rootdir: config.rootdir.clone(),
+            affected,
+            conftest_dirs,
+            everything,
+        }
+    }
+
+    /// The impact of the changes since `git_ref` on the test modules under
+    /// `args`, updating the cached import graph
+    pub fn since(args: &[PathBuf], config: &IniConfig, git_ref: &str) -> Result<Self, String> {
+        // Git resolves symlinks in the repository root, the rootdir may not
+        let rootdir = config
+            .rootdir
+            .canonicalize()
+            .unwrap_or_else(|_| config.rootdir.clone());
+        let changed: Vec<PathBuf> = changed_files(&config.rootdir, git_ref)?
+            .into_iter()
+            .map(|file| match file.strip_prefix(&rootdir) {
+                Ok(relative) => config.rootdir.join(relative),
+                Err(_) => file,
+            })
+            .collect();
+        let graph = ImportGraph::update_cache(args, config);
+        Ok(Self::new(&graph, &changed, config))
+    }
+
+    /// Whether the tests in `path` may be affected: it changed, imports a
+    /// changed file, or is below an affected conftest file
+    pub fn selects(&self, path: &Path) -> bool {
+        self.everything
+            || self
+                .affected
+                .contains(&walk::relative_nodeid_path(path, &self.rootdir))
+            || self.conftest_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn run(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    fn commit_all(dir: &Path) {
+        run(dir, &["init", "-q"]);
+        run(dir, &["add", "-A"]);
+        run(
+            dir,
+            &[
+                "-c",
+                "user.name=rytest",
+                "-c",
+                "user.email=rytest@example.com",
+                "commit",
+                "-qm",
+                "initial",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_changed_since() {
+        let dir = std::env::temp_dir().join("rytest_impact");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests/api")).unwrap();
+        fs::create_dir_all(dir.join("app")).unwrap();
+        let files = [
+            ("pytest.ini", "[pytest]\n"),
+            ("app/__init__.py", ""),
+            ("app/models.py", ""),
+            ("app/views.py", "from app import models\n"),
+            ("app/util.py", ""),
+            ("tests/test_models.py", "import app.models\n"),
+            ("tests/test_util.py", "from app.util import slugify\n"),
+            ("tests/api/conftest.py", "from app.views import index\n"),
+            ("tests/api/test_api.py", ""),
+            ("tests/test_café.py", "import app.util\n"),
+        ];
+        for (file, source) in files {
+            fs::write(dir.join(file), source).unwrap();
+        }
+        commit_all(&dir);
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        let args = [dir.join("tests")];
+        let selected = |impact: &Impact| -> Vec<String> {
+            walk::collect_paths(&args, &config)
+                .iter()
+                .filter(|path| impact.selects(path))
+                .map(|path| walk::relative_nodeid_path(path, &config.rootdir))
+                .collect()
+        };
+
+        let impact = Impact::since(&args, &config, "HEAD").unwrap();
+        assert!(selected(&impact).is_empty());
+
+        // Changing a model affects its test and, through the conftest, the api tests
+        fs::write(dir.join("app/models.py"), "class User: pass\n").unwrap();
+        fs::write(dir.join("tests/test_new.py"), "").unwrap();
+        let impact = Impact::since(&args, &config, "HEAD").unwrap();
+        assert_eq!(
+            selected(&impact),
+            vec![
+                "tests/api/test_api.py",
+                "tests/test_models.py",
+                "tests/test_new.py"
+            ]
+        );
+
+        // Git would quote a non-ASCII path unless told not to
+        fs::write(dir.join("tests/test_café.py"), "import app.util\nx = 1\n").unwrap();
+        let impact = Impact::since(&args, &config, "HEAD").unwrap();
+        assert!(selected(&impact).contains(&"tests/test_café.py".to_string()));
+
+        fs::write(dir.join("pytest.ini"), "[pytest]\naddopts = -q\n").unwrap();
+        let impact = Impact::since(&args, &config, "HEAD").unwrap();
+        assert_eq!(selected(&impact).len(), 5);
+
+        assert!(Impact::since(&args, &config, "no-such-ref").is_err());
+        let output = dir.join("diff.txt");
+        let option = format!("--output={}", output.display());
+        assert!(Impact::since(&args, &config, &option).is_err());
+        assert!(!output.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_rootdir() {
+        let base = std::env::temp_dir().join("rytest_impact_symlink");
+        let _ = fs::remove_dir_all(&base);
+        let dir = base.join("repo");
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("pytest.ini"), "[pytest]\n").unwrap();
+        fs::write(dir.join("tests/test_a.py"), "").unwrap();
+        fs::write(dir.join("tests/test_b.py"), "").unwrap();
+        commit_all(&dir);
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+
+        let config = IniConfig::locate(&[link.join("tests")], &link).unwrap();
+        assert_eq!(config.rootdir, link);
+        fs::write(dir.join("tests/test_a.py"), "x = 1\n").unwrap();
+        let args = [link.join("tests")];
+        let impact = Impact::since(&args, &config, "HEAD").unwrap();
+        assert!(impact.selects(&link.join("tests/test_a.py")));
+        assert!(!impact.selects(&link.join("tests/test_b.py")));
+        fs::remove_dir_all(base).unwrap();
+    }
+}
@@ -0,0 +1,656 @@
+//! Import statements and the module dependency graph they form, for running
+//! only the tests a change can affect.
+//!
+//! The graph covers the test modules, their conftest files and every file
+//! they import, transitively, that is found under the import roots. It is
+//! cached in pytest's cache directory under `rytest/imports`:
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "rootdir": "/abs/path",
+//!   "modules": {
+//!     "tests/test_a.py": {                  // relative to rootdir
+//!       "mtime": 1700000000000000000,       // nanoseconds, with "size" validating the entry
+//!       "size": 1234,
+//!       "dependencies": ["src/pkg/__init__.py", "src/pkg/a.py"],
+//!       "imports": [
+//!         {"level": 0, "module": "pkg.a", "names": ["helper"], "line": 3, "type_checking": false}
+//!       ]
+//!     }
+//!   }
+//! }
+//! ```
+
+use crate::config::IniConfig;
+use crate::parser::{logical_lines, split_top_level};
+use crate::source;
+use crate::walk;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The cache key the graph is stored under
+pub const CACHE_KEY: &str = "rytest/imports";
+
+/// Bumped when the cached layout changes, which discards older caches
+pub const CACHE_VERSION: u64 = 1;
+
+/// An `import` or `from ... import` statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// The leading dots of a relative import; 0 for an absolute one
+    pub level: usize,
+    /// The dotted module name, empty in `from . import x`
+    pub module: String,
+    /// The names a `from` import takes from the module, `*` included; empty for `import`
+    pub names: Vec<String>,
+    /// 1-based line of the statement
+    pub line: usize,
+    /// Whether the statement is under `if TYPE_CHECKING:`, so only type checkers run it
+    pub type_checking: bool,
+}
+
+impl Import {
+    fn to_json(&self) -> Value {
+        json!({
+            "level": self.level,
+            "module": self.module,
+            "names": self.names,
+            "line": self.line,
+            "type_checking": self.type_checking,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(Import {
+            level: value["level"].as_u64()? as usize,
+            module: value["module"].as_str()?.to_string(),
+            names: value["names"]
+                .as_array()?
+                .iter()
+                .map(|name| name.as_str().map(String::from))
+                .collect::<Option<_>>()?,
+            line: value["line"].as_u64()? as usize,
+            type_checking: value["type_checking"].as_bool()?,
+        })
+    }
+}
+
+/// The statements after the colon of `if TYPE_CHECKING:` or `if typing.TYPE_CHECKING:`
+fn type_checking_body(text: &str) -> Option<&str> {
+    let (condition, body) = text.strip_prefix("if ")?.split_once(':')?;
+    let condition = condition.trim();
+    let name = condition.rsplit('.').next().unwrap_or(condition);
+    (name == "TYPE_CHECKING").then(|| body.trim())
+}
+
+/// Parse one simple statement, adding the imports it makes
+fn parse_statement(text: &str, line: usize, type_checking: bool, imports: &mut Vec<Import>) {
+    let unaliased = |name: &str| {
+        name.split(" as ")
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    if let Some(modules) = text.strip_prefix("import ") {
+        imports.extend(
+            modules
+                .split(',')
+                .map(unaliased)
+                .filter(|module| !module.is_empty())
+                .map(|module| Import {
+                    level: 0,
+                    module,
+                    names: Vec::new(),
+                    line,
+                    type_checking,
+                }),
+        );
+    } else if let Some(rest) = text.strip_prefix("from") {
+        if !rest.starts_with([' ', '.']) {
+            return;
+        }
+        let Some((module, names)) = rest.split_once(" import ") else {
+            return;
+        };
+        let module = module.trim();
+        let dotted = module.trim_start_matches('.');
+        let names = names.trim().trim_start_matches('(').trim_end_matches(')');
+        imports.push(Import {
+            level: module.len() - dotted.len(),
+            module: dotted.trim().to_string(),
+            names: names
+                .split(',')
+                .map(unaliased)
+                .filter(|name| !name.is_empty())
+                .collect(),
+            line,
+            type_checking,
+        });
+    }
+}
+
+/// Every import statement in a module's source, wherever it appears
+pub fn extract(source: &str) -> Vec<Import> {
+    let mut imports = Vec::new();
+    // Indents of the enclosing `if TYPE_CHECKING:` statements
+    let mut guards: Vec<usize> = Vec::new();
+    for line in logical_lines(source) {
+        while guards.last().is_some_and(|&indent| line.indent <= indent) {
+            guards.pop();
+        }
+        let mut statements = line.text.as_str();
+        let mut type_checking = !guards.is_empty();
+        if let Some(body) = type_checking_body(statements) {
+            if body.is_empty() {
+                guards.push(line.indent);
+                continue;
+            }
+            statements = body;
+            type_checking = true;
+        }
+        for statement in split_top_level(statements, ';') {
+            parse_statement(
+                statement.trim(),
+                line.line_number,
+                type_checking,
+                &mut imports,
+            );
+        }
+    }
+    imports
+}
+
+/// What a name refers to in a directory on the import path
+enum Found {
+    Module(PathBuf),
+    /// A package directory, without an `__init__.py` for a namespace package
+    Package {
+        dir: PathBuf,
+        init: Option<PathBuf>,
+    },
+}
+
+impl Found {
+    /// A regular package wins over a module, which wins over a namespace package
+    fn find(dir: &Path, name: &str) -> Option<Self> {
+        let package = dir.join(name);
+        let init = package.join("__init__.py");
+        if init.is_file() {
+            return Some(Found::Package {
+                dir: package,
+                init: Some(init),
+            });
+        }
+        let module = dir.join(format!("{}.py", name));
+        if module.is_file() {
+            return Some(Found::Module(module));
+        }
+        package.is_dir().then_some(Found::Package {
+            dir: package,
+            init: None,
+        })
+    }
+}
+
+/// Import the dotted `parts` below `dir`, recording each package's
+/// `__init__.py` and the module itself
+///
+/// Returns the directory of the last package, when every part is one, where
+/// the names of a `from` import may be submodules.
+fn descend(dir: &Path, parts: &[&str], files: &mut Vec<PathBuf>) -> Option<PathBuf> {
+    let mut dir = dir.to_path_buf();
+    for part in parts {
+        match Found::find(&dir, part)? {
+            Found::Module(file) => {
+                files.push(file);
+                return None;
+            }
+            Found::Package { dir: package, init } => {
+                files.extend(init);
+                dir = package;
+            }
+        }
+    }
+    Some(dir)
+}
+
+/// The directory pytest's `prepend` import mode puts on `sys.path` for `file`:
+/// the first enclosing directory that is not a package
+pub fn import_root(file: &Path) -> Option<&Path> {
+    file.ancestors()
+        .skip(1)
+        .find(|dir| !walk::is_package_dir(dir))
+}
+
+/// Finds the files an import runs, searching roots the way `sys.path` is searched
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    /// `pythonpath` directories, then the rootdir
+    roots: Vec<PathBuf>,
+}
+
+impl Resolver {
+    pub fn new(config: &IniConfig) -> Self {
+        let mut roots = config.pythonpath_dirs();
+        roots.push(config.rootdir.clone());
+        Resolver { roots }
+    }
+
+    /// The files importing `import` from `file` runs, as far as they are
+    /// found under the import roots
+    ///
+    /// Absolute imports are looked up in the importing file's own import
+    /// root first, then the `pythonpath` directories and the rootdir. The
+    /// standard library and installed packages are not searched, so their
+    /// modules are left out.
+    pub fn resolve(&self, file: &Path, import: &Import) -> Vec<PathBuf> {
+        let parts: Vec<&str> = import
+            .module
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .collect();
+        let mut files = Vec::new();
+        let package = if import.level > 0 {
+            let Some(base) = file.ancestors().nth(import.level) else {
+                return files;
+            };
+            files.extend(Some(base.join("__init__.py")).filter(|init| init.is_file()));
+            descend(base, &parts, &mut files)
+        } else {
+            let Some(first) = parts.first() else {
+                return files;
+            };
+            let roots: Vec<&Path> = import_root(file)
+                .into_iter()
+                .chain(self.roots.iter().map(PathBuf::as_path))
+                .collect();
+            // A regular module or package anywhere on the path wins over a namespace package
+            let regular = roots.iter().find(|root| {
+                matches!(
+                    Found::find(root, first),
+                    Some(Found::Module(_) | Found::Package { init: Some(_), .. })
+                )
+            });
+            let mut package = None;
+            for root in regular.into_iter().chain(&roots) {
+                let mut found = Vec::new();
+                package = descend(root, &parts, &mut found);
+                if regular.is_some() || !found.is_empty() {
+                    files = found;
+                    break;
+                }
+            }
+            package
+        };
+        if let Some(package) = package {
+            for name in import.names.iter().filter(|name| *name != "*") {
+                descend(&package, &[name.as_str()], &mut files);
+            }
+        }
+        let mut unique = Vec::new();
+        for found in files {
+            if found != file && !unique.contains(&found) {
+                unique.push(found);
+            }
+        }
+        unique
+    }
+}
+
+/// The `__init__.py` files of the packages enclosing `file`, which run
+/// before it, outermost first
+fn package_inits(file: &Path) -> Vec<PathBuf> {
+    let mut inits: Vec<PathBuf> = file
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| walk::is_package_dir(dir))
+        .map(|dir| dir.join("__init__.py"))
+        .filter(|init| init != file)
+        .collect();
+    inits.reverse();
+    inits
+}
+
+/// Modification time in nanoseconds and size, to tell whether a file changed
+pub(crate) fn stamp(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((mtime.as_nanos() as u64, metadata.len()))
+}
+
+/// The imports of one file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleImports {
+    /// Modification time in nanoseconds when the file was parsed
+    pub mtime: u64,
+    /// Size in bytes when the file was parsed
+    pub size: u64,
+    pub imports: Vec<Import>,
+    /// The files importing this one runs, relative to the rootdir: the
+    /// enclosing packages' `__init__.py` files and whatever its imports resolve to
+    pub dependencies: Vec<String>,
+}
+
+/// Which files import which, for the test modules and everything they import
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportGraph {
+    pub rootdir: PathBuf,
+    /// Keyed by path relative to the rootdir with forward slashes, as in node ids
+    pub modules: BTreeMap<String, ModuleImports>,
+}
+
+impl ImportGraph {
+    /// Build the graph of `entries` and every file they import, transitively
+    ///
+    /// Files whose modification time and size match their entry in `cached`
+    /// are not read again. Their imports are resolved anew, since files they
+    /// import may have been added or removed.
+    pub fn build(
+        entries: &[PathBuf],
+        resolver: &Resolver,
+        rootdir: &Path,
+        cached: Option<&ImportGraph>,
+    ) -> Self {
+        let mut modules = BTreeMap::new();
+        let mut queue: VecDeque<PathBuf> = entries.iter().cloned().collect();
+        while let Some(path) = queue.pop_front() {
+            let key = walk::relative_nodeid_path(&path, rootdir);
+            if modules.contains_key(&key) {
+                continue;
+            }
+            let Some((mtime, size)) = stamp(&path) else {
+                continue;
+            };
+            let imports = match cached.and_then(|graph| graph.modules.get(&key)) {
+                Some(module) if module.mtime == mtime && module.size == size => {
+                    module.imports.clone()
+                }
+                _ => match source::read(&path) {
+                    Ok(source) => extract(&source),
+                    Err(_) => continue,
+                },
+            };
+            let mut dependencies = package_inits(&path);
+            for import in &imports {
+                for file in resolver.resolve(&path, import) {
+                    if !dependencies.contains(&file) {
+                        dependencies.push(file);
+                    }
+                }
+            }
+            let relative = dependencies
+                .iter()
+                .map(|file| walk::relative_nodeid_path(file, rootdir))
+                .collect();
+            queue.extend(dependencies);
+            modules.insert(
+                key,
+                ModuleImports {
+                    mtime,
+                    size,
+                    imports,
+                    dependencies: relative,
+                },
+            );
+        }
+        ImportGraph {
+            rootdir: rootdir.to_path_buf(),
+            modules,
+        }
+    }
+
+    /// Build the graph for the test modules under `args` and their conftest
+    /// files, reusing and then updating the cached graph
+    pub fn update_cache(args: &[PathBuf], config: &IniConfig) -> Self {
+        let mut entries = Vec::new();
+        for module in walk::collect_paths(args, config) {
+            for conftest in walk::conftest_paths(&module, &config.rootdir) {
+                if !entries.contains(&conftest) {
+                    entries.push(conftest);
+                }
+            }
+            entries.push(module);
+        }
+        let cached = crate::cache::get(config, CACHE_KEY)
+            .and_then(|value| Self::from_json(&value))
+            .filter(|graph| graph.rootdir == config.rootdir);
+        let graph = Self::build(
+            &entries,
+            &Resolver::new(config),
+            &config.rootdir,
+            cached.as_ref(),
+        );
+        // A cache that cannot be written only costs the next run a re-parse
+        let _ = crate::cache::set(config, CACHE_KEY, &graph.to_json());
+        graph
+    }
+
+    /// Every file that runs one of `changed` when imported, transitively,
+    /// the changed files included
+    pub fn affected<'a>(&self, changed: impl IntoIterator<Item = &'a str>) -> BTreeSet<String> {
+        let mut dependents: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (file, module) in &self.modules {
+            for dependency in &module.dependencies {
+                dependents.entry(dependency).or_default().push(file);
+            }
+        }
+        let mut affected = BTreeSet::new();
+        let mut queue: VecDeque<String> = changed.into_iter().map(String::from).collect();
+        while let Some(file) = queue.pop_front() {
+            if let Some(importers) = dependents.get(file.as_str()) {
+                queue.extend(
+                    importers
+                        .iter()
+                        .filter(|importer| !affected.contains(**importer))
+                        .map(|importer| importer.to_string()),
+                );
+            }
+            affected.insert(file);
+        }
+        affected
+    }
+
+    pub fn to_json(&self) -> Value {
+        let modules: Map<String, Value> = self
+            .modules
+            .iter()
+            .map(|(file, module)| {
+                let value = json!({
+                    "mtime": module.mtime,
+                    "size": module.size,
+                    "dependencies": module.dependencies,
+                    "imports": module.imports.iter().map(Import::to_json).collect::<Vec<_>>(),
+                });
+                (file.clone(), value)
+            })
+            .collect();
+        json!({
+            "version": CACHE_VERSION,
+            "rootdir": self.rootdir,
+            "modules": modules,
+        })
+    }
+
+    /// Read a graph written by `to_json`, or `None` for another version
+    pub fn from_json(value: &Value) -> Option<Self> {
+        if value["version"].as_u64()? != CACHE_VERSION {
+            return None;
+        }
+        let mut modules = BTreeMap::new();
+        for (file, module) in value["modules"].as_object()? {
+            let strings = |value: &Value| -> Option<Vec<String>> {
+                value
+                    .as_array()?
+                    .iter()
+                    .map(|item| item.as_str().map(String::from))
+                    .collect()
+            };
+            modules.insert(
+                file.clone(),
+                ModuleImports {
+                    mtime: module["mtime"].as_u64()?,
+                    size: module["size"].as_u64()?,
+                    imports: module["imports"]
+                        .as_array()?
+                        .iter()
+                        .map(Import::from_json)
+                        .collect::<Option<_>>()?,
+                    dependencies: strings(&module["dependencies"])?,
+                },
+            );
+        }
+        Some(ImportGraph {
+            rootdir: PathBuf::from(value["rootdir"].as_str()?),
+            modules,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let source = r#"
+import os, sys as system
+import pkg.sub.mod as mod; from . import sibling
+from ..parent.mod import (
+    a,
+    b as c,
+)
+from typing import TYPE_CHECKING
+
+if TYPE_CHECKING:
+    from pkg.types import Alias
+else:
+    Alias = None
+
+def lazy():
+    import json
+    if typing.TYPE_CHECKING: import lazy_types
+"#;
+        let imports = extract(source);
+        let imports: Vec<(usize, &str, Vec<&str>, usize, bool)> = imports
+            .iter()
+            .map(|import| {
+                (
+                    import.level,
+                    import.module.as_str(),
+                    import.names.iter().map(String::as_str).collect(),
+                    import.line,
+                    import.type_checking,
+                )
+            })
+            .collect();
+        assert_eq!(
+            imports,
+            vec![
+                (0, "os", vec![], 2, false),
+                (0, "sys", vec![], 2, false),
+                (0, "pkg.sub.mod", vec![], 3, false),
+                (1, "", vec!["sibling"], 3, false),
+                (2, "parent.mod", vec!["a", "b"], 4, false),
+                (0, "typing", vec!["TYPE_CHECKING"], 8, false),
+                (0, "pkg.types", vec!["Alias"], 11, true),
+                (0, "json", vec![], 16, false),
+                (0, "lazy_types", vec![], 17, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_import_graph() {
+        let dir = std::env::temp_dir().join("rytest_imports_graph");
+        let _ = fs::remove_dir_all(&dir);
+        for package in [
+            "src/app",
+            "src/app/models",
+            "tests/unit",
+            "tests/unit/helpers",
+        ] {
+            fs::create_dir_all(dir.join(package)).unwrap();
+        }
+        let files = [
+            ("src/app/__init__.py", ""),
+            ("src/app/models/__init__.py", "from .user import User\n"),
+            ("src/app/models/user.py", "import app.db\n"),
+            ("src/app/db.py", "import sqlite3\n"),
+            ("src/app/cli.py", "import app.models\n"),
+            ("tests/unit/__init__.py", ""),
+            ("tests/unit/helpers/__init__.py", ""),
+            (
+                "tests/unit/helpers/factories.py",
+                "from app import models\n",
+            ),
+            (
+                "tests/unit/test_user.py",
+                "from app.models import User\nfrom .helpers import factories\n",
+            ),
+            ("tests/unit/test_cli.py", "import app.cli\n"),
+        ];
+        for (file, source) in files {
+            fs::write(dir.join(file), source).unwrap();
+        }
+        let mut config = IniConfig::with_defaults(dir.clone());
+        config.pythonpath = vec!["src".to_string()];
+
+        let graph = ImportGraph::update_cache(&[dir.join("tests")], &config);
+        assert_eq!(
+            graph.modules["tests/unit/test_user.py"].dependencies,
+            vec![
+                "tests/unit/__init__.py",
+                "src/app/__init__.py",
+                "src/app/models/__init__.py",
+                "tests/unit/helpers/__init__.py",
+                "tests/unit/helpers/factories.py",
+            ]
+        );
+        assert_eq!(
+            graph.modules["src/app/models/user.py"].dependencies,
+            vec![
+                "src/app/__init__.py",
+                "src/app/models/__init__.py",
+                "src/app/db.py"
+            ]
+        );
+        assert_eq!(
+            graph.affected(["src/app/db.py"]),
+            BTreeSet::from(
+                [
+                    "src/app/db.py",
+                    "src/app/models/user.py",
+                    "src/app/models/__init__.py",
+                    "src/app/cli.py",
+                    "tests/unit/helpers/factories.py",
+                    "tests/unit/test_user.py",
+                    "tests/unit/test_cli.py",
+                ]
+                .map(String::from)
+            )
+        );
+        assert_eq!(
+            graph.affected(["tests/unit/helpers/factories.py"]),
+            BTreeSet::from(
+                ["tests/unit/helpers/factories.py", "tests/unit/test_user.py"].map(String::from)
+            )
+        );
+
+        // The cached graph round-trips and is reused for unchanged files
+        let cached = crate::cache::get(&config, CACHE_KEY).unwrap();
+        assert_eq!(ImportGraph::from_json(&cached), Some(graph.clone()));
+        fs::write(dir.join("src/app/db.py"), "from app import cli\n").unwrap();
+        let graph = ImportGraph::update_cache(&[dir.join("tests")], &config);
+        assert_eq!(
+            graph.modules["src/app/db.py"].dependencies,
+            vec!["src/app/__init__.py", "src/app/cli.py"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+//! Parsed modules kept in memory between collections, parsed again only
+//! when their files change.
+//!
+//! An index can also be saved to a file and loaded by other processes, so
+//! pytest-xdist workers use the modules their controller parsed once. Its
+//! collection hash is a hash of the node ids it collects, in path order, which
+//! each worker compares with the hash of the node ids it collected itself.
+
+use crate::config::IniConfig;
+use crate::fixtures::FixtureIndex;
+use crate::imports;
+use crate::parser::{ParseError, ParsedModule};
+use crate::session;
+use crate::walk;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One parsed file, with the stamp of the source it was parsed from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    stamp: Option<(u64, u64)>,
+    parsed: Result<ParsedModule, ParseError>,
+}
+
+/// The parsed test modules and conftest files of a tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleIndex {
+    python_classes: Vec<String>,
+    python_functions: Vec<String>,
+    modules: HashMap<PathBuf, Entry>,
+}
+
+/// Whether `path` is a file the index parses without being asked for it
+pub fn is_indexed_file(path: &Path, config: &IniConfig) -> bool {
+    walk::is_test_module(path, config) || path.file_name().is_some_and(|n| n == "conftest.py")
+}
+
+/// The 64-bit FNV-1a hash of `bytes`
+///
+/// Collection hashes are compared between processes, so they must not
+/// depend on the build or a random seed like the standard library's hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The collection hash of `nodeids`: the FNV-1a hash of the node ids, one per line
+pub fn hash_nodeids<S: AsRef<str>>(nodeids: impl IntoIterator<Item = S>) -> String {
+    let mut lines = String::new();
+    for nodeid in nodeids {
+        lines.push_str(nodeid.as_ref());
+        lines.push('\n');
+    }
+    format!("{:016x}", fnv1a(lines.as_bytes()))
+}
+
+impl ModuleIndex {
+    pub fn new(config: &IniConfig) -> Self {
+        ModuleIndex {
+            python_classes: config.python_classes.clone(),
+            python_functions: config.python_functions.clone(),
+            modules: HashMap::new(),
+        }
+    }
+
+    /// Parse the test modules and conftest files under `args`
+    pub fn build(args: &[PathBuf], config: &IniConfig) -> Self {
+        let mut index = Self::new(config);
+        for path in walk::collect_files(args, config, |path, _| is_indexed_file(path, config)) {
+            index.parse(&path);
+        }
+        index
+    }
+
+    /// Whether the index parses files with these patterns
+    pub fn has_patterns(&self, python_classes: &[String], python_functions: &[String]) -> bool {
+        self.python_classes == python_classes && self.python_functions == python_functions
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.modules.contains_key(path)
+    }
+
+    fn parse(&mut self, path: &Path) -> &Result<ParsedModule, ParseError> {
+        let entry = Entry {
+            stamp: imports::stamp(path),
+            parsed: session::parse_file(path, &self.python_classes, &self.python_functions),
+        };
+        self.modules.insert(path.to_path_buf(), entry);
+        &self.modules[path].parsed
+    }
+
+    /// The parsed file at `path`, parsing it again if it changed since
+    ///
+    /// Files that cannot be read are not kept.
+    pub fn get(&mut self, path: &Path) -> Result<ParsedModule, ParseError> {
+        let stamp = imports::stamp(path);
+        if stamp.is_none() {
+            self.modules.remove(path);
+            return session::parse_file(path, &self.python_classes, &self.python_functions);
+        }
+        match self.modules.get(path) {
+            Some(entry) if entry.stamp == stamp => entry.parsed.clone(),
+            _ => self.parse(path).clone(),
+        }
+    }
+
+    /// The node ids of the tests in the indexed test modules `selects` keeps,
+    /// in path order
+    ///
+    /// Files changed since they were indexed are parsed again. A module rytest
+    /// cannot collect stands for itself, as pytest collects it.
+    pub fn nodeids(&mut self, config: &IniConfig, selects: impl Fn(&Path) -> bool) -> Vec<String> {
+        let mut paths: Vec<PathBuf> = self
+            .modules
+            .keys()
+            .filter(|path| walk::is_test_module(path, config) && selects(path))
+            .cloned()
+            .collect();
+        paths.sort();
+        let mut fixtures = FixtureIndex::default();
+        let mut nodeids = Vec::new();
+        for path in paths {
+            session::index_conftests_with(&mut fixtures, &path, &config.rootdir, |conftest| {
+                self.get(conftest)
+            });
+            let resolved = self
+                .get(&path)
+                .and_then(|parsed| session::resolve_module(&mut fixtures, &path, parsed));
+            match resolved {
+                Ok(items) => nodeids.extend(
+                    session::module_tests(&path, &items, config)
+                        .into_iter()
+                        .map(|test| test.nodeid),
+                ),
+                Err(_) => nodeids.push(walk::relative_nodeid_path(&path, &config.rootdir)),
+            }
+        }
+        nodeids
+    }
+
+    /// The collection hash of the node ids of the test modules `selects` keeps
+    pub fn collection_hash(
+        &mut self,
+        config: &IniConfig,
+        selects: impl Fn(&Path) -> bool,
+    ) -> String {
+        hash_nodeids(self.nodeids(config, selects))
+    }
+
+    /// Write the index to `path`
+    ///
+    /// The file is written under another name and renamed, so a process
+    /// loading it never sees it half-written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let contents = serde_json::to_vec(self)?;
+        let partial = path.with_extension("partial");
+        fs::write(&partial, &contents)?;
+        fs::rename(&partial, path)
+    }
+
+    /// Read an index saved with [`ModuleIndex::save`]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Bring the index up to date after `path` changed
+    ///
+    /// Indexed files and new test modules or conftest files are parsed again.
+    /// Deleted files, and every file below a deleted directory, are dropped.
+    /// Returns whether the index changed.
+    pub fn update(&mut self, path: &Path, config: &IniConfig) -> bool {
+        if path.is_file() {
+            if !self.contains(path) && !is_indexed_file(path, config) {
+                return false;
+            }
+            if self.modules.get(path).map(|entry| entry.stamp) == Some(imports::stamp(path)) {
+                return false;
+            }
+            self.parse(path);
+            return true;
+        }
+        let before = self.modules.len();
+        self.modules.retain(|file, _| !file.starts_with(path));
+        self.modules.len() != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_module_index() {
+        let dir = std::env::temp_dir().join("rytest_index");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests/unit")).unwrap();
+        fs::write(dir.join("pytest.ini"), "[pytest]\n").unwrap();
+        fs::write(dir.join("tests/conftest.py"), "").unwrap();
+        fs::write(dir.join("tests/unit/test_a.py"), "def test_a(): pass\n").unwrap();
+        fs::write(dir.join("tests/helpers.py"), "def test_helper(): pass\n").unwrap();
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        let test_a = dir.join("tests/unit/test_a.py");
+        let names = |index: &mut ModuleIndex, path: &Path| -> Vec<String> {
+            let parsed = index.get(path).unwrap();
+            parsed.items.into_iter().map(|item| item.name).collect()
+        };
+
+        let mut index = ModuleIndex::build(&[dir.join("tests")], &config);
+        assert_eq!(index.len(), 2);
+        assert_eq!(names(&mut index, &test_a), vec!["test_a"]);
+
+        // Other files are parsed when asked for
+        let helpers = dir.join("tests/helpers.py");
+        assert_eq!(names(&mut index, &helpers), vec!["test_helper"]);
+        assert!(index.contains(&helpers));
+
+        // A stale entry is parsed again on lookup, even without an update
+        fs::write(&test_a, "def test_a(): pass\ndef test_b(): pass\n").unwrap();
+        assert_eq!(names(&mut index, &test_a), vec!["test_a", "test_b"]);
+        assert!(!index.update(&test_a, &config));
+
+        let test_c = dir.join("tests/unit/test_c.py");
+        fs::write(
+            &test_c,
+            "import pytest\n@pytest.mark.parametrize('x')\ndef test_c(x): pass\n",
+        )
+        .unwrap();
+        assert!(index.update(&test_c, &config));
+        assert!(index.get(&test_c).is_err());
+        assert!(index.get(&dir.join("tests/test_missing.py")).is_err());
+        assert_eq!(index.len(), 4);
+        fs::write(dir.join("tests/unit/notes.py"), "").unwrap();
+        assert!(!index.update(&dir.join("tests/unit/notes.py"), &config));
+
+        fs::remove_dir_all(dir.join("tests/unit")).unwrap();
+        assert!(index.update(&dir.join("tests/unit"), &config));
+        assert_eq!(index.len(), 2);
+
+        let saved = dir.join("collection.json");
+        index.save(&saved).unwrap();
+        let mut loaded = ModuleIndex::load(&saved).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.has_patterns(&config.python_classes, &config.python_functions));
+        assert_eq!(names(&mut loaded, &helpers), vec!["test_helper"]);
+        assert!(!dir.join("collection.partial").exists());
+        fs::write(dir.join("collection.json"), "{").unwrap();
+        assert!(ModuleIndex::load(&saved).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_collection_hash() {
+        let dir = std::env::temp_dir().join("rytest_index_hash");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("pytest.ini"), "[pytest]\n").unwrap();
+        fs::write(
+            dir.join("tests/conftest.py"),
+            "import pytest\n@pytest.fixture(params=[1, 2])\ndef n(request): pass\n",
+        )
+        .unwrap();
+        fs::write(dir.join("tests/test_a.py"), "def test_a(n): pass\n").unwrap();
+        fs::write(
+            dir.join("tests/test_b.py"),
+            "import pytest\n@pytest.mark.parametrize('x', f())\ndef test_b(x): pass\n",
+        )
+        .unwrap();
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+
+        let mut index = ModuleIndex::build(&[dir.join("tests")], &config);
+        assert_eq!(
+            index.nodeids(&config, |_| true),
+            vec![
+                "tests/test_a.py::test_a[1]",
+                "tests/test_a.py::test_a[2]",
+                "tests/test_b.py"
+            ]
+        );
+        let hash = index.collection_hash(&config, |_| true);
+        assert_eq!(hash.len(), 16);
+        assert_eq!(
+            hash,
+            hash_nodeids([
+                "tests/test_a.py::test_a[1]",
+                "tests/test_a.py::test_a[2]",
+                "tests/test_b.py"
+            ])
+        );
+        assert_eq!(
+            index.nodeids(&config, |path| path.ends_with("test_b.py")),
+            vec!["tests/test_b.py"]
+        );
+
+        // The hash survives saving, and only depends on the tests collected
+        let saved = dir.join("collection.json");
+        index.save(&saved).unwrap();
+        let mut loaded = ModuleIndex::load(&saved).unwrap();
+        assert_eq!(loaded.collection_hash(&config, |_| true), hash);
+        fs::write(dir.join("tests/test_a.py"), "def test_a(n):\n    pass\n").unwrap();
+        assert_eq!(loaded.collection_hash(&config, |_| true), hash);
+
+        // Files that collect other tests get another hash
+        fs::write(
+            dir.join("tests/test_a.py"),
+            "def test_a(n): pass\ndef test_c(): pass\n",
+        )
+        .unwrap();
+        assert_ne!(loaded.collection_hash(&config, |_| true), hash);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+//! Static fixture diagnostics, for `rytest lint` and `--rytest-lint`.
+
+use crate::fixtures::{self, Fixture, FixtureIndex, FixtureSource, RequiredFixture};
+use crate::manifest::{Manifest, NodeKind};
+use crate::verify;
+use crate::walk;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// No visible fixture, builtin or parametrize argname provides the name;
+    /// pytest fails the test at setup with "fixture 'x' not found"
+    UnknownFixture { name: String, requested_by: String },
+    /// No collected test uses the fixture, directly or through other fixtures
+    UnusedFixture { name: String },
+    /// The fixture hides another of the same name without requesting it;
+    /// `shadowed` is the other definition's location, `None` for a builtin
+    ShadowedFixture {
+        name: String,
+        shadowed: Option<String>,
+    },
+}
+
+impl Problem {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Problem::UnknownFixture { .. } => "unknown-fixture",
+            Problem::UnusedFixture { .. } => "unused-fixture",
+            Problem::ShadowedFixture { .. } => "shadowed-fixture",
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::UnknownFixture { name, requested_by } => {
+                write!(
+                    f,
+                    "fixture '{}' not found, requested by {}",
+                    name, requested_by
+                )
+            }
+            Problem::UnusedFixture { name } => write!(f, "fixture '{}' is never requested", name),
+            Problem::ShadowedFixture {
+                name,
+                shadowed: Some(location),
+            } => write!(f, "fixture '{}' shadows the one at {}", name, location),
+            Problem::ShadowedFixture {
+                name,
+                shadowed: None,
+            } => write!(f, "fixture '{}' shadows pytest's builtin fixture", name),
+        }
+    }
+}
+
+/// A problem at a line of a file relative to the rootdir
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.file,
+            self.line,
+            self.problem.code(),
+            self.problem
+        )
+    }
+}
+
+/// What a fixture definition overrides
+enum Shadowed<'a> {
+    Fixture(&'a Fixture),
+    Builtin,
+}
+
+/// The definition `fixture` replaces, as seen from where it is defined
+///
+/// A redefinition in the same module or class replaces the earlier one
+/// outright; otherwise it overrides the next closest visible definition.
+fn shadowed<'a>(index: &'a FixtureIndex, fixture: &'a Fixture) -> Option<Shadowed<'a>> {
+    let earlier = index
+        .get(&fixture.name)
+        .filter(|f| {
+            f.path == fixture.path
+                && f.classes == fixture.classes
+                && f.line_number < fixture.line_number
+        })
+        .last();
+    if let Some(earlier) = earlier {
+        return Some(Shadowed::Fixture(earlier));
+    }
+    let visible = index.visible(&fixture.name, &fixture.path, &fixture.classes);
+    let position = visible.iter().position(|f| std::ptr::eq(*f, fixture))?;
+    match position.checked_sub(1) {
+        Some(parent) => Some(Shadowed::Fixture(visible[parent])),
+        None => fixtures::builtin(&fixture.name).map(|_| Shadowed::Builtin),
+    }
+}
+
+/// The definition a test's closure resolved `required` to
+fn definition<'a>(index: &'a FixtureIndex, required: &RequiredFixture) -> Option<&'a Fixture> {
+    let FixtureSource::Defined { path, line_number } = &required.source else {
+        return None;
+    };
+    index
+        .get(&required.name)
+        .find(|fixture| fixture.path == *path && fixture.line_number == *line_number)
+}
+
+/// Mark `fixture` used, and the definitions it extends by requesting its own name
+fn mark_used<'a>(
+    index: &'a FixtureIndex,
+    fixture: &'a Fixture,
+    used: &mut HashSet<(&'a str, usize)>,
+) {
+    let mut current = Some(fixture);
+    while let Some(fixture) = current {
+        if !used.insert((&fixture.path, fixture.line_number)) {
+            break;
+        }
+        current = match shadowed(index, fixture) {
+            Some(Shadowed::Fixture(parent)) if fixture.argnames.contains(&fixture.name) => {
+                Some(parent)
+            }
+            _ => None,
+        };
+    }
+}
+
+/// Check the fixtures of the tests rytest collected into `manifest`
+///
+/// Names in `known` are provided elsewhere, e.g. by plugins, and are never
+/// reported unknown. `requested` are the fixture names of tests rytest did not
+/// collect itself: every definition of them counts as used. Only what the
+/// source shows is considered, so fixtures requested dynamically through
+/// `request.getfixturevalue` are reported unused.
+pub fn lint(manifest: &Manifest, known: &HashSet<String>, requested: &[String]) -> Vec<Diagnostic> {
+    let index = &manifest.fixtures;
+    let relative = |path: &str| walk::relative_nodeid_path(Path::new(path), &manifest.rootdir);
+    let mut diagnostics = Vec::new();
+    let mut used: HashSet<(&str, usize)> = HashSet::new();
+    let mut unknown: HashSet<(String, usize, &str)> = HashSet::new();
+
+    let tests = manifest
+        .nodes
+        .iter()
+        .filter(|node| node.kind == NodeKind::Function && !node.fallback);
+    for node in tests {
+        let defined: Vec<&Fixture> = node
+            .fixtures
+            .iter()
+            .filter_map(|required| definition(index, required))
+            .collect();
+        for fixture in &defined {
+            mark_used(index, fixture, &mut used);
+        }
+
+        for required in &node.fixtures {
+            if required.source != FixtureSource::Unknown || known.contains(&required.name) {
+                continue;
+            }
+            let requester = defined
+                .iter()
+                .find(|fixture| fixture.argnames.contains(&required.name));
+            let (file, line, requested_by) = match requester {
+                Some(fixture) => (
+                    relative(&fixture.path),
+                    fixture.line_number,
+                    format!("fixture '{}'", fixture.name),
+                ),
+                None => {
+                    let base = verify::base_nodeid(&node.nodeid);
+                    let name = base
+                        .strip_prefix(&node.file)
+                        .and_then(|rest| rest.strip_prefix("::"))
+                        .unwrap_or(base);
+                    (node.file.clone(), node.line.unwrap_or(0), name.to_string())
+                }
+            };
+            if unknown.insert((file.clone(), line, &required.name)) {
+                diagnostics.push(Diagnostic {
+                    file,
+                    line,
+                    problem: Problem::UnknownFixture {
+                        name: required.name.clone(),
+                        requested_by,
+                    },
+                });
+            }
+        }
+    }
+
+    for fixture in &index.fixtures {
+        let at = |problem| Diagnostic {
+            file: relative(&fixture.path),
+            line: fixture.line_number,
+            problem,
+        };
+        if !used.contains(&(fixture.path.as_str(), fixture.line_number))
+            && !requested.contains(&fixture.name)
+        {
+            diagnostics.push(at(Problem::UnusedFixture {
+                name: fixture.name.clone(),
+            }));
+        }
+        // Requesting its own name extends the overridden fixture rather than hiding it
+        if fixture.argnames.contains(&fixture.name) {
+            continue;
+        }
+        let shadowed = match shadowed(index, fixture) {
+            Some(Shadowed::Fixture(other)) => {
+                Some(format!("{}:{}", relative(&other.path), other.line_number))
+            }
+            Some(Shadowed::Builtin) => None,
+            None => continue,
+        };
+        diagnostics.push(at(Problem::ShadowedFixture {
+            name: fixture.name.clone(),
+            shadowed,
+        }));
+    }
+
+    diagnostics.sort_by(|a, b| a.file.cmp(&b.file).then(a.line.cmp(&b.line)));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_module;
+    use crate::session;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_lint() {
+        let patterns = |p: &str| vec![p.to_string()];
+        let parse = |source: &str, path: &str| {
+            parse_module(source, path, &patterns("Test"), &patterns("test")).unwrap()
+        };
+        let conftest = parse(
+            r#"
+@pytest.fixture
+def db(engine):
+    pass
+
+@pytest.fixture
+def user():
+    pass
+
+@pytest.fixture
+def stale():
+    pass
+
+@pytest.fixture
+def from_plugin_user():
+    pass
+"#,
+            "/r/conftest.py",
+        );
+        let module = parse(
+            r#"
+@pytest.fixture
+def user(user):
+    pass
+
+@pytest.fixture
+def db():
+    pass
+
+@pytest.fixture
+def tmp_path():
+    pass
+
+@pytest.fixture
+def helper():
+    pass
+
+@pytest.fixture
+def helper():
+    pass
+
+def test_a(db, user, tmp_path, mocker, missing):
+    pass
+
+@pytest.mark.parametrize("x", [1, 2])
+def test_b(x, missing):
+    pass
+"#,
+            "/r/tests/test_m.py",
+        );
+
+        let mut manifest = Manifest::new(PathBuf::from("/r"));
+        manifest
+            .fixtures
+            .add_file("/r/conftest.py", conftest.fixtures);
+        let items = session::resolve_module(
+            &mut manifest.fixtures,
+            Path::new("/r/tests/test_m.py"),
+            module,
+        )
+        .unwrap();
+        manifest.add_module(Path::new("/r/tests/test_m.py"), &items);
+
+        let known: HashSet<String> = ["mocker".to_string()].into();
+        let lines: Vec<String> = lint(&manifest, &known, &["from_plugin_user".to_string()])
+            .iter()
+            .map(Diagnostic::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                "conftest.py:2: unused-fixture: fixture 'db' is never requested",
+                "conftest.py:10: unused-fixture: fixture 'stale' is never requested",
+                "tests/test_m.py:6: shadowed-fixture: fixture 'db' shadows the one at conftest.py:2",
+                "tests/test_m.py:10: shadowed-fixture: fixture 'tmp_path' shadows pytest's builtin fixture",
+                "tests/test_m.py:14: unused-fixture: fixture 'helper' is never requested",
+                "tests/test_m.py:18: unused-fixture: fixture 'helper' is never requested",
+                "tests/test_m.py:18: shadowed-fixture: fixture 'helper' shadows the one at tests/test_m.py:14",
+                "tests/test_m.py:22: unknown-fixture: fixture 'missing' not found, requested by test_a",
+                "tests/test_m.py:25: unknown-fixture: fixture 'missing' not found, requested by test_b",
+            ]
+        );
+    }
+}
@@ -0,0 +1,2328 @@
+//! A native model of Python literals, evaluated like `ast.literal_eval`.
+//!
+//! Supports numbers (including complex and unary minus), strings and bytes
+//! with any non-f prefix, implicit string concatenation, tuples, lists,
+//! dicts, sets, `None`, `True`, `False` and `...`.
+//!
+//! [`Literal::evaluate`] goes further for the side-effect free expressions
+//! commonly used to build parameters: comprehensions, f-strings, arithmetic,
+//! comparisons, conditional expressions and a few pure builtins such as
+//! `range()`, `str()`, `enumerate()` and `zip()`.
+
+use crate::parser::string_end;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiteralError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for LiteralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for LiteralError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Literal {
+    None,
+    Ellipsis,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Complex(f64, f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Tuple(Vec<Literal>),
+    List(Vec<Literal>),
+    Dict(Vec<(Literal, Literal)>),
+    Set(Vec<Literal>),
+}
+
+impl Literal {
+    /// Evaluate the source of a literal expression
+    pub fn parse(src: &str) -> Result<Literal, LiteralError> {
+        LiteralParser::new(src.chars().collect(), None).parse_all()
+    }
+
+    /// Evaluate a side-effect free expression that need not be a literal
+    ///
+    /// Anything depending on names other than comprehension variables, or on
+    /// calls other than the supported builtins, is an error.
+    pub fn evaluate(src: &str) -> Result<Literal, LiteralError> {
+        LiteralParser::new(src.chars().collect(), Some(Vec::new())).parse_all()
+    }
+
+    /// Like [`Literal::evaluate`], with `names` bound
+    ///
+    /// Names may be dotted, such as `sys.platform`, and a name ending in `()`
+    /// is the value of calling it without arguments, such as `platform.system()`.
+    pub fn evaluate_with(src: &str, names: &[(String, Literal)]) -> Result<Literal, LiteralError> {
+        LiteralParser::new(src.chars().collect(), Some(names.to_vec())).parse_all()
+    }
+
+    /// Python's `bool(value)`
+    pub fn is_truthy(&self) -> bool {
+        truthy(self)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Literal::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The elements of a tuple or list
+    pub fn as_sequence(&self) -> Option<&[Literal]> {
+        match self {
+            Literal::Tuple(items) | Literal::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The id pytest derives for this value, or `None` if it falls back to `argname + index`
+    pub fn id(&self) -> Option<String> {
+        match self {
+            Literal::Str(s) => Some(ascii_escaped(s)),
+            Literal::Bytes(b) => Some(bytes_escaped(b)),
+            Literal::None | Literal::Bool(_) | Literal::Int(_) | Literal::Float(_) => {
+                Some(self.repr())
+            }
+            Literal::Complex(..) => Some(self.repr()),
+            _ => None,
+        }
+    }
+
+    /// Render the value the way Python's `repr()` does
+    pub fn repr(&self) -> String {
+        fn join(items: &[Literal]) -> String {
+            items
+                .iter()
+                .map(Literal::repr)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match self {
+            Literal::None => "None".to_string(),
+            Literal::Ellipsis => "Ellipsis".to_string(),
+            Literal::Bool(true) => "True".to_string(),
+            Literal::Bool(false) => "False".to_string(),
+            Literal::Int(i) => i.to_string(),
+            Literal::Float(f) => float_repr(*f),
+            Literal::Complex(re, im) => {
+                let component = |v: f64| {
+                    let s = float_repr(v);
+                    s.strip_suffix(".0").map(String::from).unwrap_or(s)
+                };
+                if *re == 0.0 && re.is_sign_positive() {
+                    format!("{}j", component(*im))
+                } else {
+                    let sign = if *im >= 0.0 || im.is_nan() { "+" } else { "" };
+                    format!("({}{}{}j)", component(*re), sign, component(*im))
+                }
+            }
+            Literal::Str(s) => str_repr(s),
+            Literal::Bytes(b) => bytes_repr(b),
+            Literal::Tuple(items) if items.len() == 1 => format!("({},)", items[0].repr()),
+            Literal::Tuple(items) => format!("({})", join(items)),
+            Literal::List(items) => format!("[{}]", join(items)),
+            Literal::Set(items) if items.is_empty() => "set()".to_string(),
+            Literal::Set(items) => format!("{{{}}}", join(items)),
+            Literal::Dict(items) => format!(
+                "{{{}}}",
+                items
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.repr(), v.repr()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl Literal {
+    /// Render the value the way Python's `str()` does
+    pub fn to_str(&self) -> String {
+        match self {
+            Literal::Str(s) => s.clone(),
+            _ => self.repr(),
+        }
+    }
+}
+
+/// Render a Python float the way `repr()` does
+pub fn float_repr(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    // `{:e}` gives the shortest round-tripping digits, e.g. `1.5e-7`
+    let sci = format!("{:e}", value);
+    let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if mantissa.starts_with('-') { "-" } else { "" };
+    let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+
+    if !(-4..16).contains(&exponent) {
+        let mut mantissa = digits[..1].to_string();
+        if digits.len() > 1 {
+            mantissa.push('.');
+            mantissa.push_str(&digits[1..]);
+        }
+        let exp_sign = if exponent < 0 { '-' } else { '+' };
+        return format!("{}{}e{}{:02}", sign, mantissa, exp_sign, exponent.abs());
+    }
+
+    let point = exponent + 1;
+    let body = if point <= 0 {
+        format!("0.{}{}", "0".repeat((-point) as usize), digits)
+    } else if point as usize >= digits.len() {
+        format!("{}{}.0", digits, "0".repeat(point as usize - digits.len()))
+    } else {
+        format!(
+            "{}.{}",
+            &digits[..point as usize],
+            &digits[point as usize..]
+        )
+    };
+    format!("{}{}", sign, body)
+}
+
+fn str_repr(s: &str) -> String {
+    let quote = if s.contains('\'') && !s.contains('"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut out = String::new();
+    out.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_control() => {
+                let code = c as u32;
+                if code <= 0xff {
+                    out.push_str(&format!("\\x{:02x}", code));
+                } else {
+                    out.push_str(&format!("\\u{:04x}", code));
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(quote);
+    out
+}
+
+fn bytes_repr(b: &[u8]) -> String {
+    let quote = if b.contains(&b'\'') && !b.contains(&b'"') {
+        b'"'
+    } else {
+        b'\''
+    };
+    let mut out = String::from("b");
+    out.push(quote as char);
+    for &byte in b {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            byte if byte == quote => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            0x20..=0x7e => out.push(byte as char),
+            byte => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out.push(quote as char);
+    out
+}
+
+/// Escape a string like pytest's `ascii_escaped` (`unicode_escape` encoding)
+fn ascii_escaped(value: &str) -> String {
+    let mut out = String::new();
+    for c in value.chars() {
+        let code = c as u32;
+        match c {
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            ' '..='~' => out.push(c),
+            _ if code <= 0xff => out.push_str(&format!("\\x{:02x}", code)),
+            _ if code <= 0xffff => out.push_str(&format!("\\u{:04x}", code)),
+            _ => out.push_str(&format!("\\U{:08x}", code)),
+        }
+    }
+    out
+}
+
+/// Escape bytes like pytest's `ascii_escaped` (backslash-replaced ASCII)
+fn bytes_escaped(value: &[u8]) -> String {
+    let mut out = String::new();
+    for &byte in value {
+        match byte {
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            0x20..=0x7e => out.push(byte as char),
+            byte => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out
+}
+
+/// Variables bound by enclosing comprehensions, innermost last
+type Env = Vec<(String, Literal)>;
+
+struct LiteralParser {
+    chars: Vec<char>,
+    pos: usize,
+    /// `None` when only literals are allowed, as with `ast.literal_eval`
+    env: Option<Env>,
+}
+
+/// One `for ... in ...` or `if ...` clause of a comprehension, as source spans
+enum Clause {
+    For {
+        targets: Vec<String>,
+        iter: (usize, usize),
+    },
+    If((usize, usize)),
+}
+
+impl LiteralParser {
+    fn new(chars: Vec<char>, env: Option<Env>) -> Self {
+        LiteralParser { chars, pos: 0, env }
+    }
+
+    fn parse_all(&mut self) -> Result<Literal, LiteralError> {
+        let value = self.expression_list()?;
+        self.skip_whitespace();
+        if self.pos < self.chars.len() {
+            return Err(self.error("unexpected trailing input"));
+        }
+        Ok(value)
+    }
+
+    /// Evaluate `chars[start..end]` on its own, with `env` in scope
+    fn evaluate_span(
+        &self,
+        (start, end): (usize, usize),
+        env: &Env,
+    ) -> Result<Literal, LiteralError> {
+        LiteralParser::new(self.chars[start..end].to_vec(), Some(env.clone()))
+            .parse_all()
+            .map_err(|mut e| {
+                e.offset += start;
+                e
+            })
+    }
+
+    fn error(&self, message: &str) -> LiteralError {
+        LiteralError {
+            offset: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() {
+            match self.chars[self.pos] {
+                ' ' | '\t' | '\n' | '\r' | '\x0c' => self.pos += 1,
+                '\\' if self.chars.get(self.pos + 1) == Some(&'\n') => self.pos += 2,
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), LiteralError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {:?}", c)))
+        }
+    }
+
+    fn at_closer(&mut self) -> bool {
+        matches!(self.peek(), None | Some(')' | ']' | '}'))
+    }
+
+    /// `a, b` at the top level is a tuple, as in `ast.literal_eval("1, 2")`
+    fn expression_list(&mut self) -> Result<Literal, LiteralError> {
+        let first = self.expression()?;
+        if self.peek() != Some(',') {
+            return Ok(first);
+        }
+        let mut items = vec![first];
+        while self.eat(',') {
+            if self.at_closer() {
+                break;
+            }
+            items.push(self.expression()?);
+        }
+        Ok(Literal::Tuple(items))
+    }
+
+    fn expression(&mut self) -> Result<Literal, LiteralError> {
+        if self.env.is_none() {
+            return self.complex_sum();
+        }
+        let value = self.disjunction()?;
+        if !self.eat_keyword("if") {
+            return Ok(value);
+        }
+        let condition = self.disjunction()?;
+        if !self.eat_keyword("else") {
+            return Err(self.error("expected 'else'"));
+        }
+        let otherwise = self.expression()?;
+        Ok(if truthy(&condition) { value } else { otherwise })
+    }
+
+    /// Numbers joined by `+`/`-`, which literal_eval allows for complex numbers
+    fn complex_sum(&mut self) -> Result<Literal, LiteralError> {
+        let mut value = self.unary()?;
+        loop {
+            let negate = match self.peek() {
+                Some('+') => false,
+                Some('-') => true,
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = match (value, rhs) {
+                (left, Literal::Complex(0.0, im)) if is_real(&left) => {
+                    Literal::Complex(as_f64(&left), if negate { -im } else { im })
+                }
+                _ => return Err(self.error("only complex numbers may be added or subtracted")),
+            };
+        }
+    }
+
+    /// Whether `word` is next, as a whole word
+    fn at_keyword(&mut self, word: &str) -> bool {
+        self.skip_whitespace();
+        let end = self.pos + word.chars().count();
+        end <= self.chars.len()
+            && self.chars[self.pos..end].iter().copied().eq(word.chars())
+            && !self
+                .chars
+                .get(end)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+    }
+
+    fn eat_keyword(&mut self, word: &str) -> bool {
+        let found = self.at_keyword(word);
+        if found {
+            self.pos += word.chars().count();
+        }
+        found
+    }
+
+    fn disjunction(&mut self) -> Result<Literal, LiteralError> {
+        let mut value = self.conjunction()?;
+        while self.eat_keyword("or") {
+            let rhs = self.conjunction()?;
+            if !truthy(&value) {
+                value = rhs;
+            }
+        }
+        Ok(value)
+    }
+
+    fn conjunction(&mut self) -> Result<Literal, LiteralError> {
+        let mut value = self.inversion()?;
+        while self.eat_keyword("and") {
+            let rhs = self.inversion()?;
+            if truthy(&value) {
+                value = rhs;
+            }
+        }
+        Ok(value)
+    }
+
+    fn inversion(&mut self) -> Result<Literal, LiteralError> {
+        if self.eat_keyword("not") {
+            return Ok(Literal::Bool(!truthy(&self.inversion()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison_operator(&mut self) -> Option<&'static str> {
+        let operator = match self.peek()? {
+            '=' if self.chars.get(self.pos + 1) == Some(&'=') => "==",
+            '!' if self.chars.get(self.pos + 1) == Some(&'=') => "!=",
+            '<' if self.chars.get(self.pos + 1) == Some(&'=') => "<=",
+            '>' if self.chars.get(self.pos + 1) == Some(&'=') => ">=",
+            '<' => "<",
+            '>' => ">",
+            _ => {
+                if self.eat_keyword("in") {
+                    return Some("in");
+                }
+                if self.at_keyword("not") {
+                    let start = self.pos;
+                    self.pos += 3;
+                    if self.eat_keyword("in") {
+                        return Some("not in");
+                    }
+                    self.pos = start;
+                    return None;
+                }
+                if self.eat_keyword("is") {
+                    return Some(if self.eat_keyword("not") {
+                        "is not"
+                    } else {
+                        "is"
+                    });
+                }
+                return None;
+            }
+        };
+        self.pos += operator.len();
+        Some(operator)
+    }
+
+    /// Comparisons chain, so `a < b < c` means `a < b and b < c`
+    fn comparison(&mut self) -> Result<Literal, LiteralError> {
+        let mut left = self.sum()?;
+        let mut result = None;
+        while let Some(operator) = self.comparison_operator() {
+            let right = self.sum()?;
+            let holds = compare(operator, &left, &right).map_err(|e| self.error(&e))?;
+            if result != Some(false) {
+                result = Some(holds);
+            }
+            left = right;
+        }
+        Ok(result.map_or(left, Literal::Bool))
+    }
+
+    fn sum(&mut self) -> Result<Literal, LiteralError> {
+        let mut value = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Some('+') => "+",
+                Some('-') => "-",
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let rhs = self.term()?;
+            value = binary(operator, value, rhs).map_err(|e| self.error(&e))?;
+        }
+    }
+
+    fn term(&mut self) -> Result<Literal, LiteralError> {
+        let mut value = self.unary()?;
+        loop {
+            let operator = match self.peek() {
+                Some('*') if self.chars.get(self.pos + 1) != Some(&'*') => "*",
+                Some('/') if self.chars.get(self.pos + 1) == Some(&'/') => "//",
+                Some('/') => "/",
+                Some('%') => "%",
+                _ => return Ok(value),
+            };
+            self.pos += operator.len();
+            let rhs = self.unary()?;
+            value = binary(operator, value, rhs).map_err(|e| self.error(&e))?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<Literal, LiteralError> {
+        let operand = |parser: &mut Self| -> Result<Literal, LiteralError> {
+            match parser.unary()? {
+                Literal::Bool(b) if parser.env.is_some() => Ok(Literal::Int(b as i128)),
+                value => Ok(value),
+            }
+        };
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                match operand(self)? {
+                    Literal::Int(i) => i
+                        .checked_neg()
+                        .map(Literal::Int)
+                        .ok_or_else(|| self.error("integer overflow")),
+                    Literal::Float(f) => Ok(Literal::Float(-f)),
+                    Literal::Complex(re, im) => Ok(Literal::Complex(-re, -im)),
+                    _ => Err(self.error("bad operand for unary -")),
+                }
+            }
+            Some('+') => {
+                self.pos += 1;
+                match operand(self)? {
+                    value @ (Literal::Int(_) | Literal::Float(_) | Literal::Complex(..)) => {
+                        Ok(value)
+                    }
+                    _ => Err(self.error("bad operand for unary +")),
+                }
+            }
+            _ if self.env.is_some() => self.primary(),
+            _ => self.atom(),
+        }
+    }
+
+    /// An atom followed by any subscripts, slices and method calls
+    fn primary(&mut self) -> Result<Literal, LiteralError> {
+        let mut value = self.atom()?;
+        loop {
+            match self.peek() {
+                Some('[') => {
+                    self.pos += 1;
+                    let start = match self.peek() {
+                        Some(':') => None,
+                        _ => Some(self.expression()?),
+                    };
+                    value = if self.eat(':') {
+                        let stop = match self.peek() {
+                            Some(']') => None,
+                            _ => Some(self.expression()?),
+                        };
+                        slice(&value, start.as_ref(), stop.as_ref())
+                    } else {
+                        subscript(&value, &start.unwrap_or(Literal::None))
+                    }
+                    .map_err(|e| self.error(&e))?;
+                    self.expect(']')?;
+                }
+                Some('.') => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    let name = self.identifier();
+                    if name.is_empty() || self.peek() != Some('(') {
+                        self.pos = start;
+                        return Err(self.error("attributes cannot be evaluated"));
+                    }
+                    self.pos += 1;
+                    let mut args = Vec::new();
+                    while !self.eat(')') {
+                        args.push(self.expression()?);
+                        if !self.eat(',') {
+                            self.expect(')')?;
+                            break;
+                        }
+                    }
+                    value = method(&value, &name, args).map_err(|e| {
+                        let mut error = self.error(&e);
+                        error.offset = start;
+                        error
+                    })?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn atom(&mut self) -> Result<Literal, LiteralError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unexpected end of input"))?;
+        if matches!(c, '(' | '[' | '{') {
+            if let Some(value) = self.comprehension()? {
+                return Ok(value);
+            }
+        }
+        match c {
+            '(' => {
+                self.pos += 1;
+                if self.eat(')') {
+                    return Ok(Literal::Tuple(Vec::new()));
+                }
+                let first = self.expression()?;
+                if self.eat(')') {
+                    return Ok(first);
+                }
+                let items = self.sequence_rest(first, ')')?;
+                Ok(Literal::Tuple(items))
+            }
+            '[' => {
+                self.pos += 1;
+                if self.eat(']') {
+                    return Ok(Literal::List(Vec::new()));
+                }
+                let first = self.expression()?;
+                Ok(Literal::List(self.sequence_rest(first, ']')?))
+            }
+            '{' => self.brace(),
+            '.' if self.chars.get(self.pos + 1) == Some(&'.') => {
+                if self.chars.get(self.pos + 2) == Some(&'.') {
+                    self.pos += 3;
+                    Ok(Literal::Ellipsis)
+                } else {
+                    Err(self.error("invalid syntax"))
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => self.number(),
+            c if c.is_alphabetic() || c == '_' || c == '"' || c == '\'' => {
+                let start = self.pos;
+                let word = self.identifier();
+                if self.peek_raw().is_some_and(|c| c == '"' || c == '\'') && word.len() <= 2 {
+                    self.pos = start;
+                    return self.strings();
+                }
+                match word.as_str() {
+                    "" => self.strings(),
+                    "None" => Ok(Literal::None),
+                    "True" => Ok(Literal::Bool(true)),
+                    "False" => Ok(Literal::Bool(false)),
+                    "Ellipsis" => Ok(Literal::Ellipsis),
+                    "set" if self.env.is_none() && self.eat('(') => {
+                        self.expect(')')?;
+                        Ok(Literal::Set(Vec::new()))
+                    }
+                    _ if self.env.is_some() => self.name(start, word),
+                    _ => {
+                        self.pos = start;
+                        Err(self.error(&format!("malformed node or string: {}", word)))
+                    }
+                }
+            }
+            _ => Err(self.error("invalid syntax")),
+        }
+    }
+
+    /// A bound name or comprehension variable, or a call to a supported builtin
+    fn name(&mut self, start: usize, word: String) -> Result<Literal, LiteralError> {
+        let word = self.dotted_name(word);
+        if self.peek() == Some('(') {
+            let call = format!("{}()", word);
+            let env = self.env.as_deref().unwrap_or_default();
+            let bound = env.iter().rev().find(|(name, _)| *name == call);
+            if let Some(value) = bound.map(|(_, value)| value.clone()) {
+                self.pos += 1;
+                self.expect(')')?;
+                return Ok(value);
+            }
+        }
+        if self.peek() != Some('(') {
+            let env = self.env.as_deref().unwrap_or_default();
+            return match env.iter().rev().find(|(name, _)| *name == word) {
+                Some((_, value)) => Ok(value.clone()),
+                None => {
+                    self.pos = start;
+                    Err(self.error(&format!("name {} is not defined", word)))
+                }
+            };
+        }
+
+        let args = match self.comprehension()? {
+            // A generator expression as the sole argument
+            Some(values) => vec![values],
+            None => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                while !self.eat(')') {
+                    if self.peek_word_followed_by('=') {
+                        return Err(self.error("keyword arguments cannot be evaluated"));
+                    }
+                    args.push(self.expression()?);
+                    if !self.eat(',') {
+                        self.expect(')')?;
+                        break;
+                    }
+                }
+                args
+            }
+        };
+        call(&word, args).map_err(|e| {
+            let mut error = self.error(&e);
+            error.offset = start;
+            error
+        })
+    }
+
+    /// Extend `word` with the attributes after it that are part of a bound
+    /// dotted name, leaving method calls such as `.startswith(...)` alone
+    fn dotted_name(&mut self, mut word: String) -> String {
+        while self.peek_raw() == Some('.') {
+            let start = self.pos;
+            self.pos += 1;
+            let dotted = format!("{}.{}", word, self.identifier());
+            let env = self.env.as_deref().unwrap_or_default();
+            let bound = env.iter().any(|(name, _)| {
+                name.strip_prefix(dotted.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '(']))
+            });
+            if !bound {
+                self.pos = start;
+                break;
+            }
+            word = dotted;
+        }
+        word
+    }
+
+    /// Whether the cursor is at `name =` (but not `name ==`)
+    fn peek_word_followed_by(&mut self, c: char) -> bool {
+        let start = self.pos;
+        self.skip_whitespace();
+        let word = self.identifier();
+        let found =
+            !word.is_empty() && self.peek() == Some(c) && self.chars.get(self.pos + 1) != Some(&c);
+        self.pos = start;
+        found
+    }
+
+    /// Index of the bracket closing the one at `open`
+    fn matching_close(&self, open: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        let mut i = open;
+        while i < self.chars.len() {
+            match self.chars[i] {
+                '"' | '\'' => {
+                    i = string_end(&self.chars, i);
+                    continue;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// Words and punctuation between `start` and `end` that are not nested in brackets
+    fn top_level_tokens(&self, start: usize, end: usize) -> Vec<(usize, String)> {
+        let mut tokens = Vec::new();
+        let mut depth = 0usize;
+        let mut i = start;
+        while i < end {
+            let c = self.chars[i];
+            match c {
+                '"' | '\'' => {
+                    i = string_end(&self.chars, i);
+                    continue;
+                }
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                c if c.is_alphanumeric() || c == '_' => {
+                    let word_start = i;
+                    while i < end && (self.chars[i].is_alphanumeric() || self.chars[i] == '_') {
+                        i += 1;
+                    }
+                    if depth == 0 {
+                        tokens.push((word_start, self.chars[word_start..i].iter().collect()));
+                    }
+                    continue;
+                }
+                c if depth == 0 && !c.is_whitespace() => tokens.push((i, c.to_string())),
+                _ => {}
+            }
+            i += 1;
+        }
+        tokens
+    }
+
+    /// Evaluate a list, set, dict or generator comprehension at the cursor
+    ///
+    /// Returns `None`, leaving the cursor alone, if the brackets at the cursor do not hold
+    /// one. Generators evaluate to lists.
+    fn comprehension(&mut self) -> Result<Option<Literal>, LiteralError> {
+        let Some(env) = self.env.clone() else {
+            return Ok(None);
+        };
+        self.skip_whitespace();
+        let open = self.pos;
+        let Some(close) = self.matching_close(open) else {
+            return Ok(None);
+        };
+        let tokens = self.top_level_tokens(open + 1, close);
+        let Some(first_for) = tokens.iter().position(|(_, t)| t == "for") else {
+            return Ok(None);
+        };
+
+        let mut clauses = Vec::new();
+        let mut i = first_for;
+        while i < tokens.len() {
+            let keyword_end = tokens[i].0 + tokens[i].1.len();
+            let next = tokens[i + 1..]
+                .iter()
+                .position(|(_, t)| t == "for" || t == "if")
+                .map_or(tokens.len(), |offset| i + 1 + offset);
+            let span_end = tokens.get(next).map_or(close, |(pos, _)| *pos);
+            if tokens[i].1 == "if" {
+                clauses.push(Clause::If((keyword_end, span_end)));
+            } else {
+                let in_pos = tokens[i + 1..next]
+                    .iter()
+                    .find(|(_, t)| t == "in")
+                    .map(|(pos, _)| *pos)
+                    .ok_or_else(|| self.error("expected 'in' in comprehension"))?;
+                let target: String = self.chars[keyword_end..in_pos].iter().collect();
+                let targets = target
+                    .trim()
+                    .trim_start_matches('(')
+                    .trim_end_matches(')')
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                if targets.is_empty()
+                    || !targets
+                        .iter()
+                        .all(|name| name.chars().all(|c| c.is_alphanumeric() || c == '_'))
+                {
+                    return Err(self.error("unsupported comprehension target"));
+                }
+                clauses.push(Clause::For {
+                    targets,
+                    iter: (in_pos + 2, span_end),
+                });
+            }
+            i = next;
+        }
+
+        let element_end = tokens[first_for].0;
+        let colon = tokens[..first_for]
+            .iter()
+            .find(|(_, t)| t == ":")
+            .map(|(pos, _)| *pos);
+        let kind = self.chars[open];
+        let elements = match colon {
+            Some(colon) if kind == '{' => vec![(open + 1, colon), (colon + 1, element_end)],
+            _ => vec![(open + 1, element_end)],
+        };
+        let mut values = Vec::new();
+        self.comprehend(&clauses, &mut env.clone(), &elements, &mut values)?;
+        self.pos = close + 1;
+
+        Ok(Some(match (kind, elements.len()) {
+            ('{', 2) => Literal::Dict(unique_keys(
+                values
+                    .into_iter()
+                    .filter_map(|pair| match pair {
+                        Literal::Tuple(mut kv) if kv.len() == 2 => {
+                            let value = kv.pop()?;
+                            Some((kv.pop()?, value))
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            )),
+            ('{', _) => Literal::Set(unique(values)),
+            _ => Literal::List(values),
+        }))
+    }
+
+    fn comprehend(
+        &self,
+        clauses: &[Clause],
+        env: &mut Env,
+        elements: &[(usize, usize)],
+        out: &mut Vec<Literal>,
+    ) -> Result<(), LiteralError> {
+        let Some((clause, rest)) = clauses.split_first() else {
+            let mut values = elements
+                .iter()
+                .map(|span| self.evaluate_span(*span, env))
+                .collect::<Result<Vec<_>, _>>()?;
+            out.push(if values.len() == 1 {
+                values.remove(0)
+            } else {
+                Literal::Tuple(values)
+            });
+            return Ok(());
+        };
+        match clause {
+            Clause::If(condition) => {
+                if truthy(&self.evaluate_span(*condition, env)?) {
+                    self.comprehend(rest, env, elements, out)?;
+                }
+            }
+            Clause::For { targets, iter } => {
+                let iterable = self.evaluate_span(*iter, env)?;
+                let items = iterate(&iterable).map_err(|e| self.error(&e))?;
+                for item in items {
+                    let depth = env.len();
+                    if targets.len() == 1 {
+                        env.push((targets[0].clone(), item));
+                    } else {
+                        let values = iterate(&item).map_err(|e| self.error(&e))?;
+                        if values.len() != targets.len() {
+                            return Err(self.error(&format!(
+                                "expected {} values to unpack, got {}",
+                                targets.len(),
+                                values.len()
+                            )));
+                        }
+                        env.extend(targets.iter().cloned().zip(values));
+                    }
+                    self.comprehend(rest, env, elements, out)?;
+                    env.truncate(depth);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn peek_raw(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self
+            .peek_raw()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_')
+        {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn sequence_rest(&mut self, first: Literal, close: char) -> Result<Vec<Literal>, LiteralError> {
+        let mut items = vec![first];
+        loop {
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(',')?;
+            if self.eat(close) {
+                return Ok(items);
+            }
+            items.push(self.expression()?);
+        }
+    }
+
+    fn brace(&mut self) -> Result<Literal, LiteralError> {
+        self.pos += 1;
+        if self.eat('}') {
+            return Ok(Literal::Dict(Vec::new()));
+        }
+        let first = self.expression()?;
+        if self.eat(':') {
+            let mut items = vec![(first, self.expression()?)];
+            loop {
+                if self.eat('}') {
+                    return Ok(Literal::Dict(unique_keys(items)));
+                }
+                self.expect(',')?;
+                if self.eat('}') {
+                    return Ok(Literal::Dict(unique_keys(items)));
+                }
+                let key = self.expression()?;
+                self.expect(':')?;
+                items.push((key, self.expression()?));
+            }
+        }
+        Ok(Literal::Set(unique(self.sequence_rest(first, '}')?)))
+    }
+
+    fn number(&mut self) -> Result<Literal, LiteralError> {
+        let start = self.pos;
+        let radix = match (self.peek_raw(), self.chars.get(self.pos + 1)) {
+            (Some('0'), Some('x' | 'X')) => 16,
+            (Some('0'), Some('o' | 'O')) => 8,
+            (Some('0'), Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.pos += 2;
+            let digits_start = self.pos;
+            while self
+                .peek_raw()
+                .is_some_and(|c| c.is_digit(radix) || c == '_')
+            {
+                self.pos += 1;
+            }
+            let digits: String = self.chars[digits_start..self.pos]
+                .iter()
+                .filter(|&&c| c != '_')
+                .collect();
+            return i128::from_str_radix(&digits, radix)
+                .map(Literal::Int)
+                .map_err(|_| self.error("invalid number"));
+        }
+
+        let mut is_float = false;
+        while let Some(c) = self.peek_raw() {
+            match c {
+                '0'..='9' | '_' => {}
+                '.' => is_float = true,
+                'e' | 'E' => {
+                    is_float = true;
+                    if matches!(self.chars.get(self.pos + 1), Some('+' | '-')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos]
+            .iter()
+            .filter(|&&c| c != '_')
+            .collect();
+
+        if matches!(self.peek_raw(), Some('j' | 'J')) {
+            self.pos += 1;
+            let im: f64 = text.parse().map_err(|_| self.error("invalid number"))?;
+            return Ok(Literal::Complex(0.0, im));
+        }
+        if is_float {
+            return text
+                .parse()
+                .map(Literal::Float)
+                .map_err(|_| self.error("invalid number"));
+        }
+        text.parse()
+            .map(Literal::Int)
+            .map_err(|_| self.error("integer literal too large"))
+    }
+
+    /// One or more adjacent string literals, concatenated
+    fn strings(&mut self) -> Result<Literal, LiteralError> {
+        let mut text: Option<String> = None;
+        let mut bytes: Option<Vec<u8>> = None;
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let prefix = self.identifier().to_ascii_lowercase();
+            if !matches!(self.peek_raw(), Some('"' | '\'')) {
+                self.pos = start;
+                break;
+            }
+            if prefix.contains('f') && self.env.is_none() {
+                return Err(self.error("f-strings are not literals"));
+            }
+            if !prefix.chars().all(|c| matches!(c, 'r' | 'u' | 'b' | 'f'))
+                || (prefix.contains('f') && (prefix.contains('b') || prefix.contains('u')))
+            {
+                self.pos = start;
+                return Err(self.error("invalid string prefix"));
+            }
+            let raw = prefix.contains('r');
+            let body = self.string_body()?;
+            if prefix.contains('f') {
+                if bytes.is_some() {
+                    return Err(self.error("cannot mix bytes and nonbytes literals"));
+                }
+                let formatted = self.format_fstring(&body, raw)?;
+                text.get_or_insert_with(String::new).push_str(&formatted);
+            } else if prefix.contains('b') {
+                if text.is_some() {
+                    return Err(self.error("cannot mix bytes and nonbytes literals"));
+                }
+                bytes
+                    .get_or_insert_with(Vec::new)
+                    .extend(self.decode_bytes(&body, raw)?);
+            } else {
+                if bytes.is_some() {
+                    return Err(self.error("cannot mix bytes and nonbytes literals"));
+                }
+                text.get_or_insert_with(String::new)
+                    .push_str(&self.decode_str(&body, raw)?);
+            }
+        }
+        match (text, bytes) {
+            (Some(text), _) => Ok(Literal::Str(text)),
+            (_, Some(bytes)) => Ok(Literal::Bytes(bytes)),
+            _ => Err(self.error("expected a string")),
+        }
+    }
+
+    /// The raw characters between the quotes of the string at the cursor
+    fn string_body(&mut self) -> Result<String, LiteralError> {
+        let quote = self.chars[self.pos];
+        let triple = self.chars.get(self.pos + 1) == Some(&quote)
+            && self.chars.get(self.pos + 2) == Some(&quote);
+        self.pos += if triple { 3 } else { 1 };
+        let start = self.pos;
+        while self.pos < self.chars.len() {
+            let c = self.chars[self.pos];
+            if c == '\\' {
+                self.pos += 2;
+                continue;
+            }
+            if c == '\n' && !triple {
+                break;
+            }
+            if c == quote {
+                if !triple {
+                    let body = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    return Ok(body);
+                }
+                if self.chars.get(self.pos + 1) == Some(&quote)
+                    && self.chars.get(self.pos + 2) == Some(&quote)
+                {
+                    let body = self.chars[start..self.pos].iter().collect();
+                    self.pos += 3;
+                    return Ok(body);
+                }
+            }
+            self.pos += 1;
+        }
+        Err(self.error("unterminated string literal"))
+    }
+
+    /// Substitute the replacement fields of an f-string body
+    fn format_fstring(&self, body: &str, raw: bool) -> Result<String, LiteralError> {
+        let chars: Vec<char> = body.chars().collect();
+        let mut out = String::new();
+        let mut text = String::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '{' | '}' if chars.get(i + 1) == Some(&chars[i]) => {
+                    text.push(chars[i]);
+                    i += 2;
+                    continue;
+                }
+                '}' => return Err(self.error("f-string: single '}' is not allowed")),
+                '{' => {}
+                c => {
+                    text.push(c);
+                    i += 1;
+                    continue;
+                }
+            }
+            out.push_str(&self.decode_str(&std::mem::take(&mut text), raw)?);
+
+            // The expression ends at a top-level `!`, `:` or `}`
+            let start = i + 1;
+            let mut depth = 0usize;
+            let mut end = start;
+            while end < chars.len() {
+                match chars[end] {
+                    '"' | '\'' => {
+                        end = string_end(&chars, end);
+                        continue;
+                    }
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' => depth = depth.saturating_sub(1),
+                    '}' if depth > 0 => depth -= 1,
+                    '}' | ':' if depth == 0 => break,
+                    '!' if depth == 0 && chars.get(end + 1) != Some(&'=') => break,
+                    _ => {}
+                }
+                end += 1;
+            }
+            let expression: String = chars[start..end.min(chars.len())].iter().collect();
+            let trimmed = expression.trim_end();
+            if trimmed.ends_with('=') && !trimmed.ends_with("==") && !trimmed.ends_with("!=") {
+                return Err(self.error("f-string '=' specifiers are not supported"));
+            }
+            let value = LiteralParser::new(expression.chars().collect(), self.env.clone())
+                .parse_all()
+                .map_err(|e| self.error(&format!("in f-string: {}", e.message)))?;
+
+            let mut conversion = None;
+            if chars.get(end) == Some(&'!') {
+                conversion = chars.get(end + 1).copied();
+                end += 2;
+            }
+            let mut spec = String::new();
+            if chars.get(end) == Some(&':') {
+                end += 1;
+                while end < chars.len() && chars[end] != '}' {
+                    if chars[end] == '{' {
+                        return Err(self.error("nested f-string format specs are not supported"));
+                    }
+                    spec.push(chars[end]);
+                    end += 1;
+                }
+            }
+            if chars.get(end) != Some(&'}') {
+                return Err(self.error("f-string: expecting '}'"));
+            }
+            let value = match conversion {
+                None => value,
+                Some('s') => Literal::Str(value.to_str()),
+                Some('r') => Literal::Str(value.repr()),
+                Some(c) => {
+                    return Err(self.error(&format!("f-string: unsupported conversion !{}", c)))
+                }
+            };
+            out.push_str(&format_value(&value, &spec).map_err(|e| self.error(&e))?);
+            i = end + 1;
+        }
+        out.push_str(&self.decode_str(&text, raw)?);
+        Ok(out)
+    }
+
+    fn decode_str(&self, body: &str, raw: bool) -> Result<String, LiteralError> {
+        if raw {
+            return Ok(body.to_string());
+        }
+        let mut out = String::new();
+        let mut chars = body.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            let Some(e) = chars.next() else {
+                out.push('\\');
+                break;
+            };
+            match e {
+                '\n' => {}
+                '\\' => out.push('\\'),
+                '\'' => out.push('\''),
+                '"' => out.push('"'),
+                'a' => out.push('\x07'),
+                'b' => out.push('\x08'),
+                'f' => out.push('\x0c'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'v' => out.push('\x0b'),
+                '0'..='7' => {
+                    let mut code = e.to_digit(8).unwrap_or(0);
+                    for _ in 0..2 {
+                        match chars.peek().and_then(|c| c.to_digit(8)) {
+                            Some(d) => {
+                                code = code * 8 + d;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    out.push(char::from_u32(code).ok_or_else(|| self.error("bad escape"))?);
+                }
+                'x' | 'u' | 'U' => {
+                    let len = match e {
+                        'x' => 2,
+                        'u' => 4,
+                        _ => 8,
+                    };
+                    let hex: String = chars.by_ref().take(len).collect();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == len)
+                        .ok_or_else(|| self.error("truncated \\x, \\u or \\U escape"))?;
+                    out.push(char::from_u32(code).ok_or_else(|| self.error("bad escape"))?);
+                }
+                'N' => return Err(self.error("\\N{...} escapes are not supported")),
+                other => {
+                    out.push('\\');
+                    out.push(other);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn decode_bytes(&self, body: &str, raw: bool) -> Result<Vec<u8>, LiteralError> {
+        if !body.is_ascii() {
+            return Err(self.error("bytes can only contain ASCII literal characters"));
+        }
+        if raw {
+            return Ok(body.as_bytes().to_vec());
+        }
+        // \u, \U and \N are not escapes in bytes literals
+        let escaped: String = body
+            .replace("\\\\", "\x00\x00")
+            .replace("\\u", "\\\\u")
+            .replace("\\U", "\\\\U")
+            .replace("\\N", "\\\\N")
+            .replace("\x00\x00", "\\\\");
+        Ok(self
+            .decode_str(&escaped, false)?
+            .chars()
+            .map(|c| c as u32 as u8)
+            .collect())
+    }
+}
+
+fn is_real(value: &Literal) -> bool {
+    matches!(value, Literal::Int(_) | Literal::Float(_))
+}
+
+fn as_f64(value: &Literal) -> f64 {
+    match value {
+        Literal::Int(i) => *i as f64,
+        Literal::Float(f) => *f,
+        _ => 0.0,
+    }
+}
+
+/// Ranges and repetitions longer than this are not expanded, to bound the work done per file
+const MAX_RANGE: i128 = 1_000_000;
+
+fn type_name(value: &Literal) -> &'static str {
+    match value {
+        Literal::None => "NoneType",
+        Literal::Ellipsis => "ellipsis",
+        Literal::Bool(_) => "bool",
+        Literal::Int(_) => "int",
+        Literal::Float(_) => "float",
+        Literal::Complex(..) => "complex",
+        Literal::Str(_) => "str",
+        Literal::Bytes(_) => "bytes",
+        Literal::Tuple(_) => "tuple",
+        Literal::List(_) => "list",
+        Literal::Dict(_) => "dict",
+        Literal::Set(_) => "set",
+    }
+}
+
+/// Python truthiness
+fn truthy(value: &Literal) -> bool {
+    match value {
+        Literal::None => false,
+        Literal::Ellipsis => true,
+        Literal::Bool(b) => *b,
+        Literal::Int(i) => *i != 0,
+        Literal::Float(f) => *f != 0.0,
+        Literal::Complex(re, im) => *re != 0.0 || *im != 0.0,
+        Literal::Str(s) => !s.is_empty(),
+        Literal::Bytes(b) => !b.is_empty(),
+        Literal::Tuple(items) | Literal::List(items) | Literal::Set(items) => !items.is_empty(),
+        Literal::Dict(items) => !items.is_empty(),
+    }
+}
+
+/// The items a `for` loop over `value` yields
+fn iterate(value: &Literal) -> Result<Vec<Literal>, String> {
+    match value {
+        Literal::Tuple(items) | Literal::List(items) | Literal::Set(items) => Ok(items.clone()),
+        Literal::Dict(items) => Ok(items.iter().map(|(k, _)| k.clone()).collect()),
+        Literal::Str(s) => Ok(s.chars().map(|c| Literal::Str(c.to_string())).collect()),
+        Literal::Bytes(b) => Ok(b.iter().map(|&byte| Literal::Int(byte as i128)).collect()),
+        _ => Err(format!("'{}' object is not iterable", type_name(value))),
+    }
+}
+
+/// Numbers as `(int, float)` views, treating bools as ints; `None` for non-real values
+fn as_real(value: &Literal) -> Option<(Option<i128>, f64)> {
+    match value {
+        Literal::Bool(b) => Some((Some(*b as i128), *b as i128 as f64)),
+        Literal::Int(i) => Some((Some(*i), *i as f64)),
+        Literal::Float(f) => Some((None, *f)),
+        _ => None,
+    }
+}
+
+fn as_complex(value: &Literal) -> Option<(f64, f64)> {
+    match value {
+        Literal::Complex(re, im) => Some((*re, *im)),
+        _ => as_real(value).map(|(_, f)| (f, 0.0)),
+    }
+}
+
+/// `==` across numeric types, structural otherwise
+fn values_equal(left: &Literal, right: &Literal) -> bool {
+    if let (Some(l), Some(r)) = (as_complex(left), as_complex(right)) {
+        return match (as_real(left), as_real(right)) {
+            (Some((Some(a), _)), Some((Some(b), _))) => a == b,
+            _ => l == r,
+        };
+    }
+    match (left, right) {
+        (Literal::Tuple(a), Literal::Tuple(b))
+        | (Literal::List(a), Literal::List(b))
+        | (Literal::Set(a), Literal::Set(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| values_equal(x, y))
+        }
+        _ => left == right,
+    }
+}
+
+fn unique(values: Vec<Literal>) -> Vec<Literal> {
+    let mut out: Vec<Literal> = Vec::new();
+    for value in values {
+        if !out.iter().any(|seen| values_equal(seen, &value)) {
+            out.push(value);
+        }
+    }
+    out
+}
+
+/// Dict items with equal keys merged: the first key keeps its place, the last value wins
+fn unique_keys(items: Vec<(Literal, Literal)>) -> Vec<(Literal, Literal)> {
+    let mut out: Vec<(Literal, Literal)> = Vec::new();
+    for (key, value) in items {
+        match out.iter_mut().find(|(seen, _)| values_equal(seen, &key)) {
+            Some(item) => item.1 = value,
+            None => out.push((key, value)),
+        }
+    }
+    out
+}
+
+fn compare(operator: &str, left: &Literal, right: &Literal) -> Result<bool, String> {
+    let unsupported = || {
+        format!(
+            "'{}' not supported between '{}' and '{}'",
+            operator,
+            type_name(left),
+            type_name(right)
+        )
+    };
+    let ordering = || ordering(left, right).ok_or_else(unsupported);
+    Ok(match operator {
+        "==" => values_equal(left, right),
+        "!=" => !values_equal(left, right),
+        "<" => ordering()?.is_lt(),
+        "<=" => ordering()?.is_le(),
+        ">" => ordering()?.is_gt(),
+        ">=" => ordering()?.is_ge(),
+        "in" | "not in" => {
+            let found = match (left, right) {
+                (Literal::Str(needle), Literal::Str(haystack)) => {
+                    haystack.contains(needle.as_str())
+                }
+                (_, Literal::Str(_)) => return Err(unsupported()),
+                _ => iterate(right)?.iter().any(|item| values_equal(item, left)),
+            };
+            found == (operator == "in")
+        }
+        "is" | "is not" => {
+            let singleton =
+                |v: &Literal| matches!(v, Literal::None | Literal::Bool(_) | Literal::Ellipsis);
+            if !singleton(left) && !singleton(right) {
+                return Err(format!(
+                    "'{}' is only supported for None, True and False",
+                    operator
+                ));
+            }
+            (left == right) == (operator == "is")
+        }
+        _ => return Err(unsupported()),
+    })
+}
+
+/// How `<` orders two values; sequences of the same type compare item by item
+fn ordering(left: &Literal, right: &Literal) -> Option<std::cmp::Ordering> {
+    match (left, right) {
+        (Literal::Str(a), Literal::Str(b)) => Some(a.cmp(b)),
+        (Literal::Bytes(a), Literal::Bytes(b)) => Some(a.cmp(b)),
+        (Literal::Tuple(a), Literal::Tuple(b)) | (Literal::List(a), Literal::List(b)) => {
+            match a.iter().zip(b).find(|(x, y)| !values_equal(x, y)) {
+                Some((x, y)) => ordering(x, y),
+                None => Some(a.len().cmp(&b.len())),
+            }
+        }
+        _ => match (as_real(left), as_real(right)) {
+            (Some((Some(a), _)), Some((Some(b), _))) => Some(a.cmp(&b)),
+            (Some((_, a)), Some((_, b))) => a.partial_cmp(&b),
+            _ => None,
+        },
+    }
+}
+
+/// Python's floored integer division and modulo
+fn floor_divmod(a: i128, b: i128) -> Result<(i128, i128), String> {
+    if b == 0 {
+        return Err("integer division or modulo by zero".to_string());
+    }
+    let overflow = || "integer overflow".to_string();
+    let (mut q, mut r) = (
+        a.checked_div(b).ok_or_else(overflow)?,
+        a.checked_rem(b).ok_or_else(overflow)?,
+    );
+    if r != 0 && (r < 0) != (b < 0) {
+        q -= 1;
+        r += b;
+    }
+    Ok((q, r))
+}
+
+/// The length of `len` items repeated `times` times, if short enough to expand
+fn repeated_len(len: usize, times: i128) -> Result<usize, String> {
+    let total = (len as i128).saturating_mul(times.max(0));
+    if total > MAX_RANGE {
+        return Err("repetition is too large to expand".to_string());
+    }
+    Ok(total as usize)
+}
+
+fn repeat(items: &[Literal], times: i128) -> Result<Vec<Literal>, String> {
+    let total = repeated_len(items.len(), times)?;
+    Ok(items.iter().cloned().cycle().take(total).collect())
+}
+
+fn binary(operator: &str, left: Literal, right: Literal) -> Result<Literal, String> {
+    let unsupported = || {
+        format!(
+            "unsupported operand types for {}: '{}' and '{}'",
+            operator,
+            type_name(&left),
+            type_name(&right)
+        )
+    };
+    let overflow = || "integer overflow".to_string();
+    match (as_real(&left), as_real(&right)) {
+        (Some((Some(a), _)), Some((Some(b), _))) => {
+            return Ok(match operator {
+                "+" => Literal::Int(a.checked_add(b).ok_or_else(overflow)?),
+                "-" => Literal::Int(a.checked_sub(b).ok_or_else(overflow)?),
+                "*" => Literal::Int(a.checked_mul(b).ok_or_else(overflow)?),
+                "//" => Literal::Int(floor_divmod(a, b)?.0),
+                "%" => Literal::Int(floor_divmod(a, b)?.1),
+                "/" if b == 0 => return Err("division by zero".to_string()),
+                "/" => Literal::Float(a as f64 / b as f64),
+                _ => return Err(unsupported()),
+            });
+        }
+        (Some((_, a)), Some((_, b))) => {
+            if b == 0.0 && matches!(operator, "/" | "//" | "%") {
+                return Err("float division by zero".to_string());
+            }
+            return Ok(Literal::Float(match operator {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" => a / b,
+                "//" => (a / b).floor(),
+                "%" => a - b * (a / b).floor(),
+                _ => return Err(unsupported()),
+            }));
+        }
+        _ => {}
+    }
+    if let (Some((a, b)), Some((c, d))) = (as_complex(&left), as_complex(&right)) {
+        return Ok(match operator {
+            "+" => Literal::Complex(a + c, b + d),
+            "-" => Literal::Complex(a - c, b - d),
+            "*" => Literal::Complex(a * c - b * d, a * d + b * c),
+            _ => return Err(unsupported()),
+        });
+    }
+    Ok(match (operator, &left, &right) {
+        ("+", Literal::Str(a), Literal::Str(b)) => Literal::Str(format!("{}{}", a, b)),
+        ("+", Literal::Bytes(a), Literal::Bytes(b)) => Literal::Bytes([&a[..], b].concat()),
+        ("+", Literal::List(a), Literal::List(b)) => Literal::List([&a[..], b].concat()),
+        ("+", Literal::Tuple(a), Literal::Tuple(b)) => Literal::Tuple([&a[..], b].concat()),
+        ("*", Literal::Str(s), Literal::Int(n)) | ("*", Literal::Int(n), Literal::Str(s)) => {
+            repeated_len(s.chars().count(), *n)?;
+            Literal::Str(s.repeat((*n).max(0) as usize))
+        }
+        ("*", Literal::List(items), Literal::Int(n))
+        | ("*", Literal::Int(n), Literal::List(items)) => Literal::List(repeat(items, *n)?),
+        ("*", Literal::Tuple(items), Literal::Int(n))
+        | ("*", Literal::Int(n), Literal::Tuple(items)) => Literal::Tuple(repeat(items, *n)?),
+        _ => return Err(unsupported()),
+    })
+}
+
+/// `value[start:stop]` of a sequence or string
+fn slice(
+    value: &Literal,
+    start: Option<&Literal>,
+    stop: Option<&Literal>,
+) -> Result<Literal, String> {
+    let items = match value {
+        Literal::Tuple(items) | Literal::List(items) => items.clone(),
+        Literal::Str(_) | Literal::Bytes(_) => iterate(value)?,
+        _ => {
+            return Err(format!(
+                "'{}' object is not subscriptable",
+                type_name(value)
+            ))
+        }
+    };
+    let len = items.len() as i128;
+    let bound = |bound: Option<&Literal>, default: i128| -> Result<usize, String> {
+        let i = match bound.map(|b| (b, as_real(b))) {
+            None | Some((Literal::None, _)) => default,
+            Some((_, Some((Some(i), _)))) => i,
+            _ => return Err("slice indices must be integers or None".to_string()),
+        };
+        Ok((if i < 0 { i + len } else { i }).clamp(0, len) as usize)
+    };
+    let (start, stop) = (bound(start, 0)?, bound(stop, len)?);
+    let items = items
+        .get(start..stop.max(start))
+        .unwrap_or_default()
+        .to_vec();
+    Ok(match value {
+        Literal::Tuple(_) => Literal::Tuple(items),
+        Literal::List(_) => Literal::List(items),
+        Literal::Str(_) => Literal::Str(items.iter().filter_map(Literal::as_str).collect()),
+        _ => Literal::Bytes(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    Literal::Int(byte) => Some(*byte as u8),
+                    _ => None,
+                })
+                .collect(),
+        ),
+    })
+}
+
+/// A call to one of the string methods conditions commonly use
+fn method(value: &Literal, name: &str, args: Vec<Literal>) -> Result<Literal, String> {
+    let Literal::Str(s) = value else {
+        return Err(format!(
+            "'{}' object has no supported method '{}'",
+            type_name(value),
+            name
+        ));
+    };
+    match (name, args.as_slice()) {
+        ("lower", []) => Ok(Literal::Str(s.to_lowercase())),
+        ("upper", []) => Ok(Literal::Str(s.to_uppercase())),
+        ("startswith" | "endswith", [affixes]) => {
+            let affixes = match affixes {
+                Literal::Tuple(items) => items.clone(),
+                affix => vec![affix.clone()],
+            };
+            let mut found = false;
+            for affix in &affixes {
+                let Literal::Str(affix) = affix else {
+                    return Err(format!("{}() arguments must be str", name));
+                };
+                found |= if name == "startswith" {
+                    s.starts_with(affix.as_str())
+                } else {
+                    s.ends_with(affix.as_str())
+                };
+            }
+            Ok(Literal::Bool(found))
+        }
+        _ => Err(format!("'str' object has no supported method '{}'", name)),
+    }
+}
+
+fn subscript(value: &Literal, index: &Literal) -> Result<Literal, String> {
+    if let Literal::Dict(items) = value {
+        return items
+            .iter()
+            .find(|(key, _)| values_equal(key, index))
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| format!("KeyError: {}", index.repr()));
+    }
+    let items = match value {
+        Literal::Tuple(items) | Literal::List(items) => items.clone(),
+        Literal::Str(_) | Literal::Bytes(_) => iterate(value)?,
+        _ => {
+            return Err(format!(
+                "'{}' object is not subscriptable",
+                type_name(value)
+            ))
+        }
+    };
+    let Some((Some(i), _)) = as_real(index) else {
+        return Err("indices must be integers".to_string());
+    };
+    let position = if i < 0 { i + items.len() as i128 } else { i };
+    usize::try_from(position)
+        .ok()
+        .and_then(|p| items.get(p).cloned())
+        .ok_or_else(|| "index out of range".to_string())
+}
+
+/// Call one of the pure builtins `evaluate` supports
+fn call(name: &str, mut args: Vec<Literal>) -> Result<Literal, String> {
+    let arity = |min: usize, max: usize| -> Result<(), String> {
+        if (min..=max).contains(&args.len()) {
+            Ok(())
+        } else {
+            Err(format!("{}() takes {} to {} arguments", name, min, max))
+        }
+    };
+    let int = |value: &Literal| match as_real(value) {
+        Some((Some(i), _)) => Ok(i),
+        _ => Err(format!(
+            "'{}' object cannot be interpreted as an integer",
+            type_name(value)
+        )),
+    };
+    match name {
+        "range" => {
+            arity(1, 3)?;
+            let bounds = args.iter().map(int).collect::<Result<Vec<_>, _>>()?;
+            let (start, stop, step) = match bounds.as_slice() {
+                [stop] => (0, *stop, 1),
+                [start, stop] => (*start, *stop, 1),
+                [start, stop, step] => (*start, *stop, *step),
+                _ => unreachable!(),
+            };
+            if step == 0 {
+                return Err("range() arg 3 must not be zero".to_string());
+            }
+            let too_large = || "range is too large to expand".to_string();
+            let (span, step_len) = if step > 0 {
+                (stop.checked_sub(start), Some(step))
+            } else {
+                (start.checked_sub(stop), step.checked_neg())
+            };
+            let (span, step_len) = span.zip(step_len).ok_or_else(too_large)?;
+            let len = if span > 0 {
+                (span - 1) / step_len + 1
+            } else {
+                0
+            };
+            if len > MAX_RANGE {
+                return Err(too_large());
+            }
+            Ok(Literal::List(
+                (0..len.max(0))
+                    .map(|i| Literal::Int(start + i * step))
+                    .collect(),
+            ))
+        }
+        "str" => {
+            arity(0, 1)?;
+            Ok(Literal::Str(
+                args.first().map(Literal::to_str).unwrap_or_default(),
+            ))
+        }
+        "int" => {
+            arity(0, 1)?;
+            match args.first() {
+                None => Ok(Literal::Int(0)),
+                // 2^127 is exact as a float, and every truncated float below it fits
+                Some(Literal::Float(f)) if f.trunc().abs() < 2f64.powi(127) => {
+                    Ok(Literal::Int(f.trunc() as i128))
+                }
+                Some(Literal::Float(f)) if f.is_finite() => {
+                    Err("int too large to convert".to_string())
+                }
+                Some(Literal::Str(s)) => s
+                    .trim()
+                    .replace('_', "")
+                    .parse()
+                    .map(Literal::Int)
+                    .map_err(|_| format!("invalid literal for int(): {}", str_repr(s))),
+                Some(value) => int(value).map(Literal::Int),
+            }
+        }
+        "float" => {
+            arity(0, 1)?;
+            match args.first() {
+                None => Ok(Literal::Float(0.0)),
+                Some(Literal::Str(s)) => s
+                    .trim()
+                    .to_ascii_lowercase()
+                    .parse()
+                    .map(Literal::Float)
+                    .map_err(|_| format!("could not convert string to float: {}", str_repr(s))),
+                Some(value) => as_real(value)
+                    .map(|(_, f)| Literal::Float(f))
+                    .ok_or_else(|| {
+                        format!(
+                            "float() argument must be a number, not '{}'",
+                            type_name(value)
+                        )
+                    }),
+            }
+        }
+        "bool" => {
+            arity(0, 1)?;
+            Ok(Literal::Bool(args.first().is_some_and(truthy)))
+        }
+        "len" => {
+            arity(1, 1)?;
+            let len = match &args[0] {
+                Literal::Str(s) => s.chars().count(),
+                Literal::Dict(items) => items.len(),
+                value => iterate(value)?.len(),
+            };
+            Ok(Literal::Int(len as i128))
+        }
+        "list" | "tuple" | "set" => {
+            arity(0, 1)?;
+            let items = match args.first() {
+                Some(value) => iterate(value)?,
+                None => Vec::new(),
+            };
+            Ok(match name {
+                "list" => Literal::List(items),
+                "tuple" => Literal::Tuple(items),
+                _ => Literal::Set(unique(items)),
+            })
+        }
+        "enumerate" => {
+            arity(1, 2)?;
+            let start = args.get(1).map(int).transpose()?.unwrap_or(0);
+            Ok(Literal::List(
+                iterate(&args[0])?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        let i = start.checked_add(i as i128).ok_or("integer overflow")?;
+                        Ok(Literal::Tuple(vec![Literal::Int(i), value]))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ))
+        }
+        "zip" => {
+            let columns = args.iter().map(iterate).collect::<Result<Vec<_>, _>>()?;
+            let len = columns.iter().map(Vec::len).min().unwrap_or(0);
+            Ok(Literal::List(
+                (0..len)
+                    .map(|i| Literal::Tuple(columns.iter().map(|c| c[i].clone()).collect()))
+                    .collect(),
+            ))
+        }
+        "reversed" => {
+            arity(1, 1)?;
+            let mut items = iterate(&args.remove(0))?;
+            items.reverse();
+            Ok(Literal::List(items))
+        }
+        _ => Err(format!("{}() cannot be evaluated statically", name)),
+    }
+}
+
+/// `format(value, spec)` for the common string, integer and fixed-point specs
+fn format_value(value: &Literal, spec: &str) -> Result<String, String> {
+    if spec.is_empty() {
+        return Ok(value.to_str());
+    }
+    let invalid = || format!("invalid format spec {:?} for {}", spec, type_name(value));
+    let chars: Vec<char> = spec.chars().collect();
+    let mut i = 0;
+    let mut fill = ' ';
+    let mut align = None;
+    if chars.len() >= 2 && matches!(chars[1], '<' | '>' | '^' | '=') {
+        fill = chars[0];
+        align = Some(chars[1]);
+        i = 2;
+    } else if matches!(chars[0], '<' | '>' | '^' | '=') {
+        align = Some(chars[0]);
+        i = 1;
+    }
+    let mut sign = '-';
+    if let Some(&c @ ('+' | '-' | ' ')) = chars.get(i) {
+        sign = c;
+        i += 1;
+    }
+    let alternate = chars.get(i) == Some(&'#');
+    if alternate {
+        i += 1;
+    }
+    if chars.get(i) == Some(&'0') {
+        if align.is_none() {
+            fill = '0';
+            align = Some('=');
+        }
+        i += 1;
+    }
+    let digits = |i: &mut usize| -> Option<usize> {
+        let start = *i;
+        while chars.get(*i).is_some_and(char::is_ascii_digit) {
+            *i += 1;
+        }
+        chars[start..*i].iter().collect::<String>().parse().ok()
+    };
+    let width = digits(&mut i).unwrap_or(0);
+    let grouping = match chars.get(i) {
+        Some(&c @ (',' | '_')) => {
+            i += 1;
+            Some(c)
+        }
+        _ => None,
+    };
+    let precision = if chars.get(i) == Some(&'.') {
+        i += 1;
+        Some(digits(&mut i).ok_or_else(invalid)?)
+    } else {
+        None
+    };
+    let kind = chars.get(i).copied();
+    if i + kind.map_or(0, |_| 1) != chars.len() {
+        return Err(invalid());
+    }
+
+    // Split into sign, prefix and digits so `=` alignment can pad between them
+    let (negative, prefix, body) = match (value, kind) {
+        (Literal::Str(s), None | Some('s')) => {
+            if sign != '-' || alternate || grouping.is_some() || align == Some('=') {
+                return Err(invalid());
+            }
+            let text: String = match precision {
+                Some(p) => s.chars().take(p).collect(),
+                None => s.clone(),
+            };
+            return Ok(pad(String::new(), text, fill, align.unwrap_or('<'), width));
+        }
+        (Literal::Int(_) | Literal::Bool(_), None | Some('d' | 'n' | 'x' | 'X' | 'o' | 'b')) => {
+            if precision.is_some() {
+                return Err(invalid());
+            }
+            let Some((Some(n), _)) = as_real(value) else {
+                return Err(invalid());
+            };
+            let magnitude = n.unsigned_abs();
+            let (prefix, body) = match kind {
+                Some('x') => ("0x", format!("{:x}", magnitude)),
+                Some('X') => ("0X", format!("{:X}", magnitude)),
+                Some('o') => ("0o", format!("{:o}", magnitude)),
+                Some('b') => ("0b", format!("{:b}", magnitude)),
+                _ => ("", group(&magnitude.to_string(), grouping)),
+            };
+            if grouping.is_some() && !prefix.is_empty() {
+                return Err(invalid());
+            }
+            (n < 0, if alternate { prefix } else { "" }, body)
+        }
+        (_, Some('f' | 'F' | 'e' | 'E' | '%')) | (Literal::Float(_), None) => {
+            let Some((_, f)) = as_real(value) else {
+                return Err(invalid());
+            };
+            let kind = kind.unwrap_or('r');
+            let magnitude = if kind == '%' {
+                f.abs() * 100.0
+            } else {
+                f.abs()
+            };
+            let body = if f.is_nan() {
+                "nan".to_string()
+            } else if f.is_infinite() {
+                "inf".to_string()
+            } else {
+                match (kind, precision) {
+                    ('r', None) => float_repr(magnitude),
+                    ('r', Some(_)) => return Err(invalid()),
+                    ('e' | 'E', p) => {
+                        let sci = format!("{:.*e}", p.unwrap_or(6), magnitude);
+                        let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+                        let exponent: i32 = exponent.parse().unwrap_or(0);
+                        let exp_sign = if exponent < 0 { '-' } else { '+' };
+                        format!("{}e{}{:02}", mantissa, exp_sign, exponent.abs())
+                    }
+                    (_, p) => {
+                        let fixed = format!("{:.*}", p.unwrap_or(6), magnitude);
+                        let (int_part, frac) = match fixed.split_once('.') {
+                            Some((int_part, frac)) => (int_part, format!(".{}", frac)),
+                            None => (fixed.as_str(), String::new()),
+                        };
+                        format!("{}{}", group(int_part, grouping), frac)
+                    }
+                }
+            };
+            let body = match kind {
+                'F' | 'E' => body.to_uppercase(),
+                '%' => format!("{}%", body),
+                _ => body,
+            };
+            (f.is_sign_negative() && !f.is_nan(), "", body)
+        }
+        _ => return Err(invalid()),
+    };
+    let sign = match (negative, sign) {
+        (true, _) => "-",
+        (false, '+') => "+",
+        (false, ' ') => " ",
+        _ => "",
+    };
+    Ok(pad(
+        format!("{}{}", sign, prefix),
+        body,
+        fill,
+        align.unwrap_or('>'),
+        width,
+    ))
+}
+
+/// Insert a thousands separator into a run of decimal digits
+fn group(digits: &str, separator: Option<char>) -> String {
+    let Some(separator) = separator else {
+        return digits.to_string();
+    };
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(separator);
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn pad(head: String, body: String, fill: char, align: char, width: usize) -> String {
+    let len = head.chars().count() + body.chars().count();
+    let padding = width.saturating_sub(len);
+    let fill_with = |n: usize| fill.to_string().repeat(n);
+    match align {
+        '<' => format!("{}{}{}", head, body, fill_with(padding)),
+        '^' => format!(
+            "{}{}{}{}",
+            fill_with(padding / 2),
+            head,
+            body,
+            fill_with(padding - padding / 2)
+        ),
+        '=' => format!("{}{}{}", head, fill_with(padding), body),
+        _ => format!("{}{}{}", fill_with(padding), head, body),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> Literal {
+        Literal::parse(src).unwrap()
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(eval("42"), Literal::Int(42));
+        assert_eq!(eval("-1_000"), Literal::Int(-1000));
+        assert_eq!(eval("0x1F"), Literal::Int(31));
+        assert_eq!(eval("0o17"), Literal::Int(15));
+        assert_eq!(eval("0b101"), Literal::Int(5));
+        assert_eq!(eval("1.5e3"), Literal::Float(1500.0));
+        assert_eq!(eval(".5"), Literal::Float(0.5));
+        assert_eq!(eval("-2j"), Literal::Complex(-0.0, -2.0));
+        assert_eq!(eval("1 + 2j"), Literal::Complex(1.0, 2.0));
+        assert_eq!(eval("-1.5-0.5j"), Literal::Complex(-1.5, -0.5));
+        assert!(Literal::parse("1 + 2").is_err());
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(eval("'a\\tb'"), Literal::Str("a\tb".into()));
+        assert_eq!(eval("r'a\\tb'"), Literal::Str("a\\tb".into()));
+        assert_eq!(eval("u\"\\u00e9\\x41\\101\""), Literal::Str("éAA".into()));
+        assert_eq!(
+            eval("'''multi\nline'''"),
+            Literal::Str("multi\nline".into())
+        );
+        assert_eq!(eval("'a' \"b\"  'c'"), Literal::Str("abc".into()));
+        assert_eq!(eval("b'\\x00ab'"), Literal::Bytes(vec![0, b'a', b'b']));
+        assert_eq!(eval("Rb'\\n'"), Literal::Bytes(b"\\n".to_vec()));
+        assert!(Literal::parse("f'{x}'").is_err());
+        assert!(Literal::parse("'a' b'b'").is_err());
+    }
+
+    #[test]
+    fn test_containers() {
+        assert_eq!(eval("()"), Literal::Tuple(vec![]));
+        assert_eq!(eval("(1,)"), Literal::Tuple(vec![Literal::Int(1)]));
+        assert_eq!(eval("(1)"), Literal::Int(1));
+        assert_eq!(
+            eval("1, 'a'"),
+            Literal::Tuple(vec![Literal::Int(1), Literal::Str("a".into())])
+        );
+        assert_eq!(
+            eval("[None, True, False, ...]"),
+            Literal::List(vec![
+                Literal::None,
+                Literal::Bool(true),
+                Literal::Bool(false),
+                Literal::Ellipsis
+            ])
+        );
+        assert_eq!(
+            eval("{'a': [1, 2], 'b': {}}"),
+            Literal::Dict(vec![
+                (
+                    Literal::Str("a".into()),
+                    Literal::List(vec![Literal::Int(1), Literal::Int(2)])
+                ),
+                (Literal::Str("b".into()), Literal::Dict(vec![])),
+            ])
+        );
+        assert_eq!(
+            eval("{1, 2,}"),
+            Literal::Set(vec![Literal::Int(1), Literal::Int(2)])
+        );
+        assert_eq!(eval("set()"), Literal::Set(vec![]));
+        assert_eq!(
+            eval("{1, 1, True, 2.0, 2}"),
+            Literal::Set(vec![Literal::Int(1), Literal::Float(2.0)])
+        );
+        assert!(Literal::parse("[x for x in y]").is_err());
+        assert!(Literal::parse("object()").is_err());
+    }
+
+    #[test]
+    fn test_repr() {
+        assert_eq!(eval("'it''s'").repr(), "'its'");
+        assert_eq!(eval("\"it's\"").repr(), "\"it's\"");
+        assert_eq!(eval("'a\\nb\\\\'").repr(), "'a\\nb\\\\'");
+        assert_eq!(eval("b'\\x00\\''").repr(), "b\"\\x00'\"");
+        assert_eq!(eval("(1,)").repr(), "(1,)");
+        assert_eq!(eval("{'k': 1.0}").repr(), "{'k': 1.0}");
+        assert_eq!(eval("2.0j").repr(), "2j");
+        assert_eq!(eval("1-2.5j").repr(), "(1-2.5j)");
+        assert_eq!(eval("{1, 2}").repr(), "{1, 2}");
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(eval("'héllo\\n'").id().unwrap(), "h\\xe9llo\\n");
+        assert_eq!(eval("b'a\\xff'").id().unwrap(), "a\\xff");
+        assert_eq!(eval("-3").id().unwrap(), "-3");
+        assert_eq!(eval("None").id().unwrap(), "None");
+        assert_eq!(eval("1e20").id().unwrap(), "1e+20");
+        assert_eq!(eval("[1]").id(), None);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let evaluate = |src: &str| Literal::evaluate(src).unwrap();
+        let strs = |values: &[&str]| {
+            Literal::List(values.iter().map(|v| Literal::Str(v.to_string())).collect())
+        };
+        assert_eq!(
+            evaluate("[f'db_{i}' for i in range(3)]"),
+            strs(&["db_0", "db_1", "db_2"])
+        );
+        assert_eq!(
+            evaluate("range(10, 0, -4)"),
+            Literal::List(vec![Literal::Int(10), Literal::Int(6), Literal::Int(2)])
+        );
+        assert_eq!(
+            evaluate("[(f'in_{i}', i * 2) for i in range(2)]"),
+            Literal::List(vec![
+                Literal::Tuple(vec![Literal::Str("in_0".into()), Literal::Int(0)]),
+                Literal::Tuple(vec![Literal::Str("in_1".into()), Literal::Int(2)]),
+            ])
+        );
+        assert_eq!(
+            evaluate("[x for x, y in zip('abc', [1, 0, 1]) if y and x != 'c']"),
+            strs(&["a"])
+        );
+        assert_eq!(
+            evaluate("{k: v for k, v in enumerate(['a'])}"),
+            Literal::Dict(vec![(Literal::Int(0), Literal::Str("a".into()))])
+        );
+        assert_eq!(
+            evaluate("tuple(n % 3 for n in range(5) if n > 1)"),
+            Literal::Tuple(vec![Literal::Int(2), Literal::Int(0), Literal::Int(1)])
+        );
+        assert_eq!(
+            evaluate("[f'{i:03d}-{i!r}-{s:>4}|{1.5:.2f}|{{}}' for i, s in [(7, 'ab')]]"),
+            strs(&["007-7-  ab|1.50|{}"])
+        );
+        assert_eq!(
+            evaluate("['x' * 2 + str(-7 // 2) if True else 0, 1 + 2j, 2 < 3 <= 3]"),
+            Literal::List(vec![
+                Literal::Str("xx-4".into()),
+                Literal::Complex(1.0, 2.0),
+                Literal::Bool(true)
+            ])
+        );
+        assert_eq!(evaluate("[1, 2, 3][-1]"), Literal::Int(3));
+        assert_eq!(
+            evaluate("[(1, 2, 3)[:2], 'abc'[1:], [1, 2][-5:1]]"),
+            Literal::List(vec![
+                Literal::Tuple(vec![Literal::Int(1), Literal::Int(2)]),
+                Literal::Str("bc".into()),
+                Literal::List(vec![Literal::Int(1)]),
+            ])
+        );
+        assert_eq!(evaluate("(3, 11, 'final') >= (3, 9)"), Literal::Bool(true));
+        assert_eq!(evaluate("[3, 8] < [3, 8, 0]"), Literal::Bool(true));
+
+        let names = [
+            ("sys.platform".to_string(), Literal::Str("win32".into())),
+            (
+                "platform.system()".to_string(),
+                Literal::Str("Windows".into()),
+            ),
+        ];
+        let evaluate_with = |src: &str| Literal::evaluate_with(src, &names);
+        assert_eq!(
+            evaluate_with("sys.platform.startswith(('linux', 'win'))"),
+            Ok(Literal::Bool(true))
+        );
+        assert_eq!(
+            evaluate_with("platform.system().lower() != 'windows'"),
+            Ok(Literal::Bool(false))
+        );
+        assert!(evaluate_with("sys.maxsize > 2**32").is_err());
+        assert!(evaluate_with("platform.machine()").is_err());
+        assert!(Literal::evaluate("[f(i) for i in x]").is_err());
+        assert!(Literal::evaluate("range(n)").is_err());
+        assert!(Literal::evaluate("range(2000000)").is_err());
+        assert!(Literal::evaluate("[0] * 2000000").is_err());
+        assert!(Literal::evaluate("(1, 2) * 600000").is_err());
+        assert!(Literal::evaluate("'ab' * 600000").is_err());
+        assert_eq!(Literal::evaluate("[0] * -1"), Ok(Literal::List(vec![])));
+        assert!(Literal::evaluate("sorted(key=len)").is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let min = "-170141183460469231731687303715884105727 - 1";
+        assert_eq!(Literal::evaluate(min), Ok(Literal::Int(i128::MIN)));
+        assert!(Literal::evaluate(&format!("-({})", min)).is_err());
+        assert!(Literal::evaluate(&format!("({}) // -1", min)).is_err());
+        assert!(Literal::evaluate(&format!("({}) % -1", min)).is_err());
+        assert!(Literal::evaluate(&format!("range({0}, -({0} + 1))", min)).is_err());
+        assert!(Literal::evaluate(&format!("range(0, {}, -1)", min)).is_err());
+        assert_eq!(
+            Literal::evaluate("range(5, 0, -2)"),
+            Ok(Literal::List(vec![
+                Literal::Int(5),
+                Literal::Int(3),
+                Literal::Int(1)
+            ]))
+        );
+        assert!(Literal::evaluate("int(-1e300)").is_err());
+        assert!(Literal::evaluate("int(1.7014118346046923e38)").is_err());
+        assert_eq!(Literal::evaluate("int(-2.5)"), Ok(Literal::Int(-2)));
+        assert!(Literal::evaluate(&format!("enumerate('ab', -({} + 1))", min)).is_err());
+    }
+
+    #[test]
+    fn test_dict_keys() {
+        assert_eq!(
+            eval("{1: 2, True: 3}"),
+            Literal::Dict(vec![(Literal::Int(1), Literal::Int(3))])
+        );
+        assert_eq!(
+            eval("{'a': 1, 'b': 2, 'a': 3}"),
+            Literal::Dict(vec![
+                (Literal::Str("a".into()), Literal::Int(3)),
+                (Literal::Str("b".into()), Literal::Int(2)),
+            ])
+        );
+        assert_eq!(
+            Literal::evaluate("{i % 2: i for i in range(4)}"),
+            Ok(Literal::Dict(vec![
+                (Literal::Int(0), Literal::Int(2)),
+                (Literal::Int(1), Literal::Int(3)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_float_repr() {
+        assert_eq!(float_repr(1.0), "1.0");
+        assert_eq!(float_repr(0.1), "0.1");
+        assert_eq!(float_repr(-2.5), "-2.5");
+        assert_eq!(float_repr(1e16), "1e+16");
+        assert_eq!(float_repr(1.5e-7), "1.5e-07");
+        assert_eq!(float_repr(0.0001), "0.0001");
+        assert_eq!(float_repr(123456.789), "123456.789");
+    }
+}
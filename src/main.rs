@@ -0,0 +1,799 @@
+//! `rytest` command line interface: collection without a Python interpreter.
+
+use clap::{Parser, Subcommand};
+use rytest_core::config::IniConfig;
+use rytest_core::doctest::DoctestOptions;
+use rytest_core::expression::Expression;
+use rytest_core::impact::Impact;
+use rytest_core::imports::ImportGraph;
+use rytest_core::manifest::ManifestNode;
+use rytest_core::profile::{self, Profile};
+use rytest_core::session::{CollectOptions, CollectionError};
+use rytest_core::shard::{self, Durations, Granularity};
+use rytest_core::skipping::Target;
+use rytest_core::{fixtures, lint, session, walk};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+
+/// Exit codes shared with pytest
+const EXIT_OK: u8 = 0;
+const EXIT_TESTS_FAILED: u8 = 1;
+const EXIT_INTERRUPTED: u8 = 2;
+const EXIT_INTERNAL_ERROR: u8 = 3;
+const EXIT_USAGE_ERROR: u8 = 4;
+const EXIT_NO_TESTS_COLLECTED: u8 = 5;
+
+#[derive(Parser)]
+#[command(name = "rytest", version, about = "Fast static pytest collection")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List test node ids like `pytest --collect-only -q`
+    Collect {
+        /// Files or directories to collect (defaults to testpaths or the current directory)
+        paths: Vec<PathBuf>,
+        /// Only collect tests matching the given keyword expression
+        #[arg(short = 'k', value_name = "EXPRESSION")]
+        keyword: Option<String>,
+        /// Only collect tests matching the given mark expression
+        #[arg(short = 'm', value_name = "MARKEXPR")]
+        markexpr: Option<String>,
+        /// Print the collection manifest as JSON (see `rytest_core::manifest`)
+        #[arg(long)]
+        json: bool,
+        /// Show the fixtures each test uses, like `pytest --fixtures-per-test`
+        #[arg(long, conflicts_with = "json")]
+        fixtures_per_test: bool,
+        /// Also collect doctests from all Python modules
+        #[arg(long)]
+        doctest_modules: bool,
+        /// Collect doctests from text files matching the pattern (repeatable; default `test*.txt`)
+        #[arg(long = "doctest-glob", value_name = "PAT")]
+        doctest_globs: Vec<String>,
+        /// Leave out tests that `skip` and `skipif` marks always skip
+        #[arg(long)]
+        prune_skipped: bool,
+        /// The `sys.platform` to evaluate skip conditions for (defaults to this machine's)
+        #[arg(long, value_name = "PLATFORM")]
+        platform: Option<String>,
+        /// The Python version to evaluate skip conditions for, e.g. 3.12
+        #[arg(long, value_name = "X.Y[.Z]", value_parser = parse_python_version)]
+        python_version: Option<(u32, u32, u32)>,
+        /// Only collect test files affected by changes since the git ref, through their imports
+        #[arg(long, value_name = "REF")]
+        changed_since: Option<String>,
+        /// Collect the other tests of modules with tests rytest cannot collect
+        #[arg(long)]
+        continue_on_collection_errors: bool,
+        /// Time collection phase by phase and report the phases and the slowest files on stderr
+        #[arg(long)]
+        profile: bool,
+        /// How many of the slowest files --profile reports
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        profile_top: usize,
+        /// Write the collection profile as Chrome trace JSON (implies --profile)
+        #[arg(long, value_name = "PATH")]
+        profile_trace: Option<PathBuf>,
+    },
+    /// List the node ids of one of N shards, for splitting a suite across CI jobs
+    Shard {
+        /// Files or directories to collect (defaults to testpaths or the current directory)
+        paths: Vec<PathBuf>,
+        /// The number of shards
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        total: u64,
+        /// The shard to list, from 0 to N-1
+        #[arg(long, value_name = "I")]
+        index: u64,
+        /// What to keep together in one shard: file, class or test
+        #[arg(long, value_name = "GRANULARITY", default_value = "file", value_parser = parse_granularity)]
+        by: Granularity,
+        /// Balance shards by the durations in a JSON file mapping node ids to seconds
+        /// (repeatable; later files win). Every job must be given the same files
+        #[arg(long, value_name = "PATH")]
+        durations: Vec<PathBuf>,
+        /// Only shard tests matching the given keyword expression
+        #[arg(short = 'k', value_name = "EXPRESSION")]
+        keyword: Option<String>,
+        /// Only shard tests matching the given mark expression
+        #[arg(short = 'm', value_name = "MARKEXPR")]
+        markexpr: Option<String>,
+    },
+    /// Show the files each test module imports, updating the cached import graph
+    Imports {
+        /// Files or directories to collect (defaults to testpaths or the current directory)
+        paths: Vec<PathBuf>,
+        /// Print the import graph as JSON (see `rytest_core::imports`)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Keep parsed test modules in memory for the pytest plugin, parsing changed files again
+    #[cfg(target_os = "linux")]
+    Daemon {
+        /// Files or directories to index (defaults to testpaths or the current directory)
+        paths: Vec<PathBuf>,
+        /// Show whether a daemon is running for the rootdir, and what it has indexed
+        #[arg(long, conflicts_with = "stop")]
+        status: bool,
+        /// Stop the daemon running for the rootdir
+        #[arg(long)]
+        stop: bool,
+    },
+    /// Run the tests each change touches with pytest, whenever files are saved
+    #[cfg(target_os = "linux")]
+    Watch {
+        /// Files or directories to watch (defaults to testpaths or the current directory)
+        paths: Vec<PathBuf>,
+        /// The command that runs pytest
+        #[arg(long, value_name = "COMMAND", default_value = "pytest")]
+        pytest: String,
+        /// Arguments passed on to pytest, after `--`
+        #[arg(last = true, value_name = "PYTEST_ARGS")]
+        pytest_args: Vec<String>,
+    },
+    /// Report test arguments no fixture provides, unused fixtures and shadowed fixtures
+    Lint {
+        /// Files or directories to collect (defaults to testpaths or the current directory)
+        paths: Vec<PathBuf>,
+        /// A fixture provided outside the collected files, e.g. by a plugin (repeatable)
+        #[arg(long = "known-fixture", value_name = "NAME")]
+        known_fixtures: Vec<String>,
+    },
+}
+
+fn compile(flag: &str, expr: Option<&str>) -> Result<Option<Expression>, String> {
+    expr.map(|expr| {
+        Expression::compile(expr)
+            .map_err(|e| format!("Wrong expression passed to '{}': {}: {}", flag, expr, e))
+    })
+    .transpose()
+}
+
+/// The configuration for `paths` and the absolute paths to collect from
+fn locate(paths: &[PathBuf]) -> Result<(IniConfig, Vec<PathBuf>), String> {
+    let invocation_dir = std::env::current_dir().map_err(|e| e.to_string())?;
+    let config = IniConfig::locate(paths, &invocation_dir).map_err(|e| e.to_string())?;
+    let args: Vec<PathBuf> = if paths.is_empty() {
+        walk::default_args(&config, &invocation_dir)
+    } else {
+        if let Some(path) = paths.iter().find(|p| !p.exists()) {
+            return Err(format!("file or directory not found: {}", path.display()));
+        }
+        paths.iter().map(|p| invocation_dir.join(p)).collect()
+    };
+    Ok((config, args))
+}
+
+fn parse_granularity(name: &str) -> Result<Granularity, String> {
+    Granularity::parse(name).ok_or_else(|| format!("expected file, class or test, not {}", name))
+}
+
+fn parse_python_version(version: &str) -> Result<(u32, u32, u32), String> {
+    Target::parse_version(version).ok_or_else(|| format!("not a Python version: {}", version))
+}
+
+/// The doctest options from `addopts`, then the command line, as pytest applies them
+fn doctest_options(config: &IniConfig, command_line: DoctestOptions) -> DoctestOptions {
+    let mut options = DoctestOptions::default();
+    options.extend(&config.addopts);
+    options.modules |= command_line.modules;
+    options.globs.extend(command_line.globs);
+    options
+}
+
+/// `ERROR nodeid - message` for a module rytest could not collect, with the
+/// line and column, and the source it points at below, if known
+fn print_error(error: &CollectionError) {
+    let location = match &error.error.span {
+        Some(span) => format!("{}:{}:{}", error.nodeid, span.line, span.column),
+        None => error.nodeid.clone(),
+    };
+    eprintln!("ERROR {} - {}", location, error.error.message);
+    if let Some(caret) = error.error.caret() {
+        eprintln!("{}", caret);
+    }
+}
+
+fn collect(
+    paths: Vec<PathBuf>,
+    keyword: Option<String>,
+    markexpr: Option<String>,
+    json: bool,
+    fixtures_per_test: bool,
+    changed_since: Option<String>,
+    mut options: CollectOptions,
+) -> ExitCode {
+    let start = Instant::now();
+    let (config, args) = match locate(&paths) {
+        Ok(located) => located,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    let (keyword, markexpr) = match (
+        compile("-k", keyword.as_deref()),
+        compile("-m", markexpr.as_deref()),
+    ) {
+        (Ok(k), Ok(m)) => (k, m),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+
+    options.doctests = doctest_options(&config, options.doctests);
+    let continue_on_errors = options.continue_on_errors;
+    if let Some(git_ref) = changed_since {
+        match Impact::since(&args, &config, &git_ref) {
+            Ok(impact) => options.impact = Some(impact),
+            Err(e) => {
+                eprintln!("ERROR: {}", e);
+                return ExitCode::from(EXIT_USAGE_ERROR);
+            }
+        }
+    }
+    let collection = session::collect(
+        &args,
+        &config,
+        &options,
+        keyword.as_ref(),
+        markexpr.as_ref(),
+    );
+
+    let mut stdout = io::stdout().lock();
+    let printed = if json {
+        writeln!(stdout, "{:#}", collection.manifest.to_json())
+    } else {
+        print_collection(&mut stdout, &collection, fixtures_per_test, start)
+    };
+    if let Err(code) = check_output(printed) {
+        return code;
+    }
+
+    if !collection.errors.is_empty() && continue_on_errors {
+        ExitCode::from(EXIT_TESTS_FAILED)
+    } else if !collection.errors.is_empty() {
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else if collection.tests.is_empty() {
+        ExitCode::from(EXIT_NO_TESTS_COLLECTED)
+    } else {
+        ExitCode::from(EXIT_OK)
+    }
+}
+
+/// Report a failure to write to stdout, except a reader such as `head`
+/// closing the pipe early, which is not an error
+fn check_output(printed: io::Result<()>) -> Result<(), ExitCode> {
+    match printed {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("ERROR: could not write output: {}", e);
+            Err(ExitCode::from(EXIT_INTERNAL_ERROR))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Write the collected node ids and a summary line, or each test's fixtures,
+/// with collection errors on stderr
+fn print_collection(
+    out: &mut impl Write,
+    collection: &session::Collection,
+    fixtures_per_test: bool,
+    start: Instant,
+) -> io::Result<()> {
+    if fixtures_per_test {
+        print_fixtures_per_test(out, collection)?;
+        for error in &collection.errors {
+            print_error(error);
+        }
+        return Ok(());
+    }
+    for test in &collection.tests {
+        writeln!(out, "{}", test.nodeid)?;
+    }
+    for error in &collection.errors {
+        print_error(error);
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{}",
+        summary(collection, start.elapsed().as_secs_f64())
+    )
+}
+
+/// Print a collection profile on stderr, and write it to `trace` if given
+fn print_profile(profile: &Profile, top: usize, trace: Option<&Path>) {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    eprintln!();
+    for line in profile.report(top, &cwd) {
+        eprintln!("{}", line);
+    }
+    if let Some(trace) = trace {
+        match profile.write_trace(trace) {
+            Ok(()) => eprintln!("\nChrome trace written to {}", trace.display()),
+            Err(e) => eprintln!("ERROR: could not write {}: {}", trace.display(), e),
+        }
+    }
+}
+
+/// Print the node ids of shard `index` of `total`, with its share of the suite on stderr
+fn shard(
+    paths: Vec<PathBuf>,
+    total: usize,
+    index: usize,
+    granularity: Granularity,
+    durations: Vec<PathBuf>,
+    keyword: Option<String>,
+    markexpr: Option<String>,
+) -> ExitCode {
+    if index >= total {
+        eprintln!("ERROR: --index must be below --total ({})", total);
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    }
+    let (config, args) = match locate(&paths) {
+        Ok(located) => located,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    let (keyword, markexpr) = match (
+        compile("-k", keyword.as_deref()),
+        compile("-m", markexpr.as_deref()),
+    ) {
+        (Ok(k), Ok(m)) => (k, m),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    let durations = match Durations::load_all(&durations) {
+        Ok(durations) => durations,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+
+    let options = CollectOptions {
+        doctests: doctest_options(&config, DoctestOptions::default()),
+        ..CollectOptions::default()
+    };
+    let collection = session::collect(
+        &args,
+        &config,
+        &options,
+        keyword.as_ref(),
+        markexpr.as_ref(),
+    );
+    let nodeids: Vec<String> = collection.tests.into_iter().map(|t| t.nodeid).collect();
+    let shards = shard::partition(&nodeids, total, granularity, &durations);
+    let selected = &shards[index];
+    let mut stdout = io::stdout().lock();
+    let printed = selected
+        .tests
+        .iter()
+        .try_for_each(|&i| writeln!(stdout, "{}", nodeids[i]));
+    for error in &collection.errors {
+        print_error(error);
+    }
+    if let Err(code) = check_output(printed) {
+        return code;
+    }
+    let estimate = if durations.is_empty() {
+        String::new()
+    } else {
+        format!(", about {:.1}s", selected.weight)
+    };
+    eprintln!(
+        "shard {} of {} by {}: {} of {}{}",
+        index,
+        total,
+        granularity.as_str(),
+        selected.tests.len(),
+        tests(nodeids.len()),
+        estimate
+    );
+
+    if !collection.errors.is_empty() {
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else if selected.tests.is_empty() {
+        ExitCode::from(EXIT_NO_TESTS_COLLECTED)
+    } else {
+        ExitCode::from(EXIT_OK)
+    }
+}
+
+/// Print the import graph of the test modules under `paths` and their conftest files
+fn imports(paths: Vec<PathBuf>, json: bool) -> ExitCode {
+    let (config, args) = match locate(&paths) {
+        Ok(located) => located,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    let graph = ImportGraph::update_cache(&args, &config);
+    let mut stdout = io::stdout().lock();
+    let printed = if json {
+        writeln!(stdout, "{:#}", graph.to_json())
+    } else {
+        graph.modules.iter().try_for_each(|(file, module)| {
+            let dependencies: String = module
+                .dependencies
+                .iter()
+                .map(|dependency| format!(" {}", dependency))
+                .collect();
+            writeln!(stdout, "{}:{}", file, dependencies)
+        })
+    };
+    match check_output(printed) {
+        Ok(()) => ExitCode::from(EXIT_OK),
+        Err(code) => code,
+    }
+}
+
+/// Serve parsed modules to the pytest plugin, or query or stop the running daemon
+#[cfg(target_os = "linux")]
+fn daemon(paths: Vec<PathBuf>, status: bool, stop: bool) -> ExitCode {
+    use rytest_core::daemon::{self, Client, Request, Response};
+
+    let start = Instant::now();
+    let (config, args) = match locate(&paths) {
+        Ok(located) => located,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    if !status && !stop {
+        let served = daemon::serve(&args, &config, |socket, modules| {
+            println!(
+                "rytest daemon: {} modules indexed in {:.2}s, listening on {}",
+                modules,
+                start.elapsed().as_secs_f64(),
+                socket.display()
+            );
+        });
+        return match served {
+            Ok(()) => ExitCode::from(EXIT_OK),
+            Err(e) => {
+                eprintln!("ERROR: {}", e);
+                ExitCode::from(EXIT_USAGE_ERROR)
+            }
+        };
+    }
+
+    let request = if stop { Request::Stop } else { Request::Status };
+    let response = Client::connect(&config).and_then(|mut client| client.request(&request));
+    match response {
+        Ok(Response::Status {
+            pid,
+            rootdir,
+            modules,
+            directories,
+        }) => println!(
+            "rytest daemon (pid {}) for {}: {} modules indexed, {} directories watched",
+            pid,
+            rootdir.display(),
+            modules,
+            directories
+        ),
+        Ok(Response::Stopping) => println!("rytest daemon stopped"),
+        Ok(response) => {
+            eprintln!("ERROR: unexpected response: {:?}", response);
+            return ExitCode::from(EXIT_INTERRUPTED);
+        }
+        Err(_) => {
+            println!(
+                "no rytest daemon is running for {}",
+                config.rootdir.display()
+            );
+            return ExitCode::from(EXIT_TESTS_FAILED);
+        }
+    }
+    ExitCode::from(EXIT_OK)
+}
+
+/// `1 test` or `n tests`
+fn tests(count: usize) -> String {
+    format!("{} {}", count, if count == 1 { "test" } else { "tests" })
+}
+
+/// Rerun the new, modified and affected tests with pytest after every change
+#[cfg(target_os = "linux")]
+fn watch(paths: Vec<PathBuf>, pytest: String, pytest_args: Vec<String>) -> ExitCode {
+    use rytest_core::changes::TestTree;
+    use rytest_core::watch::Watcher;
+
+    let (config, args) = match locate(&paths) {
+        Ok(located) => located,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    let mut command = pytest.split_whitespace();
+    let Some(program) = command.next() else {
+        eprintln!("ERROR: --pytest must name a command");
+        return ExitCode::from(EXIT_USAGE_ERROR);
+    };
+    let command: Vec<&str> = command.collect();
+    let mut watcher = match Watcher::new(&args, &config) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    let mut tree = TestTree::new(&args, &config);
+    for dir in tree.imported_dirs() {
+        // Directories that vanished have nothing left to import
+        let _ = watcher.add_dir(dir);
+    }
+    println!(
+        "rytest watch: {} in {} modules, watching {} directories",
+        tests(tree.tests()),
+        tree.len(),
+        watcher.len()
+    );
+
+    loop {
+        let changed = match watcher.wait() {
+            Ok(changed) => changed,
+            Err(e) => {
+                eprintln!("ERROR: {}", e);
+                return ExitCode::from(EXIT_INTERRUPTED);
+            }
+        };
+        let rerun = tree.apply(&changed);
+        for dir in tree.imported_dirs() {
+            let _ = watcher.add_dir(dir);
+        }
+        for (module, changes) in &rerun.modules {
+            println!("{}: {}", module, changes.summary());
+        }
+        for (module, error) in &rerun.errors {
+            println!("ERROR {} - {}", module, error);
+        }
+        if !rerun.affected.is_empty() {
+            let affected = tests(rerun.affected.len());
+            if rerun.causes.is_empty() {
+                println!("{} affected through imports", affected);
+            } else {
+                println!("{} affected by {}", affected, rerun.causes.join(", "));
+            }
+        }
+        let nodeids = rerun.nodeids();
+        if nodeids.is_empty() {
+            println!("rytest watch: nothing to run");
+            continue;
+        }
+        println!("rytest watch: running {}", tests(nodeids.len()));
+        let status = std::process::Command::new(program)
+            .args(&command)
+            .args(&pytest_args)
+            .args(&nodeids)
+            .current_dir(&config.rootdir)
+            .status();
+        match status {
+            Ok(status) => match status.code() {
+                Some(code) => println!("rytest watch: pytest exited with code {}", code),
+                None => println!("rytest watch: pytest was killed"),
+            },
+            Err(e) => eprintln!("ERROR: could not run {}: {}", pytest, e),
+        }
+    }
+}
+
+/// Report unknown, unused and shadowed fixtures across the collected tests
+fn lint(paths: Vec<PathBuf>, known_fixtures: Vec<String>) -> ExitCode {
+    let (config, args) = match locate(&paths) {
+        Ok(located) => located,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::from(EXIT_USAGE_ERROR);
+        }
+    };
+    // Deselecting nothing, so fixtures used only by deselected tests are not reported
+    let options = CollectOptions {
+        doctests: doctest_options(&config, DoctestOptions::default()),
+        ..CollectOptions::default()
+    };
+    let collection = session::collect(&args, &config, &options, None, None);
+    let known: HashSet<String> = known_fixtures.into_iter().collect();
+    let diagnostics = lint::lint(&collection.manifest, &known, &[]);
+
+    let mut stdout = io::stdout().lock();
+    let printed = diagnostics
+        .iter()
+        .try_for_each(|diagnostic| writeln!(stdout, "{}", diagnostic));
+    for error in &collection.errors {
+        print_error(error);
+    }
+    let printed = printed.and_then(|()| match diagnostics.len() {
+        0 => writeln!(stdout, "no fixture problems found"),
+        1 => writeln!(stdout, "1 fixture problem found"),
+        n => writeln!(stdout, "{} fixture problems found", n),
+    });
+    if let Err(code) = check_output(printed) {
+        return code;
+    }
+
+    if !diagnostics.is_empty() {
+        ExitCode::from(EXIT_TESTS_FAILED)
+    } else if !collection.errors.is_empty() {
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else {
+        ExitCode::from(EXIT_OK)
+    }
+}
+
+/// A `-` separator line with `title` centred, as pytest's terminal writer draws it
+fn sep(title: &str) -> String {
+    format!("{:-^80}", format!(" {} ", title))
+}
+
+/// Each test's fixture closure, in the layout of `pytest --fixtures-per-test`
+fn print_fixtures_per_test(
+    out: &mut impl Write,
+    collection: &session::Collection,
+) -> io::Result<()> {
+    let manifest = &collection.manifest;
+    let nodes: HashMap<&str, &ManifestNode> = manifest
+        .nodes
+        .iter()
+        .map(|node| (node.nodeid.as_str(), node))
+        .collect();
+    for test in &collection.tests {
+        let Some(node) = nodes.get(test.nodeid.as_str()) else {
+            continue;
+        };
+        let lines = fixtures::describe_closure(&node.fixtures, &manifest.rootdir);
+        if lines.is_empty() {
+            continue;
+        }
+        let name = test.nodeid.rsplit("::").next().unwrap_or_default();
+        writeln!(out)?;
+        writeln!(out, "{}", sep(&format!("fixtures used by {}", name)))?;
+        let line = node.line.unwrap_or(test.line_number);
+        writeln!(out, "{}", sep(&format!("({}:{})", node.file, line)))?;
+        for line in lines {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// The final line pytest prints after `--collect-only -q`
+fn summary(collection: &session::Collection, seconds: f64) -> String {
+    let selected = collection.tests.len();
+    let total = selected + collection.deselected + collection.skipped;
+    let mut parts = Vec::new();
+    if total == 0 && collection.errors.is_empty() {
+        return format!("no tests collected in {:.2}s", seconds);
+    }
+    let noun = if total == 1 { "test" } else { "tests" };
+    let mut left_out = Vec::new();
+    if collection.deselected > 0 {
+        left_out.push(format!("{} deselected", collection.deselected));
+    }
+    if collection.skipped > 0 {
+        left_out.push(format!("{} skipped", collection.skipped));
+    }
+    if !left_out.is_empty() {
+        parts.push(format!(
+            "{}/{} {} collected ({})",
+            selected,
+            total,
+            noun,
+            left_out.join(", ")
+        ));
+    } else {
+        parts.push(format!("{} {} collected", total, noun));
+    }
+    match collection.errors.len() {
+        0 => {}
+        1 => parts.push("1 error".to_string()),
+        n => parts.push(format!("{} errors", n)),
+    }
+    format!("{} in {:.2}s", parts.join(", "), seconds)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Collect {
+            paths,
+            keyword,
+            markexpr,
+            json,
+            fixtures_per_test,
+            doctest_modules,
+            doctest_globs,
+            prune_skipped,
+            platform,
+            python_version,
+            changed_since,
+            continue_on_collection_errors,
+            profile: profiling,
+            profile_top,
+            profile_trace,
+        } => {
+            let host = Target::host();
+            let options = CollectOptions {
+                doctests: DoctestOptions {
+                    modules: doctest_modules,
+                    globs: doctest_globs,
+                },
+                target: Target {
+                    platform: platform.or(host.platform),
+                    version: python_version,
+                    ..host
+                },
+                prune_skipped,
+                impact: None,
+                continue_on_errors: continue_on_collection_errors,
+            };
+            if profiling || profile_trace.is_some() {
+                profile::start();
+            }
+            let code = collect(
+                paths,
+                keyword,
+                markexpr,
+                json,
+                fixtures_per_test,
+                changed_since,
+                options,
+            );
+            if let Some(recorded) = profile::finish() {
+                print_profile(&recorded, profile_top, profile_trace.as_deref());
+            }
+            code
+        }
+        Command::Shard {
+            paths,
+            total,
+            index,
+            by,
+            durations,
+            keyword,
+            markexpr,
+        } => shard(
+            paths,
+            total as usize,
+            index as usize,
+            by,
+            durations,
+            keyword,
+            markexpr,
+        ),
+        Command::Imports { paths, json } => imports(paths, json),
+        #[cfg(target_os = "linux")]
+        Command::Watch {
+            paths,
+            pytest,
+            pytest_args,
+        } => watch(paths, pytest, pytest_args),
+        #[cfg(target_os = "linux")]
+        Command::Daemon {
+            paths,
+            status,
+            stop,
+        } => daemon(paths, status, stop),
+        Command::Lint {
+            paths,
+            known_fixtures,
+        } => lint(paths, known_fixtures),
+    }
+}
@@ -0,0 +1,534 @@
+//! A machine-readable record of the collected tree.
+//!
+//! The manifest is written as JSON by `rytest collect --json` and by the
+//! pytest plugin's `--rytest-json=PATH` option. Schema version 2:
+//!
+//! ```text
+//! {
+//!   "schema_version": 2,
+//!   "rootdir": "/abs/path",
+//!   "deselected": 0,
+//!   "skipped": 0,                           // tests pruned as always skipped
+//!   "nodes": [
+//!     {
+//!       "nodeid": "tests/test_a.py::TestA::test_b[1-x]",
+//!       "kind": "module" | "class" | "function" | "doctest",
+//!       "file": "tests/test_a.py",          // relative to rootdir
+//!       "line": 12,                         // 1-based, first decorator; null for modules
+//!       "classes": ["TestA"],               // enclosing classes, outermost first
+//!       "callspec": {"id": "1-x", "params": {"n": "1", "s": "'x'"}} | null,
+//!       "marks": [{"name": "slow", "args": ["..."], "kwargs": {"k": "..."}}],
+//!       "fallback": false,                  // collected by pytest rather than rytest
+//!       "fixtures": [                       // the fixture closure; empty for modules and classes
+//!         {
+//!           "name": "db",
+//!           "scope": "session",             // null if unknown or not a literal
+//!           "kind": "fixture" | "builtin" | "parametrized" | "unknown",
+//!           "file": "tests/conftest.py",    // the definition used, for kind "fixture"
+//!           "line": 3
+//!         }
+//!       ],
+//!       "skip": null | {"reason": "..."} | {"unknown": "sys.version_info < (3, 9)"},
+//!       "xfail": null | {"reason": "...", "run": true} | {"unknown": "..."},
+//!       "hypothesis": null | {"given": ["x"], "examples": 2, "max_examples": 50 | null},
+//!       "error": "..."                      // only on modules rytest could not parse
+//!     }
+//!   ],
+//!   "fixtures": [
+//!     {
+//!       "name": "db",                       // `name=` alias, else the function name
+//!       "function": "db_fixture",
+//!       "file": "tests/conftest.py",
+//!       "line": 3,
+//!       "classes": [],
+//!       "scope": "session",                 // null if not a literal
+//!       "autouse": false,
+//!       "params": ["'sqlite'", "'pg'"] | null,  // reprs
+//!       "ids": ["lite", null] | null,
+//!       "params_source": "..."              // only when params could not be evaluated
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Parameter values are Python `repr()`s and mark arguments are source text.
+//! A test's `fixtures` are in pytest's `fixturenames` order, broadest scope
+//! first, each resolved to the definition nearest the test.
+//! `skip` and `xfail` are the static outcome of a test's `skip`, `skipif` and
+//! `xfail` marks for the target interpreter; `unknown` holds a condition
+//! rytest could not evaluate.
+//! `hypothesis` is set on tests with a `@given`: the arguments it provides,
+//! which are not fixtures, the number of `@example`s, and `max_examples` from
+//! `@settings`, null when left to the settings profile.
+//! Doctests are items of the file's `DoctestModule` or `DoctestTextfile`,
+//! named like `tests/util.py::pkg.util.helper`, with the docstring's line.
+//! Modules rytest cannot parse are listed with `fallback: true`; tests pytest
+//! then collects from them are recorded with `fallback: true` as well.
+//!
+//! Version 1 had no `skipped` or top-level `fixtures`, no `doctest` nodes,
+//! and no `fixtures`, `skip`, `xfail` or `hypothesis` on nodes. [`load`]
+//! rejects manifests of any version but the current one.
+
+use crate::doctest::Doctest;
+use crate::fixtures::{FixtureIndex, RequiredFixture};
+use crate::hypothesis::Hypothesis;
+use crate::parser::{Arg, Mark, TestItem, TestKind};
+use crate::skipping::{Skipping, Verdict};
+use crate::walk;
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Module,
+    Class,
+    Function,
+    Doctest,
+}
+
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Module => "module",
+            NodeKind::Class => "class",
+            NodeKind::Function => "function",
+            NodeKind::Doctest => "doctest",
+        }
+    }
+
+    /// Whether nodes of this kind are items pytest runs
+    pub fn is_item(&self) -> bool {
+        matches!(self, NodeKind::Function | NodeKind::Doctest)
+    }
+}
+
+/// The parameter set a parametrized test was generated from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Callspec {
+    pub id: String,
+    /// `(argname, repr(value))` pairs
+    pub params: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestNode {
+    pub nodeid: String,
+    pub kind: NodeKind,
+    pub file: String,
+    pub line: Option<usize>,
+    pub classes: Vec<String>,
+    pub callspec: Option<Callspec>,
+    pub marks: Vec<Mark>,
+    pub fallback: bool,
+    pub error: Option<String>,
+    /// The fixture closure of a test rytest collected
+    pub fixtures: Vec<RequiredFixture>,
+    /// The outcome of a test's skip and xfail marks
+    pub skipping: Skipping,
+    /// Hypothesis decorators of a property-based test
+    pub hypothesis: Option<Hypothesis>,
+}
+
+impl ManifestNode {
+    /// A test known only by its node id, as reported by pytest
+    pub fn test(
+        nodeid: &str,
+        line: Option<usize>,
+        params: Vec<(String, String)>,
+        marks: Vec<Mark>,
+    ) -> Self {
+        let mut parts: Vec<&str> = nodeid.split("::").collect();
+        let name = parts.pop().unwrap_or_default();
+        let file = parts.first().copied().unwrap_or_default().to_string();
+        let callspec = name
+            .split_once('[')
+            .and_then(|(_, rest)| rest.strip_suffix(']'))
+            .map(|id| Callspec {
+                id: id.to_string(),
+                params,
+            });
+        ManifestNode {
+            nodeid: nodeid.to_string(),
+            kind: NodeKind::Function,
+            file,
+            line,
+            classes: parts.iter().skip(1).map(|c| c.to_string()).collect(),
+            callspec,
+            marks,
+            fallback: false,
+            error: None,
+            fixtures: Vec::new(),
+            skipping: Skipping::default(),
+            hypothesis: None,
+        }
+    }
+
+    /// Fixture files are rendered relative to `rootdir`, as in node ids
+    pub fn to_json(&self, rootdir: &Path) -> Value {
+        let mut node = json!({
+            "nodeid": self.nodeid,
+            "kind": self.kind.as_str(),
+            "file": self.file,
+            "line": self.line,
+            "classes": self.classes,
+            "callspec": self.callspec.as_ref().map(|callspec| json!({
+                "id": callspec.id,
+                "params": callspec
+                    .params
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                    .collect::<Map<_, _>>(),
+            })),
+            "marks": self.marks.iter().map(mark_json).collect::<Vec<_>>(),
+            "fallback": self.fallback,
+            "fixtures": self
+                .fixtures
+                .iter()
+                .map(|fixture| fixture.to_json(rootdir))
+                .collect::<Vec<_>>(),
+            "skip": verdict_json(&self.skipping.skip, None),
+            "xfail": verdict_json(&self.skipping.xfail, Some(self.skipping.xfail_run)),
+            "hypothesis": self.hypothesis.as_ref().map(|hypothesis| json!({
+                "given": hypothesis.given,
+                "examples": hypothesis.examples,
+                "max_examples": hypothesis.max_examples,
+            })),
+        });
+        if let Some(error) = &self.error {
+            node["error"] = Value::from(error.as_str());
+        }
+        node
+    }
+}
+
+fn verdict_json(verdict: &Verdict, run: Option<bool>) -> Value {
+    match verdict {
+        Verdict::No => Value::Null,
+        Verdict::Yes { reason } => {
+            let mut value = json!({"reason": reason});
+            if let Some(run) = run {
+                value["run"] = Value::from(run);
+            }
+            value
+        }
+        Verdict::Unknown { condition } => json!({"unknown": condition}),
+    }
+}
+
+fn mark_json(mark: &Mark) -> Value {
+    let mut args = Vec::new();
+    let mut kwargs = Map::new();
+    for arg in &mark.args {
+        match arg {
+            Arg::Positional(value) => args.push(Value::from(value.as_str())),
+            Arg::Keyword(name, value) => {
+                kwargs.insert(name.clone(), Value::from(value.as_str()));
+            }
+        }
+    }
+    json!({"name": mark.name, "args": args, "kwargs": kwargs})
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub rootdir: PathBuf,
+    pub nodes: Vec<ManifestNode>,
+    pub deselected: usize,
+    /// Tests dropped by `prune_skipped`
+    pub skipped: usize,
+    /// Fixtures defined in the collected modules and their conftest files
+    pub fixtures: FixtureIndex,
+}
+
+impl Manifest {
+    pub fn new(rootdir: PathBuf) -> Self {
+        Manifest {
+            rootdir,
+            nodes: Vec::new(),
+            deselected: 0,
+            skipped: 0,
+            fixtures: FixtureIndex::default(),
+        }
+    }
+
+    /// Record a module rytest parsed, with its classes and generated tests
+    pub fn add_module(&mut self, path: &Path, items: &[TestItem]) {
+        let file = walk::relative_nodeid_path(path, &self.rootdir);
+        self.nodes.push(ManifestNode {
+            nodeid: file.clone(),
+            kind: NodeKind::Module,
+            file: file.clone(),
+            line: None,
+            classes: Vec::new(),
+            callspec: None,
+            marks: Vec::new(),
+            fallback: false,
+            error: None,
+            fixtures: Vec::new(),
+            skipping: Skipping::default(),
+            hypothesis: None,
+        });
+
+        for item in items {
+            let prefix = if item.classes.is_empty() {
+                file.clone()
+            } else {
+                format!("{}::{}", file, item.classes.join("::"))
+            };
+            if item.kind == TestKind::Class {
+                self.nodes.push(ManifestNode {
+                    nodeid: format!("{}::{}", prefix, item.name),
+                    kind: NodeKind::Class,
+                    file: file.clone(),
+                    line: Some(item.first_line),
+                    classes: item.classes.clone(),
+                    callspec: None,
+                    marks: item.marks.clone(),
+                    fallback: false,
+                    error: None,
+                    fixtures: Vec::new(),
+                    skipping: Skipping::default(),
+                    hypothesis: None,
+                });
+                continue;
+            }
+            for call in item.expand() {
+                let callspec = call.id().map(|id| Callspec {
+                    id,
+                    params: call
+                        .params
+                        .iter()
+                        .map(|param| (param.argname.clone(), param.value.repr()))
+                        .collect(),
+                });
+                self.nodes.push(ManifestNode {
+                    nodeid: format!("{}::{}", prefix, call.name),
+                    kind: NodeKind::Function,
+                    file: file.clone(),
+                    line: Some(item.first_line),
+                    classes: item.classes.clone(),
+                    callspec,
+                    marks: item.call_marks(&call),
+                    fallback: false,
+                    error: None,
+                    fixtures: item.fixtures.clone(),
+                    skipping: item.call_skipping(&call).clone(),
+                    hypothesis: item.hypothesis.clone(),
+                });
+            }
+        }
+    }
+
+    /// Record the doctests rytest found in a module or text file
+    pub fn add_doctests(&mut self, path: &Path, doctests: &[Doctest]) {
+        let file = walk::relative_nodeid_path(path, &self.rootdir);
+        for doctest in doctests {
+            self.nodes.push(ManifestNode {
+                nodeid: format!("{}::{}", file, doctest.name),
+                kind: NodeKind::Doctest,
+                file: file.clone(),
+                line: Some(doctest.lineno + 1),
+                classes: Vec::new(),
+                callspec: None,
+                marks: Vec::new(),
+                fallback: false,
+                error: None,
+                fixtures: Vec::new(),
+                skipping: Skipping::default(),
+                hypothesis: None,
+            });
+        }
+    }
+
+    /// Record a module left to pytest's own collection
+    pub fn add_fallback_module(&mut self, path: &Path, error: Option<String>) {
+        let file = walk::relative_nodeid_path(path, &self.rootdir);
+        self.nodes.push(ManifestNode {
+            nodeid: file.clone(),
+            kind: NodeKind::Module,
+            file,
+            line: None,
+            classes: Vec::new(),
+            callspec: None,
+            marks: Vec::new(),
+            fallback: true,
+            error,
+            fixtures: Vec::new(),
+            skipping: Skipping::default(),
+            hypothesis: None,
+        });
+    }
+
+    /// Record a test pytest collected itself, from a module rytest fell back on
+    pub fn add_fallback_test(
+        &mut self,
+        nodeid: &str,
+        line: Option<usize>,
+        params: Vec<(String, String)>,
+        marks: Vec<Mark>,
+    ) {
+        let mut node = ManifestNode::test(nodeid, line, params, marks);
+        node.fallback = true;
+        self.nodes.push(node);
+    }
+
+    pub fn contains(&self, nodeid: &str) -> bool {
+        self.nodes.iter().any(|node| node.nodeid == nodeid)
+    }
+
+    /// Drop deselected tests, counting them in `deselected`
+    pub fn deselect(&mut self, mut deselected: impl FnMut(&ManifestNode) -> bool) {
+        let before = self.nodes.len();
+        self.nodes
+            .retain(|node| !node.kind.is_item() || !deselected(node));
+        self.deselected += before - self.nodes.len();
+    }
+
+    /// Drop tests their skip marks always skip, counting them in `skipped`
+    ///
+    /// Returns the node ids dropped.
+    pub fn prune_skipped(&mut self) -> Vec<String> {
+        let (pruned, kept): (Vec<ManifestNode>, Vec<ManifestNode>) =
+            std::mem::take(&mut self.nodes)
+                .into_iter()
+                .partition(|node| {
+                    node.kind.is_item() && matches!(node.skipping.skip, Verdict::Yes { .. })
+                });
+        self.nodes = kept;
+        self.skipped += pruned.len();
+        pruned.into_iter().map(|node| node.nodeid).collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "schema_version": SCHEMA_VERSION,
+            "rootdir": self.rootdir,
+            "deselected": self.deselected,
+            "skipped": self.skipped,
+            "nodes": self
+                .nodes
+                .iter()
+                .map(|node| node.to_json(&self.rootdir))
+                .collect::<Vec<_>>(),
+            "fixtures": self.fixtures.to_json(&self.rootdir),
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_json())?;
+        fs::write(path, json + "\n")
+    }
+}
+
+/// Read a manifest written by [`Manifest::write`], if it has the current schema version
+pub fn load(path: &Path) -> io::Result<Value> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    check_schema_version(&json)?;
+    Ok(json)
+}
+
+fn check_schema_version(json: &Value) -> io::Result<()> {
+    match json["schema_version"].as_u64() {
+        Some(version) if version == u64::from(SCHEMA_VERSION) => Ok(()),
+        Some(version) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "manifest schema version {} is not supported (expected {}); collect again to regenerate it",
+                version, SCHEMA_VERSION
+            ),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a rytest manifest: no schema_version",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    #[test]
+    fn test_manifest_nodes() {
+        let source = r#"
+import pytest
+
+pytestmark = pytest.mark.unit
+
+class TestA:
+    @pytest.mark.parametrize("n, s", [(1, "x"), (2.5, None)])
+    def test_b(self, n, s):
+        pass
+
+def test_c():
+    pass
+"#;
+        let rootdir = PathBuf::from("/repo");
+        let path = rootdir.join("tests/test_a.py");
+        let patterns = |p: &str| vec![p.to_string()];
+        let items = parse_source(
+            source,
+            "tests/test_a.py",
+            &patterns("Test"),
+            &patterns("test"),
+        )
+        .unwrap();
+
+        let mut manifest = Manifest::new(rootdir.clone());
+        manifest.add_module(&path, &items);
+        manifest.add_fallback_module(&rootdir.join("tests/test_d.py"), Some("boom".into()));
+        manifest.add_fallback_test(
+            "tests/test_d.py::TestD::test_e[a]",
+            Some(3),
+            vec![("x".into(), "'a'".into())],
+            Vec::new(),
+        );
+        manifest.deselect(|node| node.nodeid.ends_with("test_c"));
+
+        let nodeids: Vec<&str> = manifest.nodes.iter().map(|n| n.nodeid.as_str()).collect();
+        assert_eq!(
+            nodeids,
+            vec![
+                "tests/test_a.py",
+                "tests/test_a.py::TestA",
+                "tests/test_a.py::TestA::test_b[1-x]",
+                "tests/test_a.py::TestA::test_b[2.5-None]",
+                "tests/test_d.py",
+                "tests/test_d.py::TestD::test_e[a]",
+            ]
+        );
+        assert_eq!(manifest.deselected, 1);
+
+        let json = manifest.to_json();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert!(check_schema_version(&json).is_ok());
+        assert!(check_schema_version(&json!({"schema_version": 1, "nodes": []})).is_err());
+        assert!(check_schema_version(&json!({"nodes": []})).is_err());
+        let test = &json["nodes"][3];
+        assert_eq!(test["kind"], "function");
+        assert_eq!(test["line"], 7);
+        assert_eq!(test["classes"], json!(["TestA"]));
+        assert_eq!(
+            test["callspec"],
+            json!({"id": "2.5-None", "params": {"n": "2.5", "s": "None"}})
+        );
+        assert_eq!(test["marks"][0]["name"], "parametrize");
+        assert_eq!(
+            test["marks"][1],
+            json!({"name": "unit", "args": [], "kwargs": {}})
+        );
+        assert_eq!(json["nodes"][0]["callspec"], Value::Null);
+        assert_eq!(json["nodes"][4]["fallback"], true);
+        assert_eq!(json["nodes"][4]["error"], "boom");
+        let fallback = &json["nodes"][5];
+        assert_eq!(fallback["file"], "tests/test_d.py");
+        assert_eq!(fallback["classes"], json!(["TestD"]));
+        assert_eq!(fallback["callspec"]["params"]["x"], "'a'");
+        assert_eq!(fallback["fallback"], true);
+    }
+}
@@ -0,0 +1,2238 @@
+//! Static discovery of tests in Python source, without importing it.
+//!
+//! Source is first split into logical lines (joining bracketed and
+//! backslash-continued lines, skipping comments and string contents), then
+//! `class`/`def` statements are matched against pytest's naming rules while
+//! tracking indentation so that only module-level functions and methods of
+//! collected classes are reported. Like Python's namespaces, a name defined
+//! again replaces the earlier definition, and test classes also hold the tests
+//! of the base classes the module defines.
+
+use crate::fixtures::{self, Fixture, FixtureDecorator, FixtureScope, RequiredFixture};
+use crate::hypothesis::{self, Hypothesis};
+use crate::literal::{Literal, LiteralError};
+use crate::profile::{self, Phase};
+use crate::skipping::Skipping;
+use glob_match::glob_match;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// What kind of problem a [`ParseError`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ErrorKind {
+    /// The file could not be read
+    Read,
+    /// The file is not in an encoding rytest can decode
+    Encoding,
+    /// A parametrize mark rytest cannot evaluate statically
+    Parametrize,
+    /// Fixture params rytest cannot evaluate statically
+    FixtureParams,
+    /// A `pytest_generate_tests` hook parametrizes tests at collection time
+    GenerateTests,
+    /// A test class inherits from a class rytest cannot find in the module
+    Inheritance,
+    #[default]
+    Other,
+}
+
+/// The source an error points at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub path: String,
+    /// 1-based line
+    pub line: usize,
+    /// 1-based column, in characters
+    pub column: usize,
+    /// The number of characters to underline, at least 1
+    pub width: usize,
+    /// The source line, without its line ending
+    pub snippet: String,
+}
+
+impl Span {
+    /// Find `fragment` in `source`, starting on a line from `first` to `last`
+    ///
+    /// Whitespace is ignored, so a fragment taken from a logical line is
+    /// found even if it spans several physical lines. The span then ends
+    /// with the first of them.
+    pub fn find(
+        source: &str,
+        path: &str,
+        first: usize,
+        last: usize,
+        fragment: &str,
+    ) -> Option<Span> {
+        let needle: Vec<char> = fragment.chars().filter(|c| !c.is_whitespace()).collect();
+        if needle.is_empty() {
+            return None;
+        }
+        let lines: Vec<&str> = source.lines().collect();
+        // Every non-whitespace character from the first line on, with its line and column
+        let chars: Vec<(usize, usize, char)> = lines
+            .iter()
+            .enumerate()
+            .skip(first.saturating_sub(1))
+            .flat_map(|(i, line)| {
+                line.chars()
+                    .enumerate()
+                    .map(move |(j, c)| (i + 1, j + 1, c))
+            })
+            .filter(|(_, _, c)| !c.is_whitespace())
+            .collect();
+        let start = (0..chars.len()).find(|&k| {
+            chars[k].0 <= last
+                && chars.len() - k >= needle.len()
+                && chars[k..k + needle.len()]
+                    .iter()
+                    .zip(&needle)
+                    .all(|((_, _, c), n)| c == n)
+        })?;
+        let (line, column, _) = chars[start];
+        let end = chars[start..start + needle.len()]
+            .iter()
+            .take_while(|(l, _, _)| *l == line)
+            .last()
+            .map_or(column, |(_, c, _)| *c);
+        Some(Span {
+            path: path.to_string(),
+            line,
+            column,
+            width: end + 1 - column,
+            snippet: lines[line - 1].to_string(),
+        })
+    }
+
+    /// The whole of line `line`, leading whitespace aside
+    pub fn line(source: &str, path: &str, line: usize) -> Option<Span> {
+        let snippet = source.lines().nth(line.checked_sub(1)?)?;
+        let indent = snippet.chars().take_while(|c| c.is_whitespace()).count();
+        Some(Span {
+            path: path.to_string(),
+            line,
+            column: indent + 1,
+            width: snippet
+                .trim_end()
+                .chars()
+                .count()
+                .saturating_sub(indent)
+                .max(1),
+            snippet: snippet.to_string(),
+        })
+    }
+}
+
+/// Why a module, or one of its tests, cannot be collected statically
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParseError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Option<Box<Span>>,
+    /// The source text the error is about, until the error is located
+    #[serde(skip)]
+    pub(crate) fragment: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self::with_kind(ErrorKind::Other, message)
+    }
+
+    pub fn with_kind(kind: ErrorKind, message: impl Into<String>) -> Self {
+        ParseError {
+            kind,
+            message: message.into(),
+            span: None,
+            fragment: None,
+        }
+    }
+
+    /// Note the source text the error is about, for [`ParseError::locate`]
+    pub(crate) fn about(mut self, fragment: &str) -> Self {
+        self.fragment = Some(fragment.to_string());
+        self
+    }
+
+    /// Point the error at its fragment in `source`, looking on the lines from
+    /// `first` to `last` before the rest of the file, or else at line `first`
+    pub fn locate(mut self, source: &str, path: &str, first: usize, last: usize) -> Self {
+        if self.span.is_none() {
+            let fragment = self.fragment.as_deref().unwrap_or_default();
+            self.span = Span::find(source, path, first, last, fragment)
+                .or_else(|| Span::find(source, path, 1, usize::MAX, fragment))
+                .or_else(|| Span::line(source, path, first))
+                .map(Box::new);
+        }
+        self
+    }
+
+    /// The source line the error points at and a caret underline below,
+    /// indented, as Python shows a `SyntaxError`
+    pub fn caret(&self) -> Option<String> {
+        let span = self.span.as_ref()?;
+        let indent = span
+            .snippet
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .count();
+        let code: String = span.snippet.chars().skip(indent).collect();
+        // Keep tabs before the caret, so it lines up however they are shown
+        let padding: String = span
+            .snippet
+            .chars()
+            .skip(indent)
+            .take(span.column.saturating_sub(indent + 1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(format!(
+            "    {}\n    {}{}",
+            code.trim_end(),
+            padding,
+            "^".repeat(span.width.max(1))
+        ))
+    }
+
+    /// The error followed by its [`caret`](ParseError::caret) lines:
+    ///
+    /// ```text
+    /// tests/test_a.py:3:31: Invalid parametrize decorator: ...
+    ///     @pytest.mark.parametrize("x", make_values())
+    ///                                   ^^^^^^^^^^^^^
+    /// ```
+    pub fn render(&self) -> String {
+        match self.caret() {
+            Some(caret) => format!("{}\n{}", self, caret),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(span) = &self.span {
+            write!(f, "{}:{}:{}: ", span.path, span.line, span.column)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestItem {
+    pub name: String,
+    pub path: String,
+    /// Line of the `def` or `class` statement
+    pub line_number: usize,
+    /// Line of the first decorator, or `line_number`; pytest reports this as the location
+    pub first_line: usize,
+    pub kind: TestKind,
+    /// Names of the enclosing test classes, outermost first
+    pub classes: Vec<String>,
+    /// Arguments requested from fixtures or parametrization, without `self`
+    /// and the ones Hypothesis's `@given` provides
+    pub argnames: Vec<String>,
+    /// Parametrize decorators, closest to the definition first
+    pub parameters: Vec<Parameters>,
+    /// The test's own marks, then those of its classes and module
+    pub marks: Vec<Mark>,
+    /// How many of `marks` are the test's own
+    pub own_marks: usize,
+    /// The fixture closure, filled in by `FixtureIndex::resolve`
+    pub fixtures: Vec<RequiredFixture>,
+    /// The outcome of the skip and xfail marks, filled in by `skipping::evaluate_item`
+    pub skipping: Skipping,
+    /// The outcome for generated tests whose `pytest.param` marks change it, by name
+    pub call_skipping: HashMap<String, Skipping>,
+    /// Hypothesis decorators, for tests with a `@given`
+    pub hypothesis: Option<Hypothesis>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TestKind {
+    Function,
+    Class,
+    Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Parameters {
+    pub argnames: Vec<String>,
+    /// Evaluated values, one row per parameter set
+    pub argvalues: Vec<Vec<Literal>>,
+    /// Explicit ids from `ids=` or `pytest.param(id=...)`, one per parameter set
+    pub ids: Vec<Option<String>>,
+    /// Marks from `pytest.param(marks=...)`, one list per parameter set
+    pub marks: Vec<Vec<Mark>>,
+    /// Argnames passed to the fixture of that name as `request.param`
+    pub indirect: Vec<String>,
+    /// The `scope=` given to `parametrize`, or the fixture scope for fixture params
+    pub scope: Option<FixtureScope>,
+}
+
+/// One test generated from a function, with the parameters it receives
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSpec {
+    /// The test name, with the parametrize id in brackets if there is one
+    pub name: String,
+    /// One id per parametrization, in the order they apply
+    pub ids: Vec<String>,
+    /// Values in parametrization order, each parametrization's argnames in turn
+    pub params: Vec<CallParam>,
+    /// The `pytest.param` marks of its parameter sets, in parametrization order
+    pub marks: Vec<Mark>,
+}
+
+impl CallSpec {
+    /// The id in brackets after the name, e.g. `1-x`
+    pub fn id(&self) -> Option<String> {
+        (!self.ids.is_empty()).then(|| self.ids.join("-"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallParam {
+    pub argname: String,
+    pub value: Literal,
+    /// Position of the parameter set within its parametrization, as in `callspec.indices`
+    pub index: usize,
+    pub scope: FixtureScope,
+}
+
+/// A `@pytest.mark.<name>(...)` decorator or `pytestmark` entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mark {
+    pub name: String,
+    pub args: Vec<Arg>,
+}
+
+/// A call argument, kept as source text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Arg {
+    Positional(String),
+    Keyword(String, String),
+}
+
+/// A statement with its continuation lines joined
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogicalLine {
+    pub line_number: usize,
+    pub indent: usize,
+    pub text: String,
+}
+
+pub fn matches_pattern(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| {
+        if pattern.contains('*') {
+            glob_match(pattern, name)
+        } else {
+            name.starts_with(pattern)
+        }
+    })
+}
+
+/// Split source into logical lines, dropping blank lines and comments
+pub fn logical_lines(source: &str) -> Vec<LogicalLine> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut lines = Vec::new();
+    let mut text = String::new();
+    let mut line_number = 1;
+    let mut start_line = 1;
+    let mut indent = 0;
+    let mut depth = 0usize;
+    let mut at_line_start = true;
+    let mut i = 0;
+
+    while i < chars.len() {
+        if at_line_start {
+            let mut width = 0;
+            while i < chars.len() && matches!(chars[i], ' ' | '\t' | '\x0c') {
+                width = if chars[i] == '\t' {
+                    (width / 8 + 1) * 8
+                } else {
+                    width + 1
+                };
+                i += 1;
+            }
+            if i >= chars.len() {
+                break;
+            }
+            if chars[i] == '\n' || chars[i] == '\r' || chars[i] == '#' {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                i += 1;
+                line_number += 1;
+                continue;
+            }
+            indent = width;
+            start_line = line_number;
+            at_line_start = false;
+        }
+
+        let c = chars[i];
+        match c {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '\n' => {
+                line_number += 1;
+                if depth > 0 {
+                    text.push(' ');
+                } else {
+                    lines.push(LogicalLine {
+                        line_number: start_line,
+                        indent,
+                        text: std::mem::take(&mut text).trim_end().to_string(),
+                    });
+                    at_line_start = true;
+                }
+            }
+            '\r' => {}
+            '\\' if chars.get(i + 1) == Some(&'\n') => {
+                text.push(' ');
+                line_number += 1;
+                i += 1;
+            }
+            '\\' if chars.get(i + 1) == Some(&'\r') && chars.get(i + 2) == Some(&'\n') => {
+                text.push(' ');
+                line_number += 1;
+                i += 2;
+            }
+            '"' | '\'' => {
+                let end = string_end(&chars, i);
+                for &sc in &chars[i..end] {
+                    if sc == '\n' {
+                        line_number += 1;
+                    }
+                    text.push(sc);
+                }
+                i = end;
+                continue;
+            }
+            '(' | '[' | '{' => {
+                depth += 1;
+                text.push(c);
+            }
+            ')' | ']' | '}' => {
+                depth = depth.saturating_sub(1);
+                text.push(c);
+            }
+            _ => text.push(c),
+        }
+        i += 1;
+    }
+
+    if !text.trim().is_empty() {
+        lines.push(LogicalLine {
+            line_number: start_line,
+            indent,
+            text: text.trim_end().to_string(),
+        });
+    }
+    lines
+}
+
+/// Index just past the string literal whose opening quote is at `start`
+pub(crate) fn string_end(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let mut i = start + if triple { 3 } else { 1 };
+    while i < chars.len() {
+        match chars[i] {
+            // Raw strings still cannot end on an escaped quote
+            '\\' if chars.get(i + 1) == Some(&'\r') && chars.get(i + 2) == Some(&'\n') => i += 2,
+            '\\' => i += 1,
+            '\n' if !triple => return i,
+            c if c == quote => {
+                if !triple {
+                    return i + 1;
+                }
+                if chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote) {
+                    return i + 3;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+/// Split `s` on top-level occurrences of `sep`, ignoring brackets and strings
+pub fn split_top_level(s: &str, sep: char) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                let end = string_end(&chars, i);
+                current.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            c if c == sep && depth == 0 => {
+                parts.push(std::mem::take(&mut current).trim().to_string());
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+    let last = current.trim();
+    if !last.is_empty() {
+        parts.push(last.to_string());
+    }
+    parts
+}
+
+/// Split a call expression into its callee and the source between its parentheses
+pub fn split_call(expr: &str) -> Option<(&str, &str)> {
+    let open = expr.find('(')?;
+    let offsets: Vec<usize> = expr.char_indices().map(|(idx, _)| idx).collect();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut depth = 0usize;
+    let mut i = offsets.iter().position(|&idx| idx == open)?;
+    while i < chars.len() {
+        let (idx, c) = (offsets[i], chars[i]);
+        match c {
+            '"' | '\'' => {
+                i = string_end(&chars, i);
+                continue;
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((expr[..open].trim(), &expr[open + 1..idx]));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Split call arguments into positional and keyword arguments
+pub fn parse_args(args: &str) -> Vec<Arg> {
+    split_top_level(args, ',')
+        .into_iter()
+        .map(|arg| {
+            let ident_len = arg
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(arg.len());
+            let rest = arg[ident_len..].trim_start();
+            if ident_len > 0 && rest.starts_with('=') && !rest.starts_with("==") {
+                Arg::Keyword(arg[..ident_len].to_string(), rest[1..].trim().to_string())
+            } else {
+                Arg::Positional(arg)
+            }
+        })
+        .collect()
+}
+
+/// Evaluate a parameter expression, timed as literal evaluation when profiling
+pub(crate) fn evaluate(src: &str) -> Result<Literal, LiteralError> {
+    profile::time(Phase::Literal, None, || Literal::evaluate(src))
+}
+
+/// Decode a string literal such as an argname or an explicit id
+fn string_value(src: &str) -> Option<String> {
+    match Literal::parse(src) {
+        Ok(Literal::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+/// One parameter set of argvalues: its value, explicit id, marks and source
+type Argvalue = (Literal, Option<String>, Vec<Mark>, String);
+
+/// A parametrize mark rytest cannot evaluate
+fn invalid(message: impl fmt::Display) -> ParseError {
+    ParseError::with_kind(
+        ErrorKind::Parametrize,
+        format!("Invalid parametrize decorator: {}", message),
+    )
+}
+
+impl Parameters {
+    /// Build parameters from the arguments of a `parametrize` mark
+    pub fn from_mark(mark: &Mark) -> Result<Self, ParseError> {
+        let mut positional = Vec::new();
+        let mut keywords = HashMap::new();
+        for arg in &mark.args {
+            match arg {
+                Arg::Positional(value) => positional.push(value.as_str()),
+                Arg::Keyword(name, value) => {
+                    keywords.insert(name.as_str(), value.as_str());
+                }
+            }
+        }
+
+        let missing = || invalid("missing arguments").about("parametrize");
+        let argnames_src = positional
+            .first()
+            .copied()
+            .or_else(|| keywords.get("argnames").copied())
+            .ok_or_else(missing)?;
+        let argvalues_src = positional
+            .get(1)
+            .copied()
+            .or_else(|| keywords.get("argvalues").copied())
+            .ok_or_else(missing)?;
+
+        let argnames = Self::parse_argnames(argnames_src)?;
+        let mut argvalues = Vec::new();
+        let mut ids = Vec::new();
+        let mut marks = Vec::new();
+        for (value, id, set_marks, source) in Self::parse_argvalues(argvalues_src)? {
+            let row = if argnames.len() == 1 {
+                vec![value]
+            } else {
+                match value.as_sequence() {
+                    Some(items) if items.len() == argnames.len() => items.to_vec(),
+                    _ => {
+                        return Err(invalid(format!(
+                            "expected {} values in {}",
+                            argnames.len(),
+                            value.repr()
+                        ))
+                        .about(&source))
+                    }
+                }
+            };
+            argvalues.push(row);
+            ids.push(id);
+            marks.push(set_marks);
+        }
+
+        if let Some(src) = keywords.get("ids") {
+            let explicit = Self::parse_ids(src, ids.len())?;
+            for (slot, id) in ids.iter_mut().zip(explicit) {
+                if slot.is_none() {
+                    *slot = id;
+                }
+            }
+        }
+
+        let indirect = match keywords.get("indirect").map(|src| evaluate(src)) {
+            Some(Ok(Literal::Bool(true))) => argnames.clone(),
+            Some(Ok(names)) => names
+                .as_sequence()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|name| name.as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let scope = keywords
+            .get("scope")
+            .and_then(|src| string_value(src))
+            .and_then(|scope| FixtureScope::parse(&scope));
+
+        Ok(Parameters {
+            argnames,
+            argvalues,
+            ids,
+            marks,
+            indirect,
+            scope,
+        })
+    }
+
+    fn parse_argnames(src: &str) -> Result<Vec<String>, ParseError> {
+        if let Some(names) = string_value(src) {
+            return Ok(names
+                .split(',')
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(String::from)
+                .collect());
+        }
+        let inner = src
+            .strip_prefix(['(', '['])
+            .and_then(|v| v.strip_suffix([')', ']']))
+            .ok_or_else(|| invalid(format!("bad argnames {}", src)).about(src))?;
+        split_top_level(inner, ',')
+            .iter()
+            .map(|name| {
+                string_value(name)
+                    .ok_or_else(|| invalid(format!("bad argname {}", name)).about(name))
+            })
+            .collect()
+    }
+
+    /// Evaluate an `ids=` list, stringifying entries the way pytest's `_idval` does
+    ///
+    /// A `None` entry keeps the generated id. A callable, or any entry pytest
+    /// would reject, is an error so that pytest collects the module itself.
+    fn parse_ids(src: &str, count: usize) -> Result<Vec<Option<String>>, ParseError> {
+        let explicit = evaluate(src).map_err(|e| {
+            invalid(format!(
+                "ids {} cannot be evaluated statically ({})",
+                src, e
+            ))
+            .about(src)
+        })?;
+        let entries = match &explicit {
+            Literal::None => return Ok(Vec::new()),
+            Literal::Tuple(items) | Literal::List(items) => items,
+            _ => return Err(invalid(format!("unsupported ids {}", src)).about(src)),
+        };
+        if entries.len() != count {
+            return Err(invalid(format!(
+                "{} parameter sets specified, with different number of ids: {}",
+                count,
+                entries.len()
+            ))
+            .about(src));
+        }
+        entries
+            .iter()
+            .map(|id| match id {
+                Literal::None => Ok(None),
+                _ => id
+                    .id()
+                    .map(Some)
+                    .ok_or_else(|| invalid(format!("unsupported id {}", id.repr())).about(src)),
+            })
+            .collect()
+    }
+
+    /// Evaluate argvalues into one value and explicit id per parameter set
+    ///
+    /// A list or tuple display is evaluated element by element so that
+    /// `pytest.param(...)` entries can be unwrapped; anything else, such as a
+    /// comprehension or `range()`, is evaluated as a whole.
+    fn parse_argvalues(src: &str) -> Result<Vec<Argvalue>, ParseError> {
+        let not_static = |value: &str, e: LiteralError| {
+            invalid(format!("{} cannot be evaluated statically ({})", value, e)).about(value)
+        };
+        let elements = src
+            .strip_prefix(['[', '('])
+            .and_then(|v| v.strip_suffix([']', ')']))
+            .map(|inner| split_top_level(inner, ','))
+            // A comprehension is a single element with a top-level `for`
+            .filter(|values| {
+                values.len() != 1 || !split_top_level(&values[0], ' ').iter().any(|t| t == "for")
+            });
+        let Some(elements) = elements else {
+            let value = evaluate(src).map_err(|e| not_static(src, e))?;
+            let values = value
+                .as_sequence()
+                .ok_or_else(|| invalid("argvalues must be a list").about(src))?;
+            return Ok(values
+                .iter()
+                .map(|value| (value.clone(), None, Vec::new(), src.to_string()))
+                .collect());
+        };
+        elements
+            .iter()
+            .map(|element| {
+                let (value, id, marks) = Self::unwrap_param(element)?;
+                let literal = evaluate(&value).map_err(|e| not_static(&value, e))?;
+                Ok((literal, id, marks, element.clone()))
+            })
+            .collect()
+    }
+
+    /// Unwrap `pytest.param(values..., id=..., marks=...)` into its values, id and marks
+    fn unwrap_param(value: &str) -> Result<(String, Option<String>, Vec<Mark>), ParseError> {
+        if let Some((callee, args)) = split_call(value) {
+            if callee == "pytest.param" || callee == "param" {
+                let mut values = Vec::new();
+                let mut id = None;
+                let mut marks = Vec::new();
+                for arg in parse_args(args) {
+                    match arg {
+                        Arg::Positional(v) => values.push(v),
+                        Arg::Keyword(name, v) if name == "id" => id = string_value(&v),
+                        Arg::Keyword(name, v) if name == "marks" => marks = Self::parse_marks(&v)?,
+                        Arg::Keyword(..) => {}
+                    }
+                }
+                let value = if values.len() == 1 {
+                    values.remove(0)
+                } else {
+                    format!("({})", values.join(", "))
+                };
+                return Ok((value, id, marks));
+            }
+        }
+        Ok((value.to_string(), None, Vec::new()))
+    }
+
+    /// Parse the `marks=` of a `pytest.param`: one mark, or a list or tuple of marks
+    fn parse_marks(src: &str) -> Result<Vec<Mark>, ParseError> {
+        let elements = match src.strip_prefix(['[', '(']) {
+            Some(inner) => split_top_level(inner.strip_suffix([']', ')']).unwrap_or(inner), ','),
+            None => vec![src.to_string()],
+        };
+        elements
+            .iter()
+            .map(|element| {
+                parse_mark(element)
+                    .filter(|mark| mark.name != "parametrize")
+                    .ok_or_else(|| {
+                        invalid(format!("marks {} cannot be evaluated statically", src))
+                            .about(element)
+                    })
+            })
+            .collect()
+    }
+
+    /// The `pytest.param` marks of parameter set `index`
+    pub fn set_marks(&self, index: usize) -> &[Mark] {
+        self.marks.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Ids for every parameter set, deduplicated the way pytest does
+    pub fn set_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .argvalues
+            .iter()
+            .enumerate()
+            .map(|(idx, row)| {
+                if let Some(Some(id)) = self.ids.get(idx) {
+                    return id.clone();
+                }
+                self.argnames
+                    .iter()
+                    .zip(row)
+                    .map(|(name, value)| value.id().unwrap_or_else(|| format!("{}{}", name, idx)))
+                    .collect::<Vec<_>>()
+                    .join("-")
+            })
+            .collect();
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for id in &ids {
+            *counts.entry(id.clone()).or_default() += 1;
+        }
+        let mut suffixes: HashMap<String, usize> = HashMap::new();
+        for idx in 0..ids.len() {
+            let id = ids[idx].clone();
+            if counts[&id] > 1 {
+                let sep = if id.ends_with(|c: char| c.is_ascii_digit()) {
+                    "_"
+                } else {
+                    ""
+                };
+                let suffix = suffixes.entry(id.clone()).or_default();
+                let mut new_id = format!("{}{}{}", id, sep, suffix);
+                while ids.contains(&new_id) {
+                    *suffix += 1;
+                    new_id = format!("{}{}{}", id, sep, suffix);
+                }
+                *suffix += 1;
+                ids[idx] = new_id;
+            }
+        }
+        ids
+    }
+}
+
+impl TestItem {
+    /// The node id suffix below the module, e.g. `TestClass::test_method`
+    pub fn qualified_name(&self) -> String {
+        let mut parts = self.classes.clone();
+        parts.push(self.name.clone());
+        parts.join("::")
+    }
+
+    /// One callspec per generated test, in pytest's order
+    pub fn expand(&self) -> Vec<CallSpec> {
+        let mut expanded = vec![(
+            Vec::<String>::new(),
+            Vec::<CallParam>::new(),
+            Vec::<Mark>::new(),
+        )];
+        for params in &self.parameters {
+            let ids = params.set_ids();
+            let scope = params.scope.unwrap_or(FixtureScope::Function);
+            let mut next = Vec::new();
+            for (id_parts, values, marks) in &expanded {
+                for (index, (id, row)) in ids.iter().zip(&params.argvalues).enumerate() {
+                    let mut id_parts = id_parts.clone();
+                    id_parts.push(id.clone());
+                    let mut marks = marks.clone();
+                    marks.extend(params.set_marks(index).iter().cloned());
+                    let mut values = values.clone();
+                    values.extend(params.argnames.iter().zip(row).map(|(argname, value)| {
+                        CallParam {
+                            argname: argname.clone(),
+                            value: value.clone(),
+                            index,
+                            scope,
+                        }
+                    }));
+                    next.push((id_parts, values, marks));
+                }
+            }
+            expanded = next;
+        }
+
+        expanded
+            .into_iter()
+            .map(|(ids, params, marks)| {
+                let name = if self.parameters.is_empty() {
+                    self.name.clone()
+                } else {
+                    format!("{}[{}]", self.name, ids.join("-"))
+                };
+                CallSpec {
+                    name,
+                    ids,
+                    params,
+                    marks,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether a `pytest.param` of the test has marks
+    pub fn has_param_marks(&self) -> bool {
+        self.parameters
+            .iter()
+            .any(|params| params.marks.iter().any(|marks| !marks.is_empty()))
+    }
+
+    /// The marks of one generated test, as pytest's `Function` gathers them:
+    /// the test's own, its parameter sets', then its classes' and module's
+    pub fn call_marks(&self, call: &CallSpec) -> Vec<Mark> {
+        let own = self.own_marks.min(self.marks.len());
+        let mut marks = self.marks[..own].to_vec();
+        marks.extend(call.marks.iter().cloned());
+        marks.extend(self.marks[own..].iter().cloned());
+        marks
+    }
+
+    /// The outcome of the skip and xfail marks of one generated test
+    pub fn call_skipping(&self, call: &CallSpec) -> &Skipping {
+        self.call_skipping.get(&call.name).unwrap_or(&self.skipping)
+    }
+}
+
+/// Recognise a mark decorator such as `pytest.mark.slow` or `pytest.mark.parametrize(...)`
+pub fn parse_mark(expr: &str) -> Option<Mark> {
+    let expr = expr.trim().trim_start_matches('@').trim();
+    let (path, args) = match split_call(expr) {
+        Some((callee, args)) => (callee, parse_args(args)),
+        None => (expr, Vec::new()),
+    };
+    let name = if path == "parametrize" {
+        "parametrize"
+    } else {
+        path.strip_prefix("pytest.mark.")
+            .or_else(|| path.strip_prefix("mark."))?
+            .split('.')
+            .next()?
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some(Mark {
+        name: name.to_string(),
+        args,
+    })
+}
+
+/// Parse the right-hand side of a `pytestmark = ...` assignment
+fn parse_pytestmark(value: &str) -> Vec<Mark> {
+    let value = value.trim();
+    match value
+        .strip_prefix(['[', '('])
+        .and_then(|v| v.strip_suffix([']', ')']))
+    {
+        Some(inner) if parse_mark(value).is_none() => split_top_level(inner, ',')
+            .iter()
+            .filter_map(|m| parse_mark(m))
+            .collect(),
+        _ => parse_mark(value).into_iter().collect(),
+    }
+}
+
+pub(crate) fn def_name(text: &str) -> Option<&str> {
+    let rest = text
+        .strip_prefix("async ")
+        .map(str::trim_start)
+        .unwrap_or(text);
+    let name = rest.strip_prefix("def ")?.split('(').next()?.trim();
+    Some(name)
+}
+
+/// The arguments pytest requests for a function, as `getfuncargnames` finds them
+///
+/// Positional-only, variadic and defaulted parameters are left out, and so is
+/// the first parameter of a `bound` method, positional-only or not.
+fn def_argnames(text: &str, bound: bool) -> Vec<String> {
+    let Some((_, params)) = split_call(text) else {
+        return Vec::new();
+    };
+    let mut params = split_top_level(params, ',');
+    if bound
+        && params
+            .first()
+            .is_some_and(|p| p != "/" && !p.starts_with('*'))
+    {
+        params.remove(0);
+    }
+    let keyword_start = params
+        .iter()
+        .position(|param| param == "/")
+        .map_or(0, |slash| slash + 1);
+    params[keyword_start..]
+        .iter()
+        .filter(|param| !param.starts_with('*') && split_top_level(param, '=').len() == 1)
+        .map(|param| {
+            param
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .collect()
+}
+
+/// The positional-or-keyword parameters of a function, defaulted or not,
+/// without the first parameter of a `bound` method unless it is positional-only
+fn def_positional(text: &str, bound: bool) -> Vec<String> {
+    let Some((_, params)) = split_call(text) else {
+        return Vec::new();
+    };
+    let params = split_top_level(params, ',');
+    let start = params
+        .iter()
+        .position(|param| param == "/")
+        .map_or(0, |slash| slash + 1);
+    params[start..]
+        .iter()
+        .take_while(|param| !param.starts_with('*'))
+        .map(|param| {
+            param
+                .split([':', '='])
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .skip(usize::from(bound && start == 0))
+        .collect()
+}
+
+pub(crate) fn class_name(text: &str) -> Option<&str> {
+    let name = text
+        .strip_prefix("class ")?
+        .split(['(', ':'])
+        .next()?
+        .trim();
+    Some(name)
+}
+
+/// The base classes of a `class` statement, without `object` and keyword arguments
+fn class_bases(text: &str) -> Vec<String> {
+    let Some((_, args)) = split_call(text.split_once(':').map_or(text, |(head, _)| head)) else {
+        return Vec::new();
+    };
+    parse_args(args)
+        .into_iter()
+        .filter_map(|arg| match arg {
+            Arg::Positional(base) if base != "object" && !base.is_empty() => Some(base),
+            _ => None,
+        })
+        .collect()
+}
+
+fn pytestmark_value(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix("pytestmark")?.trim_start();
+    let rest = rest.strip_prefix('=')?;
+    (!rest.starts_with('=')).then_some(rest)
+}
+
+/// Whether a statement binds `pytest_generate_tests`, by assignment or import
+fn binds_generate_tests(text: &str) -> bool {
+    const HOOK: &str = "pytest_generate_tests";
+    if let Some(rest) = text.strip_prefix(HOOK) {
+        let rest = rest.trim_start();
+        return rest.starts_with('=') && !rest.starts_with("==");
+    }
+    let names = match text.strip_prefix("from ") {
+        Some(rest) => rest.split_once(" import ").map(|(_, names)| names),
+        None => text.strip_prefix("import "),
+    };
+    names.is_some_and(|names| {
+        names
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .any(|name| name.rsplit(" as ").next().unwrap_or_default().trim() == HOOK)
+    })
+}
+
+enum Scope {
+    Class { name: String },
+    Function,
+}
+
+/// A test function or a class of any name, defined at module level or in a class body
+struct Definition {
+    item: TestItem,
+    /// For a class, the definitions of its bases, or the first base the module does not define
+    bases: Result<Vec<usize>, String>,
+    /// For a class, its decorator and `pytestmark` marks
+    marks: Vec<Mark>,
+    /// For a class, the definitions in its body, each name once
+    members: Vec<usize>,
+}
+
+/// The test functions and classes a module defines, by namespace
+///
+/// As in Python, defining a name again replaces the earlier definition but
+/// keeps its place, so a test defined in both branches of an `if` is only
+/// collected once.
+#[derive(Default)]
+struct Namespaces {
+    definitions: Vec<Definition>,
+    module: Vec<usize>,
+    /// The latest definition of each class, by its chain of names
+    classes: HashMap<Vec<String>, usize>,
+}
+
+/// A class pytest collects, with what it inherits
+struct CollectedClass {
+    chain: Vec<String>,
+    /// The chains of the classes in its method resolution order, itself first
+    mro: Vec<Vec<String>>,
+    /// The marks of the classes in its method resolution order, bases first
+    marks: Vec<Mark>,
+}
+
+/// What [`Namespaces::collect`] finds
+#[derive(Default)]
+struct Collected {
+    items: Vec<TestItem>,
+    classes: Vec<CollectedClass>,
+    /// Classes that cannot be collected, with the lines of their definition
+    errors: Vec<(ParseError, usize, usize)>,
+}
+
+impl Namespaces {
+    /// Add `item`, in the namespace of its `classes`, with the `bases` of a class
+    fn define(&mut self, item: TestItem, bases: Vec<String>) {
+        let parent = if item.classes.is_empty() {
+            None
+        } else {
+            match self.classes.get(&item.classes) {
+                Some(&class) => Some(class),
+                None => return,
+            }
+        };
+        // A class statement looks names up in the enclosing class body, then the module
+        let bases = bases
+            .into_iter()
+            .map(|base| {
+                let mut sibling = item.classes.clone();
+                sibling.push(base.clone());
+                self.classes
+                    .get(&sibling)
+                    .or_else(|| self.classes.get(std::slice::from_ref(&base)))
+                    .copied()
+                    .ok_or(base)
+            })
+            .collect();
+        let index = self.definitions.len();
+        let name = item.name.clone();
+        if item.kind == TestKind::Class {
+            let mut chain = item.classes.clone();
+            chain.push(name.clone());
+            self.classes.insert(chain, index);
+        }
+        let marks = item.marks.clone();
+        self.definitions.push(Definition {
+            item,
+            bases,
+            marks,
+            members: Vec::new(),
+        });
+        let members = match parent {
+            Some(class) => &self.definitions[class].members,
+            None => &self.module,
+        };
+        let existing = members
+            .iter()
+            .position(|&member| self.definitions[member].item.name == name);
+        let members = match parent {
+            Some(class) => &mut self.definitions[class].members,
+            None => &mut self.module,
+        };
+        match existing {
+            Some(position) => members[position] = index,
+            None => members.push(index),
+        }
+    }
+
+    /// Add a `pytestmark` assignment's marks to the class `chain`
+    fn mark_class(&mut self, chain: &[String], marks: Vec<Mark>) {
+        if let Some(&class) = self.classes.get(chain) {
+            self.definitions[class].marks.extend(marks);
+        }
+    }
+
+    /// The chain of names a definition was made under
+    fn chain(&self, definition: usize) -> Vec<String> {
+        let item = &self.definitions[definition].item;
+        let mut chain = item.classes.clone();
+        chain.push(item.name.clone());
+        chain
+    }
+
+    /// The C3 linearization of a class and its bases, as Python's `__mro__`
+    fn mro(&self, class: usize) -> Result<Vec<usize>, ParseError> {
+        let bases = self.definitions[class].bases.as_ref().map_err(|base| {
+            ParseError::with_kind(
+                ErrorKind::Inheritance,
+                format!(
+                    "base class {} of {} is not a class defined in the module",
+                    base, self.definitions[class].item.name
+                ),
+            )
+            .about(base)
+        })?;
+        let mut sequences = bases
+            .iter()
+            .map(|&base| self.mro(base))
+            .collect::<Result<Vec<_>, _>>()?;
+        sequences.push(bases.clone());
+        let mut mro = vec![class];
+        loop {
+            sequences.retain(|sequence| !sequence.is_empty());
+            if sequences.is_empty() {
+                return Ok(mro);
+            }
+            let head = sequences
+                .iter()
+                .map(|sequence| sequence[0])
+                .find(|head| !sequences.iter().any(|s| s[1..].contains(head)))
+                .ok_or_else(|| {
+                    ParseError::with_kind(
+                        ErrorKind::Inheritance,
+                        format!(
+                            "the bases of {} have no consistent method resolution order",
+                            self.definitions[class].item.name
+                        ),
+                    )
+                })?;
+            mro.push(head);
+            for sequence in &mut sequences {
+                if sequence[0] == head {
+                    sequence.remove(0);
+                }
+            }
+        }
+    }
+
+    /// The members of a class with those it inherits, as pytest collects them:
+    /// the most derived definition of each name, the bases' members first
+    fn inherited(&self, mro: &[usize]) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let groups: Vec<Vec<usize>> = mro
+            .iter()
+            .map(|&class| {
+                self.definitions[class]
+                    .members
+                    .iter()
+                    .copied()
+                    .filter(|&member| seen.insert(self.definitions[member].item.name.clone()))
+                    .collect()
+            })
+            .collect();
+        groups.into_iter().rev().flatten().collect()
+    }
+
+    /// The tests and test classes pytest collects from the module
+    fn collect(&self, python_classes: &[String]) -> Collected {
+        let mut collected = Collected::default();
+        self.collect_members(&self.module, &[], python_classes, &mut collected);
+        collected
+    }
+
+    fn collect_members(
+        &self,
+        members: &[usize],
+        classes: &[String],
+        python_classes: &[String],
+        collected: &mut Collected,
+    ) {
+        for &member in members {
+            let mut item = self.definitions[member].item.clone();
+            item.classes = classes.to_vec();
+            if item.kind != TestKind::Class {
+                item.kind = if classes.is_empty() {
+                    TestKind::Function
+                } else {
+                    TestKind::Method
+                };
+                collected.items.push(item);
+                continue;
+            }
+            if !matches_pattern(&item.name, python_classes) {
+                continue;
+            }
+            let mro = match self.mro(member) {
+                Ok(mro) => mro,
+                Err(err) => {
+                    collected
+                        .errors
+                        .push((err, item.first_line, item.line_number));
+                    continue;
+                }
+            };
+            let mut chain = classes.to_vec();
+            chain.push(item.name.clone());
+            collected.items.push(item);
+            collected.classes.push(CollectedClass {
+                chain: chain.clone(),
+                mro: mro.iter().map(|&class| self.chain(class)).collect(),
+                marks: mro
+                    .iter()
+                    .rev()
+                    .flat_map(|&class| self.definitions[class].marks.iter().cloned())
+                    .collect(),
+            });
+            self.collect_members(&self.inherited(&mro), &chain, python_classes, collected);
+        }
+    }
+}
+
+/// Everything found in one module or conftest file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParsedModule {
+    pub items: Vec<TestItem>,
+    pub fixtures: Vec<Fixture>,
+    /// Scopes binding `pytest_generate_tests`: enclosing class names, empty for the module
+    pub generate_tests: Vec<Vec<String>>,
+    /// Why tests were left out, when parsed with [`parse_module_partial`]
+    pub errors: Vec<ParseError>,
+}
+
+/// Find the tests defined in a module's source
+pub fn parse_source(
+    source: &str,
+    path: &str,
+    python_classes: &[String],
+    python_functions: &[String],
+) -> Result<Vec<TestItem>, ParseError> {
+    Ok(parse_module(source, path, python_classes, python_functions)?.items)
+}
+
+/// Find the tests and fixtures defined in a module's source
+///
+/// Fails on the first test rytest cannot collect statically.
+pub fn parse_module(
+    source: &str,
+    path: &str,
+    python_classes: &[String],
+    python_functions: &[String],
+) -> Result<ParsedModule, ParseError> {
+    let mut parsed = parse_module_partial(source, path, python_classes, python_functions);
+    if !parsed.errors.is_empty() {
+        return Err(parsed.errors.swap_remove(0));
+    }
+    Ok(parsed)
+}
+
+/// Find the tests and fixtures defined in a module's source, leaving out
+/// the tests rytest cannot collect statically and recording why in `errors`
+pub fn parse_module_partial(
+    source: &str,
+    path: &str,
+    python_classes: &[String],
+    python_functions: &[String],
+) -> ParsedModule {
+    let mut namespaces = Namespaces::default();
+    let mut fixtures = Vec::new();
+    let mut generate_tests = Vec::new();
+    let mut scopes: Vec<(usize, Scope)> = Vec::new();
+    let mut decorators: Vec<Mark> = Vec::new();
+    let mut fixture_decorator: Option<FixtureDecorator> = None;
+    let mut hypothesis_decorators = Vec::new();
+    let mut decorator_line = None;
+    let mut staticmethod = false;
+    let mut module_marks = Vec::new();
+    let mut dynamic_globals = None;
+
+    for line in logical_lines(source) {
+        while scopes
+            .last()
+            .is_some_and(|(indent, _)| *indent >= line.indent)
+        {
+            scopes.pop();
+        }
+        let text = line.text.as_str();
+
+        if let Some(expr) = text.strip_prefix('@') {
+            decorator_line.get_or_insert(line.line_number);
+            if let Some(mark) = parse_mark(expr) {
+                decorators.push(mark);
+            } else if let Some(fixture) = fixtures::parse_decorator(expr) {
+                fixture_decorator = Some(fixture);
+            } else if let Some(decorator) = hypothesis::parse_decorator(expr) {
+                hypothesis_decorators.push(decorator);
+            } else if expr.trim() == "staticmethod" {
+                staticmethod = true;
+            }
+            continue;
+        }
+        // Decorators apply bottom-up, so the closest one is the first mark
+        let mut marks = std::mem::take(&mut decorators);
+        marks.reverse();
+        let fixture_decorator = fixture_decorator.take();
+        let hypothesis_decorators = std::mem::take(&mut hypothesis_decorators);
+        let first_line = decorator_line.take().unwrap_or(line.line_number);
+        let is_static = std::mem::take(&mut staticmethod);
+
+        // Only module level and class bodies can hold tests
+        let in_namespace = scopes
+            .iter()
+            .all(|(_, scope)| matches!(scope, Scope::Class { .. }));
+        let classes: Vec<String> = scopes
+            .iter()
+            .filter_map(|(_, scope)| match scope {
+                Scope::Class { name, .. } => Some(name.clone()),
+                Scope::Function => None,
+            })
+            .collect();
+
+        if let Some(name) = class_name(text) {
+            if in_namespace {
+                let item = TestItem {
+                    name: name.to_string(),
+                    path: path.to_string(),
+                    line_number: line.line_number,
+                    first_line,
+                    kind: TestKind::Class,
+                    classes: classes.clone(),
+                    argnames: Vec::new(),
+                    parameters: Vec::new(),
+                    marks,
+                    own_marks: 0,
+                    fixtures: Vec::new(),
+                    skipping: Skipping::default(),
+                    call_skipping: HashMap::new(),
+                    hypothesis: None,
+                };
+                namespaces.define(item, class_bases(text));
+            }
+            scopes.push((
+                line.indent,
+                Scope::Class {
+                    name: name.to_string(),
+                },
+            ));
+        } else if let Some(name) = def_name(text) {
+            let bound = !classes.is_empty() && !is_static;
+            let mut argnames = def_argnames(text, bound);
+            if name == "pytest_generate_tests" && in_namespace {
+                generate_tests.push(classes);
+            } else if let Some(decorator) = fixture_decorator.filter(|_| in_namespace) {
+                fixtures.push(Fixture {
+                    name: decorator.name.unwrap_or_else(|| name.to_string()),
+                    function: name.to_string(),
+                    path: path.to_string(),
+                    line_number: first_line,
+                    classes,
+                    argnames,
+                    scope: decorator.scope,
+                    autouse: decorator.autouse,
+                    params: decorator.params,
+                    ids: decorator.ids,
+                });
+            } else if in_namespace && matches_pattern(name, python_functions) {
+                let hypothesis = Hypothesis::from_decorators(
+                    &hypothesis_decorators,
+                    &def_positional(text, bound),
+                    &argnames,
+                );
+                if let Some(hypothesis) = &hypothesis {
+                    argnames.retain(|argname| !hypothesis.provides(argname));
+                }
+                let item = TestItem {
+                    name: name.to_string(),
+                    path: path.to_string(),
+                    line_number: line.line_number,
+                    first_line,
+                    kind: if classes.is_empty() {
+                        TestKind::Function
+                    } else {
+                        TestKind::Method
+                    },
+                    classes,
+                    argnames,
+                    parameters: Vec::new(),
+                    marks,
+                    own_marks: 0,
+                    fixtures: Vec::new(),
+                    skipping: Skipping::default(),
+                    call_skipping: HashMap::new(),
+                    hypothesis,
+                };
+                namespaces.define(item, Vec::new());
+            }
+            scopes.push((line.indent, Scope::Function));
+        } else if binds_generate_tests(text) {
+            if in_namespace {
+                generate_tests.push(classes);
+            }
+        } else if let Some(value) = pytestmark_value(text) {
+            if scopes.is_empty() {
+                module_marks.extend(parse_pytestmark(value));
+            } else if in_namespace {
+                namespaces.mark_class(&classes, parse_pytestmark(value));
+            }
+        } else if scopes.is_empty() && (text.starts_with("globals()") || text.starts_with("vars()"))
+        {
+            dynamic_globals.get_or_insert(line.line_number);
+        }
+    }
+
+    let Collected {
+        mut items,
+        classes,
+        errors: class_errors,
+    } = namespaces.collect(python_classes);
+    let mut errors = Vec::new();
+    for (err, first, last) in class_errors {
+        errors.push(err.locate(source, path, first, last));
+    }
+    if let Some(line) = dynamic_globals {
+        errors.push(
+            ParseError::new(
+                "the module defines names through globals(), which rytest cannot follow statically",
+            )
+            .about("globals()")
+            .locate(source, path, line, line),
+        );
+    }
+
+    // Classes also have the fixtures and hooks of their bases, which their own override
+    let class_marks: HashMap<Vec<String>, Vec<Mark>> = classes
+        .iter()
+        .map(|class| (class.chain.clone(), class.marks.clone()))
+        .collect();
+    let defined = std::mem::take(&mut fixtures);
+    fixtures.extend(
+        defined
+            .iter()
+            .filter(|f| f.classes.is_empty() || class_marks.contains_key(&f.classes))
+            .cloned(),
+    );
+    for class in &classes {
+        let inherited: Vec<Fixture> = class.mro[1..]
+            .iter()
+            .rev()
+            .flat_map(|base| defined.iter().filter(move |f| &f.classes == base))
+            .map(|f| Fixture {
+                classes: class.chain.clone(),
+                ..f.clone()
+            })
+            .collect();
+        let at = fixtures
+            .iter()
+            .position(|f| f.classes == class.chain)
+            .unwrap_or(fixtures.len());
+        fixtures.splice(at..at, inherited);
+        if class.mro[1..]
+            .iter()
+            .any(|base| generate_tests.contains(base))
+            && !generate_tests.contains(&class.chain)
+        {
+            generate_tests.push(class.chain.clone());
+        }
+    }
+    generate_tests.retain(|chain| chain.is_empty() || class_marks.contains_key(chain));
+
+    // Marks apply from the definition outwards: own, enclosing classes, module
+    items.retain_mut(|item| {
+        item.own_marks = item.marks.len();
+        for depth in (1..=item.classes.len()).rev() {
+            if let Some(marks) = class_marks.get(&item.classes[..depth]) {
+                item.marks.extend(marks.iter().cloned());
+            }
+        }
+        item.marks.extend(module_marks.iter().cloned());
+        if item.kind == TestKind::Class {
+            return true;
+        }
+        let parameters = item
+            .marks
+            .iter()
+            .filter(|mark| mark.name == "parametrize")
+            .map(Parameters::from_mark)
+            .collect::<Result<_, _>>();
+        match parameters {
+            Ok(parameters) => {
+                item.parameters = parameters;
+                true
+            }
+            Err(err) => {
+                // A class's parametrize mark fails for each of its tests
+                let err = err.locate(source, path, item.first_line, item.line_number);
+                if !errors.contains(&err) {
+                    errors.push(err);
+                }
+                false
+            }
+        }
+    });
+
+    ParsedModule {
+        items,
+        fixtures,
+        generate_tests,
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{FixtureParams, FixtureScope};
+
+    fn patterns(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn parse(source: &str) -> Vec<TestItem> {
+        parse_source(
+            source,
+            "test_mod.py",
+            &patterns(&["Test"]),
+            &patterns(&["test"]),
+        )
+        .unwrap()
+    }
+
+    fn mark(line: &str) -> Mark {
+        parse_mark(line).unwrap()
+    }
+
+    #[test]
+    fn test_pattern_matching() {
+        let patterns = patterns(&["test_*.py", "*_test.py"]);
+        assert!(matches_pattern("test_example.py", &patterns));
+        assert!(matches_pattern("example_test.py", &patterns));
+        assert!(!matches_pattern("example.py", &patterns));
+
+        let class_patterns = vec!["Test".to_string()];
+        assert!(matches_pattern("TestExample", &class_patterns));
+        assert!(!matches_pattern("Example", &class_patterns));
+    }
+
+    #[test]
+    fn test_logical_lines() {
+        let source = "@pytest.mark.parametrize(\n    \"x\",\n    [1, 2],  # comment\n)\ndef test_a(x):\n    \"\"\"Docstring\n    def test_fake(): pass\n    \"\"\"\n";
+        let lines = logical_lines(source);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].line_number, 1);
+        assert_eq!(lines[1].text, "def test_a(x):");
+        assert_eq!(lines[1].line_number, 5);
+        assert_eq!(lines[2].indent, 4);
+        assert!(lines[2].text.starts_with("\"\"\"Docstring"));
+
+        // Backslash continuations with Windows line endings, in and out of strings
+        let source = "x = 1 + \\\r\n    2\r\ny = 'a\\\r\nb'\r\ndef test_a():\r\n    pass\r\n";
+        let lines = logical_lines(source);
+        let texts: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec!["x = 1 +      2", "y = 'a\\\r\nb'", "def test_a():", "pass"]
+        );
+        let numbers: Vec<usize> = lines.iter().map(|l| l.line_number).collect();
+        assert_eq!(numbers, vec![1, 3, 5, 6]);
+    }
+
+    #[test]
+    fn test_parametrize_parsing() {
+        let params =
+            Parameters::from_mark(&mark(r#"@pytest.mark.parametrize("value", [1, 2, 3])"#))
+                .unwrap();
+        assert_eq!(params.argnames, vec!["value"]);
+        assert_eq!(params.argvalues.len(), 3);
+        assert_eq!(params.set_ids(), vec!["1", "2", "3"]);
+
+        let params = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize(("x", "y"), [(1, 2), (3, 4)])"#,
+        ))
+        .unwrap();
+        assert_eq!(params.argnames, vec!["x", "y"]);
+        assert_eq!(
+            params.argvalues,
+            vec![
+                vec![Literal::Int(1), Literal::Int(2)],
+                vec![Literal::Int(3), Literal::Int(4)]
+            ]
+        );
+        assert_eq!(params.set_ids(), vec!["1-2", "3-4"]);
+
+        let params = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize("value", [1, 2, 3], ids=["a", "b", "c"])"#,
+        ))
+        .unwrap();
+        assert_eq!(params.set_ids(), vec!["a", "b", "c"]);
+
+        let params = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize("value", [1, 2, 3, 4], ids=[10, None, 2.5, "é"])"#,
+        ))
+        .unwrap();
+        assert_eq!(params.set_ids(), vec!["10", "2", "2.5", "\\xe9"]);
+
+        for ids in ["lambda v: str(v)", "idfn", "[(1,), 'b', 'c']", "['a', 'b']"] {
+            let src = format!(
+                r#"@pytest.mark.parametrize("value", [1, 2, 3], ids={})"#,
+                ids
+            );
+            let error = Parameters::from_mark(&mark(&src)).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Parametrize, "{}", ids);
+        }
+
+        let params = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize("a,b", [pytest.param(1, "x", id="first"), (2.5, None), ([1], "x")])"#,
+        ))
+        .unwrap();
+        assert_eq!(params.set_ids(), vec!["first", "2.5-None", "a2-x"]);
+
+        let params =
+            Parameters::from_mark(&mark(r#"@pytest.mark.parametrize("x", range(1, 4))"#)).unwrap();
+        assert_eq!(params.set_ids(), vec!["1", "2", "3"]);
+
+        let params =
+            Parameters::from_mark(&mark(r#"@pytest.mark.parametrize("x", [1, 1, "a", "a"])"#))
+                .unwrap();
+        assert_eq!(params.set_ids(), vec!["1_0", "1_1", "a0", "a1"]);
+
+        let params = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize("x,y", [(f"in_{i}", i) for i in range(2)], indirect=["x"])"#,
+        ))
+        .unwrap();
+        assert_eq!(params.set_ids(), vec!["in_0-0", "in_1-1"]);
+        assert_eq!(params.indirect, vec!["x"]);
+        assert_eq!(params.scope, None);
+    }
+
+    #[test]
+    fn test_param_marks() {
+        let params = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize("x", [pytest.param(1, marks=pytest.mark.slow), 2, pytest.param(3, marks=(pytest.mark.a, pytest.mark.skipif(True, reason="r")), id="three")])"#,
+        ))
+        .unwrap();
+        let names = |index: usize| -> Vec<&str> {
+            params
+                .set_marks(index)
+                .iter()
+                .map(|m| m.name.as_str())
+                .collect()
+        };
+        assert_eq!(names(0), vec!["slow"]);
+        assert!(names(1).is_empty());
+        assert_eq!(names(2), vec!["a", "skipif"]);
+        assert_eq!(params.set_ids(), vec!["1", "2", "three"]);
+
+        // Marks that are not `pytest.mark` expressions cannot be evaluated
+        let err = Parameters::from_mark(&mark(
+            r#"@pytest.mark.parametrize("x", [pytest.param(1, marks=SLOW)])"#,
+        ))
+        .unwrap_err();
+        assert!(err
+            .message
+            .contains("marks SLOW cannot be evaluated statically"));
+
+        // Each generated test has the test's own marks, its parameter sets', then the module's
+        let items = parse(
+            r#"
+import pytest
+
+pytestmark = pytest.mark.unit
+
+@pytest.mark.parametrize("y", [pytest.param(0, marks=pytest.mark.b), 1])
+@pytest.mark.parametrize("x", [pytest.param(0, marks=[pytest.mark.a])])
+def test_xy(x, y):
+    pass
+"#,
+        );
+        let calls = items[0].expand();
+        let names: Vec<Vec<String>> = calls
+            .iter()
+            .map(|call| {
+                items[0]
+                    .call_marks(call)
+                    .into_iter()
+                    .map(|m| m.name)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                vec!["parametrize", "parametrize", "a", "b", "unit"],
+                vec!["parametrize", "parametrize", "a", "unit"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_classes_and_scopes() {
+        let items = parse(
+            r#"
+import pytest
+
+pytestmark = [pytest.mark.slow]
+
+def helper():
+    def test_nested():
+        pass
+
+@pytest.mark.smoke
+class TestOuter:
+    def test_method(self):
+        pass
+
+    class TestInner:
+        @pytest.mark.parametrize("x", [1, 2])
+        def test_inner(self, x):
+            pass
+
+class Helper:
+    def test_ignored(self):
+        pass
+
+async def test_after():
+    pass
+"#,
+        );
+        let names: Vec<String> = items
+            .iter()
+            .flat_map(|item| match item.kind {
+                TestKind::Class => Vec::new(),
+                _ => item
+                    .expand()
+                    .into_iter()
+                    .map(|call| format!("{}::{}", item.classes.join("::"), call.name))
+                    .collect(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "TestOuter::test_method",
+                "TestOuter::TestInner::test_inner[1]",
+                "TestOuter::TestInner::test_inner[2]",
+                "::test_after",
+            ]
+        );
+
+        let inner = items.iter().find(|i| i.name == "test_inner").unwrap();
+        let mark_names: Vec<&str> = inner.marks.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(mark_names, vec!["parametrize", "smoke", "slow"]);
+        assert_eq!(inner.kind, TestKind::Method);
+        assert_eq!(inner.line_number, 17);
+        assert_eq!(inner.first_line, 16);
+    }
+
+    #[test]
+    fn test_class_namespaces() {
+        let source = r#"import pytest
+
+class Base:
+    pytestmark = [pytest.mark.base]
+
+    @pytest.fixture
+    def value(self):
+        return 1
+
+    def test_shared(self, value):
+        pass
+
+    def test_overridden(self):
+        pass
+
+@pytest.mark.level
+class TestBase(Base):
+    def test_base(self):
+        pass
+
+class TestLevel1(TestBase, object):
+    def test_overridden(self):
+        pass
+
+if FAST:
+    def test_twice():
+        pass
+else:
+    @pytest.mark.slow
+    def test_twice():
+        pass
+
+def test_after():
+    pass
+"#;
+        let parsed = parse_module(
+            source,
+            "test_mod.py",
+            &patterns(&["Test"]),
+            &patterns(&["test"]),
+        )
+        .unwrap();
+        let names: Vec<String> = parsed
+            .items
+            .iter()
+            .filter(|item| item.kind != TestKind::Class)
+            .map(|item| format!("{}::{}", item.classes.join("::"), item.name))
+            .collect();
+        // Inherited tests come first, and the most derived definition wins
+        assert_eq!(
+            names,
+            vec![
+                "TestBase::test_shared",
+                "TestBase::test_overridden",
+                "TestBase::test_base",
+                "TestLevel1::test_shared",
+                "TestLevel1::test_base",
+                "TestLevel1::test_overridden",
+                "::test_twice",
+                "::test_after",
+            ]
+        );
+        let find = |classes: &[&str], name: &str| {
+            parsed
+                .items
+                .iter()
+                .find(|item| item.classes == classes && item.name == name)
+                .unwrap()
+        };
+        assert_eq!(find(&["TestLevel1"], "test_overridden").line_number, 22);
+        let twice = find(&[], "test_twice");
+        assert_eq!(twice.line_number, 30);
+        assert_eq!(twice.marks[0].name, "slow");
+
+        // Marks and fixtures of the bases apply to the tests of a subclass
+        let shared = find(&["TestLevel1"], "test_shared");
+        assert_eq!(shared.line_number, 10);
+        let mark_names: Vec<&str> = shared.marks.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(mark_names, vec!["base", "level"]);
+        let fixture_classes: Vec<&[String]> = parsed
+            .fixtures
+            .iter()
+            .map(|f| f.classes.as_slice())
+            .collect();
+        assert_eq!(
+            fixture_classes,
+            vec![vec!["TestBase".to_string()], vec!["TestLevel1".to_string()]]
+        );
+
+        // A base the module does not define hands the class back to pytest
+        let source = "from base import Base\n\nclass TestA(Base):\n    def test_a(self):\n        pass\n\ndef test_b():\n    pass\n";
+        let parsed = parse_module_partial(
+            source,
+            "test_mod.py",
+            &patterns(&["Test"]),
+            &patterns(&["test"]),
+        );
+        let names: Vec<&str> = parsed.items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["test_b"]);
+        assert_eq!(parsed.errors[0].kind, ErrorKind::Inheritance);
+        assert_eq!(
+            parsed.errors[0].to_string(),
+            "test_mod.py:3:13: base class Base of TestA is not a class defined in the module"
+        );
+
+        let source = "for i in range(3):\n    globals()[f'test_{i}'] = make(i)\n";
+        let err = parse_module(
+            source,
+            "test_mod.py",
+            &patterns(&["Test"]),
+            &patterns(&["test"]),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("globals()"));
+    }
+
+    #[test]
+    fn test_generate_tests_hooks() {
+        let source = r#"
+from helpers import (
+    other,
+    make_hook as pytest_generate_tests,
+)
+
+class TestA:
+    def pytest_generate_tests(self, metafunc):
+        pass
+
+    class TestInner:
+        pytest_generate_tests = staticmethod(hook)
+
+class Helper:
+    def pytest_generate_tests(self, metafunc):
+        pass
+
+def test_b():
+    pytest_generate_tests = None
+"#;
+        let parsed = parse_module(
+            source,
+            "test_mod.py",
+            &patterns(&["Test"]),
+            &patterns(&["test"]),
+        )
+        .unwrap();
+        assert_eq!(
+            parsed.generate_tests,
+            vec![
+                vec![],
+                vec!["TestA".to_string()],
+                vec!["TestA".to_string(), "TestInner".to_string()],
+            ]
+        );
+        assert!(binds_generate_tests("import pytest_generate_tests"));
+        assert!(!binds_generate_tests("pytest_generate_tests == x"));
+        assert!(!binds_generate_tests(
+            "from m import pytest_generate_tests as hook"
+        ));
+    }
+
+    #[test]
+    fn test_fixture_definitions() {
+        let module = parse_module(
+            r#"
+import pytest
+
+@pytest.fixture(scope="module", params=["a", "b"], name="letter")
+def letter_fixture(request):
+    return request.param
+
+@pytest.fixture
+def test_looks_like_a_test():
+    pass
+
+class TestThing:
+    @pytest.fixture(autouse=True)
+    def setup(self):
+        pass
+
+    def test_it(self, letter):
+        pass
+
+class Helper:
+    @pytest.fixture
+    def ignored(self):
+        pass
+"#,
+            "test_mod.py",
+            &patterns(&["Test"]),
+            &patterns(&["test"]),
+        )
+        .unwrap();
+
+        let names: Vec<&str> = module.items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["TestThing", "test_it"]);
+
+        let fixtures: Vec<(&str, &str, usize, Option<FixtureScope>, bool)> = module
+            .fixtures
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.function.as_str(),
+                    f.line_number,
+                    f.scope,
+                    f.autouse,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fixtures,
+            vec![
+                (
+                    "letter",
+                    "letter_fixture",
+                    4,
+                    Some(FixtureScope::Module),
+                    false
+                ),
+                (
+                    "test_looks_like_a_test",
+                    "test_looks_like_a_test",
+                    8,
+                    Some(FixtureScope::Function),
+                    false
+                ),
+                ("setup", "setup", 13, Some(FixtureScope::Function), true),
+            ]
+        );
+        assert_eq!(module.fixtures[2].classes, vec!["TestThing"]);
+        assert_eq!(module.fixtures[0].argnames, vec!["request"]);
+        assert!(module.fixtures[2].argnames.is_empty());
+        assert_eq!(module.items[1].argnames, vec!["letter"]);
+        assert_eq!(
+            def_argnames(
+                "def f(a, /, b, c=1, *args, d, e: int = 2, **kw) -> None:",
+                false
+            ),
+            vec!["b", "d"]
+        );
+        assert_eq!(def_argnames("def test(self, /, x):", true), vec!["x"]);
+        assert_eq!(def_argnames("def test(self, x, /, y):", true), vec!["y"]);
+        assert_eq!(def_argnames("def test(cls, x):", true), vec!["x"]);
+        assert_eq!(def_argnames("def test(*args, x):", true), vec!["x"]);
+        assert_eq!(
+            def_positional("def f(self, a, /, b, c=1, *args, d, **kw):", true),
+            vec!["b", "c"]
+        );
+        assert_eq!(
+            module.fixtures[0].params,
+            Some(FixtureParams::Values(vec![
+                Literal::Str("a".into()),
+                Literal::Str("b".into())
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_stacked_parametrize() {
+        let items = parse(
+            r#"
+@pytest.mark.parametrize("x", [1, 2])
+@pytest.mark.parametrize("y", ["a", "b"])
+def test_matrix(x, y):
+    pass
+"#,
+        );
+        let names: Vec<String> = items[0].expand().into_iter().map(|c| c.name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "test_matrix[a-1]",
+                "test_matrix[a-2]",
+                "test_matrix[b-1]",
+                "test_matrix[b-2]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hypothesis_tests() {
+        let items = parse(
+            r#"
+from hypothesis import example, given, settings, strategies as st
+
+@settings(max_examples=25)
+@given(n=st.integers(), label=st.text())
+@example(0, label="zero")
+def test_property(tmp_path, n, label):
+    pass
+
+class TestProperties:
+    @given(st.booleans())
+    def test_method(self, flag):
+        pass
+
+@settings(max_examples=5)
+def test_plain(tmp_path):
+    pass
+"#,
+        );
+        assert_eq!(items[0].argnames, vec!["tmp_path"]);
+        assert_eq!(
+            items[0].hypothesis,
+            Some(Hypothesis {
+                given: vec!["n".into(), "label".into()],
+                examples: 1,
+                max_examples: Some(25),
+            })
+        );
+        assert!(items[0].marks.is_empty());
+        assert!(items[2].argnames.is_empty());
+        assert_eq!(items[2].hypothesis.as_ref().unwrap().given, vec!["flag"]);
+        assert_eq!(items[3].argnames, vec!["tmp_path"]);
+        assert_eq!(items[3].hypothesis, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let source = r#"import pytest
+
+@pytest.mark.parametrize("x", make_values())
+def test_dynamic(x):
+    pass
+
+class TestRows:
+	@pytest.mark.parametrize(
+	    "x, y",
+	    [(1, 2), (3,)],
+	)
+	def test_rows(self, x, y):
+	    pass
+
+def test_plain():
+    pass
+"#;
+        let classes = patterns(&["Test"]);
+        let functions = patterns(&["test"]);
+        let err = parse_module(source, "tests/test_a.py", &classes, &functions).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Parametrize);
+        assert_eq!(
+            err.to_string(),
+            "tests/test_a.py:3:31: Invalid parametrize decorator: make_values() cannot be \
+             evaluated statically (make_values() cannot be evaluated statically at offset 0)"
+        );
+        assert_eq!(
+            err.caret().unwrap(),
+            "    @pytest.mark.parametrize(\"x\", make_values())\n                                  ^^^^^^^^^^^^^"
+        );
+
+        // Other tests are still collected, and each error recorded
+        let parsed = parse_module_partial(source, "tests/test_a.py", &classes, &functions);
+        let names: Vec<&str> = parsed.items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["TestRows", "test_plain"]);
+        assert_eq!(parsed.errors.len(), 2);
+        let span = parsed.errors[1].span.as_ref().unwrap();
+        assert_eq!((span.line, span.column, span.width), (10, 15, 4));
+        assert_eq!(
+            parsed.errors[1].caret().unwrap(),
+            "    [(1, 2), (3,)],\n             ^^^^"
+        );
+
+        // Without a fragment to find, the error points at the first line
+        let err = ParseError::new("broken").locate(source, "tests/test_a.py", 15, 15);
+        assert_eq!(
+            err.render(),
+            "tests/test_a.py:15:1: broken\n    def test_plain():\n    ^^^^^^^^^^^^^^^^^"
+        );
+        assert_eq!(ParseError::new("unlocated").render(), "unlocated");
+
+        // A blank or whitespace-only line still gets a single caret
+        let source = "def test_a():\n    \t\n\n    pass\n";
+        let span = Span::line(source, "tests/test_a.py", 2).unwrap();
+        assert_eq!((span.column, span.width), (6, 1));
+        let err = ParseError::new("blank").locate(source, "tests/test_a.py", 2, 2);
+        assert_eq!(err.render(), "tests/test_a.py:2:6: blank\n    \n    ^");
+        let span = Span::line(source, "tests/test_a.py", 3).unwrap();
+        assert_eq!((span.column, span.width), (1, 1));
+        assert_eq!(Span::line(source, "tests/test_a.py", 5), None);
+    }
+}
@@ -0,0 +1,424 @@
+//! Timing collection phase by phase, for `rytest collect --profile` and the
+//! plugin's `--rytest-profile`.
+//!
+//! Recording is per thread: [`start`] begins it and [`finish`] hands over the
+//! [`Profile`]. In between, [`time`] and [`timer`] record how long each phase
+//! takes, so code deep in the parser is timed without passing a profile
+//! around. When no profile is being recorded, they only check a thread-local.
+//!
+//! Phases nest: parsing a module evaluates literals, and the plugin's hooks
+//! read, parse and create nodes. The report counts each phase's self time,
+//! leaving out the phases nested in it, so the phases add up to the total.
+
+use crate::walk;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A part of collection that is timed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Phase {
+    /// Finding the files to collect
+    Walk,
+    /// Reading and decoding files
+    Read,
+    /// Parsing modules and resolving their fixtures
+    Parse,
+    /// Evaluating parameter literals
+    Literal,
+    /// Creating pytest nodes
+    Nodes,
+    /// The plugin's collection hooks, besides the phases above
+    Hooks,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::Walk,
+        Phase::Read,
+        Phase::Parse,
+        Phase::Literal,
+        Phase::Nodes,
+        Phase::Hooks,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Walk => "walk",
+            Phase::Read => "read",
+            Phase::Parse => "parse",
+            Phase::Literal => "literal eval",
+            Phase::Nodes => "node creation",
+            Phase::Hooks => "hook calls",
+        }
+    }
+}
+
+/// One timed phase
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub phase: Phase,
+    /// The file the phase worked on, if it is about one
+    pub file: Option<String>,
+    /// When it started, since recording started
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+impl Event {
+    fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+/// The phases recorded on one thread
+#[derive(Debug, Clone)]
+pub struct Profile {
+    origin: Instant,
+    /// Events in the order they ended
+    pub events: Vec<Event>,
+    /// From [`start`] to [`finish`]
+    pub total: Duration,
+}
+
+/// A phase's share of a profile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhaseTime {
+    pub phase: Phase,
+    pub time: Duration,
+    pub calls: usize,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Profile>> = const { RefCell::new(None) };
+}
+
+/// Start recording a profile on this thread, discarding any being recorded
+pub fn start() {
+    ACTIVE.with(|active| {
+        *active.borrow_mut() = Some(Profile {
+            origin: Instant::now(),
+            events: Vec::new(),
+            total: Duration::ZERO,
+        })
+    });
+}
+
+/// Stop recording on this thread, returning the profile if one was recorded
+pub fn finish() -> Option<Profile> {
+    let mut profile = ACTIVE.with(|active| active.borrow_mut().take())?;
+    profile.total = profile.origin.elapsed();
+    Some(profile)
+}
+
+/// Whether a profile is being recorded on this thread
+pub fn is_active() -> bool {
+    ACTIVE.with(|active| active.borrow().is_some())
+}
+
+/// Records a phase when dropped, from [`timer`]
+#[must_use]
+pub struct Timer {
+    phase: Phase,
+    /// The file and start, unless no profile is being recorded
+    started: Option<(Option<String>, Instant)>,
+}
+
+/// Time `phase` on `file` until the returned timer is dropped, if a profile
+/// is being recorded
+pub fn timer(phase: Phase, file: Option<&Path>) -> Timer {
+    Timer {
+        phase,
+        started: is_active().then(|| {
+            (
+                file.map(|path| path.to_string_lossy().into_owned()),
+                Instant::now(),
+            )
+        }),
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let Some((file, start)) = self.started.take() else {
+            return;
+        };
+        let duration = start.elapsed();
+        ACTIVE.with(|active| {
+            if let Some(profile) = active.borrow_mut().as_mut() {
+                profile.events.push(Event {
+                    phase: self.phase,
+                    file,
+                    start: start.saturating_duration_since(profile.origin),
+                    duration,
+                });
+            }
+        });
+    }
+}
+
+/// Run `f`, recording it as `phase` on `file` if a profile is being recorded
+pub fn time<T>(phase: Phase, file: Option<&Path>, f: impl FnOnce() -> T) -> T {
+    let _timer = timer(phase, file);
+    f()
+}
+
+impl Profile {
+    /// A profile of `events`, for reports of profiles recorded elsewhere
+    pub fn from_events(events: Vec<Event>, total: Duration) -> Self {
+        Profile {
+            origin: Instant::now(),
+            events,
+            total,
+        }
+    }
+
+    /// Each event's self time, and its file or else that of the event it is nested in
+    fn attributed(&self) -> Vec<(&Event, Duration, Option<&str>)> {
+        let mut order: Vec<usize> = (0..self.events.len()).collect();
+        // Outer events first when they start together
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&self.events[a], &self.events[b]);
+            a.start.cmp(&b.start).then(b.duration.cmp(&a.duration))
+        });
+        let mut own: Vec<Duration> = self.events.iter().map(|e| e.duration).collect();
+        let mut files: Vec<Option<&str>> = self.events.iter().map(|e| e.file.as_deref()).collect();
+        let mut open: Vec<usize> = Vec::new();
+        for i in order {
+            let event = &self.events[i];
+            while open
+                .last()
+                .is_some_and(|&outer| self.events[outer].end() <= event.start)
+            {
+                open.pop();
+            }
+            if let Some(&outer) = open.last() {
+                own[outer] = own[outer].saturating_sub(event.duration);
+                files[i] = files[i].or(files[outer]);
+            }
+            open.push(i);
+        }
+        self.events
+            .iter()
+            .zip(own)
+            .zip(files)
+            .map(|((event, own), file)| (event, own, file))
+            .collect()
+    }
+
+    /// The self time and calls of each phase that ran
+    pub fn phases(&self) -> Vec<PhaseTime> {
+        let mut phases: HashMap<Phase, PhaseTime> = HashMap::new();
+        for (event, own, _) in self.attributed() {
+            let entry = phases.entry(event.phase).or_insert(PhaseTime {
+                phase: event.phase,
+                time: Duration::ZERO,
+                calls: 0,
+            });
+            entry.time += own;
+            entry.calls += 1;
+        }
+        Phase::ALL
+            .iter()
+            .filter_map(|phase| phases.remove(phase))
+            .collect()
+    }
+
+    /// The `top` files collection spent the most time on, slowest first
+    pub fn slowest_files(&self, top: usize) -> Vec<(String, Duration)> {
+        let mut files: HashMap<&str, Duration> = HashMap::new();
+        for (_, own, file) in self.attributed() {
+            if let Some(file) = file {
+                *files.entry(file).or_default() += own;
+            }
+        }
+        let mut files: Vec<(String, Duration)> = files
+            .into_iter()
+            .map(|(file, time)| (file.to_string(), time))
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        files.truncate(top);
+        files
+    }
+
+    /// The phase breakdown and the `top` slowest files, with paths relative to `rootdir`
+    pub fn report(&self, top: usize, rootdir: &Path) -> Vec<String> {
+        let millis = |time: Duration| time.as_secs_f64() * 1e3;
+        let total = self.total.as_secs_f64();
+        let percent = |time: Duration| {
+            if total > 0.0 {
+                100.0 * time.as_secs_f64() / total
+            } else {
+                0.0
+            }
+        };
+        let mut lines = vec![format!(
+            "{:<14} {:>9} {:>7} {:>7}",
+            "phase", "ms", "%", "calls"
+        )];
+        let mut timed = Duration::ZERO;
+        for phase in self.phases() {
+            timed += phase.time;
+            lines.push(format!(
+                "{:<14} {:>9.1} {:>6.1}% {:>7}",
+                phase.phase.as_str(),
+                millis(phase.time),
+                percent(phase.time),
+                phase.calls
+            ));
+        }
+        let untimed = self.total.saturating_sub(timed);
+        lines.push(format!(
+            "{:<14} {:>9.1} {:>6.1}%",
+            "other",
+            millis(untimed),
+            percent(untimed)
+        ));
+        lines.push(format!("{:<14} {:>9.1}", "total", millis(self.total)));
+
+        let files = self.slowest_files(top);
+        if !files.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("slowest {} files:", files.len()));
+            for (file, time) in files {
+                lines.push(format!(
+                    "{:>9.1}ms  {}",
+                    millis(time),
+                    walk::relative_nodeid_path(Path::new(&file), rootdir)
+                ));
+            }
+        }
+        lines
+    }
+
+    /// The profile in the Chrome trace event format, for `chrome://tracing` or Perfetto
+    pub fn to_chrome_trace(&self) -> Value {
+        let micros = |d: Duration| d.as_secs_f64() * 1e6;
+        let mut events: Vec<&Event> = self.events.iter().collect();
+        events.sort_by(|a, b| a.start.cmp(&b.start).then(b.duration.cmp(&a.duration)));
+        let pid = std::process::id();
+        let mut trace = vec![json!({
+            "name": "process_name",
+            "ph": "M",
+            "pid": pid,
+            "tid": 1,
+            "args": {"name": "rytest collection"},
+        })];
+        trace.extend(events.into_iter().map(|event| {
+            let mut args = serde_json::Map::new();
+            if let Some(file) = &event.file {
+                args.insert("file".to_string(), json!(file));
+            }
+            json!({
+                "name": event.phase.as_str(),
+                "cat": "rytest",
+                "ph": "X",
+                "ts": micros(event.start),
+                "dur": micros(event.duration),
+                "pid": pid,
+                "tid": 1,
+                "args": args,
+            })
+        }));
+        json!({"traceEvents": trace, "displayTimeUnit": "ms"})
+    }
+
+    /// Write the Chrome trace to `path`
+    pub fn write_trace(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, format!("{:#}\n", self.to_chrome_trace()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(phase: Phase, file: Option<&str>, start: u64, duration: u64) -> Event {
+        Event {
+            phase,
+            file: file.map(String::from),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(duration),
+        }
+    }
+
+    #[test]
+    fn test_profile_report() {
+        let profile = Profile::from_events(
+            vec![
+                event(Phase::Literal, None, 12, 3),
+                event(Phase::Parse, Some("/root/tests/test_a.py"), 10, 8),
+                event(Phase::Read, Some("/root/tests/test_a.py"), 2, 8),
+                event(Phase::Hooks, Some("/root/tests/test_a.py"), 0, 20),
+                event(Phase::Parse, Some("/root/tests/test_b.py"), 20, 5),
+                event(Phase::Walk, None, 20, 30),
+            ],
+            Duration::from_millis(60),
+        );
+        let phases: Vec<(Phase, u128, usize)> = profile
+            .phases()
+            .iter()
+            .map(|p| (p.phase, p.time.as_millis(), p.calls))
+            .collect();
+        assert_eq!(
+            phases,
+            vec![
+                (Phase::Walk, 25, 1),
+                (Phase::Read, 8, 1),
+                (Phase::Parse, 10, 2),
+                (Phase::Literal, 3, 1),
+                (Phase::Hooks, 4, 1),
+            ]
+        );
+        // The literal evaluated while parsing test_a.py counts towards it
+        let files: Vec<(String, u128)> = profile
+            .slowest_files(5)
+            .into_iter()
+            .map(|(file, time)| (file, time.as_millis()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("/root/tests/test_a.py".to_string(), 20),
+                ("/root/tests/test_b.py".to_string(), 5),
+            ]
+        );
+
+        let report = profile.report(1, Path::new("/root"));
+        assert_eq!(report[1], "walk                25.0   41.7%       1");
+        assert_eq!(report[6], "other               10.0   16.7%");
+        assert_eq!(report[report.len() - 1], "     20.0ms  tests/test_a.py");
+    }
+
+    #[test]
+    fn test_time() {
+        assert!(!is_active());
+        assert_eq!(time(Phase::Parse, None, || 1), 1);
+        assert!(finish().is_none());
+
+        start();
+        let path = Path::new("test_a.py");
+        let n = time(Phase::Parse, Some(path), || {
+            time(Phase::Literal, None, || 2) + 1
+        });
+        assert_eq!(n, 3);
+        let profile = finish().unwrap();
+        assert!(!is_active());
+        let phases: Vec<Phase> = profile.events.iter().map(|e| e.phase).collect();
+        assert_eq!(phases, vec![Phase::Literal, Phase::Parse]);
+        assert!(profile.events[1].duration >= profile.events[0].duration);
+        assert!(profile.total >= profile.events[1].duration);
+
+        let trace = profile.to_chrome_trace();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1]["name"], "parse");
+        assert_eq!(events[1]["ph"], "X");
+        assert_eq!(events[1]["args"]["file"], "test_a.py");
+        assert_eq!(events[2]["name"], "literal eval");
+    }
+}
@@ -0,0 +1,1759 @@
+//! PyO3 bindings exposing the collector to the pytest plugin.
+#![allow(non_local_definitions)]
+
+use crate::cache;
+use crate::config::{ConfigError, IniConfig};
+#[cfg(target_os = "linux")]
+use crate::daemon::Client;
+use crate::doctest;
+use crate::fixtures::{self, FixtureParams};
+use crate::impact::Impact;
+use crate::index::{self, ModuleIndex};
+use crate::lint;
+use crate::literal::Literal;
+use crate::manifest::{Manifest, ManifestNode, NodeKind};
+use crate::parser::{
+    CallParam, CallSpec, ErrorKind, Mark, ParseError, ParsedModule, TestItem, TestKind,
+};
+use crate::profile::{self, Phase};
+use crate::session;
+use crate::shard::{self, Durations, Granularity};
+use crate::skipping::{self, Target};
+use crate::source;
+use crate::verify::{self, Difference, Mismatch};
+use crate::walk;
+use pyo3::prelude::*;
+use pyo3::sync::GILOnceCell;
+use pyo3::types::{PyBytes, PyComplex, PyDict, PyList, PySet, PyTuple};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A Python module implemented in Rust for faster pytest collection
+#[pymodule]
+fn rytest_core(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Collector>()?;
+    Ok(())
+}
+
+/// `(nodeid, line, [(argname, repr(value))], [mark names])` of a test pytest collected
+type PytestItem = (String, Option<usize>, Vec<(String, String)>, Vec<String>);
+
+#[pyclass]
+struct Collector {
+    python_classes: Vec<String>,
+    python_functions: Vec<String>,
+    /// The interpreter `skipif` and `xfail` conditions are evaluated for
+    target: Target,
+    /// Everything collected so far, for `--rytest-json`
+    manifest: Mutex<Manifest>,
+    /// Per directory, why its conftest files require pytest's own collection
+    conftest_fallbacks: Mutex<HashMap<PathBuf, Option<String>>>,
+    /// The files a change affects, for `--rytest-changed-since`
+    impact: Mutex<Option<Impact>>,
+    /// A connection to `rytest daemon`, dropped if it stops answering
+    #[cfg(target_os = "linux")]
+    daemon: Mutex<Option<Client>>,
+    /// The modules the xdist controller parsed, on a worker
+    shared: Mutex<Option<SharedCollection>>,
+    /// What to do with modules rytest cannot collect, from `--rytest-errors`
+    errors: ErrorMode,
+    /// The pytest classes nodes are built from, once first needed
+    symbols: GILOnceCell<Arc<PytestSymbols>>,
+}
+
+/// What an xdist worker takes from its controller, and what it collects
+struct SharedCollection {
+    index: ModuleIndex,
+    /// The controller's collection hash
+    hash: String,
+    config: IniConfig,
+    /// The node ids collected from each test module, or the module's own for
+    /// one rytest did not collect whole, as the index lists them
+    collected: BTreeMap<PathBuf, Vec<String>>,
+}
+
+/// What the plugin does with a module rytest cannot collect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ErrorMode {
+    /// Leave it to pytest's own collection
+    #[default]
+    Fallback,
+    /// Report it as a collection error
+    Report,
+    /// Collect its other tests, and warn about each test left out
+    Continue,
+}
+
+impl ErrorMode {
+    fn from_config(config: &PyAny) -> PyResult<Self> {
+        let mode: Option<String> = config
+            .call_method1("getoption", ("rytest_errors", config.py().None()))?
+            .extract()?;
+        Ok(match mode.as_deref() {
+            Some("report") => ErrorMode::Report,
+            Some("continue") => ErrorMode::Continue,
+            _ => ErrorMode::Fallback,
+        })
+    }
+}
+
+/// Conftest hooks that change which nodes a directory or module produces
+///
+/// rytest builds nodes without calling them, so directories governed by a
+/// conftest implementing any of these are left to pytest.
+const COLLECTION_HOOKS: [&str; 6] = [
+    "pytest_collect_directory",
+    "pytest_collect_file",
+    "pytest_ignore_collect",
+    "pytest_pycollect_makemodule",
+    "pytest_pycollect_makeitem",
+    "pytest_generate_tests",
+];
+
+impl From<ParseError> for PyErr {
+    fn from(err: ParseError) -> PyErr {
+        match err.kind {
+            ErrorKind::Read => pyo3::exceptions::PyIOError::new_err(err.to_string()),
+            _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+impl From<ConfigError> for PyErr {
+    fn from(err: ConfigError) -> PyErr {
+        match err {
+            ConfigError::Io(..) => pyo3::exceptions::PyIOError::new_err(err.to_string()),
+            ConfigError::Parse(..) => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// Convert a natively evaluated literal into the Python object it denotes
+fn literal_to_object(py: Python, literal: &Literal) -> PyResult<PyObject> {
+    let items = |values: &[Literal]| -> PyResult<Vec<PyObject>> {
+        values.iter().map(|v| literal_to_object(py, v)).collect()
+    };
+    Ok(match literal {
+        Literal::None => py.None(),
+        Literal::Ellipsis => py.Ellipsis(),
+        Literal::Bool(b) => b.into_py(py),
+        Literal::Int(i) => i.into_py(py),
+        Literal::Float(f) => f.into_py(py),
+        Literal::Complex(re, im) => PyComplex::from_doubles(py, *re, *im).into_py(py),
+        Literal::Str(s) => s.into_py(py),
+        Literal::Bytes(b) => PyBytes::new(py, b).into_py(py),
+        Literal::Tuple(values) => PyTuple::new(py, items(values)?).into_py(py),
+        Literal::List(values) => PyList::new(py, items(values)?).into_py(py),
+        Literal::Set(values) => PySet::new(py, &items(values)?)?.into_py(py),
+        Literal::Dict(pairs) => {
+            let dict = PyDict::new(py);
+            for (key, value) in pairs {
+                dict.set_item(literal_to_object(py, key)?, literal_to_object(py, value)?)?;
+            }
+            dict.into_py(py)
+        }
+    })
+}
+
+/// The pytest classes nodes are built from, looked up once per session
+///
+/// Looking them up per node was a large share of the time spent creating
+/// nodes, so the collector resolves them on first use and keeps them.
+struct PytestSymbols {
+    function: PyObject,
+    class: PyObject,
+    module: PyObject,
+    package: PyObject,
+    /// `pathlib.Path`
+    path: PyObject,
+    /// `_pytest.python.CallSpec2`
+    callspec: PyObject,
+    /// `_pytest.scope.Scope`
+    scope: PyObject,
+    collection_warning: PyObject,
+    /// `pytest.Collector.CollectError`
+    collect_error: PyObject,
+    /// `defer(collector, build)` from [`DEFERRED_COLLECT`]
+    defer: PyObject,
+}
+
+/// Replaces the `collect` of a Module or Class node with one that first does
+/// what pytest's own does before it looks for tests: registering the xunit
+/// setup functions (`_inject_*` before pytest 8) and the fixtures defined
+/// in the collector. It then returns the nodes `build` creates under it, so
+/// every test node is created with the fixtures it can see already known.
+const DEFERRED_COLLECT: &str = r#"
+import pytest
+
+
+def defer(collector, build):
+    is_class = isinstance(collector, pytest.Class)
+    scopes = ("class", "method") if is_class else ("module", "function")
+
+    def collect():
+        for scope in scopes:
+            for prefix in ("_register", "_inject"):
+                setup = getattr(collector, f"{prefix}_setup_{scope}_fixture", None)
+                if setup is not None:
+                    setup()
+                    break
+        fixtures = collector.session._fixturemanager
+        if is_class:
+            fixtures.parsefactories(collector.newinstance(), collector.nodeid)
+        else:
+            fixtures.parsefactories(collector)
+        return build(collector)
+
+    collector.collect = collect
+"#;
+
+/// The node id of a test or class, from its module's node id
+fn test_nodeid(module: &str, classes: &[String], name: &str) -> String {
+    let mut nodeid = module.to_string();
+    for part in classes.iter().map(String::as_str).chain([name]) {
+        nodeid.push_str("::");
+        nodeid.push_str(part);
+    }
+    nodeid
+}
+
+/// The tests rytest found in one module, shared by the collectors of its classes
+struct ModuleTests {
+    items: Vec<TestItem>,
+    /// The module's node id
+    nodeid: String,
+}
+
+impl PytestSymbols {
+    fn load(py: Python) -> PyResult<Self> {
+        let pytest = py.import("pytest")?;
+        let attr = |module: &PyAny, name: &str| -> PyResult<PyObject> {
+            Ok(module.getattr(name)?.into_py(py))
+        };
+        Ok(PytestSymbols {
+            function: attr(pytest, "Function")?,
+            class: attr(pytest, "Class")?,
+            module: attr(pytest, "Module")?,
+            package: attr(pytest, "Package")?,
+            path: attr(py.import("pathlib")?, "Path")?,
+            callspec: attr(py.import("_pytest.python")?, "CallSpec2")?,
+            scope: attr(py.import("_pytest.scope")?, "Scope")?,
+            collection_warning: attr(pytest, "PytestCollectionWarning")?,
+            collect_error: attr(pytest.getattr("Collector")?, "CollectError")?,
+            defer: attr(
+                PyModule::from_code(
+                    py,
+                    DEFERRED_COLLECT,
+                    "rytest_deferred.py",
+                    "rytest_deferred",
+                )?,
+                "defer",
+            )?,
+        })
+    }
+
+    /// The `path` argument of a file or directory node under `parent`
+    fn node_path<'py>(
+        &self,
+        py: Python<'py>,
+        path: &Path,
+        parent: &'py PyAny,
+    ) -> PyResult<&'py PyAny> {
+        if parent.hasattr("path")? {
+            // Relative to the parent's path, as pytest builds them
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            parent.getattr("path")?.call_method1("__truediv__", (name,))
+        } else {
+            Ok(self.path.call1(py, (path,))?.into_ref(py))
+        }
+    }
+
+    /// Create a Package node for a directory
+    fn package_node(&self, py: Python, path: &Path, parent: &PyAny) -> PyResult<PyObject> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("path", self.node_path(py, path, parent)?)?;
+        self.package
+            .call_method(py, "from_parent", (parent,), Some(kwargs))
+    }
+
+    /// Create a Module node for a file
+    fn module_node(&self, py: Python, path: &Path, parent: &PyAny) -> PyResult<PyObject> {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("path", self.node_path(py, path, parent)?)?;
+        self.module
+            .call_method(py, "from_parent", (parent,), Some(kwargs))
+    }
+
+    /// The `parametrize` marks of the test function `item` under `parent`,
+    /// closest first, as the objects the module holds
+    fn parametrize_marks<'py>(
+        &self,
+        item: &TestItem,
+        parent: &'py PyAny,
+    ) -> PyResult<Vec<&'py PyAny>> {
+        let function = parent.getattr("obj")?.getattr(item.name.as_str())?;
+        let mut marks = Vec::new();
+        if let Ok(own) = function.getattr("pytestmark") {
+            for mark in own.iter()? {
+                let mark = mark?;
+                if mark.getattr("name")?.extract::<&str>()? == "parametrize" {
+                    marks.push(mark);
+                }
+            }
+        }
+        for mark in parent
+            .call_method1("iter_markers", ("parametrize",))?
+            .iter()?
+        {
+            marks.push(mark?);
+        }
+        Ok(marks)
+    }
+
+    /// The marks of parameter set `index` of the `parametrize` mark `mark`,
+    /// from the `pytest.param` object it holds
+    fn param_marks(mark: &PyAny, index: usize) -> PyResult<&PyAny> {
+        let args = mark.getattr("args")?;
+        let argvalues = if args.len()? > 1 {
+            args.get_item(1)?
+        } else {
+            mark.getattr("kwargs")?.get_item("argvalues")?
+        };
+        let value = argvalues.iter()?.nth(index).ok_or_else(|| {
+            pyo3::exceptions::PyIndexError::new_err("parameter set index out of range")
+        })??;
+        value.getattr("marks")
+    }
+
+    /// Build the `CallSpec2` pytest would give one generated test
+    ///
+    /// Each parametrization is applied with `setmulti`, as `Metafunc.parametrize`
+    /// does, so indices, scopes and `pytest.param` marks are there for pytest
+    /// to reorder and select tests by.
+    fn callspec<'py>(
+        &self,
+        py: Python<'py>,
+        item: &TestItem,
+        call: &CallSpec,
+        parent: &'py PyAny,
+    ) -> PyResult<&'py PyAny> {
+        let mut callspec = self.callspec.call0(py)?.into_ref(py);
+        let mut params = call.params.iter();
+        // Fixture params are applied first, then the parametrize marks, closest first
+        let from_marks = item
+            .marks
+            .iter()
+            .filter(|mark| mark.name == "parametrize")
+            .count();
+        let first_mark = item.parameters.len().saturating_sub(from_marks);
+        let mut parametrize_marks = None;
+        for (position, (parametrization, id)) in item.parameters.iter().zip(&call.ids).enumerate() {
+            let chunk: Vec<&CallParam> = params
+                .by_ref()
+                .take(parametrization.argnames.len())
+                .collect();
+            let Some(first) = chunk.first() else {
+                continue;
+            };
+            let valset = chunk
+                .iter()
+                .map(|param| literal_to_object(py, &param.value))
+                .collect::<PyResult<Vec<_>>>()?;
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("argnames", &parametrization.argnames)?;
+            kwargs.set_item("valset", valset)?;
+            kwargs.set_item("id", id)?;
+            if parametrization.set_marks(first.index).is_empty() || position < first_mark {
+                kwargs.set_item("marks", PyList::empty(py))?;
+            } else {
+                if parametrize_marks.is_none() {
+                    parametrize_marks = Some(self.parametrize_marks(item, parent)?);
+                }
+                let marks = parametrize_marks.as_deref().unwrap_or_default();
+                let mark = marks.get(position - first_mark).ok_or_else(|| {
+                    pyo3::exceptions::PyValueError::new_err(format!(
+                        "{} has fewer parametrize marks than rytest found",
+                        item.name
+                    ))
+                })?;
+                kwargs.set_item("marks", Self::param_marks(mark, first.index)?)?;
+            }
+            kwargs.set_item(
+                "scope",
+                self.scope.as_ref(py).call1((first.scope.as_str(),))?,
+            )?;
+            kwargs.set_item("param_index", first.index)?;
+            callspec = match callspec.call_method("setmulti", (), Some(kwargs)) {
+                Ok(next) => next,
+                // pytest 7 also asks which values go to fixtures rather than the function
+                Err(err) if err.is_instance_of::<pyo3::exceptions::PyTypeError>(py) => {
+                    let valtypes = PyDict::new(py);
+                    for argname in &parametrization.argnames {
+                        let valtype = if parametrization.indirect.contains(argname) {
+                            "params"
+                        } else {
+                            "funcargs"
+                        };
+                        valtypes.set_item(argname, valtype)?;
+                    }
+                    kwargs.set_item("valtypes", valtypes)?;
+                    callspec.call_method("setmulti", (), Some(kwargs))?
+                }
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(callspec)
+    }
+
+    /// Have pytest collect the tests of the module `module` from `items`
+    ///
+    /// Fails if a test is in a class rytest did not find, rather than creating
+    /// it somewhere else in the tree.
+    fn defer_module(
+        self: &Arc<Self>,
+        py: Python,
+        module: &PyAny,
+        items: Vec<TestItem>,
+        nodeid: String,
+    ) -> PyResult<()> {
+        let classes: std::collections::HashSet<Vec<String>> = items
+            .iter()
+            .filter(|item| item.kind == TestKind::Class)
+            .map(|item| [&item.classes[..], std::slice::from_ref(&item.name)].concat())
+            .collect();
+        if let Some(item) = items
+            .iter()
+            .find(|item| !item.classes.is_empty() && !classes.contains(&item.classes))
+        {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "rytest found {}::{}::{} but not its class",
+                nodeid,
+                item.classes.join("::"),
+                item.name
+            )));
+        }
+        let tests = Arc::new(ModuleTests { items, nodeid });
+        self.defer_children(py, module, tests, Vec::new())
+    }
+
+    /// Make the `collect` of `collector`, the module or the class `chain`
+    /// names, create the nodes of the tests and classes directly in it
+    fn defer_children(
+        self: &Arc<Self>,
+        py: Python,
+        collector: &PyAny,
+        tests: Arc<ModuleTests>,
+        chain: Vec<String>,
+    ) -> PyResult<()> {
+        let symbols = Arc::clone(self);
+        let build = pyo3::types::PyCFunction::new_closure(
+            py,
+            Some("build"),
+            None,
+            move |args, _kwargs| -> PyResult<Vec<PyObject>> {
+                symbols.test_nodes(args.py(), &tests, &chain, args.get_item(0)?)
+            },
+        )?;
+        self.defer.call1(py, (collector, build))?;
+        Ok(())
+    }
+
+    /// Create the nodes of the tests and classes directly in the class
+    /// `chain` of a module, or in the module itself if `chain` is empty
+    ///
+    /// Node ids are set to the ones rytest reports, and locations to where
+    /// the tests are defined, so pytest does not look them up from the
+    /// functions when reporting.
+    fn test_nodes(
+        self: &Arc<Self>,
+        py: Python,
+        tests: &Arc<ModuleTests>,
+        chain: &[String],
+        parent: &PyAny,
+    ) -> PyResult<Vec<PyObject>> {
+        let mut nodes = Vec::new();
+        let mut location_path = None;
+        for item in tests.items.iter().filter(|item| item.classes == chain) {
+            let qualified = |name: &str, separator: &str| {
+                let mut names = item.classes.clone();
+                names.push(name.to_string());
+                names.join(separator)
+            };
+            match &item.kind {
+                TestKind::Function | TestKind::Method => {
+                    if location_path.is_none() {
+                        location_path = Some(parent.getattr("session")?.call_method1(
+                            "_node_location_to_relpath",
+                            (parent.getattr("path")?,),
+                        )?);
+                    }
+                    for call in item.expand() {
+                        let kwargs = PyDict::new(py);
+                        kwargs.set_item("name", &call.name)?;
+                        kwargs.set_item("originalname", &item.name)?;
+                        if let Some(id) = call.id() {
+                            kwargs.set_item("callspec", self.callspec(py, item, &call, parent)?)?;
+                            let keywords = PyDict::new(py);
+                            keywords.set_item(id, true)?;
+                            kwargs.set_item("keywords", keywords)?;
+                        }
+                        let node = self.function.call_method(
+                            py,
+                            "from_parent",
+                            (parent,),
+                            Some(kwargs),
+                        )?;
+                        let node_ref = node.as_ref(py);
+                        node_ref.setattr(
+                            "_nodeid",
+                            test_nodeid(&tests.nodeid, &item.classes, &call.name),
+                        )?;
+                        let location = (
+                            location_path,
+                            item.first_line - 1,
+                            qualified(&call.name, "."),
+                        );
+                        // pytest 7 and later cache the location as `location`, older as `_location`
+                        node_ref.setattr("location", location.clone())?;
+                        node_ref.setattr("_location", location)?;
+                        nodes.push(node);
+                    }
+                }
+                TestKind::Class => {
+                    let kwargs = PyDict::new(py);
+                    kwargs.set_item("name", &item.name)?;
+                    let node =
+                        self.class
+                            .call_method(py, "from_parent", (parent,), Some(kwargs))?;
+                    node.as_ref(py).setattr(
+                        "_nodeid",
+                        test_nodeid(&tests.nodeid, &item.classes, &item.name),
+                    )?;
+                    let mut chain = chain.to_vec();
+                    chain.push(item.name.clone());
+                    self.defer_children(py, node.as_ref(py), Arc::clone(tests), chain)?;
+                    nodes.push(node);
+                }
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+impl Collector {
+    /// Build a collector from natively loaded ini configuration
+    fn from_ini_config(config: &IniConfig) -> Self {
+        Collector {
+            python_classes: config.python_classes.clone(),
+            python_functions: config.python_functions.clone(),
+            target: Target::host(),
+            manifest: Mutex::new(Manifest::new(config.rootdir.clone())),
+            conftest_fallbacks: Mutex::new(HashMap::new()),
+            impact: Mutex::new(None),
+            #[cfg(target_os = "linux")]
+            daemon: Mutex::new(Client::connect(config).ok()),
+            shared: Mutex::new(None),
+            errors: ErrorMode::default(),
+            symbols: GILOnceCell::new(),
+        }
+    }
+
+    /// The interpreter pytest runs in
+    fn running_target(py: Python) -> PyResult<Target> {
+        let sys = py.import("sys")?;
+        let version_info = sys.getattr("version_info")?;
+        Ok(Target {
+            platform: Some(sys.getattr("platform")?.extract()?),
+            version: Some((
+                version_info.getattr("major")?.extract()?,
+                version_info.getattr("minor")?.extract()?,
+                version_info.getattr("micro")?.extract()?,
+            )),
+            implementation: Some(sys.getattr("implementation")?.getattr("name")?.extract()?),
+        })
+    }
+
+    /// Resolve the pytest node classes on first use
+    fn symbols(&self, py: Python) -> PyResult<&Arc<PytestSymbols>> {
+        self.symbols
+            .get_or_try_init(py, || PytestSymbols::load(py).map(Arc::new))
+    }
+
+    /// Evaluate the skip and xfail marks of each item
+    fn evaluate_skipping(&self, items: &mut [TestItem]) {
+        for item in items {
+            skipping::evaluate_item(item, &self.target);
+        }
+    }
+
+    fn manifest(&self) -> std::sync::MutexGuard<'_, Manifest> {
+        self.manifest.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Index the fixtures of the conftest files governing `module`
+    fn index_conftests(&self, module: &Path) {
+        let mut manifest = self.manifest();
+        let rootdir = manifest.rootdir.clone();
+        session::index_conftests(
+            &mut manifest.fixtures,
+            module,
+            &rootdir,
+            &self.python_classes,
+            &self.python_functions,
+        );
+    }
+
+    /// Load the conftest files governing `dir` through pytest's plugin manager
+    ///
+    /// Like pytest's own directory collection, this imports every conftest from
+    /// the confcutdir down to `dir`, parents first, so their fixtures and hooks
+    /// are registered before anything below them is collected. Returns why the
+    /// directory must be left to pytest: a conftest implementing one of
+    /// `COLLECTION_HOOKS`, or one that failed to import, which pytest then
+    /// reports on the right node. Nodes without a pytest config load nothing.
+    fn load_conftests(&self, py: Python, dir: &Path, node: &PyAny) -> PyResult<Option<String>> {
+        if let Some(reason) = self
+            .conftest_fallbacks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(dir)
+        {
+            return Ok(reason.clone());
+        }
+        let Ok(config) = node.getattr("config") else {
+            return Ok(None);
+        };
+        let reason = match Self::conftest_modules(py, dir, config) {
+            Ok(modules) => {
+                let rootdir = self.manifest().rootdir.clone();
+                let mut reason = None;
+                for module in modules.iter()? {
+                    let module = module?;
+                    if let Some(hook) = COLLECTION_HOOKS
+                        .iter()
+                        .find(|hook| module.getattr(**hook).is_ok_and(|hook| hook.is_callable()))
+                    {
+                        let file: PathBuf = module.getattr("__file__")?.extract()?;
+                        reason = Some(format!(
+                            "{} implements {}",
+                            walk::relative_nodeid_path(&file, &rootdir),
+                            hook
+                        ));
+                        break;
+                    }
+                }
+                reason
+            }
+            Err(err) => Some(format!("conftest.py failed to import: {}", err.value(py))),
+        };
+        self.conftest_fallbacks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(dir.to_path_buf(), reason.clone());
+        Ok(reason)
+    }
+
+    /// Import the conftest modules governing `dir` and return them, outermost first
+    fn conftest_modules<'py>(
+        py: Python<'py>,
+        dir: &Path,
+        config: &'py PyAny,
+    ) -> PyResult<&'py PyAny> {
+        let pluginmanager = config.getattr("pluginmanager")?;
+        let path = py.import("pathlib")?.call_method1("Path", (dir,))?;
+        let importmode = config.call_method1("getoption", ("importmode",))?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("rootpath", config.getattr("rootpath")?)?;
+        if !pluginmanager.hasattr("_loadconftestmodules")? {
+            // pytest 7 imports them on lookup
+            return pluginmanager.call_method(
+                "_getconftestmodules",
+                (path, importmode),
+                Some(kwargs),
+            );
+        }
+        // pytest 8.1 added consider_namespace_packages
+        if let Ok(namespace) = config.call_method1("getini", ("consider_namespace_packages",)) {
+            kwargs.set_item("consider_namespace_packages", namespace)?;
+        }
+        pluginmanager.call_method("_loadconftestmodules", (path, importmode), Some(kwargs))?;
+        pluginmanager.call_method1("_getconftestmodules", (path,))
+    }
+
+    fn get_config_patterns(config: &PyAny, name: &str) -> PyResult<Vec<String>> {
+        let patterns = config.call_method1("getini", (name,))?;
+        let patterns: Vec<String> = patterns.extract()?;
+        Ok(patterns)
+    }
+
+    /// The ini configuration as pytest resolved it, for walking and caching natively
+    fn ini_config(config: &PyAny) -> PyResult<IniConfig> {
+        let path = |value: &PyAny| -> PyResult<PathBuf> { Ok(value.str()?.to_str()?.into()) };
+        let mut ini = IniConfig::with_defaults(path(config.getattr("rootpath")?)?);
+        let inipath = config.getattr("inipath")?;
+        if !inipath.is_none() {
+            ini.inifile = Some(path(inipath)?);
+        }
+        ini.python_files = Self::get_config_patterns(config, "python_files")?;
+        ini.python_classes = Self::get_config_patterns(config, "python_classes")?;
+        ini.python_functions = Self::get_config_patterns(config, "python_functions")?;
+        ini.testpaths = Self::get_config_patterns(config, "testpaths")?;
+        ini.norecursedirs = Self::get_config_patterns(config, "norecursedirs")?;
+        // `pythonpath` entries come back as absolute paths
+        ini.pythonpath = config
+            .call_method1("getini", ("pythonpath",))?
+            .iter()?
+            .map(|entry| Ok(path(entry?)?.to_string_lossy().into_owned()))
+            .collect::<PyResult<_>>()?;
+        ini.cache_dir = config.call_method1("getini", ("cache_dir",))?.extract()?;
+        Ok(ini)
+    }
+
+    /// Connect to the daemon for the rootdir, unless `--rytest-no-daemon` is given
+    #[cfg(target_os = "linux")]
+    fn connect_daemon(config: &PyAny) -> Option<Client> {
+        let disabled = config
+            .call_method1("getoption", ("rytest_no_daemon", false))
+            .and_then(|value| value.is_true())
+            .unwrap_or(false);
+        if disabled {
+            return None;
+        }
+        Client::connect(&Self::ini_config(config).ok()?).ok()
+    }
+
+    /// The file as the daemon parsed it, if one is connected and answers
+    #[cfg(target_os = "linux")]
+    fn parse_with_daemon(&self, path: &Path) -> Option<Result<ParsedModule, ParseError>> {
+        let mut daemon = self.daemon.lock().unwrap_or_else(|e| e.into_inner());
+        let client = daemon.as_mut()?;
+        let parsed = profile::time(Phase::Parse, Some(path), || {
+            client.parse(path, &self.python_classes, &self.python_functions)
+        });
+        if parsed.is_err() {
+            *daemon = None;
+        }
+        parsed.ok()
+    }
+
+    /// Load the index the xdist controller saved at `path`, whose node ids
+    /// have the collection hash `hash`
+    fn take_shared_collection(
+        &self,
+        path: &Path,
+        hash: &str,
+        config: &IniConfig,
+    ) -> PyResult<usize> {
+        let index = ModuleIndex::load(path)?;
+        let len = index.len();
+        *self.shared.lock().unwrap_or_else(|e| e.into_inner()) = Some(SharedCollection {
+            index,
+            hash: hash.to_string(),
+            config: config.clone(),
+            collected: BTreeMap::new(),
+        });
+        Ok(len)
+    }
+
+    /// On a worker, record the node ids collected from the module at `path`,
+    /// from its `items`, or the module's own if rytest left it to pytest
+    fn record_collected(&self, path: &Path, items: Option<&[TestItem]>) {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(shared) = shared.as_mut() else {
+            return;
+        };
+        if !walk::is_test_module(path, &shared.config) {
+            return;
+        }
+        let module = walk::relative_nodeid_path(path, &shared.config.rootdir);
+        let nodeids = match items {
+            Some(items) => items
+                .iter()
+                .filter(|item| item.kind != TestKind::Class)
+                .flat_map(|item| {
+                    item.expand()
+                        .into_iter()
+                        .map(|call| test_nodeid(&module, &item.classes, &call.name))
+                })
+                .collect(),
+            None => vec![module],
+        };
+        shared.collected.insert(path.to_path_buf(), nodeids);
+    }
+
+    /// The file as the xdist controller parsed it, if it has not changed since
+    fn parse_shared(&self, path: &Path) -> Option<Result<ParsedModule, ParseError>> {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        let index = &mut shared.as_mut()?.index;
+        if !index.has_patterns(&self.python_classes, &self.python_functions)
+            || !index.contains(path)
+        {
+            return None;
+        }
+        Some(profile::time(Phase::Parse, Some(path), || index.get(path)))
+    }
+
+    /// Parse a Python file and look for tests and fixtures
+    fn parse(&self, path: &Path) -> Result<ParsedModule, ParseError> {
+        if let Some(parsed) = self.parse_shared(path) {
+            return parsed;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(parsed) = self.parse_with_daemon(path) {
+            return parsed;
+        }
+        session::parse_file(path, &self.python_classes, &self.python_functions)
+    }
+
+    /// The tests of the module at `path`, with the errors of those left out
+    ///
+    /// Tests are only left out under `--rytest-errors=continue`; otherwise
+    /// any error fails the whole module.
+    fn resolve(&self, path: &Path) -> Result<(Vec<TestItem>, Vec<ParseError>), ParseError> {
+        let parsed = self.parse(path);
+        if self.errors != ErrorMode::Continue {
+            return parsed
+                .and_then(|parsed| {
+                    session::resolve_module(&mut self.manifest().fixtures, path, parsed)
+                })
+                .map(|items| (items, Vec::new()));
+        }
+        // The daemon and the xdist controller only keep modules parsed whole
+        let parsed = parsed.or_else(|err| match err.kind {
+            ErrorKind::Parametrize => {
+                session::parse_file_partial(path, &self.python_classes, &self.python_functions)
+            }
+            _ => Err(err),
+        })?;
+        session::resolve_module_partial(&mut self.manifest().fixtures, path, parsed)
+    }
+
+    /// A module node that fails to collect with `error`, for `--rytest-errors=report`
+    fn create_error_node(
+        &self,
+        py: Python,
+        path: &Path,
+        parent: &PyAny,
+        error: &ParseError,
+    ) -> PyResult<PyObject> {
+        let symbols = self.symbols(py)?;
+        let module = symbols.module_node(py, path, parent)?;
+        let collect_error = symbols.collect_error.clone_ref(py);
+        let message = error.render();
+        let collect = pyo3::types::PyCFunction::new_closure(
+            py,
+            Some("collect"),
+            None,
+            move |args, _kwargs| -> PyResult<()> {
+                let py = args.py();
+                let error = collect_error.call1(py, (message.as_str(),))?;
+                Err(PyErr::from_value(error.as_ref(py)))
+            },
+        )?;
+        module.as_ref(py).setattr("collect", collect)?;
+        Ok(module)
+    }
+
+    /// Collect a directory recursively
+    fn collect_dir(&self, py: Python, path: &Path, parent: &PyAny) -> PyResult<Option<PyObject>> {
+        // Check for infinite recursion by looking at parent chain
+        let mut current = parent;
+        while let Ok(parent_path) = current.getattr("path") {
+            if let Ok(parent_path_str) = parent_path.extract::<String>() {
+                if parent_path_str == path.to_str().unwrap_or("") {
+                    // Found same path in parent chain, stop recursion
+                    return Ok(None);
+                }
+            }
+            if let Ok(parent_obj) = current.getattr("parent") {
+                if parent_obj.is_none() {
+                    break;
+                }
+                current = parent_obj;
+            } else {
+                break;
+            }
+        }
+
+        if self.load_conftests(py, path, parent)?.is_some() {
+            return Ok(None);
+        }
+
+        // Check if it's a package directory
+        if walk::is_package_dir(path) {
+            // Create a Package node
+            let symbols = self.symbols(py)?;
+            let package = symbols.package_node(py, path, parent)?;
+
+            // The tests of __init__.py are created under its own Module node,
+            // when the package collects it through pytest_collect_file
+            for entry in fs::read_dir(path).map_err(|e| {
+                pyo3::exceptions::PyIOError::new_err(format!("Failed to read directory: {}", e))
+            })? {
+                let entry = entry.map_err(|e| {
+                    pyo3::exceptions::PyIOError::new_err(format!(
+                        "Failed to read directory entry: {}",
+                        e
+                    ))
+                })?;
+                let entry_path = entry.path();
+
+                if entry_path.is_file() {
+                    if entry_path.file_name().unwrap() != "__init__.py" {
+                        let _ = self.pytest_collect_file(
+                            entry_path.to_str().unwrap(),
+                            package.clone_ref(py),
+                        )?;
+                    }
+                } else if entry_path.is_dir() {
+                    let _ = self.collect_dir(py, &entry_path, package.as_ref(py))?;
+                }
+            }
+
+            Ok(Some(package))
+        } else {
+            // Not a package directory, just collect Python files
+            let mut collected = false;
+            for entry in fs::read_dir(path).map_err(|e| {
+                pyo3::exceptions::PyIOError::new_err(format!("Failed to read directory: {}", e))
+            })? {
+                let entry = entry.map_err(|e| {
+                    pyo3::exceptions::PyIOError::new_err(format!(
+                        "Failed to read directory entry: {}",
+                        e
+                    ))
+                })?;
+                let entry_path = entry.path();
+
+                if entry_path.is_file()
+                    && self
+                        .pytest_collect_file(entry_path.to_str().unwrap(), parent.into())?
+                        .is_some()
+                {
+                    collected = true;
+                }
+            }
+
+            Ok(if collected { Some(parent.into()) } else { None })
+        }
+    }
+}
+
+#[pymethods]
+impl Collector {
+    #[new]
+    fn new(config: PyObject) -> PyResult<Self> {
+        Python::with_gil(|py| {
+            let config = config.as_ref(py);
+
+            let python_classes = Self::get_config_patterns(config, "python_classes")?;
+            let python_functions = Self::get_config_patterns(config, "python_functions")?;
+            let rootdir: PathBuf = config
+                .getattr("rootpath")?
+                .str()?
+                .extract::<String>()?
+                .into();
+
+            Ok(Collector {
+                python_classes,
+                python_functions,
+                target: Self::running_target(py)?,
+                manifest: Mutex::new(Manifest::new(rootdir)),
+                conftest_fallbacks: Mutex::new(HashMap::new()),
+                impact: Mutex::new(None),
+                #[cfg(target_os = "linux")]
+                daemon: Mutex::new(Self::connect_daemon(config)),
+                shared: Mutex::new(None),
+                errors: ErrorMode::from_config(config)?,
+                symbols: GILOnceCell::new(),
+            })
+        })
+    }
+
+    /// Create a collector from the ini configuration governing `args`, without a pytest Config
+    #[staticmethod]
+    #[pyo3(signature = (args=Vec::new(), invocation_dir=None))]
+    fn from_ini(args: Vec<PathBuf>, invocation_dir: Option<PathBuf>) -> PyResult<Self> {
+        let invocation_dir = match invocation_dir {
+            Some(dir) => dir,
+            None => std::env::current_dir()?,
+        };
+        let config = IniConfig::locate(&args, &invocation_dir)?;
+        Ok(Self::from_ini_config(&config))
+    }
+
+    /// The socket of the daemon modules are parsed by, if one is connected
+    fn daemon_socket(&self) -> Option<PathBuf> {
+        #[cfg(target_os = "linux")]
+        if let Some(client) = &*self.daemon.lock().unwrap_or_else(|e| e.into_inner()) {
+            return Some(client.socket().to_path_buf());
+        }
+        None
+    }
+
+    /// Check if a file should be collected for tests
+    fn pytest_collect_file(&self, path: &str, parent: PyObject) -> PyResult<Option<PyObject>> {
+        let path = Path::new(path);
+
+        // Only process .py files
+        if path.extension().and_then(|s| s.to_str()) != Some("py") {
+            return Ok(None);
+        }
+
+        let _hook = profile::timer(Phase::Hooks, Some(path));
+        Python::with_gil(|py| {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            if let Some(reason) = self.load_conftests(py, dir, parent.as_ref(py))? {
+                self.manifest().add_fallback_module(path, Some(reason));
+                self.record_collected(path, None);
+                return Ok(None);
+            }
+            self.index_conftests(path);
+            // Parse the file to find test items, leaving it to pytest if we can't
+            let (mut items, errors) = match self.resolve(path) {
+                Ok(resolved) => resolved,
+                Err(err) if err.kind == ErrorKind::Read => return Err(err.into()),
+                Err(err) if self.errors == ErrorMode::Report => {
+                    self.record_collected(path, None);
+                    return Ok(Some(self.create_error_node(
+                        py,
+                        path,
+                        parent.as_ref(py),
+                        &err,
+                    )?));
+                }
+                Err(err) => {
+                    self.manifest()
+                        .add_fallback_module(path, Some(err.to_string()));
+                    self.record_collected(path, None);
+                    return Ok(None);
+                }
+            };
+            self.evaluate_skipping(&mut items);
+            self.manifest().add_module(path, &items);
+            // The controller's index only has the modules rytest collects whole
+            self.record_collected(path, Some(&items[..]).filter(|_| errors.is_empty()));
+
+            let _nodes = profile::timer(Phase::Nodes, Some(path));
+            let symbols = self.symbols(py)?;
+            let module = symbols.module_node(py, path, parent.as_ref(py))?;
+            for error in &errors {
+                let warning = symbols.collection_warning.call1(py, (error.render(),))?;
+                module.call_method1(py, "warn", (warning,))?;
+            }
+            let nodeid = walk::relative_nodeid_path(path, &self.manifest().rootdir);
+            symbols.defer_module(py, module.as_ref(py), items, nodeid)?;
+
+            Ok(Some(module))
+        })
+    }
+
+    /// Check if a directory should be collected for tests
+    fn pytest_collect_directory(&self, path: &str, parent: PyObject) -> PyResult<Option<PyObject>> {
+        let _walk = profile::timer(Phase::Walk, None);
+        Python::with_gil(|py| self.collect_dir(py, Path::new(path), parent.as_ref(py)))
+    }
+
+    /// Record tests pytest collected itself from modules we fell back on
+    ///
+    /// Node ids already in the manifest are ignored.
+    fn record_fallback_tests(&self, tests: Vec<PytestItem>) {
+        let mut manifest = self.manifest();
+        for (nodeid, line, params, marks) in tests {
+            if manifest.contains(&nodeid) {
+                continue;
+            }
+            let marks = marks
+                .into_iter()
+                .map(|name| Mark {
+                    name,
+                    args: Vec::new(),
+                })
+                .collect();
+            manifest.add_fallback_test(&nodeid, line, params, marks);
+        }
+    }
+
+    /// Fixture definitions indexed so far, optionally only those named `name`
+    #[pyo3(signature = (name=None))]
+    fn fixtures(&self, py: Python, name: Option<&str>) -> PyResult<Vec<PyObject>> {
+        let manifest = self.manifest();
+        let mut fixtures = Vec::new();
+        for fixture in &manifest.fixtures.fixtures {
+            if name.is_some_and(|name| name != fixture.name) {
+                continue;
+            }
+            let dict = PyDict::new(py);
+            dict.set_item("name", &fixture.name)?;
+            dict.set_item("function", &fixture.function)?;
+            dict.set_item("path", &fixture.path)?;
+            dict.set_item("line", fixture.line_number)?;
+            dict.set_item("classes", &fixture.classes)?;
+            dict.set_item("scope", fixture.scope.map(|scope| scope.as_str()))?;
+            dict.set_item("autouse", fixture.autouse)?;
+            match &fixture.params {
+                Some(FixtureParams::Values(values)) => {
+                    let values = values
+                        .iter()
+                        .map(|value| literal_to_object(py, value))
+                        .collect::<PyResult<Vec<_>>>()?;
+                    dict.set_item("params", values)?;
+                }
+                Some(FixtureParams::Unresolved(source)) => {
+                    dict.set_item("params", py.None())?;
+                    dict.set_item("params_source", source)?;
+                }
+                None => dict.set_item("params", py.None())?,
+            }
+            dict.set_item("ids", fixture.ids.clone())?;
+            fixtures.push(dict.into_py(py));
+        }
+        Ok(fixtures)
+    }
+
+    /// Absolute paths of the modules rytest collected itself
+    fn rytest_modules(&self) -> Vec<PathBuf> {
+        let manifest = self.manifest();
+        manifest
+            .nodes
+            .iter()
+            .filter(|node| node.kind == NodeKind::Module && !node.fallback)
+            .map(|node| manifest.rootdir.join(&node.file))
+            .collect()
+    }
+
+    /// Compare what rytest collected with what pytest's own collection produced
+    ///
+    /// `tests` are the items pytest collected from `rytest_modules()`, and `errors`
+    /// the `(file, message)` of modules it failed on. Returns report lines, empty
+    /// if both agree.
+    fn verify(&self, tests: Vec<PytestItem>, errors: Vec<(String, String)>) -> Vec<String> {
+        let pytest: Vec<ManifestNode> = tests
+            .into_iter()
+            .map(|(nodeid, line, params, marks)| {
+                let marks = marks
+                    .into_iter()
+                    .map(|name| Mark {
+                        name,
+                        args: Vec::new(),
+                    })
+                    .collect();
+                ManifestNode::test(&nodeid, line, params, marks)
+            })
+            .collect();
+        let manifest = self.manifest();
+        let rytest: Vec<ManifestNode> = manifest
+            .nodes
+            .iter()
+            .filter(|node| !node.fallback)
+            .cloned()
+            .collect();
+
+        let mut mismatches: Vec<Mismatch> = errors
+            .into_iter()
+            .map(|(file, message)| Mismatch {
+                nodeid: file.clone(),
+                file,
+                difference: Difference::PytestError(message),
+            })
+            .collect();
+        let failed: Vec<String> = mismatches.iter().map(|m| m.file.clone()).collect();
+        mismatches.extend(
+            verify::diff(&rytest, &pytest)
+                .into_iter()
+                .filter(|m| !failed.contains(&m.file)),
+        );
+        verify::report(&mismatches)
+    }
+
+    /// Report unknown, unused and shadowed fixtures, one line per problem
+    ///
+    /// `known` are fixtures plugins provide, and `tests` the `(nodeid, fixturenames)`
+    /// of every collected item; only those of tests rytest did not collect are used.
+    fn lint(&self, known: Vec<String>, tests: Vec<(String, Vec<String>)>) -> Vec<String> {
+        let manifest = self.manifest();
+        let rytest: std::collections::HashSet<&str> = manifest
+            .nodes
+            .iter()
+            .filter(|node| node.kind == NodeKind::Function && !node.fallback)
+            .map(|node| node.nodeid.as_str())
+            .collect();
+        let requested: Vec<String> = tests
+            .into_iter()
+            .filter(|(nodeid, _)| !rytest.contains(nodeid.as_str()))
+            .flat_map(|(_, names)| names)
+            .collect();
+        let known = known.into_iter().collect();
+        lint::lint(&manifest, &known, &requested)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Drop deselected tests from the manifest
+    fn deselect(&self, nodeids: Vec<String>) {
+        let nodeids: std::collections::HashSet<String> = nodeids.into_iter().collect();
+        self.manifest()
+            .deselect(|node| nodeids.contains(&node.nodeid));
+    }
+
+    /// Restrict collection to the files changed since `git_ref` and those
+    /// importing them, returning how many of the test files under `args` that leaves
+    fn changed_since(
+        &self,
+        config: PyObject,
+        git_ref: &str,
+        args: Vec<PathBuf>,
+    ) -> PyResult<(usize, usize)> {
+        let ini = Python::with_gil(|py| Self::ini_config(config.as_ref(py)))?;
+        let impact =
+            Impact::since(&args, &ini, git_ref).map_err(pyo3::exceptions::PyValueError::new_err)?;
+        let modules = walk::collect_paths(&args, &ini);
+        let selected = modules.iter().filter(|path| impact.selects(path)).count();
+        *self.impact.lock().unwrap_or_else(|e| e.into_inner()) = Some(impact);
+        Ok((selected, modules.len()))
+    }
+
+    /// Whether a change since the `changed_since` ref affects `path`
+    fn is_affected(&self, path: PathBuf) -> bool {
+        self.impact
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_none_or(|impact| impact.selects(&path))
+    }
+
+    /// Drop the tests `skip` and `skipif` marks always skip from the manifest,
+    /// returning their node ids
+    fn prune_skipped(&self) -> Vec<String> {
+        self.manifest().prune_skipped()
+    }
+
+    /// Parse the test modules and conftest files under `args` once for every
+    /// xdist worker, returning the file they are saved in, its collection
+    /// hash and the number of files
+    fn share_collection(
+        &self,
+        config: PyObject,
+        args: Vec<PathBuf>,
+    ) -> PyResult<(PathBuf, String, usize)> {
+        let ini = Python::with_gil(|py| Self::ini_config(config.as_ref(py)))?;
+        let mut index = ModuleIndex::build(&args, &ini);
+        let path =
+            cache::mkdir(&ini, "rytest")?.join(format!("collection-{}.json", std::process::id()));
+        index.save(&path)?;
+        // Workers never collect the modules `--rytest-changed-since` leaves out
+        let hash = index.collection_hash(&ini, |path| self.is_affected(path.to_path_buf()));
+        Ok((path, hash, index.len()))
+    }
+
+    /// Take parsed modules from the file the xdist controller shared,
+    /// returning how many there are
+    ///
+    /// Fails if the file cannot be read; the worker then parses files itself.
+    /// Whether it collects the tests the controller did is only known once it
+    /// has, from `check_shared_collection`.
+    fn use_shared_collection(
+        &self,
+        config: PyObject,
+        path: PathBuf,
+        hash: &str,
+    ) -> PyResult<usize> {
+        let ini = Python::with_gil(|py| Self::ini_config(config.as_ref(py)))?;
+        self.take_shared_collection(&path, hash, &ini)
+    }
+
+    /// Check that the node ids this worker collected have the collection
+    /// hash of the xdist controller's
+    fn check_shared_collection(&self) -> PyResult<()> {
+        let shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(shared) = shared.as_ref() else {
+            return Ok(());
+        };
+        let found = index::hash_nodeids(shared.collected.values().flatten());
+        if found != shared.hash {
+            return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "this worker collected other tests than the controller \
+                 (collection hash {}, expected {})",
+                found, shared.hash
+            )));
+        }
+        Ok(())
+    }
+
+    /// The indices of the `nodeids` in shard `which`, an `(index, total)` pair, for `--rytest-shard`
+    ///
+    /// Shards are balanced by the durations in the `durations` files, merged
+    /// in order, or else by test count.
+    fn shard(
+        &self,
+        nodeids: Vec<String>,
+        which: (usize, usize),
+        by: &str,
+        durations: Vec<PathBuf>,
+    ) -> PyResult<Vec<usize>> {
+        let (index, total) = which;
+        let value_error = pyo3::exceptions::PyValueError::new_err;
+        let granularity = Granularity::parse(by)
+            .ok_or_else(|| value_error(format!("expected file, class or test, not {}", by)))?;
+        if total == 0 || index >= total {
+            return Err(value_error(format!(
+                "expected INDEX/TOTAL with INDEX below TOTAL, not {}/{}",
+                index, total
+            )));
+        }
+        let durations = Durations::load_all(&durations).map_err(value_error)?;
+        let mut shards = shard::partition(&nodeids, total, granularity, &durations);
+        Ok(shards.swap_remove(index).tests)
+    }
+
+    /// Merge the `(nodeid, seconds)` each test took into the timing file at
+    /// `path`, for `--rytest-store-durations`
+    fn store_durations(&self, path: PathBuf, durations: Vec<(String, f64)>) -> PyResult<()> {
+        Durations::store(&path, &durations).map_err(pyo3::exceptions::PyOSError::new_err)
+    }
+
+    /// `--rytest-fixtures-per-test` lines for each of `nodeids`, empty for tests rytest did not collect
+    fn fixtures_per_test(&self, nodeids: Vec<String>) -> Vec<Vec<String>> {
+        let manifest = self.manifest();
+        let nodes: std::collections::HashMap<&str, &ManifestNode> = manifest
+            .nodes
+            .iter()
+            .map(|node| (node.nodeid.as_str(), node))
+            .collect();
+        nodeids
+            .iter()
+            .map(|nodeid| {
+                nodes
+                    .get(nodeid.as_str())
+                    .map(|node| fixtures::describe_closure(&node.fixtures, &manifest.rootdir))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// The doctests in the module or text file `path`, as `(name, lineno)` pairs
+    ///
+    /// Found doctests are recorded in the manifest. Returns `None` when the file
+    /// may have doctests rytest cannot see: it cannot be read, it sets
+    /// `__test__`, or it has `>>>` prompts outside the docstrings rytest found.
+    fn doctests(&self, path: PathBuf) -> Option<Vec<(String, usize)>> {
+        let is_module = path.extension().is_some_and(|e| e == "py");
+        let source = if is_module {
+            source::read(&path)
+        } else {
+            fs::read_to_string(&path)
+        }
+        .ok()?;
+        let found = if is_module {
+            let found = doctest::module_doctests(&source, &walk::module_name(&path));
+            if source.contains("__test__") || (found.is_empty() && doctest::has_examples(&source)) {
+                return None;
+            }
+            found
+        } else {
+            let name = path.file_name()?.to_string_lossy();
+            doctest::textfile_doctest(&source, &name)
+                .into_iter()
+                .collect()
+        };
+        let mut manifest = self.manifest();
+        let file = walk::relative_nodeid_path(&path, &manifest.rootdir);
+        let recorded = manifest
+            .nodes
+            .iter()
+            .any(|node| node.kind == NodeKind::Doctest && node.file == file);
+        if !recorded {
+            manifest.add_doctests(&path, &found);
+        }
+        Some(
+            found
+                .into_iter()
+                .map(|doctest| (doctest.name, doctest.lineno))
+                .collect(),
+        )
+    }
+
+    /// Start timing collection phases, for `--rytest-profile`
+    fn start_profile(&self) {
+        profile::start();
+    }
+
+    /// Stop timing, returning the report lines with the `top` slowest files
+    ///
+    /// The profile is also written to `trace` as Chrome trace JSON if given,
+    /// and the report ends saying whether it was. Returns nothing if no
+    /// profile was started.
+    #[pyo3(signature = (top, trace=None))]
+    fn finish_profile(&self, top: usize, trace: Option<PathBuf>) -> Vec<String> {
+        let Some(profile) = profile::finish() else {
+            return Vec::new();
+        };
+        let mut report = profile.report(top, &self.manifest().rootdir);
+        if let Some(trace) = trace {
+            report.push(String::new());
+            report.push(match profile.write_trace(&trace) {
+                Ok(()) => format!("Chrome trace written to {}", trace.display()),
+                Err(e) => format!(
+                    "Could not write the Chrome trace to {}: {}",
+                    trace.display(),
+                    e
+                ),
+            });
+        }
+        report
+    }
+
+    /// The collection manifest as a JSON string
+    fn manifest_json(&self) -> String {
+        format!("{:#}", self.manifest().to_json())
+    }
+
+    /// Write the collection manifest to `path`
+    fn write_manifest(&self, path: PathBuf) -> PyResult<()> {
+        Ok(self.manifest().write(&path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_collector() -> Collector {
+        let mut config = IniConfig::with_defaults(std::env::temp_dir());
+        config.python_classes = vec!["Test".to_string()];
+        config.python_functions = vec!["test_".to_string()];
+        Collector::from_ini_config(&config)
+    }
+
+    #[test]
+    fn test_module_creation() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let m = pyo3::wrap_pymodule!(rytest_core);
+            let _module = m(py);
+        });
+    }
+
+    #[test]
+    fn test_running_target() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let target = Collector::running_target(py).unwrap();
+            let sys = py.import("sys").unwrap();
+            let platform: String = sys.getattr("platform").unwrap().extract().unwrap();
+            assert_eq!(target.platform, Some(platform));
+            assert_eq!(target.version.map(|(major, _, _)| major), Some(3));
+            assert!(target.implementation.is_some());
+        });
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_with_daemon() {
+        use crate::daemon::{self, Request};
+
+        let dir = std::env::temp_dir().join("rytest_python_daemon");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("pytest.ini"), "[pytest]\n").unwrap();
+        let test_a = dir.join("tests/test_a.py");
+        fs::write(&test_a, "def test_a(): pass\n").unwrap();
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let server = {
+            let config = config.clone();
+            let args = vec![dir.join("tests")];
+            std::thread::spawn(move || {
+                daemon::serve(&args, &config, |_, _| ready_tx.send(()).unwrap())
+            })
+        };
+        ready_rx.recv().unwrap();
+
+        let collector = Collector::from_ini_config(&config);
+        assert_eq!(
+            collector.daemon_socket(),
+            Some(daemon::socket_path(&config))
+        );
+        let parsed = collector.parse(&test_a).unwrap();
+        assert_eq!(parsed.items[0].name, "test_a");
+
+        // Once the daemon is gone, files are parsed in process
+        daemon::Client::connect(&config)
+            .unwrap()
+            .request(&Request::Stop)
+            .unwrap();
+        server.join().unwrap().unwrap();
+        let parsed = collector.parse(&test_a).unwrap();
+        assert_eq!(parsed.items[0].name, "test_a");
+        assert_eq!(collector.daemon_socket(), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_shared() {
+        let dir = std::env::temp_dir().join("rytest_python_shared");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::write(dir.join("pytest.ini"), "[pytest]\n").unwrap();
+        let test_a = dir.join("tests/test_a.py");
+        fs::write(&test_a, "def test_a(): pass\n").unwrap();
+        let config = IniConfig::locate(&[dir.join("tests")], &dir).unwrap();
+        let saved = dir.join("collection.json");
+        let mut index = ModuleIndex::build(&[dir.join("tests")], &config);
+        index.save(&saved).unwrap();
+        let hash = index.collection_hash(&config, |_| true);
+
+        // The worker checks the node ids it collected against the controller's
+        let collector = Collector::from_ini_config(&config);
+        assert_eq!(
+            collector
+                .take_shared_collection(&saved, "0", &config)
+                .unwrap(),
+            1
+        );
+        let (items, _) = collector.resolve(&test_a).unwrap();
+        collector.record_collected(&test_a, Some(&items));
+        let err = collector.check_shared_collection().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("collected other tests than the controller"));
+
+        let collector = Collector::from_ini_config(&config);
+        assert_eq!(
+            collector
+                .take_shared_collection(&saved, &hash, &config)
+                .unwrap(),
+            1
+        );
+        assert!(collector.check_shared_collection().is_err());
+        let parsed = collector.parse(&test_a).unwrap();
+        assert_eq!(parsed.items[0].name, "test_a");
+        let (items, _) = collector.resolve(&test_a).unwrap();
+        collector.record_collected(&test_a, Some(&items));
+        collector.check_shared_collection().unwrap();
+        collector.record_collected(&test_a, None);
+        assert!(collector.check_shared_collection().is_err());
+
+        // A file changed since the controller parsed it is parsed again
+        fs::write(&test_a, "def test_other(): pass\n").unwrap();
+        let parsed = collector.parse(&test_a).unwrap();
+        assert_eq!(parsed.items[0].name, "test_other");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_literal_conversion() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let ast = py.import("ast").unwrap();
+            for src in [
+                "None",
+                "(1, -2.5, 3j, 'a\\n', b'\\x00', ...)",
+                "[{'k': {1, 2}}, (), 1 - 2j, set()]",
+            ] {
+                let native = literal_to_object(py, &Literal::parse(src).unwrap()).unwrap();
+                let expected = ast.call_method1("literal_eval", (src,)).unwrap();
+                assert!(native.as_ref(py).eq(expected).unwrap(), "{}", src);
+            }
+        });
+    }
+
+    #[test]
+    fn test_doctests() {
+        let dir = std::env::temp_dir().join("rytest_python_doctests");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("plain.py"), "def f():\n    \"\"\"Docs.\"\"\"\n").unwrap();
+        fs::write(dir.join("dynamic.py"), "__test__ = {'x': '>>> 1'}\n").unwrap();
+        fs::write(dir.join("doc.py"), "'''\n>>> 1\n1\n'''\n").unwrap();
+        fs::write(dir.join("test_x.txt"), ">>> 1\n1\n").unwrap();
+
+        let collector = test_collector();
+        assert_eq!(collector.doctests(dir.join("plain.py")), Some(Vec::new()));
+        assert_eq!(collector.doctests(dir.join("dynamic.py")), None);
+        assert_eq!(
+            collector.doctests(dir.join("doc.py")),
+            Some(vec![("doc".to_string(), 0)])
+        );
+        assert_eq!(
+            collector.doctests(dir.join("test_x.txt")),
+            Some(vec![("test_x.txt".to_string(), 0)])
+        );
+        assert!(collector
+            .manifest()
+            .contains("rytest_python_doctests/test_x.txt::test_x.txt"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_conftest_fallback() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let collector = test_collector();
+            let dir = std::env::temp_dir().join("rytest_conftest_hooks");
+            let locals = PyDict::new(py);
+            py.run(
+                r#"
+import pathlib
+import types
+
+class PluginManager:
+    def __init__(self):
+        self.loaded = []
+
+    def _loadconftestmodules(self, path, importmode, rootpath, consider_namespace_packages=False):
+        self.loaded.append(path.name)
+
+    def _getconftestmodules(self, path):
+        if path.name != "hooked":
+            return []
+        module = types.ModuleType("conftest")
+        module.__file__ = str(path / "conftest.py")
+        module.pytest_generate_tests = lambda metafunc: None
+        return [module]
+
+class Config:
+    pluginmanager = PluginManager()
+    rootpath = pathlib.Path("/")
+
+    def getoption(self, name):
+        return "prepend"
+
+    def getini(self, name):
+        raise ValueError(name)
+
+class Node:
+    config = Config()
+
+node = Node()
+"#,
+                Some(locals),
+                None,
+            )
+            .unwrap();
+            let node = locals.get_item("node").unwrap().unwrap();
+
+            assert_eq!(
+                collector
+                    .load_conftests(py, &dir.join("plain"), node)
+                    .unwrap(),
+                None
+            );
+            let hooked = dir.join("hooked");
+            let reason = Some(
+                "rytest_conftest_hooks/hooked/conftest.py implements pytest_generate_tests"
+                    .to_string(),
+            );
+            assert_eq!(collector.load_conftests(py, &hooked, node).unwrap(), reason);
+
+            // Modules there are left to pytest, and each directory is loaded once
+            let module = hooked.join("test_a.py");
+            let result = collector
+                .pytest_collect_file(module.to_str().unwrap(), node.into())
+                .unwrap();
+            assert!(result.is_none());
+            assert_eq!(collector.manifest().nodes[0].error, reason);
+            let loaded: Vec<String> = node
+                .getattr("config")
+                .and_then(|config| config.getattr("pluginmanager"))
+                .and_then(|pm| pm.getattr("loaded"))
+                .and_then(|loaded| loaded.extract())
+                .unwrap();
+            assert_eq!(loaded, vec!["plain", "hooked"]);
+
+            // Without a pytest config there is nothing to load
+            assert_eq!(
+                collector
+                    .load_conftests(py, &dir.join("other"), py.None().as_ref(py))
+                    .unwrap(),
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn test_file_collection() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let collector = test_collector();
+            let parent = py.None();
+
+            // Create a temporary test file
+            let test_content = r#"
+@pytest.mark.parametrize("value", [1, 2, 3])
+def test_parametrized(value):
+    assert value > 0
+
+def test_simple():
+    assert True
+
+class TestExample:
+    def test_method(self):
+        pass
+"#;
+            let temp_dir = std::env::temp_dir();
+            let test_file = temp_dir.join("test_temp.py");
+            fs::write(&test_file, test_content).unwrap();
+
+            // Should collect our test file
+            let result = collector
+                .pytest_collect_file(test_file.to_str().unwrap(), parent)
+                .unwrap();
+            assert!(result.is_some());
+
+            // Clean up
+            fs::remove_file(test_file).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_package_collection() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let collector = test_collector();
+            let parent = py.None();
+
+            // Create a temporary package directory
+            let temp_dir = std::env::temp_dir();
+            let pkg_dir = temp_dir.join("test_pkg");
+            fs::create_dir(&pkg_dir).unwrap();
+
+            // Create __init__.py
+            let init_content = r#"
+def test_init():
+    assert True
+"#;
+            fs::write(pkg_dir.join("__init__.py"), init_content).unwrap();
+
+            // Create a test module
+            let test_content = r#"
+@pytest.mark.parametrize("value", [1, 2, 3])
+def test_parametrized(value):
+    assert value > 0
+
+def test_example():
+    assert True
+
+class TestExample:
+    def test_method(self):
+        pass
+"#;
+            fs::write(pkg_dir.join("test_module.py"), test_content).unwrap();
+
+            // Should collect our package
+            let result = collector
+                .pytest_collect_directory(pkg_dir.to_str().unwrap(), parent)
+                .unwrap();
+            assert!(result.is_some());
+
+            // Clean up
+            fs::remove_dir_all(pkg_dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,1237 @@
+//! Whole-session collection on top of the parser and walker, without Python.
+
+use crate::config::IniConfig;
+use crate::doctest::{self, Doctest, DoctestOptions};
+use crate::expression::Expression;
+use crate::fixtures::{FixtureIndex, FixtureScope};
+use crate::impact::Impact;
+use crate::manifest::Manifest;
+use crate::parser::{
+    self, CallParam, ErrorKind, ParseError, ParsedModule, Span, TestItem, TestKind,
+};
+use crate::profile::{self, Phase};
+use crate::skipping::{self, Target, Verdict};
+use crate::source;
+use crate::walk;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single runnable test, as listed by `pytest --collect-only -q`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectedTest {
+    pub nodeid: String,
+    pub path: PathBuf,
+    pub line_number: usize,
+    /// Names `-k` matches against: directories, module, classes, test and marks
+    pub keywords: Vec<String>,
+    pub marks: Vec<String>,
+    /// Parameter values from parametrize marks and fixture params
+    pub params: Vec<CallParam>,
+    /// Always skipped, by `skip` or a `skipif` that holds for the target
+    pub skipped: bool,
+}
+
+/// A module that could not be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionError {
+    pub path: PathBuf,
+    pub nodeid: String,
+    pub error: ParseError,
+}
+
+#[derive(Debug, Default)]
+pub struct Collection {
+    pub tests: Vec<CollectedTest>,
+    pub errors: Vec<CollectionError>,
+    pub deselected: usize,
+    /// Tests pruned because they are always skipped
+    pub skipped: usize,
+    /// Every collected node, with deselected tests already removed
+    pub manifest: Manifest,
+}
+
+/// How to collect, beyond the ini configuration
+#[derive(Debug, Clone, Default)]
+pub struct CollectOptions {
+    pub doctests: DoctestOptions,
+    /// The interpreter `skipif` and `xfail` conditions are evaluated for
+    pub target: Target,
+    /// Leave out the tests `skip` and `skipif` marks always skip
+    pub prune_skipped: bool,
+    /// Only collect from the files a change affects
+    pub impact: Option<Impact>,
+    /// Collect the other tests of a module with tests rytest cannot collect,
+    /// instead of none, recording an error for each
+    pub continue_on_errors: bool,
+}
+
+fn read_source(path: &Path) -> Result<String, ParseError> {
+    let reading = profile::timer(Phase::Read, Some(path));
+    let bytes = fs::read(path).map_err(|e| {
+        ParseError::with_kind(ErrorKind::Read, format!("Failed to read file: {}", e))
+    })?;
+    let decoded = source::decode(&bytes);
+    drop(reading);
+    decoded.map_err(|e| {
+        let mut error = ParseError::with_kind(ErrorKind::Encoding, e.message);
+        let lossy = String::from_utf8_lossy(bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&bytes));
+        error.span = lossy.lines().nth(e.line - 1).map(|snippet| {
+            Box::new(Span {
+                path: path.to_string_lossy().into_owned(),
+                line: e.line,
+                column: e.column,
+                width: 1,
+                snippet: snippet.to_string(),
+            })
+        });
+        error
+    })
+}
+
+/// Parse a test module or conftest file from disk
+pub fn parse_file(
+    path: &Path,
+    python_classes: &[String],
+    python_functions: &[String],
+) -> Result<ParsedModule, ParseError> {
+    let source = read_source(path)?;
+    let _parse = profile::timer(Phase::Parse, Some(path));
+    parser::parse_module(
+        &source,
+        &path.to_string_lossy(),
+        python_classes,
+        python_functions,
+    )
+}
+
+/// Parse a file from disk like [`parse_file`], but only fail if it cannot
+/// be read, recording the tests left out in the module's `errors`
+pub fn parse_file_partial(
+    path: &Path,
+    python_classes: &[String],
+    python_functions: &[String],
+) -> Result<ParsedModule, ParseError> {
+    let source = read_source(path)?;
+    let _parse = profile::timer(Phase::Parse, Some(path));
+    Ok(parser::parse_module_partial(
+        &source,
+        &path.to_string_lossy(),
+        python_classes,
+        python_functions,
+    ))
+}
+
+/// Add the fixtures of the conftest files governing `module` that are not indexed yet
+///
+/// Conftest files rytest cannot parse contribute no fixtures.
+/// Conftest files defining `pytest_generate_tests` are noted in the index.
+pub fn index_conftests(
+    index: &mut FixtureIndex,
+    module: &Path,
+    rootdir: &Path,
+    python_classes: &[String],
+    python_functions: &[String],
+) {
+    index_conftests_with(index, module, rootdir, |conftest| {
+        parse_file(conftest, python_classes, python_functions)
+    });
+}
+
+/// Index conftest files like [`index_conftests`], parsing them with `parse`
+pub fn index_conftests_with(
+    index: &mut FixtureIndex,
+    module: &Path,
+    rootdir: &Path,
+    mut parse: impl FnMut(&Path) -> Result<ParsedModule, ParseError>,
+) {
+    for conftest in walk::conftest_paths(module, rootdir) {
+        let key = conftest.to_string_lossy();
+        if index.contains_file(&key) {
+            continue;
+        }
+        let parsed = parse(&conftest).unwrap_or_default();
+        if parsed.generate_tests.iter().any(Vec::is_empty) {
+            index.generate_tests.push(key.to_string());
+        }
+        index.add_file(&key, parsed.fixtures);
+    }
+}
+
+/// Index the fixtures of a parsed module, then apply the parametrizations its tests get from them
+///
+/// Fails when a fixture the tests use has params rytest cannot evaluate, or
+/// when a `pytest_generate_tests` hook in the module, a test class or a
+/// conftest file in scope may parametrize the tests at collection time.
+pub fn resolve_module(
+    index: &mut FixtureIndex,
+    path: &Path,
+    parsed: ParsedModule,
+) -> Result<Vec<TestItem>, ParseError> {
+    let (items, mut errors) = resolve_module_partial(index, path, parsed)?;
+    if !errors.is_empty() {
+        return Err(errors.swap_remove(0));
+    }
+    Ok(items)
+}
+
+/// Resolve a module like [`resolve_module`], but leave out the tests that
+/// cannot be resolved, returning why with the errors already in `parsed`
+///
+/// Still fails when a `pytest_generate_tests` hook may parametrize the tests.
+pub fn resolve_module_partial(
+    index: &mut FixtureIndex,
+    path: &Path,
+    parsed: ParsedModule,
+) -> Result<(Vec<TestItem>, Vec<ParseError>), ParseError> {
+    let _resolve = profile::timer(Phase::Parse, Some(path));
+    let ParsedModule {
+        mut items,
+        fixtures,
+        generate_tests,
+        mut errors,
+    } = parsed;
+    index.add_file(&path.to_string_lossy(), fixtures);
+    let tests = || items.iter().filter(|item| item.kind != TestKind::Class);
+    let hook = index
+        .generate_tests
+        .iter()
+        .find(|conftest| {
+            Path::new(conftest)
+                .parent()
+                .is_some_and(|dir| path.starts_with(dir))
+        })
+        .cloned()
+        .filter(|_| tests().next().is_some())
+        .or_else(|| {
+            generate_tests
+                .iter()
+                .find(|classes| tests().any(|item| item.classes.starts_with(classes)))
+                .map(|classes| match classes.last() {
+                    Some(class) => format!("class {}", class),
+                    None => "the module".to_string(),
+                })
+        });
+    if let Some(hook) = hook {
+        return Err(ParseError::with_kind(
+            ErrorKind::GenerateTests,
+            format!(
+                "pytest_generate_tests in {} parametrizes tests at collection time, \
+                 which rytest cannot evaluate statically",
+                hook
+            ),
+        ));
+    }
+    items.retain_mut(|item| match index.resolve(item) {
+        Ok(()) => true,
+        Err(err) => {
+            if !errors.contains(&err) {
+                errors.push(err);
+            }
+            false
+        }
+    });
+    Ok((items, errors))
+}
+
+/// The keywords every test in the file `module_id` has: the rootdir and path parts
+fn base_keywords(module_id: &str, config: &IniConfig) -> Vec<String> {
+    let mut keywords: Vec<String> = config
+        .rootdir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .into_iter()
+        .collect();
+    keywords.extend(module_id.split('/').map(String::from));
+    keywords
+}
+
+/// Expand parsed items of one module into runnable tests
+pub fn module_tests(path: &Path, items: &[TestItem], config: &IniConfig) -> Vec<CollectedTest> {
+    let module_id = walk::relative_nodeid_path(path, &config.rootdir);
+    let base_keywords = base_keywords(&module_id, config);
+
+    let mut tests = Vec::new();
+    for item in items.iter().filter(|item| item.kind != TestKind::Class) {
+        let prefix = if item.classes.is_empty() {
+            module_id.clone()
+        } else {
+            format!("{}::{}", module_id, item.classes.join("::"))
+        };
+        for call in item.expand() {
+            let mut marks: Vec<String> =
+                item.call_marks(&call).into_iter().map(|m| m.name).collect();
+            // Hypothesis's pytest plugin marks its tests, for `-m hypothesis`
+            if item.hypothesis.is_some() {
+                marks.push("hypothesis".to_string());
+            }
+            let skipped = matches!(item.call_skipping(&call).skip, Verdict::Yes { .. });
+            let mut keywords = base_keywords.clone();
+            keywords.extend(item.classes.iter().cloned());
+            keywords.push(call.name.clone());
+            keywords.extend(marks.iter().cloned());
+            tests.push(CollectedTest {
+                nodeid: format!("{}::{}", prefix, call.name),
+                path: path.to_path_buf(),
+                line_number: item.line_number,
+                keywords,
+                marks,
+                params: call.params,
+                skipped,
+            });
+        }
+    }
+    tests
+}
+
+/// The doctest items of one module or text file
+pub fn doctest_tests(path: &Path, doctests: &[Doctest], config: &IniConfig) -> Vec<CollectedTest> {
+    let file_id = walk::relative_nodeid_path(path, &config.rootdir);
+    doctests
+        .iter()
+        .map(|doctest| {
+            let mut keywords = base_keywords(&file_id, config);
+            keywords.push(doctest.name.clone());
+            CollectedTest {
+                nodeid: format!("{}::{}", file_id, doctest.name),
+                path: path.to_path_buf(),
+                line_number: doctest.lineno + 1,
+                keywords,
+                marks: Vec::new(),
+                params: Vec::new(),
+                skipped: false,
+            }
+        })
+        .collect()
+}
+
+/// The doctests pytest's doctest plugin collects from `path`
+///
+/// Files that cannot be read are left out; pytest reports those when it
+/// imports them.
+fn find_doctests(path: &Path, options: &DoctestOptions, explicit: bool) -> Vec<Doctest> {
+    let is_module = options.is_module(path);
+    if !is_module && !options.is_textfile(path, explicit) {
+        return Vec::new();
+    }
+    let source = if is_module {
+        source::read(path)
+    } else {
+        fs::read_to_string(path)
+    };
+    let Ok(source) = source else {
+        return Vec::new();
+    };
+    if is_module {
+        doctest::module_doctests(&source, &walk::module_name(path))
+    } else {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        doctest::textfile_doctest(&source, &name)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Scopes whose parametrized fixtures pytest groups tests by, broadest first
+const HIGH_SCOPES: [FixtureScope; 4] = [
+    FixtureScope::Session,
+    FixtureScope::Package,
+    FixtureScope::Module,
+    FixtureScope::Class,
+];
+
+/// Keys identifying the `scope`-scoped parameter instances a test uses
+///
+/// Two tests sharing a key can reuse one fixture setup, as with pytest's
+/// `get_parametrized_fixture_argkeys`.
+fn argkeys(test: &CollectedTest, scope: FixtureScope) -> Vec<String> {
+    let scoped = match scope {
+        FixtureScope::Session => String::new(),
+        FixtureScope::Package => test
+            .path
+            .parent()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        FixtureScope::Module => test.path.to_string_lossy().into_owned(),
+        FixtureScope::Class | FixtureScope::Function => test
+            .nodeid
+            .rsplit_once("::")
+            .map_or(test.nodeid.clone(), |(parent, _)| parent.to_string()),
+    };
+    let mut keys: Vec<String> = Vec::new();
+    for param in test.params.iter().filter(|p| p.scope == scope) {
+        let key = format!("{}\0{}\0{}", param.argname, param.index, scoped);
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+/// Reorder tests so that higher-scoped parametrized fixtures are set up as few times as possible
+///
+/// A port of pytest's `reorder_items`, which changes the order tests are
+/// listed in once such fixtures are involved.
+fn reorder_tests(tests: Vec<CollectedTest>) -> Vec<CollectedTest> {
+    let keys_by_test: Vec<Vec<Vec<String>>> = HIGH_SCOPES
+        .iter()
+        .map(|scope| tests.iter().map(|test| argkeys(test, *scope)).collect())
+        .collect();
+    if keys_by_test.iter().flatten().all(Vec::is_empty) {
+        return tests;
+    }
+    let mut tests_by_key: Vec<HashMap<String, Vec<usize>>> = keys_by_test
+        .iter()
+        .map(|keys| {
+            let mut by_key: HashMap<String, Vec<usize>> = HashMap::new();
+            for (index, test_keys) in keys.iter().enumerate() {
+                for key in test_keys {
+                    by_key.entry(key.clone()).or_default().push(index);
+                }
+            }
+            by_key
+        })
+        .collect();
+
+    let order = reorder_at_scope(
+        (0..tests.len()).collect(),
+        0,
+        &keys_by_test,
+        &mut tests_by_key,
+    );
+    let mut tests: Vec<Option<CollectedTest>> = tests.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|index| tests[index].take())
+        .collect()
+}
+
+fn reorder_at_scope(
+    items: Vec<usize>,
+    scope: usize,
+    keys_by_test: &[Vec<Vec<String>>],
+    tests_by_key: &mut [HashMap<String, Vec<usize>>],
+) -> Vec<usize> {
+    if scope >= HIGH_SCOPES.len() || items.len() < 3 {
+        return items;
+    }
+    let members: HashSet<usize> = items.iter().copied().collect();
+    let mut ignore: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<usize> = items.into();
+    let mut done = Vec::new();
+    let mut done_set = HashSet::new();
+    while !queue.is_empty() {
+        let mut no_key = Vec::new();
+        let mut no_key_set = HashSet::new();
+        let mut slicing_key = None;
+        while let Some(item) = queue.pop_front() {
+            if done_set.contains(&item) || no_key_set.contains(&item) {
+                continue;
+            }
+            let Some(key) = keys_by_test[scope][item]
+                .iter()
+                .rfind(|key| !ignore.contains(*key))
+                .cloned()
+            else {
+                no_key.push(item);
+                no_key_set.insert(item);
+                continue;
+            };
+            // Pull every test sharing the key to the front, keeping their order
+            let matching: Vec<usize> = tests_by_key[scope][&key]
+                .iter()
+                .copied()
+                .filter(|index| members.contains(index))
+                .collect();
+            for &index in matching.iter().rev() {
+                queue.push_front(index);
+                for (by_key, keys) in tests_by_key.iter_mut().zip(keys_by_test) {
+                    for other in &keys[index] {
+                        if let Some(indices) = by_key.get_mut(other) {
+                            indices.retain(|&i| i != index);
+                            indices.insert(0, index);
+                        }
+                    }
+                }
+            }
+            slicing_key = Some(key);
+            break;
+        }
+        if !no_key.is_empty() {
+            for index in reorder_at_scope(no_key, scope + 1, keys_by_test, tests_by_key) {
+                if done_set.insert(index) {
+                    done.push(index);
+                }
+            }
+        }
+        if let Some(key) = slicing_key {
+            ignore.insert(key);
+        }
+    }
+    done
+}
+
+/// Collect every test under `args`, applying `-k` and `-m` selection
+///
+/// A file's doctests come before its tests, as pytest's doctest plugin
+/// creates its collector first. Files no change affects are left out before
+/// parsing, if `options` has an impact. Tests are pruned as skipped after selection.
+pub fn collect(
+    args: &[PathBuf],
+    config: &IniConfig,
+    options: &CollectOptions,
+    keyword: Option<&Expression>,
+    markexpr: Option<&Expression>,
+) -> Collection {
+    let mut collection = Collection {
+        manifest: Manifest::new(config.rootdir.clone()),
+        ..Collection::default()
+    };
+    let doctests = &options.doctests;
+    let is_python = |path: &Path| path.extension().is_some_and(|e| e == "py");
+    let has_tests = |path: &Path, explicit| {
+        if explicit {
+            is_python(path)
+        } else {
+            walk::is_test_module(path, config)
+        }
+    };
+    let walking = profile::timer(Phase::Walk, None);
+    let files = walk::collect_files(args, config, |path, explicit| {
+        (has_tests(path, explicit)
+            || doctests.is_module(path)
+            || doctests.is_textfile(path, explicit))
+            && options
+                .impact
+                .as_ref()
+                .is_none_or(|impact| impact.selects(path))
+    });
+    drop(walking);
+
+    let mut tests = Vec::new();
+    for path in files {
+        let explicit = args.contains(&path);
+        let found = find_doctests(&path, doctests, explicit);
+        collection.manifest.add_doctests(&path, &found);
+        tests.extend(doctest_tests(&path, &found, config));
+        if !has_tests(&path, explicit) {
+            continue;
+        }
+
+        let fixtures = &mut collection.manifest.fixtures;
+        index_conftests(
+            fixtures,
+            &path,
+            &config.rootdir,
+            &config.python_classes,
+            &config.python_functions,
+        );
+        let resolved = if options.continue_on_errors {
+            parse_file_partial(&path, &config.python_classes, &config.python_functions)
+                .and_then(|parsed| resolve_module_partial(fixtures, &path, parsed))
+        } else {
+            parse_file(&path, &config.python_classes, &config.python_functions)
+                .and_then(|parsed| resolve_module(fixtures, &path, parsed))
+                .map(|items| (items, Vec::new()))
+        };
+        let mut items = match resolved {
+            Ok((items, errors)) => {
+                let nodeid = walk::relative_nodeid_path(&path, &config.rootdir);
+                collection
+                    .errors
+                    .extend(errors.into_iter().map(|error| CollectionError {
+                        path: path.clone(),
+                        nodeid: nodeid.clone(),
+                        error,
+                    }));
+                items
+            }
+            Err(error) => {
+                collection
+                    .manifest
+                    .add_fallback_module(&path, Some(error.to_string()));
+                collection.errors.push(CollectionError {
+                    nodeid: walk::relative_nodeid_path(&path, &config.rootdir),
+                    path,
+                    error,
+                });
+                continue;
+            }
+        };
+        for item in &mut items {
+            skipping::evaluate_item(item, &options.target);
+        }
+        collection.manifest.add_module(&path, &items);
+        tests.extend(module_tests(&path, &items, config));
+    }
+
+    let mut deselected = HashSet::new();
+    for test in reorder_tests(tests) {
+        let selected = keyword.is_none_or(|expr| expr.matches_keywords(&test.keywords))
+            && markexpr.is_none_or(|expr| expr.matches_marks(&test.marks));
+        if selected {
+            collection.tests.push(test);
+        } else {
+            collection.deselected += 1;
+            deselected.insert(test.nodeid);
+        }
+    }
+    collection
+        .manifest
+        .deselect(|node| deselected.contains(&node.nodeid));
+
+    if options.prune_skipped {
+        let selected = collection.tests.len();
+        collection.tests.retain(|test| !test.skipped);
+        collection.skipped = selected - collection.tests.len();
+        collection.manifest.prune_skipped();
+    }
+    collection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::FixtureScope;
+    use crate::Literal;
+
+    const TEST_MODULE: &str = r#"
+import pytest
+
+@pytest.mark.parametrize("value", [1, 2, 3])
+def test_parametrized(value):
+    assert value > 0
+
+def test_simple():
+    assert True
+
+class TestExample:
+    def test_method(self):
+        pass
+"#;
+
+    fn nodeids(collection: &Collection) -> Vec<&str> {
+        collection.tests.iter().map(|t| t.nodeid.as_str()).collect()
+    }
+
+    #[test]
+    fn test_file_collection() {
+        let dir = std::env::temp_dir().join("rytest_session_file");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("test_temp.py"), TEST_MODULE).unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let collection = collect(
+            &[dir.join("test_temp.py")],
+            &config,
+            &CollectOptions::default(),
+            None,
+            None,
+        );
+        assert_eq!(
+            nodeids(&collection),
+            vec![
+                "test_temp.py::test_parametrized[1]",
+                "test_temp.py::test_parametrized[2]",
+                "test_temp.py::test_parametrized[3]",
+                "test_temp.py::test_simple",
+                "test_temp.py::TestExample::test_method",
+            ]
+        );
+        assert_eq!(collection.tests[3].line_number, 8);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_continue_on_errors() {
+        let dir = std::env::temp_dir().join("rytest_session_errors");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("conftest.py"),
+            "import pytest\n\n@pytest.fixture(params=load_params())\ndef db(request):\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("test_a.py"),
+            "import pytest\n\n@pytest.mark.parametrize('x', 3)\ndef test_x(x):\n    pass\n\n\
+             def test_db(db):\n    pass\n\ndef test_ok():\n    pass\n",
+        )
+        .unwrap();
+        let config = IniConfig::with_defaults(dir.clone());
+        let args = [dir.join("test_a.py")];
+
+        let collection = collect(&args, &config, &CollectOptions::default(), None, None);
+        assert!(collection.tests.is_empty());
+        assert_eq!(collection.errors.len(), 1);
+        assert_eq!(collection.errors[0].error.kind, ErrorKind::Parametrize);
+
+        let options = CollectOptions {
+            continue_on_errors: true,
+            ..CollectOptions::default()
+        };
+        let collection = collect(&args, &config, &options, None, None);
+        assert_eq!(nodeids(&collection), vec!["test_a.py::test_ok"]);
+        let errors: Vec<(ErrorKind, String, usize)> = collection
+            .errors
+            .iter()
+            .map(|e| {
+                let span = e.error.span.as_ref().unwrap();
+                (e.error.kind, e.nodeid.clone(), span.line)
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ErrorKind::Parametrize, "test_a.py".to_string(), 3),
+                (ErrorKind::FixtureParams, "test_a.py".to_string(), 3),
+            ]
+        );
+        // The fixture's error points into the conftest file
+        assert!(collection.errors[1]
+            .error
+            .span
+            .as_ref()
+            .unwrap()
+            .path
+            .ends_with("conftest.py"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_file_encodings() {
+        let dir = std::env::temp_dir().join("rytest_session_encodings");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let parse = |name: &str, source: &[u8]| {
+            let path = dir.join(name);
+            fs::write(&path, source).unwrap();
+            parse_file(&path, &["Test*".to_string()], &["test*".to_string()])
+        };
+        let names = |parsed: ParsedModule| -> Vec<(String, usize)> {
+            parsed
+                .items
+                .into_iter()
+                .map(|item| (item.name, item.line_number))
+                .collect()
+        };
+
+        let parsed = parse("test_bom.py", b"\xef\xbb\xbfdef test_first():\n    pass\n").unwrap();
+        assert_eq!(names(parsed), vec![("test_first".to_string(), 1)]);
+
+        let parsed = parse(
+            "test_latin1.py",
+            b"# -*- coding: latin-1 -*-\nimport pytest\n\n\
+              @pytest.mark.parametrize('name', ['caf\xe9'])\ndef test_caf\xe9(name):\n    pass\n",
+        )
+        .unwrap();
+        assert_eq!(names(parsed.clone()), vec![("test_café".to_string(), 5)]);
+        assert_eq!(
+            parsed.items[0].parameters[0].argvalues,
+            vec![vec![Literal::Str("café".to_string())]]
+        );
+
+        let error = parse("test_bad.py", b"def test_x():\n    s = '\xe9'\n").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Encoding);
+        let span = error.span.as_ref().unwrap();
+        assert_eq!((span.line, span.column), (2, 10));
+        assert!(error
+            .to_string()
+            .ends_with(":2:10: 'utf-8' codec can't decode byte 0xe9"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_package_collection() {
+        let dir = std::env::temp_dir().join("rytest_session_package");
+        let _ = fs::remove_dir_all(&dir);
+        let pkg_dir = dir.join("test_pkg");
+        fs::create_dir_all(&pkg_dir).unwrap();
+        fs::write(
+            pkg_dir.join("__init__.py"),
+            "def test_init():\n    assert True\n",
+        )
+        .unwrap();
+        fs::write(pkg_dir.join("test_module.py"), TEST_MODULE).unwrap();
+        fs::write(
+            dir.join("conftest.py"),
+            "import pytest\n\n@pytest.fixture(scope=\"session\")\ndef db():\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            pkg_dir.join("test_broken.py"),
+            "@pytest.mark.parametrize(\"x\", 3)\ndef test_x(x):\n    pass\n",
+        )
+        .unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let keyword = Expression::compile("simple or method").unwrap();
+        let collection = collect(
+            std::slice::from_ref(&dir),
+            &config,
+            &CollectOptions::default(),
+            Some(&keyword),
+            None,
+        );
+        assert_eq!(
+            nodeids(&collection),
+            vec![
+                "test_pkg/test_module.py::test_simple",
+                "test_pkg/test_module.py::TestExample::test_method",
+            ]
+        );
+        assert_eq!(collection.deselected, 3);
+        assert_eq!(collection.errors.len(), 1);
+        assert_eq!(collection.errors[0].nodeid, "test_pkg/test_broken.py");
+        assert_eq!(collection.manifest.deselected, 3);
+        assert!(collection
+            .manifest
+            .contains("test_pkg/test_module.py::TestExample"));
+        assert!(!collection
+            .manifest
+            .contains("test_pkg/test_module.py::test_parametrized[1]"));
+
+        let fixtures = &collection.manifest.fixtures;
+        let db: Vec<_> = fixtures.get("db").collect();
+        assert_eq!(db.len(), 1);
+        assert_eq!(db[0].scope, Some(FixtureScope::Session));
+        assert_eq!(db[0].line_number, 3);
+        assert!(fixtures.contains_file(&dir.join("conftest.py").to_string_lossy()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_tests() {
+        let dir = std::env::temp_dir().join("rytest_session_generate");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("hooked")).unwrap();
+        fs::write(
+            dir.join("hooked/conftest.py"),
+            "def pytest_generate_tests(metafunc):\n    pass\n",
+        )
+        .unwrap();
+        fs::write(dir.join("hooked/test_a.py"), "def test_a(x):\n    pass\n").unwrap();
+        fs::write(
+            dir.join("test_b.py"),
+            r#"
+def test_plain():
+    pass
+
+class TestB:
+    def pytest_generate_tests(self, metafunc):
+        metafunc.parametrize("x", [1, 2])
+
+    def test_b(self, x):
+        pass
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("test_c.py"),
+            "class TestHelper:\n    def pytest_generate_tests(self, metafunc):\n        pass\n\ndef test_c():\n    pass\n",
+        )
+        .unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let collection = collect(
+            std::slice::from_ref(&dir),
+            &config,
+            &CollectOptions::default(),
+            None,
+            None,
+        );
+        // A class with the hook but no tests does not affect the module's other tests
+        assert_eq!(nodeids(&collection), vec!["test_c.py::test_c"]);
+        let errors: Vec<(&str, String)> = collection
+            .errors
+            .iter()
+            .map(|e| (e.nodeid.as_str(), e.error.to_string()))
+            .collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, "hooked/test_a.py");
+        assert!(errors[0].1.contains("hooked/conftest.py"));
+        assert_eq!(errors[1].0, "test_b.py");
+        assert!(errors[1].1.contains("class TestB"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_doctests() {
+        let dir = std::env::temp_dir().join("rytest_session_doctests");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("pkg")).unwrap();
+        fs::write(dir.join("pkg/__init__.py"), "").unwrap();
+        fs::write(
+            dir.join("pkg/util.py"),
+            "def double(x):\n    \"\"\"\n    >>> double(2)\n    4\n    \"\"\"\n    return 2 * x\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("pkg/test_util.py"),
+            "def test_double():\n    '>>> 1'\n",
+        )
+        .unwrap();
+        fs::write(dir.join("test_notes.txt"), ">>> 1 + 1\n2\n").unwrap();
+        fs::write(dir.join("README.rst"), ">>> 1 + 1\n2\n").unwrap();
+        fs::write(dir.join("setup.py"), "'>>> 1'\nimport setuptools\n").unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let collection = collect(
+            std::slice::from_ref(&dir),
+            &config,
+            &CollectOptions::default(),
+            None,
+            None,
+        );
+        assert_eq!(
+            nodeids(&collection),
+            vec![
+                "pkg/test_util.py::test_double",
+                "test_notes.txt::test_notes.txt"
+            ]
+        );
+
+        // Explicitly given text files are collected whatever their name
+        let mut options = CollectOptions::default();
+        options.doctests.extend(&["--doctest-modules".to_string()]);
+        let keyword = Expression::compile("double or notes or README").unwrap();
+        let collection = collect(
+            &[dir.clone(), dir.join("README.rst")],
+            &config,
+            &options,
+            Some(&keyword),
+            None,
+        );
+        assert_eq!(
+            nodeids(&collection),
+            vec![
+                "pkg/test_util.py::pkg.test_util.test_double",
+                "pkg/test_util.py::test_double",
+                "pkg/util.py::pkg.util.double",
+                "test_notes.txt::test_notes.txt",
+                "README.rst::README.rst",
+            ]
+        );
+        assert_eq!(collection.tests[2].line_number, 2);
+        let json = collection.manifest.to_json();
+        let node = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["nodeid"] == "pkg/util.py::pkg.util.double")
+            .unwrap();
+        assert_eq!(node["kind"], "doctest");
+        assert_eq!(node["line"], 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fixture_parametrization() {
+        let dir = std::env::temp_dir().join("rytest_session_fixtures");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("conftest.py"),
+            "@pytest.fixture(scope=\"module\", params=[\"a\", \"b\"])\ndef res(request):\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("test_fix.py"),
+            r#"
+@pytest.fixture(params=[f"n{i}" for i in range(2)])
+def num(request, res):
+    pass
+
+def test_one(num):
+    pass
+
+@pytest.mark.parametrize("num", [7], indirect=True)
+def test_two(num, x=1):
+    pass
+"#,
+        )
+        .unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let collection = collect(
+            std::slice::from_ref(&dir),
+            &config,
+            &CollectOptions::default(),
+            None,
+            None,
+        );
+        // Tests sharing a module-scoped param are grouped together, as pytest reorders them
+        assert_eq!(
+            nodeids(&collection),
+            vec![
+                "test_fix.py::test_one[a-n0]",
+                "test_fix.py::test_one[a-n1]",
+                "test_fix.py::test_two[a-7]",
+                "test_fix.py::test_one[b-n0]",
+                "test_fix.py::test_one[b-n1]",
+                "test_fix.py::test_two[b-7]",
+            ]
+        );
+        assert!(collection.manifest.contains("test_fix.py::test_one[b-n1]"));
+        let json = collection.manifest.to_json();
+        let node = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["nodeid"] == "test_fix.py::test_one[a-n0]")
+            .unwrap();
+        assert_eq!(
+            node["fixtures"],
+            serde_json::json!([
+                {"name": "res", "scope": "module", "kind": "fixture", "file": "conftest.py", "line": 1},
+                {"name": "num", "scope": "function", "kind": "fixture", "file": "test_fix.py", "line": 2},
+                {"name": "request", "scope": "function", "kind": "builtin", "file": null, "line": null},
+            ])
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune_skipped() {
+        let dir = std::env::temp_dir().join("rytest_session_skipping");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("test_skips.py"),
+            r#"
+import sys
+import pytest
+
+@pytest.mark.skip(reason="broken")
+def test_broken():
+    pass
+
+@pytest.mark.skipif(sys.platform == "win32", reason="posix only")
+def test_posix():
+    pass
+
+@pytest.mark.skipif(not HAS_NUMPY, reason="needs numpy")
+def test_numpy():
+    pass
+
+@pytest.mark.xfail(sys.version_info < (3, 12), reason="old", run=False)
+@pytest.mark.parametrize("x", [1, 2])
+def test_new(x):
+    pass
+
+@pytest.mark.skipif(sys.platform == "win32", reason="posix only")
+class TestPosix:
+    def test_method(self):
+        pass
+"#,
+        )
+        .unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let mut options = CollectOptions {
+            target: Target {
+                platform: Some("win32".into()),
+                version: Some((3, 11, 0)),
+                implementation: None,
+            },
+            prune_skipped: true,
+            ..CollectOptions::default()
+        };
+        let keyword = Expression::compile("not numpy").unwrap();
+        let collection = collect(
+            std::slice::from_ref(&dir),
+            &config,
+            &options,
+            Some(&keyword),
+            None,
+        );
+        assert_eq!(
+            nodeids(&collection),
+            vec!["test_skips.py::test_new[1]", "test_skips.py::test_new[2]"]
+        );
+        assert_eq!((collection.deselected, collection.skipped), (1, 3));
+        assert!(!collection.manifest.contains("test_skips.py::test_broken"));
+        let json = collection.manifest.to_json();
+        assert_eq!(json["skipped"], 3);
+        let node = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["nodeid"] == "test_skips.py::test_new[1]")
+            .unwrap();
+        assert_eq!(node["skip"], serde_json::Value::Null);
+        assert_eq!(
+            node["xfail"],
+            serde_json::json!({"reason": "old", "run": false})
+        );
+
+        // Without pruning skipped tests are listed, with the verdicts in the manifest
+        options.target.platform = Some("linux".into());
+        options.prune_skipped = false;
+        let collection = collect(std::slice::from_ref(&dir), &config, &options, None, None);
+        assert_eq!(collection.tests.len(), 6);
+        assert_eq!(collection.skipped, 0);
+        let skipped: Vec<&str> = collection
+            .tests
+            .iter()
+            .filter(|test| test.skipped)
+            .map(|test| test.nodeid.as_str())
+            .collect();
+        assert_eq!(skipped, vec!["test_skips.py::test_broken"]);
+        let json = collection.manifest.to_json();
+        let node = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["nodeid"] == "test_skips.py::test_numpy")
+            .unwrap();
+        assert_eq!(
+            node["skip"],
+            serde_json::json!({"unknown": "not HAS_NUMPY"})
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_param_marks() {
+        let dir = std::env::temp_dir().join("rytest_session_param_marks");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("test_params.py"),
+            r#"
+import pytest
+
+@pytest.mark.parametrize("x", [
+    1,
+    pytest.param(2, marks=pytest.mark.slow),
+    pytest.param(3, marks=[pytest.mark.skip(reason="broken"), pytest.mark.db]),
+])
+def test_x(x):
+    pass
+"#,
+        )
+        .unwrap();
+        let config = IniConfig::with_defaults(dir.clone());
+        let run = |options: &CollectOptions, keyword: &str, markexpr: &str| {
+            let compile =
+                |expr: &str| (!expr.is_empty()).then(|| Expression::compile(expr).unwrap());
+            let (keyword, markexpr) = (compile(keyword), compile(markexpr));
+            let collection = collect(
+                std::slice::from_ref(&dir),
+                &config,
+                options,
+                keyword.as_ref(),
+                markexpr.as_ref(),
+            );
+            nodeids(&collection)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        let options = CollectOptions::default();
+        assert_eq!(run(&options, "", "slow"), vec!["test_params.py::test_x[2]"]);
+        assert_eq!(
+            run(&options, "", "not slow"),
+            vec!["test_params.py::test_x[1]", "test_params.py::test_x[3]"]
+        );
+        assert_eq!(run(&options, "db", ""), vec!["test_params.py::test_x[3]"]);
+
+        let pruning = CollectOptions {
+            prune_skipped: true,
+            ..CollectOptions::default()
+        };
+        assert_eq!(
+            run(&pruning, "", ""),
+            vec!["test_params.py::test_x[1]", "test_params.py::test_x[2]"]
+        );
+
+        // The manifest lists the marks of each parameter set
+        let collection = collect(std::slice::from_ref(&dir), &config, &options, None, None);
+        let json = collection.manifest.to_json();
+        let node = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["nodeid"] == "test_params.py::test_x[3]")
+            .unwrap();
+        let marks: Vec<&str> = node["marks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|mark| mark["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(marks, vec!["parametrize", "skip", "db"]);
+        assert_eq!(node["skip"], serde_json::json!({"reason": "broken"}));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_hypothesis() {
+        let dir = std::env::temp_dir().join("rytest_session_hypothesis");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("test_props.py"),
+            r#"
+from hypothesis import example, given, strategies as st
+
+@pytest.fixture
+def n():
+    return 1
+
+@given(n=st.integers())
+@example(n=0)
+def test_roundtrip(tmp_path, n):
+    pass
+
+def test_plain(n):
+    pass
+"#,
+        )
+        .unwrap();
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let markexpr = Expression::compile("hypothesis").unwrap();
+        let collection = collect(
+            std::slice::from_ref(&dir),
+            &config,
+            &CollectOptions::default(),
+            None,
+            Some(&markexpr),
+        );
+        assert_eq!(nodeids(&collection), vec!["test_props.py::test_roundtrip"]);
+        let json = collection.manifest.to_json();
+        let node = json["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|node| node["nodeid"] == "test_props.py::test_roundtrip")
+            .unwrap();
+        // `n` comes from `@given`, not the fixture of the same name
+        let fixtures: Vec<&str> = node["fixtures"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|fixture| fixture["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(fixtures, vec!["tmp_path_factory", "tmp_path", "request"]);
+        assert_eq!(
+            node["hypothesis"],
+            serde_json::json!({"given": ["n"], "examples": 1, "max_examples": null})
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,391 @@
+//! Splitting collected tests into shards for parallel CI jobs.
+//!
+//! Tests are grouped into units (files, classes or single tests) that are
+//! never split across shards, and the units are dealt out greedily, largest
+//! first, to the shard with the least work so far. Work is measured in
+//! seconds when durations are known and in tests otherwise. The result only
+//! depends on the set of node ids and the durations, so every job computes
+//! the same partition from its own collection.
+//!
+//! Durations are therefore only read from files every job is given the same
+//! copies of. Nothing is kept in pytest's cache: a job's own records would
+//! hold only the tests of the shard it ran, which differ from machine to
+//! machine, so the plugin stores them in a file the job names to be shared.
+
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What is kept together in one shard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Granularity {
+    /// Every test of a module
+    #[default]
+    File,
+    /// Every test of a class, and each module's plain test functions
+    Class,
+    /// Nothing; tests are assigned one by one
+    Test,
+}
+
+impl Granularity {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "file" => Some(Granularity::File),
+            "class" => Some(Granularity::Class),
+            "test" => Some(Granularity::Test),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::File => "file",
+            Granularity::Class => "class",
+            Granularity::Test => "test",
+        }
+    }
+
+    /// The unit `nodeid` belongs to
+    ///
+    /// Classes are the node id segments between the module and the test,
+    /// so a module's functions share the module's unit, as with xdist's
+    /// `--dist loadscope`.
+    pub fn unit<'a>(&self, nodeid: &'a str) -> &'a str {
+        // Parametrize ids may contain anything, `::` included
+        let name_end = nodeid.find('[').unwrap_or(nodeid.len());
+        let segments: Vec<usize> = nodeid[..name_end]
+            .match_indices("::")
+            .map(|(i, _)| i)
+            .collect();
+        match self {
+            Granularity::File => &nodeid[..segments.first().copied().unwrap_or(name_end)],
+            Granularity::Class if segments.len() >= 2 => &nodeid[..segments[segments.len() - 1]],
+            Granularity::Class => &nodeid[..segments.first().copied().unwrap_or(name_end)],
+            Granularity::Test => nodeid,
+        }
+    }
+}
+
+/// How long each test took, in seconds
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Durations {
+    seconds: HashMap<String, f64>,
+}
+
+impl Durations {
+    /// Durations from JSON: an object mapping node ids to seconds, as
+    /// pytest-split's `.test_durations` holds them, or a list of
+    /// `[nodeid, seconds]` pairs
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let pairs: Vec<(&str, &Value)> = match value {
+            Value::Object(map) => map.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+            Value::Array(pairs) => pairs
+                .iter()
+                .map(|pair| Some((pair.get(0)?.as_str()?, pair.get(1)?)))
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        let seconds = pairs
+            .into_iter()
+            .map(|(nodeid, seconds)| Some((nodeid.to_string(), seconds.as_f64()?)))
+            .collect::<Option<_>>()?;
+        Some(Durations { seconds })
+    }
+
+    pub fn to_json(&self) -> Value {
+        // Sorted, so a stored file does not churn between runs
+        let sorted: BTreeMap<&String, &f64> = self.seconds.iter().collect();
+        Value::Object(
+            sorted
+                .into_iter()
+                .map(|(nodeid, seconds)| (nodeid.clone(), Value::from(*seconds)))
+                .collect::<Map<_, _>>(),
+        )
+    }
+
+    /// Read a timing file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .ok()
+            .and_then(|value| Self::from_json(&value))
+            .ok_or_else(|| {
+                format!(
+                    "{}: expected a JSON object mapping node ids to seconds",
+                    path.display()
+                )
+            })
+    }
+
+    /// Read and merge timing files, later files replacing the durations of
+    /// the same tests in earlier ones
+    pub fn load_all(paths: &[PathBuf]) -> Result<Self, String> {
+        let mut merged = Durations::default();
+        for path in paths {
+            merged.seconds.extend(Self::load(path)?.seconds);
+        }
+        Ok(merged)
+    }
+
+    /// Merge `durations` into the timing file at `path`, replacing those of
+    /// the same tests, and create the file if it does not exist
+    pub fn store(path: &Path, durations: &[(String, f64)]) -> Result<(), String> {
+        let mut stored = if path.exists() {
+            Self::load(path)?
+        } else {
+            Self::default()
+        };
+        stored.seconds.extend(durations.iter().cloned());
+        fs::write(path, format!("{:#}\n", stored.to_json()))
+            .map_err(|e| format!("could not write {}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, nodeid: &str) -> Option<f64> {
+        self.seconds.get(nodeid).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.seconds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seconds.is_empty()
+    }
+}
+
+/// One part of a partition
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Shard {
+    /// Indices into the partitioned node ids, in collection order
+    pub tests: Vec<usize>,
+    /// The expected work: seconds with durations, tests without
+    pub weight: f64,
+}
+
+/// Partition `nodeids` into `total` shards of about equal weight
+///
+/// A test without a known duration weighs the mean of the known durations
+/// of `nodeids`, or 1 if none is known.
+pub fn partition(
+    nodeids: &[String],
+    total: usize,
+    granularity: Granularity,
+    durations: &Durations,
+) -> Vec<Shard> {
+    let known: Vec<f64> = nodeids
+        .iter()
+        .filter_map(|nodeid| durations.get(nodeid))
+        .collect();
+    let default = if known.is_empty() {
+        1.0
+    } else {
+        known.iter().sum::<f64>() / known.len() as f64
+    };
+
+    let mut units: BTreeMap<&str, (f64, Vec<usize>)> = BTreeMap::new();
+    for (i, nodeid) in nodeids.iter().enumerate() {
+        let unit = units.entry(granularity.unit(nodeid)).or_default();
+        unit.0 += durations.get(nodeid).unwrap_or(default);
+        unit.1.push(i);
+    }
+    let mut units: Vec<(&str, (f64, Vec<usize>))> = units.into_iter().collect();
+    // Heaviest first; the sort is stable, so equal units stay in name order
+    units.sort_by(|(_, (a, _)), (_, (b, _))| b.total_cmp(a));
+
+    let mut shards = vec![Shard::default(); total.max(1)];
+    for (_, (weight, tests)) in units {
+        let lightest = (0..shards.len())
+            .min_by(|&a, &b| shards[a].weight.total_cmp(&shards[b].weight))
+            .unwrap_or_default();
+        shards[lightest].weight += weight;
+        shards[lightest].tests.extend(tests);
+    }
+    for shard in &mut shards {
+        shard.tests.sort_unstable();
+    }
+    shards
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn nodeids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_unit() {
+        let nodeid = "tests/test_a.py::TestA::TestNested::test_x[a::b]";
+        assert_eq!(Granularity::File.unit(nodeid), "tests/test_a.py");
+        assert_eq!(
+            Granularity::Class.unit(nodeid),
+            "tests/test_a.py::TestA::TestNested"
+        );
+        assert_eq!(Granularity::Test.unit(nodeid), nodeid);
+        assert_eq!(
+            Granularity::Class.unit("tests/test_a.py::test_y"),
+            "tests/test_a.py"
+        );
+        assert_eq!(Granularity::File.unit("docs/index.txt"), "docs/index.txt");
+        assert_eq!(Granularity::parse("class"), Some(Granularity::Class));
+        assert_eq!(Granularity::parse("module"), None);
+    }
+
+    #[test]
+    fn test_partition() {
+        let ids = nodeids(&[
+            "a.py::test_1",
+            "a.py::test_2",
+            "a.py::test_3",
+            "b.py::TestB::test_1",
+            "b.py::TestB::test_2",
+            "b.py::test_3",
+            "c.py::test_1",
+        ]);
+        let assigned = |shards: &[Shard]| -> Vec<Vec<&str>> {
+            shards
+                .iter()
+                .map(|shard| shard.tests.iter().map(|&i| ids[i].as_str()).collect())
+                .collect()
+        };
+
+        let shards = partition(&ids, 2, Granularity::File, &Durations::default());
+        assert_eq!(
+            assigned(&shards),
+            vec![
+                vec![
+                    "a.py::test_1",
+                    "a.py::test_2",
+                    "a.py::test_3",
+                    "c.py::test_1"
+                ],
+                vec!["b.py::TestB::test_1", "b.py::TestB::test_2", "b.py::test_3"],
+            ]
+        );
+        assert_eq!(shards[0].weight, 4.0);
+
+        let shards = partition(&ids, 3, Granularity::Class, &Durations::default());
+        assert_eq!(
+            assigned(&shards)[1],
+            vec!["b.py::TestB::test_1", "b.py::TestB::test_2"]
+        );
+
+        // Every test lands in exactly one shard, whatever the order
+        let mut reversed = ids.clone();
+        reversed.reverse();
+        let shards = partition(&reversed, 4, Granularity::Test, &Durations::default());
+        let mut all: Vec<usize> = shards.iter().flat_map(|s| s.tests.clone()).collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..ids.len()).collect::<Vec<_>>());
+        assert_eq!(
+            partition(&ids, 4, Granularity::Test, &Durations::default())
+                .iter()
+                .map(|s| s.tests.len())
+                .collect::<Vec<_>>(),
+            vec![2, 2, 2, 1]
+        );
+
+        // A slow test gets a shard to itself
+        let mut seconds: Map<String, Value> = ids.iter().map(|id| (id.clone(), json!(1))).collect();
+        seconds.insert("a.py::test_1".to_string(), json!(12));
+        let durations = Durations::from_json(&Value::Object(seconds)).unwrap();
+        let shards = partition(&ids, 2, Granularity::Test, &durations);
+        assert_eq!(assigned(&shards)[0], vec!["a.py::test_1"]);
+        assert_eq!(shards[1].weight, 6.0);
+
+        // Tests without a duration weigh the mean of the known ones
+        let durations =
+            Durations::from_json(&json!({"a.py::test_1": 3, "c.py::test_1": 1})).unwrap();
+        let shards = partition(&ids, 1, Granularity::File, &durations);
+        assert_eq!(shards[0].weight, 14.0);
+
+        assert_eq!(partition(&[], 3, Granularity::File, &durations).len(), 3);
+    }
+
+    #[test]
+    fn test_durations() {
+        let pairs = json!([["a.py::test_1", 1.5], ["a.py::test_2", 2]]);
+        let durations = Durations::from_json(&pairs).unwrap();
+        assert_eq!(durations.get("a.py::test_2"), Some(2.0));
+        assert_eq!(
+            durations.to_json(),
+            json!({"a.py::test_1": 1.5, "a.py::test_2": 2.0})
+        );
+        assert!(Durations::from_json(&json!({"a.py::test_1": "slow"})).is_none());
+
+        let dir = std::env::temp_dir().join("rytest_shard");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let stored = dir.join("stored.json");
+        Durations::store(&stored, &[("a.py::test_1".to_string(), 1.0)]).unwrap();
+        Durations::store(&stored, &[("a.py::test_2".to_string(), 3.0)]).unwrap();
+        Durations::store(&stored, &[("a.py::test_1".to_string(), 2.0)]).unwrap();
+        let loaded = Durations::load(&stored).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.get("a.py::test_1"), Some(2.0));
+
+        fs::write(dir.join("durations.json"), "[1, 2]").unwrap();
+        assert!(Durations::load(&dir.join("durations.json")).is_err());
+        assert!(Durations::store(&dir.join("durations.json"), &[]).is_err());
+        assert!(Durations::load(&dir.join("missing.json")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_shared_durations() {
+        let ids = nodeids(&[
+            "a.py::test_1",
+            "a.py::test_2",
+            "b.py::test_1",
+            "c.py::test_1",
+            "d.py::test_1",
+        ]);
+        // Two CI machines, each having recorded only the tests of its own shard
+        let dir = std::env::temp_dir().join("rytest_shared_durations");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let recorded: [&[(&str, f64)]; 2] = [
+            &[("a.py::test_1", 9.0), ("a.py::test_2", 1.0)],
+            &[("b.py::test_1", 0.5), ("d.py::test_1", 4.0)],
+        ];
+        let mut files = Vec::new();
+        for (job, durations) in recorded.into_iter().enumerate() {
+            let durations: Vec<(String, f64)> = durations
+                .iter()
+                .map(|&(nodeid, seconds)| (nodeid.to_string(), seconds))
+                .collect();
+            let file = dir.join(format!("{}.json", job));
+            Durations::store(&file, &durations).unwrap();
+            files.push(file);
+        }
+        // The tests both jobs run, each balancing by the durations it reads
+        let run = |read: &dyn Fn(usize) -> Durations| {
+            let mut all: Vec<usize> = (0..2)
+                .flat_map(|job| {
+                    partition(&ids, 2, Granularity::File, &read(job))
+                        .swap_remove(job)
+                        .tests
+                })
+                .collect();
+            all.sort_unstable();
+            all
+        };
+        // By its own recording, d.py runs in both jobs and b.py in neither
+        assert_eq!(
+            run(&|job| Durations::load(&files[job]).unwrap()),
+            vec![0, 1, 3, 4, 4]
+        );
+        // Given the same files, every test runs exactly once
+        assert_eq!(Durations::load_all(&files).unwrap().len(), 4);
+        assert_eq!(
+            run(&|_| Durations::load_all(&files).unwrap()),
+            (0..ids.len()).collect::<Vec<_>>()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,396 @@
+//! Static evaluation of `skip`, `skipif` and `xfail` marks, which pytest's
+//! skipping plugin otherwise evaluates when each test is set up.
+
+use crate::literal::Literal;
+use crate::parser::{Arg, Mark, TestItem};
+use serde::{Deserialize, Serialize};
+
+/// The interpreter conditions are evaluated for
+///
+/// Unknown fields leave the conditions that use them unevaluated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Target {
+    /// `sys.platform`, e.g. `linux`, `darwin` or `win32`
+    pub platform: Option<String>,
+    /// `sys.version_info[:3]`
+    pub version: Option<(u32, u32, u32)>,
+    /// `sys.implementation.name`, e.g. `cpython` or `pypy`
+    pub implementation: Option<String>,
+}
+
+impl Target {
+    /// The platform rytest itself runs on, with no Python version
+    pub fn host() -> Self {
+        let platform = match std::env::consts::OS {
+            "linux" => Some("linux"),
+            "macos" => Some("darwin"),
+            "windows" => Some("win32"),
+            _ => None,
+        };
+        Target {
+            platform: platform.map(String::from),
+            ..Target::default()
+        }
+    }
+
+    /// Parse a Python version such as `3.12` or `3.12.1`
+    pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+        let parts: Vec<u32> = version
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        match parts[..] {
+            [major, minor] => Some((major, minor, 0)),
+            [major, minor, micro] => Some((major, minor, micro)),
+            _ => None,
+        }
+    }
+
+    /// The names a condition can use, as `Literal::evaluate_with` binds them
+    fn names(&self) -> Vec<(String, Literal)> {
+        let mut names = Vec::new();
+        let mut bind = |name: &str, value: Literal| names.push((name.to_string(), value));
+        let str = |value: &str| Literal::Str(value.to_string());
+        if let Some(platform) = &self.platform {
+            bind("sys.platform", str(platform));
+            bind(
+                "os.name",
+                str(if platform == "win32" { "nt" } else { "posix" }),
+            );
+            let system = match platform.as_str() {
+                "linux" => Some("Linux"),
+                "darwin" => Some("Darwin"),
+                "win32" => Some("Windows"),
+                _ => None,
+            };
+            if let Some(system) = system {
+                bind("platform.system()", str(system));
+            }
+        }
+        if let Some((major, minor, micro)) = self.version {
+            let [major, minor, micro] = [major, minor, micro].map(|n| Literal::Int(n.into()));
+            bind(
+                "sys.version_info",
+                Literal::Tuple(vec![
+                    major.clone(),
+                    minor.clone(),
+                    micro.clone(),
+                    str("final"),
+                    Literal::Int(0),
+                ]),
+            );
+            bind("sys.version_info.major", major);
+            bind("sys.version_info.minor", minor);
+            bind("sys.version_info.micro", micro);
+        }
+        if let Some(implementation) = &self.implementation {
+            bind("sys.implementation.name", str(implementation));
+            let name = match implementation.as_str() {
+                "cpython" => "CPython",
+                "pypy" => "PyPy",
+                other => other,
+            };
+            bind("platform.python_implementation()", str(name));
+        }
+        names
+    }
+}
+
+/// The outcome of one kind of mark for a test
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum Verdict {
+    /// No mark applies, or none of their conditions holds
+    #[default]
+    No,
+    Yes {
+        reason: String,
+    },
+    /// A condition rytest could not evaluate, as source text
+    Unknown {
+        condition: String,
+    },
+}
+
+/// What pytest's skipping plugin does with a test, as far as the marks tell statically
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Skipping {
+    /// Whether `skip` or `skipif` skip the test at setup
+    pub skip: Verdict,
+    /// Whether `xfail` expects the test to fail
+    pub xfail: Verdict,
+    /// The `run` argument of the `xfail` mark that applies; with `run=False`
+    /// the test is reported as xfailed without being run
+    pub xfail_run: bool,
+}
+
+impl Default for Skipping {
+    fn default() -> Self {
+        Skipping {
+            skip: Verdict::No,
+            xfail: Verdict::No,
+            xfail_run: true,
+        }
+    }
+}
+
+/// A mark's keyword argument, as source text
+fn keyword<'a>(mark: &'a Mark, name: &str) -> Option<&'a str> {
+    mark.args.iter().find_map(|arg| match arg {
+        Arg::Keyword(key, value) if key == name => Some(value.as_str()),
+        _ => None,
+    })
+}
+
+/// The `reason` of a mark: the string's value, or its source if not a literal
+fn reason(mark: &Mark) -> Option<String> {
+    keyword(mark, "reason").map(|src| match Literal::parse(src) {
+        Ok(Literal::Str(reason)) => reason,
+        _ => src.to_string(),
+    })
+}
+
+/// The conditions of a `skipif` or `xfail` mark: positional arguments, else `condition=`
+fn conditions(mark: &Mark) -> Vec<&str> {
+    let positional: Vec<&str> = mark
+        .args
+        .iter()
+        .filter_map(|arg| match arg {
+            Arg::Positional(value) => Some(value.as_str()),
+            Arg::Keyword(..) => None,
+        })
+        .collect();
+    if positional.is_empty() {
+        keyword(mark, "condition").into_iter().collect()
+    } else {
+        positional
+    }
+}
+
+/// Evaluate the conditions of a `skipif` or `xfail` mark, as pytest's
+/// `evaluate_condition` does for each in turn
+///
+/// A string condition is evaluated as an expression; its default reason is
+/// `condition: <expression>`. Any other condition needs `reason=`.
+fn evaluate_mark(mark: &Mark, names: &[(String, Literal)]) -> Verdict {
+    let mut verdict = Verdict::No;
+    for condition in conditions(mark) {
+        let (expression, default_reason) = match Literal::parse(condition) {
+            Ok(Literal::Str(expression)) => {
+                let reason = format!("condition: {}", expression);
+                (expression, Some(reason))
+            }
+            _ => (condition.to_string(), None),
+        };
+        let Some(reason) = reason(mark).or(default_reason) else {
+            // pytest fails the test instead of evaluating it
+            return Verdict::Unknown {
+                condition: expression,
+            };
+        };
+        match Literal::evaluate_with(&expression, names) {
+            Ok(value) if value.is_truthy() => return Verdict::Yes { reason },
+            Ok(_) => {}
+            Err(_) => {
+                if verdict == Verdict::No {
+                    verdict = Verdict::Unknown {
+                        condition: expression,
+                    };
+                }
+            }
+        }
+    }
+    verdict
+}
+
+/// Evaluate the `skip`, `skipif` and `xfail` marks of a test, closest first
+///
+/// A condition that holds skips the test whatever the conditions rytest could
+/// not evaluate turn out to be, though pytest may report another reason.
+pub fn evaluate(marks: &[Mark], target: &Target) -> Skipping {
+    let names = target.names();
+    let mut skipping = Skipping::default();
+
+    let mut skip = Verdict::No;
+    for mark in marks.iter().filter(|mark| mark.name == "skipif") {
+        let verdict = if conditions(mark).is_empty() {
+            Verdict::Yes {
+                reason: reason(mark).unwrap_or_else(|| "unconditional skip".to_string()),
+            }
+        } else {
+            evaluate_mark(mark, &names)
+        };
+        match verdict {
+            Verdict::Yes { .. } => {
+                skip = verdict;
+                break;
+            }
+            Verdict::Unknown { .. } if skip == Verdict::No => skip = verdict,
+            _ => {}
+        }
+    }
+    if !matches!(skip, Verdict::Yes { .. }) {
+        if let Some(mark) = marks.iter().find(|mark| mark.name == "skip") {
+            let positional = mark.args.iter().find_map(|arg| match arg {
+                Arg::Positional(value) => Literal::parse(value).ok()?.as_str().map(String::from),
+                Arg::Keyword(..) => None,
+            });
+            skip = Verdict::Yes {
+                reason: reason(mark)
+                    .or(positional)
+                    .unwrap_or_else(|| "unconditional skip".to_string()),
+            };
+        }
+    }
+    skipping.skip = skip;
+
+    for mark in marks.iter().filter(|mark| mark.name == "xfail") {
+        let verdict = if conditions(mark).is_empty() {
+            Verdict::Yes {
+                reason: reason(mark).unwrap_or_default(),
+            }
+        } else {
+            evaluate_mark(mark, &names)
+        };
+        if verdict == Verdict::No {
+            continue;
+        }
+        skipping.xfail_run = keyword(mark, "run")
+            .and_then(|run| Literal::parse(run).ok())
+            .is_none_or(|run| run != Literal::Bool(false));
+        let decided = matches!(verdict, Verdict::Yes { .. });
+        skipping.xfail = verdict;
+        if decided {
+            break;
+        }
+    }
+    skipping
+}
+
+/// Evaluate the skip and xfail marks of a test, and of each generated test
+/// whose `pytest.param` marks add to them
+pub fn evaluate_item(item: &mut TestItem, target: &Target) {
+    item.skipping = evaluate(&item.marks, target);
+    item.call_skipping.clear();
+    if !item.has_param_marks() {
+        return;
+    }
+    for call in item.expand() {
+        if !call.marks.is_empty() {
+            let skipping = evaluate(&item.call_marks(&call), target);
+            item.call_skipping.insert(call.name, skipping);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_mark;
+
+    fn marks(decorators: &[&str]) -> Vec<Mark> {
+        decorators
+            .iter()
+            .map(|decorator| parse_mark(decorator).unwrap())
+            .collect()
+    }
+
+    fn skip(decorators: &[&str], target: &Target) -> Verdict {
+        evaluate(&marks(decorators), target).skip
+    }
+
+    #[test]
+    fn test_skip_marks() {
+        let linux = Target {
+            platform: Some("linux".into()),
+            version: Some((3, 11, 4)),
+            implementation: Some("cpython".into()),
+        };
+        let yes = |reason: &str| Verdict::Yes {
+            reason: reason.to_string(),
+        };
+        let unknown = |condition: &str| Verdict::Unknown {
+            condition: condition.to_string(),
+        };
+
+        assert_eq!(
+            skip(&["pytest.mark.skip"], &linux),
+            yes("unconditional skip")
+        );
+        assert_eq!(skip(&["pytest.mark.skip('slow')"], &linux), yes("slow"));
+        assert_eq!(
+            skip(
+                &["pytest.mark.skipif(sys.platform == 'win32', reason='posix only')"],
+                &linux
+            ),
+            Verdict::No
+        );
+        assert_eq!(
+            skip(
+                &["pytest.mark.skipif(sys.version_info[:2] < (3, 12), reason='needs 3.12')"],
+                &linux
+            ),
+            yes("needs 3.12")
+        );
+        assert_eq!(
+            skip(&["pytest.mark.skipif(\"os.name != 'nt'\")"], &linux),
+            yes("condition: os.name != 'nt'")
+        );
+        assert_eq!(
+            skip(
+                &["pytest.mark.skipif(platform.python_implementation() == 'PyPy', reason='x')"],
+                &linux
+            ),
+            Verdict::No
+        );
+        // A condition that holds wins over one rytest cannot evaluate
+        assert_eq!(
+            skip(
+                &[
+                    "pytest.mark.skipif(not HAS_NUMPY, reason='numpy')",
+                    "pytest.mark.skipif(True, reason='always')",
+                ],
+                &linux
+            ),
+            yes("always")
+        );
+        assert_eq!(
+            skip(
+                &["pytest.mark.skipif(not HAS_NUMPY, reason='numpy')"],
+                &linux
+            ),
+            unknown("not HAS_NUMPY")
+        );
+        // Booleans need a reason, or pytest fails the test
+        assert_eq!(
+            skip(&["pytest.mark.skipif(False)"], &linux),
+            unknown("False")
+        );
+        assert_eq!(
+            skip(
+                &["pytest.mark.skipif(sys.version_info < (3, 9), reason='old')"],
+                &Target::default()
+            ),
+            unknown("sys.version_info < (3, 9)")
+        );
+
+        let skipping = evaluate(
+            &marks(&[
+                "pytest.mark.xfail(sys.platform == 'darwin', reason='mac')",
+                "pytest.mark.xfail(reason='flaky', run=False)",
+            ]),
+            &linux,
+        );
+        assert_eq!(skipping.skip, Verdict::No);
+        assert_eq!(skipping.xfail, yes("flaky"));
+        assert!(!skipping.xfail_run);
+        assert_eq!(evaluate(&[], &linux), Skipping::default());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(Target::parse_version("3.12"), Some((3, 12, 0)));
+        assert_eq!(Target::parse_version("3.9.18"), Some((3, 9, 18)));
+        assert_eq!(Target::parse_version("3"), None);
+        assert_eq!(Target::parse_version("3.x"), None);
+    }
+}
@@ -0,0 +1,328 @@
+//! Decoding Python source files the way CPython does.
+//!
+//! A module is UTF-8 unless it declares another encoding in a PEP 263 coding
+//! cookie, such as `# -*- coding: latin-1 -*-`, on its first line, or on its
+//! second line when the first is blank or a comment. A UTF-8 byte order mark
+//! is dropped, and conflicts with a cookie naming any other encoding.
+//!
+//! Besides UTF-8, the encodings rytest decodes itself are ASCII, Latin-1,
+//! Latin-9 and cp1252, the legacy encodings found in real test suites. A
+//! module in any other encoding fails to decode, and is left to pytest.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Why a module cannot be decoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    /// 1-based line of the offending byte, or of the cookie
+    pub line: usize,
+    /// 1-based column, in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (line {}, column {})",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// The source encodings rytest can decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Ascii,
+    Latin1,
+    Latin9,
+    Cp1252,
+}
+
+impl Encoding {
+    /// The encoding a cookie names, resolving aliases as Python's codec
+    /// registry does
+    pub fn lookup(name: &str) -> Option<Self> {
+        // The tokenizer's `get_normal_name` goes first, so `utf-8-sig` is UTF-8
+        let short: String = name
+            .chars()
+            .take(12)
+            .map(|c| match c {
+                '_' => '-',
+                c => c.to_ascii_lowercase(),
+            })
+            .collect();
+        let has_prefix = |prefix: &str| {
+            short == prefix
+                || short
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('-'))
+        };
+        if has_prefix("utf-8") {
+            return Some(Encoding::Utf8);
+        }
+        if ["latin-1", "iso-8859-1", "iso-latin-1"]
+            .into_iter()
+            .any(has_prefix)
+        {
+            return Some(Encoding::Latin1);
+        }
+        // `encodings.normalize_encoding`, and `codecs.lookup` lowercases
+        let normalized: String = name
+            .to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '.')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("_");
+        match normalized.as_str() {
+            "utf_8" | "utf8" | "u8" | "utf" | "utf8_ucs2" | "utf8_ucs4" | "cp65001" => {
+                Some(Encoding::Utf8)
+            }
+            "ascii" | "us_ascii" | "646" | "us" | "ansi_x3.4_1968" | "ansi_x3.4_1986"
+            | "ansi_x3_4_1968" | "cp367" | "csascii" | "ibm367" | "iso646_us"
+            | "iso_646.irv_1991" | "iso_ir_6" => Some(Encoding::Ascii),
+            "latin_1" | "latin1" | "latin" | "l1" | "iso8859_1" | "iso_8859_1" | "8859"
+            | "cp819" | "ibm819" | "iso_ir_100" | "csisolatin1" | "iso_latin_1" => {
+                Some(Encoding::Latin1)
+            }
+            "iso8859_15" | "iso_8859_15" | "latin9" | "latin_9" | "l9" | "csisolatin9" => {
+                Some(Encoding::Latin9)
+            }
+            "cp1252" | "windows_1252" | "1252" => Some(Encoding::Cp1252),
+            _ => None,
+        }
+    }
+
+    /// The codec name Python reports in decoding errors
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Ascii => "ascii",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Latin9 => "iso8859-15",
+            Encoding::Cp1252 => "cp1252",
+        }
+    }
+
+    /// The character a byte of a single-byte encoding stands for
+    fn char(&self, byte: u8) -> Option<char> {
+        match (self, byte) {
+            (_, 0..=0x7f) => Some(byte as char),
+            (Encoding::Latin1, _) => Some(byte as char),
+            (Encoding::Latin9, _) => Some(match byte {
+                0xa4 => '€',
+                0xa6 => 'Š',
+                0xa8 => 'š',
+                0xb4 => 'Ž',
+                0xb8 => 'ž',
+                0xbc => 'Œ',
+                0xbd => 'œ',
+                0xbe => 'Ÿ',
+                _ => byte as char,
+            }),
+            (Encoding::Cp1252, 0x80..=0x9f) => CP1252[byte as usize - 0x80],
+            (Encoding::Cp1252, _) => Some(byte as char),
+            (Encoding::Ascii | Encoding::Utf8, _) => None,
+        }
+    }
+}
+
+/// cp1252's 0x80 to 0x9f, where it departs from Latin-1; five are undefined
+const CP1252: [Option<char>; 32] = [
+    Some('€'),
+    None,
+    Some('‚'),
+    Some('ƒ'),
+    Some('„'),
+    Some('…'),
+    Some('†'),
+    Some('‡'),
+    Some('ˆ'),
+    Some('‰'),
+    Some('Š'),
+    Some('‹'),
+    Some('Œ'),
+    None,
+    Some('Ž'),
+    None,
+    None,
+    Some('\u{2018}'),
+    Some('\u{2019}'),
+    Some('\u{201c}'),
+    Some('\u{201d}'),
+    Some('•'),
+    Some('–'),
+    Some('—'),
+    Some('˜'),
+    Some('™'),
+    Some('š'),
+    Some('›'),
+    Some('œ'),
+    None,
+    Some('ž'),
+    Some('Ÿ'),
+];
+
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// The encoding named by the coding cookie on `line`, if it has one
+///
+/// PEP 263's `^[ \t\f]*#.*?coding[:=][ \t]*([-\w.]+)`.
+fn cookie(line: &[u8]) -> Option<&[u8]> {
+    let start = line
+        .iter()
+        .position(|b| !matches!(b, b' ' | b'\t' | b'\x0c'))?;
+    let comment = line[start..].strip_prefix(b"#")?;
+    let at = comment
+        .windows(7)
+        .position(|w| w[..6] == *b"coding" && matches!(w[6], b':' | b'='))?;
+    let rest = &comment[at + 7..];
+    let rest = &rest[rest
+        .iter()
+        .position(|b| !matches!(b, b' ' | b'\t'))
+        .unwrap_or(rest.len())..];
+    let len = rest
+        .iter()
+        .position(|&b| !(b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.')))
+        .unwrap_or(rest.len());
+    (len > 0).then_some(&rest[..len])
+}
+
+/// Whether `line` is blank or only a comment, so the cookie may follow it
+fn is_blank_or_comment(line: &[u8]) -> bool {
+    line.iter()
+        .find(|b| !matches!(b, b' ' | b'\t' | b'\x0c'))
+        .is_none_or(|b| matches!(b, b'#' | b'\r' | b'\n'))
+}
+
+/// The encoding of a module's bytes, with the byte order mark left out
+pub fn detect(bytes: &[u8]) -> Result<(Encoding, &[u8]), DecodeError> {
+    let (bom, bytes) = match bytes.strip_prefix(BOM) {
+        Some(rest) => (true, rest),
+        None => (false, bytes),
+    };
+    let mut lines = bytes.split_inclusive(|&b| b == b'\n');
+    let first = lines.next().unwrap_or_default();
+    let found = match cookie(first) {
+        Some(name) => Some((1, name)),
+        None if is_blank_or_comment(first) => lines.next().and_then(cookie).map(|name| (2, name)),
+        None => None,
+    };
+    let Some((line, name)) = found else {
+        return Ok((Encoding::Utf8, bytes));
+    };
+    let name = String::from_utf8_lossy(name);
+    let error = |message: String| DecodeError {
+        line,
+        column: 1,
+        message,
+    };
+    match Encoding::lookup(&name) {
+        Some(Encoding::Utf8) => Ok((Encoding::Utf8, bytes)),
+        Some(_) if bom => Err(error(format!("encoding problem: {} with BOM", name))),
+        Some(encoding) => Ok((encoding, bytes)),
+        None => Err(error(format!("unknown encoding: {}", name))),
+    }
+}
+
+/// Decode a module's bytes as CPython does before tokenizing them
+pub fn decode(bytes: &[u8]) -> Result<String, DecodeError> {
+    let (encoding, bytes) = detect(bytes)?;
+    let invalid = |offset: usize| {
+        let line_start = bytes[..offset]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let prefix = &bytes[line_start..offset];
+        DecodeError {
+            line: bytes[..offset].iter().filter(|&&b| b == b'\n').count() + 1,
+            column: match encoding {
+                Encoding::Utf8 => String::from_utf8_lossy(prefix).chars().count() + 1,
+                _ => prefix.len() + 1,
+            },
+            message: format!(
+                "'{}' codec can't decode byte {:#04x}",
+                encoding.name(),
+                bytes[offset]
+            ),
+        }
+    };
+    match encoding {
+        Encoding::Utf8 => std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|e| invalid(e.valid_up_to())),
+        _ => bytes
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| encoding.char(b).ok_or_else(|| invalid(i)))
+            .collect(),
+    }
+}
+
+/// Read a module and decode it, failing with `InvalidData` if it cannot be decoded
+pub fn read(path: &Path) -> io::Result<String> {
+    decode(&fs::read(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"\xef\xbb\xbfx = 1\n").unwrap(), "x = 1\n");
+        assert_eq!(
+            decode(b"# -*- coding: latin-1 -*-\nname = '\xe9t\xe9'\n").unwrap(),
+            "# -*- coding: latin-1 -*-\nname = 'été'\n"
+        );
+        assert_eq!(
+            decode(b"#!/usr/bin/env python\n# vim: set fileencoding=cp1252 :\nx = '\x80'\n")
+                .unwrap()
+                .lines()
+                .last(),
+            Some("x = '€'")
+        );
+        assert_eq!(
+            decode(b"# coding=iso-8859-15\nx = '\xa4'\n").unwrap(),
+            "# coding=iso-8859-15\nx = '€'\n"
+        );
+        // The cookie only counts on the second line after a comment or blank line
+        assert!(decode(b"import os\n# coding: latin-1\nx = '\xe9'\n").is_err());
+        assert_eq!(
+            decode(b"\xef\xbb\xbf# coding: utf-8\nx = 1\n").unwrap(),
+            "# coding: utf-8\nx = 1\n"
+        );
+
+        let error = decode(b"x = 1\ny = '\xc3\xa9\xe9'\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 7));
+        assert_eq!(error.message, "'utf-8' codec can't decode byte 0xe9");
+        let error = decode(b"# coding: ascii\nx = '\xe9'\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 6));
+        let error = decode(b"\xef\xbb\xbf# coding: latin-1\n").unwrap_err();
+        assert_eq!(error.message, "encoding problem: latin-1 with BOM");
+        let error = decode(b"\n# coding: klingon\n").unwrap_err();
+        assert_eq!(
+            (error.line, error.message.as_str()),
+            (2, "unknown encoding: klingon")
+        );
+        assert!(decode(b"# coding: cp1252\nx = '\x81'\n").is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(Encoding::lookup("UTF8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::lookup("utf_8_sig"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::lookup("latin-1-unix"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::lookup("ISO_8859-1"), Some(Encoding::Latin1));
+        assert_eq!(Encoding::lookup("Windows-1252"), Some(Encoding::Cp1252));
+        assert_eq!(Encoding::lookup("latin9"), Some(Encoding::Latin9));
+        assert_eq!(Encoding::lookup("shift_jis"), None);
+    }
+}
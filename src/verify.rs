@@ -0,0 +1,339 @@
+//! Differences between rytest's collection and pytest's own, for `--rytest-verify`.
+
+use crate::manifest::{ManifestNode, NodeKind};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Difference {
+    /// Collected by pytest but not by rytest
+    Missing,
+    /// Collected by rytest but not by pytest
+    Unexpected,
+    Line {
+        rytest: Option<usize>,
+        pytest: Option<usize>,
+    },
+    ParametrizeIds {
+        rytest: Vec<String>,
+        pytest: Vec<String>,
+    },
+    Params {
+        rytest: Vec<(String, String)>,
+        pytest: Vec<(String, String)>,
+    },
+    Marks {
+        rytest: Vec<String>,
+        pytest: Vec<String>,
+    },
+    /// The tests common to both are in a different order
+    Order,
+    /// pytest could not collect the module itself
+    PytestError(String),
+}
+
+/// One difference, attributed to a test or (for `Order` and `PytestError`) a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub file: String,
+    pub nodeid: String,
+    pub difference: Difference,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn line(line: &Option<usize>) -> String {
+            line.map_or_else(|| "?".to_string(), |l| l.to_string())
+        }
+        fn params(params: &[(String, String)]) -> String {
+            params
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        match self {
+            Difference::Missing => f.write_str("collected by pytest but not rytest"),
+            Difference::Unexpected => f.write_str("collected by rytest but not pytest"),
+            Difference::Line { rytest, pytest } => {
+                write!(
+                    f,
+                    "line {} (rytest) != {} (pytest)",
+                    line(rytest),
+                    line(pytest)
+                )
+            }
+            Difference::ParametrizeIds { rytest, pytest } => write!(
+                f,
+                "parametrize ids [{}] (rytest) != [{}] (pytest)",
+                rytest.join(", "),
+                pytest.join(", ")
+            ),
+            Difference::Params { rytest, pytest } => write!(
+                f,
+                "parameters {} (rytest) != {} (pytest)",
+                params(rytest),
+                params(pytest)
+            ),
+            Difference::Marks { rytest, pytest } => write!(
+                f,
+                "marks [{}] (rytest) != [{}] (pytest)",
+                rytest.join(", "),
+                pytest.join(", ")
+            ),
+            Difference::Order => f.write_str("tests are collected in a different order"),
+            Difference::PytestError(message) => write!(f, "pytest failed to collect: {}", message),
+        }
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.nodeid, self.difference)
+    }
+}
+
+/// The node id without its parametrize id, e.g. `test_a.py::test_b`
+pub(crate) fn base_nodeid(nodeid: &str) -> &str {
+    match nodeid.rfind("::") {
+        Some(sep) => match nodeid[sep..].find('[') {
+            Some(bracket) => &nodeid[..sep + bracket],
+            None => nodeid,
+        },
+        None => nodeid,
+    }
+}
+
+fn param_id(nodeid: &str) -> String {
+    nodeid[base_nodeid(nodeid).len()..]
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string()
+}
+
+fn mark_names(node: &ManifestNode) -> Vec<String> {
+    node.marks.iter().map(|mark| mark.name.clone()).collect()
+}
+
+fn params(node: &ManifestNode) -> Vec<(String, String)> {
+    node.callspec
+        .as_ref()
+        .map(|callspec| callspec.params.clone())
+        .unwrap_or_default()
+}
+
+/// Compare the tests of one file
+fn diff_file(file: &str, rytest: &[&ManifestNode], pytest: &[&ManifestNode]) -> Vec<Mismatch> {
+    let mismatch = |nodeid: &str, difference| Mismatch {
+        file: file.to_string(),
+        nodeid: nodeid.to_string(),
+        difference,
+    };
+    let mut mismatches = Vec::new();
+    let rytest_ids: HashSet<&str> = rytest.iter().map(|n| n.nodeid.as_str()).collect();
+    let pytest_ids: HashSet<&str> = pytest.iter().map(|n| n.nodeid.as_str()).collect();
+
+    // Group differing parametrizations of the same function into one mismatch
+    let mut reported = HashSet::new();
+    let mut by_base: Vec<(&str, Vec<String>, Vec<String>)> = Vec::new();
+    for (nodes, is_rytest) in [(rytest, true), (pytest, false)] {
+        for node in nodes {
+            let base = base_nodeid(&node.nodeid);
+            let index = match by_base.iter().position(|(b, ..)| *b == base) {
+                Some(index) => index,
+                None => {
+                    by_base.push((base, Vec::new(), Vec::new()));
+                    by_base.len() - 1
+                }
+            };
+            let ids = if is_rytest {
+                &mut by_base[index].1
+            } else {
+                &mut by_base[index].2
+            };
+            ids.push(param_id(&node.nodeid));
+        }
+    }
+    for (base, rytest_params, pytest_params) in by_base {
+        let parametrized = rytest_params
+            .iter()
+            .chain(&pytest_params)
+            .any(|id| !id.is_empty());
+        if parametrized
+            && !rytest_params.is_empty()
+            && !pytest_params.is_empty()
+            && rytest_params != pytest_params
+        {
+            reported.insert(base);
+            mismatches.push(mismatch(
+                base,
+                Difference::ParametrizeIds {
+                    rytest: rytest_params,
+                    pytest: pytest_params,
+                },
+            ));
+        }
+    }
+
+    let by_nodeid: HashMap<&str, &ManifestNode> =
+        rytest.iter().map(|n| (n.nodeid.as_str(), *n)).collect();
+    for expected in pytest {
+        if reported.contains(base_nodeid(&expected.nodeid)) {
+            continue;
+        }
+        let Some(actual) = by_nodeid.get(expected.nodeid.as_str()) else {
+            mismatches.push(mismatch(&expected.nodeid, Difference::Missing));
+            continue;
+        };
+        if actual.line != expected.line {
+            mismatches.push(mismatch(
+                &expected.nodeid,
+                Difference::Line {
+                    rytest: actual.line,
+                    pytest: expected.line,
+                },
+            ));
+        }
+        if params(actual) != params(expected) {
+            mismatches.push(mismatch(
+                &expected.nodeid,
+                Difference::Params {
+                    rytest: params(actual),
+                    pytest: params(expected),
+                },
+            ));
+        }
+        if mark_names(actual) != mark_names(expected) {
+            mismatches.push(mismatch(
+                &expected.nodeid,
+                Difference::Marks {
+                    rytest: mark_names(actual),
+                    pytest: mark_names(expected),
+                },
+            ));
+        }
+    }
+    for actual in rytest {
+        if !pytest_ids.contains(actual.nodeid.as_str())
+            && !reported.contains(base_nodeid(&actual.nodeid))
+        {
+            mismatches.push(mismatch(&actual.nodeid, Difference::Unexpected));
+        }
+    }
+
+    let common = |nodes: &[&ManifestNode], other: &HashSet<&str>| -> Vec<String> {
+        nodes
+            .iter()
+            .filter(|n| other.contains(n.nodeid.as_str()))
+            .map(|n| n.nodeid.clone())
+            .collect()
+    };
+    if common(rytest, &pytest_ids) != common(pytest, &rytest_ids) {
+        mismatches.push(mismatch(file, Difference::Order));
+    }
+    mismatches
+}
+
+fn tests_in<'a>(nodes: &'a [ManifestNode], file: &str) -> Vec<&'a ManifestNode> {
+    nodes
+        .iter()
+        .filter(|n| n.kind == NodeKind::Function && n.file == file)
+        .collect()
+}
+
+/// Compare the tests rytest collected against the ones pytest collected, file by file
+///
+/// Only function nodes are compared; files appear in the order pytest collected them.
+pub fn diff(rytest: &[ManifestNode], pytest: &[ManifestNode]) -> Vec<Mismatch> {
+    let mut files: Vec<&str> = Vec::new();
+    for node in pytest.iter().chain(rytest) {
+        if !files.contains(&node.file.as_str()) {
+            files.push(&node.file);
+        }
+    }
+    files
+        .iter()
+        .flat_map(|file| diff_file(file, &tests_in(rytest, file), &tests_in(pytest, file)))
+        .collect()
+}
+
+/// Human readable report lines, grouped by file
+pub fn report(mismatches: &[Mismatch]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current: Option<&str> = None;
+    for mismatch in mismatches {
+        if current != Some(mismatch.file.as_str()) {
+            let count = mismatches
+                .iter()
+                .filter(|m| m.file == mismatch.file)
+                .count();
+            let noun = if count == 1 { "mismatch" } else { "mismatches" };
+            lines.push(format!("{}: {} {}", mismatch.file, count, noun));
+            current = Some(&mismatch.file);
+        }
+        let name = mismatch
+            .nodeid
+            .strip_prefix(&mismatch.file)
+            .and_then(|rest| rest.strip_prefix("::"))
+            .unwrap_or("<module>");
+        lines.push(format!("  {}: {}", name, mismatch.difference));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Mark;
+
+    fn test(nodeid: &str, line: usize, marks: &[&str]) -> ManifestNode {
+        let marks = marks
+            .iter()
+            .map(|name| Mark {
+                name: name.to_string(),
+                args: Vec::new(),
+            })
+            .collect();
+        ManifestNode::test(nodeid, Some(line), Vec::new(), marks)
+    }
+
+    #[test]
+    fn test_diff() {
+        let rytest = vec![
+            test("t.py::test_same", 1, &[]),
+            test("t.py::test_line", 3, &[]),
+            test("t.py::test_p[a]", 5, &["parametrize"]),
+            test("t.py::test_p[b]", 5, &["parametrize"]),
+            test("t.py::TestA::test_marks", 9, &["slow"]),
+            test("t.py::test_extra", 12, &[]),
+            test("u.py::test_b", 1, &[]),
+            test("u.py::test_a", 3, &[]),
+        ];
+        let pytest = vec![
+            test("t.py::test_same", 1, &[]),
+            test("t.py::test_line", 4, &[]),
+            test("t.py::test_p[a]", 5, &["parametrize"]),
+            test("t.py::test_p[c]", 5, &["parametrize"]),
+            test("t.py::TestA::test_marks", 9, &["slow", "unit"]),
+            test("t.py::test_missing", 14, &[]),
+            test("u.py::test_a", 3, &[]),
+            test("u.py::test_b", 1, &[]),
+        ];
+
+        let mismatches = diff(&rytest, &pytest);
+        assert_eq!(
+            report(&mismatches),
+            vec![
+                "t.py: 5 mismatches",
+                "  test_p: parametrize ids [a, b] (rytest) != [a, c] (pytest)",
+                "  test_line: line 3 (rytest) != 4 (pytest)",
+                "  TestA::test_marks: marks [slow] (rytest) != [slow, unit] (pytest)",
+                "  test_missing: collected by pytest but not rytest",
+                "  test_extra: collected by rytest but not pytest",
+                "u.py: 1 mismatch",
+                "  <module>: tests are collected in a different order",
+            ]
+        );
+        assert!(diff(&pytest, &pytest).is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+//! Filesystem traversal following pytest's directory collection rules.
+
+use crate::config::IniConfig;
+use glob_match::glob_match;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Check if a directory is a Python package (has __init__.py)
+pub fn is_package_dir(path: &Path) -> bool {
+    path.join("__init__.py").is_file()
+}
+
+/// Virtualenvs are never recursed into, as with pytest's `_in_venv`
+fn is_venv(path: &Path) -> bool {
+    path.join("pyvenv.cfg").is_file()
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(|n| n.to_str()).unwrap_or("")
+}
+
+/// Check if a directory should be skipped according to `norecursedirs`
+pub fn is_norecurse(path: &Path, config: &IniConfig) -> bool {
+    let name = file_name(path);
+    name == "__pycache__"
+        || is_venv(path)
+        || config
+            .norecursedirs
+            .iter()
+            .any(|pattern| glob_match(pattern, name))
+}
+
+/// Check if a file found while walking a directory is a test module
+pub fn is_test_module(path: &Path, config: &IniConfig) -> bool {
+    let name = file_name(path);
+    name.ends_with(".py")
+        && config
+            .python_files
+            .iter()
+            .any(|pattern| glob_match(pattern, name))
+}
+
+/// The paths to collect when none are given on the command line
+pub fn default_args(config: &IniConfig, invocation_dir: &Path) -> Vec<PathBuf> {
+    let testpaths: Vec<PathBuf> = config
+        .testpaths
+        .iter()
+        .map(|p| config.rootdir.join(p))
+        .filter(|p| p.exists())
+        .collect();
+    if testpaths.is_empty() || invocation_dir != config.rootdir {
+        vec![invocation_dir.to_path_buf()]
+    } else {
+        testpaths
+    }
+}
+
+/// Expand command line arguments into the test modules to parse, in collection order
+pub fn collect_paths(args: &[PathBuf], config: &IniConfig) -> Vec<PathBuf> {
+    collect_files(args, config, |path, explicit| {
+        // Explicitly given files are collected regardless of `python_files`
+        if explicit {
+            file_name(path).ends_with(".py")
+        } else {
+            is_test_module(path, config)
+        }
+    })
+}
+
+/// Expand command line arguments into the files `wanted` accepts, in collection order
+///
+/// `wanted` is told whether the file was given explicitly rather than found
+/// while walking a directory.
+pub fn collect_files(
+    args: &[PathBuf],
+    config: &IniConfig,
+    wanted: impl Fn(&Path, bool) -> bool,
+) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for arg in args {
+        if arg.is_dir() {
+            walk_dir(arg, config, &wanted, &mut paths);
+        } else if arg.is_file() && wanted(arg, true) {
+            paths.push(arg.clone());
+        }
+    }
+    paths
+}
+
+fn walk_dir(
+    dir: &Path,
+    config: &IniConfig,
+    wanted: &impl Fn(&Path, bool) -> bool,
+    paths: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    entries.sort_by(|a, b| file_name(a).cmp(file_name(b)));
+
+    for entry in entries {
+        if entry.is_dir() {
+            if !is_norecurse(&entry, config) {
+                walk_dir(&entry, config, wanted, paths);
+            }
+        } else if entry.is_file() && wanted(&entry, false) {
+            paths.push(entry);
+        }
+    }
+}
+
+/// The name pytest's default `prepend` import mode imports a module under
+///
+/// Enclosing directories with an `__init__.py` are packages, so with both
+/// `pkg` and `pkg/sub` being packages `pkg/sub/mod.py` is `pkg.sub.mod` and
+/// `pkg/__init__.py` is `pkg`.
+pub fn module_name(path: &Path) -> String {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let mut parts = Vec::new();
+    if stem != "__init__" {
+        parts.push(stem);
+    }
+    let mut dir = path.parent();
+    while let Some(package) = dir.filter(|dir| is_package_dir(dir)) {
+        parts.push(file_name(package));
+        dir = package.parent();
+    }
+    parts.reverse();
+    parts.join(".")
+}
+
+/// The `conftest.py` files that apply to `module`, outermost first
+///
+/// Only directories inside `rootdir` are considered, as with the default `confcutdir`.
+pub fn conftest_paths(module: &Path, rootdir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = module
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(rootdir) || module.parent() == Some(*dir))
+        .map(|dir| dir.join("conftest.py"))
+        .filter(|path| path.is_file())
+        .collect();
+    paths.reverse();
+    paths
+}
+
+/// Render a path relative to the rootdir with forward slashes, as in node ids
+pub fn relative_nodeid_path(path: &Path, rootdir: &Path) -> String {
+    let relative = path.strip_prefix(rootdir).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_paths() {
+        let dir = std::env::temp_dir().join("rytest_walk");
+        let _ = fs::remove_dir_all(&dir);
+        for sub in [
+            "tests/unit",
+            "tests/.hidden",
+            "build",
+            "venv_like",
+            "tests/__pycache__",
+        ] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        fs::write(dir.join("venv_like/pyvenv.cfg"), "").unwrap();
+        for file in [
+            "tests/test_b.py",
+            "tests/a_test.py",
+            "tests/helpers.py",
+            "tests/unit/test_a.py",
+            "tests/.hidden/test_hidden.py",
+            "tests/__pycache__/test_cached.py",
+            "build/test_built.py",
+            "venv_like/test_venv.py",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let config = IniConfig::with_defaults(dir.clone());
+        let paths: Vec<String> = collect_paths(std::slice::from_ref(&dir), &config)
+            .iter()
+            .map(|p| relative_nodeid_path(p, &dir))
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["tests/a_test.py", "tests/test_b.py", "tests/unit/test_a.py"]
+        );
+
+        // Explicit files bypass python_files
+        let paths = collect_paths(&[dir.join("tests/helpers.py")], &config);
+        assert_eq!(paths, vec![dir.join("tests/helpers.py")]);
+
+        fs::write(dir.join("tests/__init__.py"), "").unwrap();
+        fs::write(dir.join("tests/unit/__init__.py"), "").unwrap();
+        assert_eq!(
+            module_name(&dir.join("tests/unit/test_a.py")),
+            "tests.unit.test_a"
+        );
+        assert_eq!(module_name(&dir.join("tests/__init__.py")), "tests");
+        assert_eq!(module_name(&dir.join("build/test_built.py")), "test_built");
+
+        fs::write(dir.join("conftest.py"), "").unwrap();
+        fs::write(dir.join("tests/unit/conftest.py"), "").unwrap();
+        assert_eq!(
+            conftest_paths(&dir.join("tests/unit/test_a.py"), &dir),
+            vec![dir.join("conftest.py"), dir.join("tests/unit/conftest.py")]
+        );
+        assert_eq!(
+            conftest_paths(&dir.join("tests/unit/test_a.py"), &dir.join("tests")),
+            vec![dir.join("tests/unit/conftest.py")]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+//! Watching a source tree for changed Python files with inotify.
+
+use crate::config::IniConfig;
+use crate::walk;
+use inotify::{EventMask, Inotify, WatchMask};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for more events after the first, so an editor's
+/// save (write, rename, chmod) is reported once
+const SETTLE: Duration = Duration::from_millis(50);
+
+/// The events that change what a directory's Python files contain
+fn watch_mask() -> WatchMask {
+    WatchMask::CLOSE_WRITE
+        | WatchMask::MODIFY
+        | WatchMask::CREATE
+        | WatchMask::DELETE
+        | WatchMask::MOVED_FROM
+        | WatchMask::MOVED_TO
+        | WatchMask::ONLYDIR
+}
+
+/// Watches every directory pytest would recurse into below some paths
+pub struct Watcher {
+    inotify: Inotify,
+    config: IniConfig,
+    /// The directory each watch descriptor id watches
+    dirs: HashMap<i32, PathBuf>,
+    buffer: Vec<u8>,
+}
+
+impl Watcher {
+    /// Watch the directories under `args`, or containing them for files
+    pub fn new(args: &[PathBuf], config: &IniConfig) -> io::Result<Self> {
+        let mut watcher = Watcher {
+            inotify: Inotify::init()?,
+            config: config.clone(),
+            dirs: HashMap::new(),
+            buffer: vec![0; 64 * 1024],
+        };
+        for arg in args {
+            if arg.is_dir() {
+                watcher.watch_tree(arg, &mut Vec::new())?;
+            } else if let Some(parent) = arg.parent() {
+                watcher.watch(parent)?;
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// The number of directories watched
+    pub fn len(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Also watch `dir`, but not the directories below it
+    pub fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+        if self.dirs.values().any(|watched| watched == dir) {
+            return Ok(());
+        }
+        self.watch(dir)
+    }
+
+    fn watch(&mut self, dir: &Path) -> io::Result<()> {
+        let wd = self.inotify.watches().add(dir, watch_mask())?;
+        self.dirs
+            .insert(wd.get_watch_descriptor_id(), dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Watch `dir` and the directories below it, adding their Python files to `found`
+    fn watch_tree(&mut self, dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+        self.watch(dir)?;
+        let paths = walk::collect_files(&[dir.to_path_buf()], &self.config, |path, _| {
+            path.extension().is_some_and(|ext| ext == "py")
+        });
+        found.extend(paths);
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.filter_map(Result::ok) {
+                let path = entry.path();
+                if path.is_dir() && !walk::is_norecurse(&path, &self.config) {
+                    // The directory may be gone again already
+                    if self.watch(&path).is_ok() {
+                        dirs.push(path);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Block until Python files change, and return the changed paths
+    ///
+    /// Created and modified files are returned with the files of new
+    /// directories, which are watched from then on. Deleted files and
+    /// directories are returned too, so callers can drop what they knew.
+    pub fn wait(&mut self) -> io::Result<Vec<PathBuf>> {
+        loop {
+            let mut changed = BTreeSet::new();
+            let mut new_dirs = Vec::new();
+            let mut blocking = true;
+            loop {
+                let events = if blocking {
+                    self.inotify.read_events_blocking(&mut self.buffer)
+                } else {
+                    self.inotify.read_events(&mut self.buffer)
+                };
+                let events = match events {
+                    Ok(events) => events,
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err),
+                };
+                for event in events {
+                    let id = event.wd.get_watch_descriptor_id();
+                    if event.mask.contains(EventMask::IGNORED) {
+                        self.dirs.remove(&id);
+                        continue;
+                    }
+                    let (Some(dir), Some(name)) = (self.dirs.get(&id), event.name) else {
+                        continue;
+                    };
+                    let path = dir.join(name);
+                    if event.mask.contains(EventMask::ISDIR) {
+                        if event
+                            .mask
+                            .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+                        {
+                            if !walk::is_norecurse(&path, &self.config) {
+                                new_dirs.push(path);
+                            }
+                        } else {
+                            changed.insert(path);
+                        }
+                    } else if path.extension().is_some_and(|ext| ext == "py") {
+                        changed.insert(path);
+                    }
+                }
+                blocking = false;
+                thread::sleep(SETTLE);
+            }
+            for dir in new_dirs {
+                let mut found = Vec::new();
+                // A directory removed again before we got to it has no files
+                let _ = self.watch_tree(&dir, &mut found);
+                changed.extend(found);
+            }
+            if !changed.is_empty() {
+                return Ok(changed.into_iter().collect());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_watcher() {
+        let dir = std::env::temp_dir().join("rytest_watch");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tests/__pycache__")).unwrap();
+        let config = IniConfig::with_defaults(dir.clone());
+        let mut watcher = Watcher::new(&[dir.join("tests")], &config).unwrap();
+        assert_eq!(watcher.len(), 1);
+
+        fs::write(dir.join("tests/test_a.py"), "def test_a(): pass\n").unwrap();
+        fs::write(dir.join("tests/README.md"), "").unwrap();
+        assert_eq!(watcher.wait().unwrap(), vec![dir.join("tests/test_a.py")]);
+
+        // New directories are watched, and their files reported
+        fs::create_dir_all(dir.join("tests/unit")).unwrap();
+        fs::write(dir.join("tests/unit/test_b.py"), "").unwrap();
+        assert_eq!(
+            watcher.wait().unwrap(),
+            vec![dir.join("tests/unit/test_b.py")]
+        );
+        fs::write(dir.join("tests/unit/test_c.py"), "").unwrap();
+        assert_eq!(
+            watcher.wait().unwrap(),
+            vec![dir.join("tests/unit/test_c.py")]
+        );
+
+        fs::remove_dir_all(dir.join("tests/unit")).unwrap();
+        let changed = watcher.wait().unwrap();
+        assert!(changed.contains(&dir.join("tests/unit")), "{:?}", changed);
+
+        fs::create_dir_all(dir.join("src/app")).unwrap();
+        watcher.add_dir(&dir.join("src")).unwrap();
+        watcher.add_dir(&dir.join("src")).unwrap();
+        assert_eq!(watcher.len(), 2);
+        fs::write(dir.join("src/app/models.py"), "").unwrap();
+        fs::write(dir.join("src/util.py"), "").unwrap();
+        assert_eq!(watcher.wait().unwrap(), vec![dir.join("src/util.py")]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}